@@ -3,6 +3,7 @@ use clap::{Parser, Subcommand};
 use gl_client::bitcoin::Network;
 use std::{path::PathBuf, str::FromStr};
 mod error;
+mod lnurl;
 pub mod model;
 mod node;
 mod scheduler;
@@ -35,6 +36,9 @@ pub enum Commands {
     /// Interact with the node
     #[command(subcommand)]
     Node(node::Command),
+    /// Serve LNURL requests from the node
+    #[command(subcommand)]
+    Lnurl(lnurl::Command),
 }
 
 pub async fn run(cli: Cli) -> Result<()> {
@@ -82,5 +86,15 @@ pub async fn run(cli: Cli) -> Result<()> {
             )
             .await?
         }
+        Commands::Lnurl(cmd) => {
+            lnurl::command_handler(
+                cmd,
+                lnurl::Config {
+                    data_dir,
+                    network: cli.network,
+                },
+            )
+            .await?
+        }
     })
 }
@@ -0,0 +1,97 @@
+use crate::error::{Error, Result};
+use crate::util::{self, CREDENTIALS_FILE_NAME};
+use clap::Subcommand;
+use gl_client::bitcoin::Network;
+use gl_client::lnurl::server::{LnurlPayService, ServerConfig};
+use gl_client::node::reconnect::RetryPolicy;
+use std::net::SocketAddr;
+use std::path::Path;
+
+pub struct Config<P: AsRef<Path>> {
+    pub data_dir: P,
+    pub network: Network,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Serve LNURL-pay requests and Lightning Addresses with invoices
+    /// from the node
+    Serve {
+        #[arg(
+            long,
+            default_value = "127.0.0.1:8080",
+            help = "The address to listen on"
+        )]
+        listen: SocketAddr,
+        #[arg(
+            long,
+            required = true,
+            help = "The public URL of the server, e.g., https://example.com"
+        )]
+        base_url: String,
+        #[arg(long, default_value_t = 1_000)]
+        min_sendable: u64,
+        #[arg(long, default_value_t = 1_000_000_000)]
+        max_sendable: u64,
+        #[arg(long, default_value_t = 0, help = "The maximum length of comments")]
+        comment_allowed: u64,
+        #[arg(
+            long,
+            help = "The user names to serve, can be repeated. Serves any user name if omitted"
+        )]
+        user: Vec<String>,
+    },
+}
+
+pub async fn command_handler<P: AsRef<Path>>(cmd: Command, config: Config<P>) -> Result<()> {
+    match cmd {
+        Command::Serve {
+            listen,
+            base_url,
+            min_sendable,
+            max_sendable,
+            comment_allowed,
+            user,
+        } => {
+            let server_config = ServerConfig::new(&base_url)
+                .map_err(Error::custom)?
+                .with_sendable(min_sendable, max_sendable)
+                .with_comment_allowed(comment_allowed)
+                .with_users(user);
+            serve_handler(config, listen, server_config).await
+        }
+    }
+}
+
+async fn serve_handler<P: AsRef<Path>>(
+    config: Config<P>,
+    listen: SocketAddr,
+    server_config: ServerConfig,
+) -> Result<()> {
+    let creds_path = config.data_dir.as_ref().join(CREDENTIALS_FILE_NAME);
+    let creds = match util::read_credentials(&creds_path) {
+        Some(c) => c,
+        None => {
+            return Err(Error::CredentialsNotFoundError(format!(
+                "could not read from {}",
+                creds_path.display()
+            )))
+        }
+    };
+
+    let scheduler = gl_client::scheduler::Scheduler::new(config.network, creds)
+        .await
+        .map_err(Error::custom)?;
+
+    // A long-running server outlives the node's sessions, so have the
+    // client schedule the node again when it goes offline.
+    let node: gl_client::node::ClnClient = scheduler
+        .node_reconnecting(RetryPolicy::default())
+        .await
+        .map_err(Error::custom)?;
+    println!("Serving LNURL-pay requests on {}", listen);
+    LnurlPayService::new(node, server_config)
+        .serve(listen)
+        .await
+        .map_err(Error::custom)
+}
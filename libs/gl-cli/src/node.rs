@@ -1,8 +1,10 @@
 use crate::error::{Error, Result};
 use crate::model;
-use crate::util::{self, CREDENTIALS_FILE_NAME, SEED_FILE_NAME};
+use crate::util::{self, CREDENTIALS_FILE_NAME, HISTORY_FILE_NAME, SEED_FILE_NAME};
 use clap::Subcommand;
 use futures::stream::StreamExt;
+use gl_client::lnurl::bip353::{DohResolver, HumanReadableName};
+use gl_client::lnurl::{LnUrlHttpClearnetClient, LNURL};
 use gl_client::pb::StreamLogRequest;
 use gl_client::{bitcoin::Network, pb::cln};
 use std::path::Path;
@@ -44,7 +46,7 @@ pub enum Command {
         #[arg(long)]
         deschashonly: Option<bool>,
     },
-    /// Pay a bolt11 invoice
+    /// Pay a bolt11 invoice or a human-readable name like ₿alice@example.com
     Pay {
         #[arg(required = true)]
         bolt11: String,
@@ -70,6 +72,11 @@ pub enum Command {
         maxfee: Option<u64>,
         #[arg(long)]
         description: Option<String>,
+        /// Pay a human-readable name as a lightning address if the
+        /// DNS resolver says it has no BIP-353 record. That answer
+        /// is not DNSSEC-proven, so this trusts the resolver.
+        #[arg(long)]
+        lnurl_fallback: bool,
     },
     /// Establish a new connection with another lightning node.
     Connect {
@@ -98,6 +105,13 @@ pub enum Command {
         )]
         status: Option<String>,
     },
+    /// Show payments, invoices, forwards and on-chain transactions, newest first
+    History {
+        #[arg(long, default_value_t = 50, help = "Maximum number of entries to show")]
+        limit: usize,
+        #[arg(long, help = "Only show entries older than the one with this id")]
+        before: Option<String>,
+    },
     /// Stop the node
     Stop,
 }
@@ -143,6 +157,7 @@ pub async fn command_handler<P: AsRef<Path>>(cmd: Command, config: Config<P>) ->
             exclude,
             maxfee,
             description,
+            lnurl_fallback,
         } => {
             pay_handler(
                 config,
@@ -158,6 +173,7 @@ pub async fn command_handler<P: AsRef<Path>>(cmd: Command, config: Config<P>) ->
                 exclude,
                 maxfee,
                 description,
+                lnurl_fallback,
             )
             .await
         }
@@ -194,6 +210,7 @@ pub async fn command_handler<P: AsRef<Path>>(cmd: Command, config: Config<P>) ->
 
             listpays_handler(config, bolt11, payment_hash, status).await
         }
+        Command::History { limit, before } => history_handler(config, limit, before).await,
         Command::Stop => stop(config).await,
     }
 }
@@ -297,6 +314,51 @@ async fn stop<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
     Ok(())
 }
 
+async fn history_handler<P: AsRef<Path>>(
+    config: Config<P>,
+    limit: usize,
+    before: Option<String>,
+) -> Result<()> {
+    let creds_path = config.data_dir.as_ref().join(CREDENTIALS_FILE_NAME);
+    let creds = match util::read_credentials(&creds_path) {
+        Some(c) => c,
+        None => {
+            return Err(Error::CredentialsNotFoundError(format!(
+                "could not read from {}",
+                creds_path.display()
+            )))
+        }
+    };
+
+    let scheduler = gl_client::scheduler::Scheduler::new(config.network, creds)
+        .await
+        .map_err(Error::custom)?;
+
+    let mut node: gl_client::node::ClnClient = scheduler.node().await.map_err(Error::custom)?;
+    // On-chain transactions are stamped with the time we first saw
+    // them, so keep the history between runs.
+    let history_path = config.data_dir.as_ref().join(HISTORY_FILE_NAME);
+    let mut history = std::fs::read(&history_path)
+        .ok()
+        .and_then(|data| gl_client::history::History::from_bytes(&data).ok())
+        .unwrap_or_default();
+    history.sync(&mut node).await.map_err(Error::custom)?;
+    let data = history.to_bytes().map_err(Error::custom)?;
+    std::fs::write(&history_path, data).map_err(Error::custom)?;
+
+    for a in history.page(before.as_deref(), limit) {
+        let sign = match a.direction {
+            gl_client::wallet::Direction::Incoming => '+',
+            gl_client::wallet::Direction::Outgoing => '-',
+        };
+        println!(
+            "{} {:?} {:?} {}{}msat {}",
+            a.timestamp, a.kind, a.status, sign, a.amount_msat, a.id
+        );
+    }
+    Ok(())
+}
+
 async fn getinfo_handler<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
     let creds_path = config.data_dir.as_ref().join(CREDENTIALS_FILE_NAME);
     let creds = match util::read_credentials(&creds_path) {
@@ -452,6 +514,7 @@ async fn pay_handler<P: AsRef<Path>>(
     exclude: Option<Vec<String>>,
     maxfee: Option<u64>,
     description: Option<String>,
+    lnurl_fallback: bool,
 ) -> Result<()> {
     let creds_path = config.data_dir.as_ref().join(CREDENTIALS_FILE_NAME);
     let creds = match util::read_credentials(&creds_path) {
@@ -469,6 +532,21 @@ async fn pay_handler<P: AsRef<Path>>(
         .map_err(Error::custom)?;
 
     let mut node: gl_client::node::ClnClient = scheduler.node().await.map_err(Error::custom)?;
+    let (bolt11, amount_msat) = if bolt11.parse::<HumanReadableName>().is_ok() {
+        let resolved = LNURL::new(LnUrlHttpClearnetClient::new())
+            .get_invoice_for_name(
+                &DohResolver::new(),
+                &bolt11,
+                amount_msat,
+                lnurl_fallback,
+                &mut node,
+            )
+            .await
+            .map_err(Error::custom)?;
+        (resolved.invoice, resolved.amount_msat)
+    } else {
+        (bolt11, amount_msat)
+    };
     let res = node
         .pay(cln::PayRequest {
             bolt11,
@@ -2,13 +2,15 @@ use crate::error::{Error, Result};
 use crate::util;
 use clap::Subcommand;
 use core::fmt::Debug;
+use gl_client::persist::FileStateStore;
 use gl_client::{credentials, pairing, scheduler::Scheduler, signer::Signer};
 use lightning_signer::bitcoin::Network;
 use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
 use std::{fs, io};
 use tokio::task;
-use util::{CREDENTIALS_FILE_NAME, SEED_FILE_NAME};
+use util::{CREDENTIALS_FILE_NAME, SEED_FILE_NAME, SIGNER_STATE_FILE_NAME};
 
 pub struct Config<P: AsRef<Path>> {
     pub data_dir: P,
@@ -40,7 +42,7 @@ pub enum Command {
         description: Option<String>,
         #[arg(
             long,
-            help = "A set of restrictions to restrict the node access for the device"
+            help = "A set of restrictions to restrict the node access for the device, either as rune restrictions or as predefined rule sets, e.g., invoice-only,expires=30d"
         )]
         restrictions: Option<String>,
     },
@@ -104,8 +106,15 @@ async fn register_handler<P: AsRef<Path>>(
     };
 
     // Initialize a signer and scheduler with default credentials.
+    // Keep the signer state, so the unique id of the rune we get
+    // issued is not handed out again.
     let creds = credentials::Nobody::new();
-    let signer = Signer::new(seed, config.network, creds.clone())
+    let store = Arc::new(FileStateStore::new(
+        config.data_dir.as_ref().join(SIGNER_STATE_FILE_NAME),
+    ));
+    let signer = Signer::builder(seed, config.network, creds.clone())
+        .with_state_store(store)
+        .build()
         .map_err(|e| Error::custom(format!("Failed to create signer: {}", e)))?;
     let scheduler = Scheduler::new(config.network, creds)
         .await
@@ -145,8 +154,15 @@ async fn recover_handler<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
     let seed = seed.unwrap(); // we checked if it is none before.
 
     // Initialize a signer and scheduler with default credentials.
+    // Keep the signer state, so the unique id of the rune we get
+    // issued is not handed out again.
     let creds = credentials::Nobody::new();
-    let signer = Signer::new(seed, config.network, creds.clone())
+    let store = Arc::new(FileStateStore::new(
+        config.data_dir.as_ref().join(SIGNER_STATE_FILE_NAME),
+    ));
+    let signer = Signer::builder(seed, config.network, creds.clone())
+        .with_state_store(store)
+        .build()
         .map_err(|e| Error::custom(format!("Failed to create signer: {}", e)))?;
     let scheduler = Scheduler::new(config.network, creds)
         .await
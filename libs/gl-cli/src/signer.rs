@@ -2,11 +2,16 @@ use crate::error::{Error, Result};
 use crate::util;
 use clap::Subcommand;
 use core::fmt::Debug;
-use gl_client::signer::Signer;
+use gl_client::persist::FileStateStore;
+use gl_client::signer::{AuditQuery, FileAuditLog, Signer, SignerPolicy};
 use lightning_signer::bitcoin::Network;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::{join, signal};
-use util::{CREDENTIALS_FILE_NAME, SEED_FILE_NAME};
+use util::{
+    CREDENTIALS_FILE_NAME, SEED_FILE_NAME, SIGNER_AUDIT_FILE_NAME, SIGNER_AUDIT_KEEP,
+    SIGNER_AUDIT_MAX_BYTES, SIGNER_STATE_FILE_NAME,
+};
 
 pub struct Config<P: AsRef<Path>> {
     pub data_dir: P,
@@ -16,19 +21,65 @@ pub struct Config<P: AsRef<Path>> {
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Starts a signer that connects to greenlight
-    Run,
+    Run {
+        #[arg(
+            long,
+            help = "A JSON file with the policy profile the signer should enforce"
+        )]
+        policy: Option<PathBuf>,
+    },
     /// Prints the version of the signer used
     Version,
+    /// Exports the audit journal of the signer as JSON
+    Audit {
+        #[arg(long, help = "Only entries at or after this UNIX timestamp")]
+        since: Option<u64>,
+        #[arg(long, help = "Only entries before this UNIX timestamp")]
+        until: Option<u64>,
+        #[arg(
+            long,
+            help = "Only entries for this request type, e.g., SignWithdrawal"
+        )]
+        request: Option<String>,
+        #[arg(long, help = "Only rejected requests")]
+        rejected: bool,
+    },
+    /// Lists the runes issued by the signer as JSON
+    ListRunes,
+    /// Revokes a rune by its unique id. Stop the signer before running this
+    RevokeRune {
+        #[arg(help = "The unique id of the rune, as shown by list-runes")]
+        id: u64,
+    },
 }
 
 pub async fn command_handler<P: AsRef<Path>>(cmd: Command, config: Config<P>) -> Result<()> {
     match cmd {
-        Command::Run => run_handler(config).await,
+        Command::Run { policy } => run_handler(config, policy).await,
         Command::Version => version(config).await,
+        Command::Audit {
+            since,
+            until,
+            request,
+            rejected,
+        } => {
+            audit(
+                config,
+                AuditQuery {
+                    since,
+                    until,
+                    request,
+                    rejected_only: rejected,
+                },
+            )
+            .await
+        }
+        Command::ListRunes => list_runes(config).await,
+        Command::RevokeRune { id } => revoke_rune(config, id).await,
     }
 }
 
-async fn run_handler<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
+async fn run_handler<P: AsRef<Path>>(config: Config<P>, policy: Option<PathBuf>) -> Result<()> {
     // Check if we can find a seed file, if we can not find one, we need to register first.
     let seed_path = config.data_dir.as_ref().join(SEED_FILE_NAME);
     let seed = util::read_seed(&seed_path);
@@ -53,7 +104,31 @@ async fn run_handler<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
             )))
         }
     };
-    let signer = Signer::new(seed, config.network, creds.clone())
+    // Keep a local copy of the signer state, so we do not have to
+    // rely on the node to tell us what state we were in.
+    let store = Arc::new(FileStateStore::new(
+        config.data_dir.as_ref().join(SIGNER_STATE_FILE_NAME),
+    ));
+    let policy = match policy {
+        Some(path) => util::read_policy(&path).map_err(|e| {
+            Error::custom(format!(
+                "Failed to load policy from {}: {}",
+                path.display(),
+                e
+            ))
+        })?,
+        None => SignerPolicy::default(),
+    };
+    let audit_log = Arc::new(FileAuditLog::new(
+        config.data_dir.as_ref().join(SIGNER_AUDIT_FILE_NAME),
+        SIGNER_AUDIT_MAX_BYTES,
+        SIGNER_AUDIT_KEEP,
+    ));
+    let signer = Signer::builder(seed, config.network, creds.clone())
+        .with_state_store(store)
+        .with_audit_log(audit_log)
+        .with_policy(policy)
+        .build()
         .map_err(|e| Error::custom(format!("Failed to create signer: {}", e)))?;
 
     let (tx, rx) = tokio::sync::mpsc::channel(1);
@@ -90,3 +165,61 @@ async fn version<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
     println!("{}", signer.version());
     Ok(())
 }
+
+async fn audit<P: AsRef<Path>>(config: Config<P>, query: AuditQuery) -> Result<()> {
+    let seed_path = config.data_dir.as_ref().join(SEED_FILE_NAME);
+    let seed = util::read_seed(&seed_path).ok_or_else(|| {
+        Error::SeedNotFoundError(format!("could not read from {}", seed_path.display()))
+    })?;
+
+    let audit_log = Arc::new(FileAuditLog::new(
+        config.data_dir.as_ref().join(SIGNER_AUDIT_FILE_NAME),
+        SIGNER_AUDIT_MAX_BYTES,
+        SIGNER_AUDIT_KEEP,
+    ));
+    let signer = Signer::builder(seed, config.network, gl_client::credentials::Nobody::new())
+        .with_audit_log(audit_log)
+        .build()
+        .map_err(|e| Error::custom(format!("Failed to create signer: {}", e)))?;
+    let entries = signer
+        .audit(&query)
+        .map_err(|e| Error::custom(format!("Failed to read audit journal: {}", e)))?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&entries).map_err(Error::custom)?
+    );
+    Ok(())
+}
+
+/// Create a signer operating on the local copy of the signer state.
+fn local_signer<P: AsRef<Path>>(config: &Config<P>) -> Result<Signer> {
+    let seed_path = config.data_dir.as_ref().join(SEED_FILE_NAME);
+    let seed = util::read_seed(&seed_path).ok_or_else(|| {
+        Error::SeedNotFoundError(format!("could not read from {}", seed_path.display()))
+    })?;
+    let store = Arc::new(FileStateStore::new(
+        config.data_dir.as_ref().join(SIGNER_STATE_FILE_NAME),
+    ));
+    Signer::builder(seed, config.network, gl_client::credentials::Nobody::new())
+        .with_state_store(store)
+        .build()
+        .map_err(|e| Error::custom(format!("Failed to create signer: {}", e)))
+}
+
+async fn list_runes<P: AsRef<Path>>(config: Config<P>) -> Result<()> {
+    let signer = local_signer(&config)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&signer.list_runes()).map_err(Error::custom)?
+    );
+    Ok(())
+}
+
+async fn revoke_rune<P: AsRef<Path>>(config: Config<P>, id: u64) -> Result<()> {
+    let signer = local_signer(&config)?;
+    signer
+        .revoke_rune(id)
+        .map_err(|e| Error::custom(format!("Failed to revoke rune {}: {}", id, e)))?;
+    println!("Revoked rune {}", id);
+    Ok(())
+}
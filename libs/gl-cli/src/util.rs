@@ -1,5 +1,6 @@
 use dirs;
 use gl_client::credentials;
+use gl_client::signer::SignerPolicy;
 use std::path::PathBuf;
 use std::{
     fs::{self, File},
@@ -10,6 +11,12 @@ use thiserror;
 
 pub const SEED_FILE_NAME: &str = "hsm_secret";
 pub const CREDENTIALS_FILE_NAME: &str = "credentials.gfs";
+pub const SIGNER_STATE_FILE_NAME: &str = "signer_state.json";
+pub const SIGNER_AUDIT_FILE_NAME: &str = "signer_audit.jsonl";
+/// Rotate the audit journal at this size, keeping this many old files.
+pub const SIGNER_AUDIT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const SIGNER_AUDIT_KEEP: usize = 5;
+pub const HISTORY_FILE_NAME: &str = "history.json";
 pub const DEFAULT_GREENLIGHT_DIR: &str = "greenlight";
 
 // -- Seed section
@@ -48,23 +55,62 @@ pub fn write_seed(file_path: impl AsRef<Path>, seed: impl AsRef<[u8]>) -> Result
 
 // -- Credentials section
 
+/// Writes the credentials, encrypted with the passphrase from
+/// `GL_CREDENTIALS_PASSPHRASE` if it is set.
 pub fn write_credentials(file_path: impl AsRef<Path>, creds: impl AsRef<[u8]>) -> Result<()> {
+    let creds = match credentials_key() {
+        Some(key) if !credentials::is_encrypted(creds.as_ref()) => {
+            credentials::encrypt(creds.as_ref(), &key).map_err(UtilsError::custom)?
+        }
+        _ => creds.as_ref().to_vec(),
+    };
     let mut file = File::create(&file_path)?;
-    file.write_all(creds.as_ref())?;
+    file.write_all(&creds)?;
     file.sync_all()?;
 
     Ok(())
 }
 
+/// Reads the credentials. Unencrypted credentials are encrypted in
+/// place if `GL_CREDENTIALS_PASSPHRASE` is set. Encrypted credentials
+/// that can't be decrypted are not returned.
 pub fn read_credentials(file_path: impl AsRef<Path>) -> Option<credentials::Device> {
-    let cred_data = fs::read(file_path).ok();
+    let cred_data = fs::read(&file_path).ok();
     if let Some(data) = cred_data {
-        let creds = credentials::Device::from_bytes(data);
+        let creds = match credentials::Device::try_from_bytes(&data) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!(
+                    "Could not load credentials from {}: {}",
+                    file_path.as_ref().display(),
+                    e
+                );
+                return None;
+            }
+        };
+        if credentials_key().is_some() && !credentials::is_encrypted(&data) {
+            if let Err(e) = write_credentials(&file_path, &data) {
+                eprintln!("Could not encrypt credentials: {}", e);
+            }
+        }
         return Some(creds);
     }
     None
 }
 
+fn credentials_key() -> Option<credentials::CredentialsKey> {
+    std::env::var(credentials::PASSPHRASE_ENV)
+        .ok()
+        .map(credentials::CredentialsKey::Passphrase)
+}
+
+// -- Policy section
+
+pub fn read_policy(file_path: impl AsRef<Path>) -> Result<SignerPolicy> {
+    let data = fs::read_to_string(file_path)?;
+    SignerPolicy::from_json(&data).map_err(UtilsError::custom)
+}
+
 // -- Misc
 
 pub struct DataDir(pub PathBuf);
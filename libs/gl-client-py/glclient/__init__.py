@@ -4,10 +4,11 @@ from pyln import grpc as clnpb  # type: ignore
 from pyln.grpc import Amount, AmountOrAll, AmountOrAny  # noqa: F401
 from . import glclient as native
 from .glclient import backup_decrypt_with_seed  # noqa: F401
+from .glclient import parse_payment_input  # noqa: F401
 from .tls import TlsConfig
 from google.protobuf.message import Message as PbMessage
 from binascii import hexlify, unhexlify
-from typing import Optional, List, Iterable, Any, Type, TypeVar
+from typing import Optional, List, Iterable, Any, Type, TypeVar, Callable, Dict
 import logging
 from glclient.glclient import Credentials
 
@@ -43,6 +44,27 @@ class Signer(object):
     def run_in_foreground(self) -> None:
         return self.inner.run_in_foreground()
 
+    def set_approval_handler(
+        self, handler: Callable[[Dict[str, Any]], bool], timeout: Optional[int] = None
+    ) -> None:
+        """Ask `handler` to approve requests the signer cannot match up
+        with a request from this client, e.g., payments initiated
+        elsewhere. `handler` is called with a `dict` describing the
+        request, and the request is denied if it does not return
+        `True` within `timeout` seconds. Must be called before
+        starting the signer.
+        """
+        self.inner.set_approval_handler(handler, timeout)
+
+    def enable_pairing_queue(self, timeout: Optional[int] = None) -> None:
+        """Hold pairing requests from new devices until they are
+        answered with `approve_pairing` or `deny_pairing`, instead of
+        approving them right away. Requests that are not answered
+        within `timeout` seconds are denied. Must be called before
+        starting the signer.
+        """
+        self.inner.enable_pairing_queue(timeout)
+
     def node_id(self) -> bytes:
         return bytes(self.inner.node_id())
 
@@ -63,6 +85,43 @@ class Signer(object):
     ) -> str:
         return self.inner.create_rune(restrictions, rune)
 
+    def list_runes(self) -> List[Dict[str, Any]]:
+        """List the runes this signer issued, with their unique `id`,
+        `restrictions`, `created_at` and `last_used` timestamps, and
+        whether they were `revoked`.
+        """
+        return self.inner.list_runes()
+
+    def revoke_rune(self, id: int) -> None:
+        """Revoke the rune with the unique id `id`. The signer rejects
+        all requests authorized by it, or by runes carved from it.
+        """
+        self.inner.revoke_rune(id)
+
+    def lnurl_auth(self, lnurl: str) -> None:
+        """Log into the service behind an LNURL-auth `lnurl` (LUD-04),
+        with a key derived from the node's keys for the service's
+        domain.
+        """
+        self.inner.lnurl_auth(lnurl)
+
+    def pending_pairings(self) -> List[Dict[str, Any]]:
+        """List the pairing requests waiting for an answer, with the
+        `device_id`, `device_name` and `pubkey` of the device, the
+        `restrictions` it asked for, and when the request `expires_at`.
+        """
+        return self.inner.pending_pairings()
+
+    def approve_pairing(self, device_id: str, restrictions: Optional[str] = None) -> None:
+        """Approve the pairing request from `device_id`. Any
+        `restrictions`, e.g., `readonly,expires=30d`, are added to the
+        ones the device asked for.
+        """
+        self.inner.approve_pairing(device_id, restrictions)
+
+    def deny_pairing(self, device_id: str) -> None:
+        self.inner.deny_pairing(device_id)
+
     def is_running(self) -> bool:
         return self.handle is not None
 
@@ -404,6 +463,27 @@ class Node(object):
 
         return res.FromString(bytes(self.inner.call(uri, bytes(req))))
 
+    def parse_input(self, input: str) -> Dict[str, Any]:
+        """Decode a payment string, checking that it is meant for the
+        network of this node. See `parse_payment_input`.
+        """
+        return self.inner.parse_input(input)
+
+    def pay_input(
+        self,
+        input: str,
+        amount_msat: Optional[int] = None,
+        lnurl_fallback: bool = False,
+    ) -> clnpb.PayResponse:
+        """Pay a BOLT11 invoice, BOLT12 offer or invoice, LNURL,
+        lightning address, node id or BIP-21 URI. `amount_msat` is
+        required if the destination does not specify the amount.
+        A BIP-353 name only falls back to a lightning address lookup
+        if `lnurl_fallback` is set.
+        """
+        res = self.inner.pay_input(input, amount_msat, lnurl_fallback)
+        return clnpb.PayResponse.FromString(bytes(res))
+
     def trampoline_pay(
         self,
         bolt11: str,
@@ -570,6 +650,86 @@ class Node(object):
         res = nodepb.LspInvoiceResponse
         return res.FromString(bytes(self.inner.call(uri, bytes(req))))
 
+    def wallet(self) -> "Wallet":
+        """Typed wallet operations: balance, payments, invoices, fees
+        and channels.
+        """
+        return Wallet(self.inner.wallet())
+
+
+class Wallet(object):
+    """High-level wallet operations on a node, returning plain dicts
+    instead of protobuf messages. Amounts are in millisatoshis unless
+    the name says otherwise.
+    """
+
+    def __init__(self, inner: native.Wallet) -> None:
+        self.inner = inner
+
+    def balance(self) -> Dict[str, int]:
+        """The funds of the node, on-chain and in channels."""
+        return self.inner.balance()
+
+    def payments(self) -> List[Dict[str, Any]]:
+        """Sent payments and paid invoices, newest first."""
+        return self.inner.payments()
+
+    def pay(self, input: str, amount_msat: Optional[int] = None) -> Dict[str, Any]:
+        """Pay anything `parse_payment_input` accepts."""
+        return self.inner.pay(input, amount_msat)
+
+    def create_invoice(
+        self,
+        label: str,
+        description: str,
+        amount_msat: Optional[int] = None,
+        expiry: Optional[int] = None,
+    ) -> Dict[str, Any]:
+        return self.inner.create_invoice(label, description, amount_msat, expiry)
+
+    def invoice(self, label: str) -> Optional[Dict[str, Any]]:
+        return self.inner.invoice(label)
+
+    def invoices(self) -> List[Dict[str, Any]]:
+        return self.inner.invoices()
+
+    def wait_invoice(self, label: str) -> Dict[str, Any]:
+        """Wait until the invoice is paid or expires."""
+        return self.inner.wait_invoice(label)
+
+    def cancel_invoice(self, label: str) -> None:
+        """Delete an unpaid or expired invoice, so it can no longer be
+        paid.
+        """
+        self.inner.cancel_invoice(label)
+
+    def fee_estimates(self) -> Dict[str, Any]:
+        """On-chain feerates in satoshis per 1000 virtual bytes."""
+        return self.inner.fee_estimates()
+
+    def channels(self) -> List[Dict[str, Any]]:
+        return self.inner.channels()
+
+    def open_channel(
+        self,
+        peer_id: bytes,
+        address: Optional[str] = None,
+        amount_sat: Optional[int] = None,
+        announce: bool = False,
+    ) -> Dict[str, Any]:
+        """Open a channel with `peer_id`, connecting to it at
+        `address` (`host:port`) first if given. Without `amount_sat`
+        all on-chain funds go into the channel.
+        """
+        return self.inner.open_channel(
+            normalize_node_id(peer_id), address, amount_sat, announce
+        )
+
+    def close_channel(
+        self, id: str, destination: Optional[str] = None
+    ) -> Dict[str, Any]:
+        return self.inner.close_channel(id, destination)
+
 
 def normalize_node_id(node_id, string=False):
     if len(node_id) == 66:
@@ -7,7 +7,7 @@ package adds a pythonic facade on top of this to improve usability.
 
 """
 
-from typing import Optional, List
+from typing import Any, Callable, Dict, Optional, List
 import glclient.glclient as native
 
 class TlsConfig:
@@ -39,6 +39,10 @@ class Signer:
     def sign_challenge(self, challenge: bytes) -> bytes: ...
     def run_in_thread(self) -> SignerHandle: ...
     def run_in_foreground(self) -> None: ...
+    def set_approval_handler(
+        self, handler: Callable[[Dict[str, Any]], bool], timeout: Optional[int]
+    ) -> None: ...
+    def enable_pairing_queue(self, timeout: Optional[int]) -> None: ...
     def node_id(self) -> bytes: ...
     def version(self) -> str: ...
     def is_running(self) -> bool: ...
@@ -46,6 +50,12 @@ class Signer:
     def create_rune(
         self, restrictions: List[List[str]], rune: Optional[str] = None
     ) -> str: ...
+    def list_runes(self) -> List[Dict[str, Any]]: ...
+    def revoke_rune(self, id: int) -> None: ...
+    def lnurl_auth(self, lnurl: str) -> None: ...
+    def pending_pairings(self) -> List[Dict[str, Any]]: ...
+    def approve_pairing(self, device_id: str, restrictions: Optional[str]) -> None: ...
+    def deny_pairing(self, device_id: str) -> None: ...
 
 class Scheduler:
     def __init__(self, network: str, creds: Optional[Credentials]): ...
@@ -91,6 +101,37 @@ class Node:
         label: Optional[str] = None,
     ) -> bytes: ...
     def configure(self, payload: bytes) -> None: ...
+    def parse_input(self, input: str) -> Dict[str, Any]: ...
+    def pay_input(
+        self, input: str, amount_msat: Optional[int], lnurl_fallback: Optional[bool]
+    ) -> bytes: ...
+    def wallet(self) -> Wallet: ...
+
+class Wallet:
+    def balance(self) -> Dict[str, int]: ...
+    def payments(self) -> List[Dict[str, Any]]: ...
+    def pay(self, input: str, amount_msat: Optional[int]) -> Dict[str, Any]: ...
+    def create_invoice(
+        self,
+        label: str,
+        description: str,
+        amount_msat: Optional[int],
+        expiry: Optional[int],
+    ) -> Dict[str, Any]: ...
+    def invoice(self, label: str) -> Optional[Dict[str, Any]]: ...
+    def invoices(self) -> List[Dict[str, Any]]: ...
+    def wait_invoice(self, label: str) -> Dict[str, Any]: ...
+    def cancel_invoice(self, label: str) -> None: ...
+    def fee_estimates(self) -> Dict[str, Any]: ...
+    def channels(self) -> List[Dict[str, Any]]: ...
+    def open_channel(
+        self,
+        peer_id: bytes,
+        address: Optional[str],
+        amount_sat: Optional[int],
+        announce: bool,
+    ) -> Dict[str, Any]: ...
+    def close_channel(self, id: str, destination: Optional[str]) -> Dict[str, Any]: ...
 
 class LspClient:
     def rpc_call(self, peer_id: bytes, method: str, params: bytes) -> bytes: ...
@@ -104,3 +145,4 @@ class LspClient:
     def list_lsp_servers(self) -> List[str]: ...
 
 def backup_decrypt_with_seed(encrypted: bytes, seed: bytes) -> bytes: ...
+def parse_payment_input(input: str, network: str) -> Dict[str, Any]: ...
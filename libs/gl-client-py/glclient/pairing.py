@@ -41,6 +41,10 @@ class NewDeviceClient(object):
         the attestation device.
 
         Second message is either the pairing response or an error.
+
+        `restrictions` are either rune restrictions, e.g.,
+        `method=pay&time<1700000000`, or predefined rule sets by name,
+        e.g., `invoice-only,expires=30d`.
         """
         for m in self._inner.pair_device(name, description, restrictions):
             yield from self._recv(m)
@@ -7,10 +7,12 @@ extern crate log;
 mod credentials;
 mod node;
 mod pairing;
+mod payment_input;
 mod runtime;
 mod scheduler;
 mod signer;
 mod tls;
+mod wallet;
 
 pub use node::Node;
 pub use scheduler::Scheduler;
@@ -37,6 +39,7 @@ fn glclient(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Signer>()?;
     m.add_class::<SignerHandle>()?;
     m.add_class::<Node>()?;
+    m.add_class::<wallet::Wallet>()?;
     m.add_class::<Scheduler>()?;
     m.add_class::<TlsConfig>()?;
     m.add_class::<credentials::Credentials>()?;
@@ -44,6 +47,7 @@ fn glclient(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<pairing::AttestationDeviceClient>()?;
 
     m.add_function(wrap_pyfunction!(backup_decrypt_with_seed, m)?)?;
+    m.add_function(wrap_pyfunction!(payment_input::parse_payment_input, m)?)?;
 
     Ok(())
 }
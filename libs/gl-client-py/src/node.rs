@@ -1,11 +1,14 @@
 use crate::credentials::Credentials;
+use crate::payment_input;
 use crate::runtime::exec;
 use crate::scheduler::convert;
+use crate::wallet::Wallet;
 use gl_client as gl;
 use gl_client::pb;
 use prost::Message;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use tonic::{Code, Status};
 
 #[pyclass]
@@ -82,6 +85,40 @@ impl Node {
         convert(Ok(res))
     }
 
+    fn parse_input<'a>(&self, py: Python<'a>, input: &str) -> PyResult<&'a PyDict> {
+        let mut cln_client = self.cln_client.clone();
+        let input = exec(gl::payment_input::PaymentInput::parse_for_node(
+            input,
+            &mut cln_client,
+        ))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        payment_input::to_dict(py, input)
+    }
+
+    fn pay_input(
+        &self,
+        input: &str,
+        amount_msat: Option<u64>,
+        lnurl_fallback: Option<bool>,
+    ) -> PyResult<Vec<u8>> {
+        let mut cln_client = self.cln_client.clone();
+        convert(exec(async {
+            let input =
+                gl::payment_input::PaymentInput::parse_for_node(input, &mut cln_client).await?;
+            input
+                .pay(
+                    &mut cln_client,
+                    amount_msat,
+                    lnurl_fallback.unwrap_or(false),
+                )
+                .await
+        }))
+    }
+
+    fn wallet(&self) -> Wallet {
+        Wallet::new(gl::wallet::Wallet::new(self.cln_client.clone()))
+    }
+
     fn configure(&self, payload: &[u8]) -> PyResult<()> {
         let req = pb::GlConfig::decode(payload).map_err(error_decoding_request)?;
 
@@ -0,0 +1,73 @@
+use gl_client::bitcoin::Network;
+use gl_client::payment_input::{LnUrlKind, PaymentInput};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+#[pyfunction]
+pub fn parse_payment_input<'a>(py: Python<'a>, input: &str, network: &str) -> PyResult<&'a PyDict> {
+    let network: Network = network
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("Unknown network {}", network)))?;
+    let input =
+        PaymentInput::parse(input, network).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    to_dict(py, input)
+}
+
+pub fn to_dict(py: Python<'_>, input: PaymentInput) -> PyResult<&PyDict> {
+    let d = PyDict::new(py);
+    match input {
+        PaymentInput::Bolt11 {
+            invoice,
+            amount_msat,
+        } => {
+            d.set_item("kind", "bolt11")?;
+            d.set_item("invoice", invoice)?;
+            d.set_item("amount_msat", amount_msat)?;
+        }
+        PaymentInput::Bolt12Offer { offer, amount_msat } => {
+            d.set_item("kind", "bolt12_offer")?;
+            d.set_item("offer", offer)?;
+            d.set_item("amount_msat", amount_msat)?;
+        }
+        PaymentInput::Bolt12Invoice { invoice } => {
+            d.set_item("kind", "bolt12_invoice")?;
+            d.set_item("invoice", invoice)?;
+        }
+        PaymentInput::LnUrl { lnurl, url, kind } => {
+            d.set_item("kind", "lnurl")?;
+            d.set_item("lnurl", lnurl)?;
+            d.set_item("url", url)?;
+            d.set_item(
+                "lnurl_kind",
+                kind.map(|k| match k {
+                    LnUrlKind::Pay => "pay",
+                    LnUrlKind::Withdraw => "withdraw",
+                    LnUrlKind::Auth => "auth",
+                    LnUrlKind::Channel => "channel",
+                }),
+            )?;
+        }
+        PaymentInput::LightningAddress(name) => {
+            d.set_item("kind", "lightning_address")?;
+            d.set_item("address", name.to_string())?;
+        }
+        PaymentInput::NodeId { node_id } => {
+            d.set_item("kind", "node_id")?;
+            d.set_item("node_id", PyBytes::new(py, &node_id.serialize()))?;
+        }
+        PaymentInput::OnChain {
+            address,
+            amount_msat,
+            label,
+            message,
+        } => {
+            d.set_item("kind", "onchain")?;
+            d.set_item("address", address)?;
+            d.set_item("amount_msat", amount_msat)?;
+            d.set_item("label", label)?;
+            d.set_item("message", message)?;
+        }
+    }
+    Ok(d)
+}
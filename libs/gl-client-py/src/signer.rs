@@ -1,7 +1,12 @@
 use crate::credentials::Credentials;
+use crate::runtime::exec;
 use gl_client::bitcoin::Network;
+use gl_client::signer::{ApprovalHandler, ApprovalRequest, PairingDecision};
 use log::warn;
+use pyo3::types::{PyBytes, PyDict};
 use pyo3::{exceptions::PyValueError, prelude::*};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 #[pyclass]
@@ -37,6 +42,30 @@ impl Signer {
         Ok(Signer { inner })
     }
 
+    /// Register a callable that is asked to approve requests the
+    /// signer cannot resolve on its own. It is called with a `dict`
+    /// describing the request and must return a `bool`. Requests that
+    /// are not approved within `timeout` seconds are denied.
+    fn set_approval_handler(&mut self, handler: PyObject, timeout: Option<u64>) {
+        let timeout = timeout
+            .map(Duration::from_secs)
+            .unwrap_or(gl_client::signer::DEFAULT_APPROVAL_TIMEOUT);
+        self.inner = self
+            .inner
+            .clone()
+            .with_approval_handler(Arc::new(PyApprovalHandler { callback: handler }), timeout);
+    }
+
+    /// Hold pairing requests until they are approved or denied with
+    /// `approve_pairing` or `deny_pairing`. Requests that are not
+    /// answered within `timeout` seconds are denied.
+    fn enable_pairing_queue(&mut self, timeout: Option<u64>) {
+        let timeout = timeout
+            .map(Duration::from_secs)
+            .unwrap_or(gl_client::signer::DEFAULT_PAIRING_TIMEOUT);
+        self.inner = self.inner.clone().with_pairing_queue(timeout);
+    }
+
     fn run_in_thread(&mut self) -> PyResult<SignerHandle> {
         trace!("Starting a new thread for signer");
         let inner = self.inner.clone();
@@ -102,6 +131,64 @@ impl Signer {
             .create_rune(rune, restrictions)
             .map_err(|e| PyValueError::new_err(e.to_string()))
     }
+
+    fn list_runes<'a>(&self, py: Python<'a>) -> PyResult<Vec<&'a PyDict>> {
+        self.inner
+            .list_runes()
+            .into_iter()
+            .map(|r| {
+                let d = PyDict::new(py);
+                d.set_item("id", r.id)?;
+                d.set_item("restrictions", r.restrictions)?;
+                d.set_item("created_at", r.created_at)?;
+                d.set_item("last_used", r.last_used)?;
+                d.set_item("revoked", r.revoked)?;
+                Ok(d)
+            })
+            .collect()
+    }
+
+    fn revoke_rune(&self, id: u64) -> PyResult<()> {
+        self.inner
+            .revoke_rune(id)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn lnurl_auth(&self, lnurl: &str) -> PyResult<()> {
+        exec(self.inner.lnurl_auth(lnurl)).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn pending_pairings<'a>(&self, py: Python<'a>) -> PyResult<Vec<&'a PyDict>> {
+        self.inner
+            .pending_pairings()
+            .into_iter()
+            .map(|r| {
+                let d = PyDict::new(py);
+                d.set_item("device_id", r.device_id)?;
+                d.set_item("device_name", r.device_name)?;
+                d.set_item("restrictions", r.restrictions)?;
+                d.set_item("pubkey", PyBytes::new(py, &r.pubkey))?;
+                d.set_item("expires_at", r.expires_at)?;
+                Ok(d)
+            })
+            .collect()
+    }
+
+    fn approve_pairing(&self, device_id: &str, restrictions: Option<String>) -> PyResult<()> {
+        let decision = match restrictions {
+            Some(r) => PairingDecision::Narrow(r),
+            None => PairingDecision::Approve,
+        };
+        self.inner
+            .decide_pairing(device_id, decision)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn deny_pairing(&self, device_id: &str) -> PyResult<()> {
+        self.inner
+            .decide_pairing(device_id, PairingDecision::Deny)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
 }
 
 #[pyclass]
@@ -120,3 +207,106 @@ impl SignerHandle {
         Ok(())
     }
 }
+
+/// Forwards approval requests to a Python callable.
+struct PyApprovalHandler {
+    callback: PyObject,
+}
+
+impl PyApprovalHandler {
+    fn to_dict<'a>(py: Python<'a>, request: &ApprovalRequest) -> PyResult<&'a PyDict> {
+        let d = PyDict::new(py);
+        match request {
+            ApprovalRequest::Invoice {
+                payee,
+                payment_hash,
+                amount_msat,
+                description,
+            } => {
+                d.set_item("kind", "invoice")?;
+                d.set_item("payee", PyBytes::new(py, payee))?;
+                d.set_item("payment_hash", PyBytes::new(py, payment_hash))?;
+                d.set_item("amount_msat", amount_msat)?;
+                d.set_item("description", description)?;
+            }
+            ApprovalRequest::Keysend {
+                destination,
+                payment_hash,
+                amount_msat,
+            } => {
+                d.set_item("kind", "keysend")?;
+                d.set_item(
+                    "destination",
+                    destination.as_ref().map(|v| PyBytes::new(py, v)),
+                )?;
+                d.set_item("payment_hash", PyBytes::new(py, payment_hash))?;
+                d.set_item("amount_msat", amount_msat)?;
+            }
+            ApprovalRequest::Onchain {
+                txid,
+                outputs,
+                fee_sat,
+            } => {
+                d.set_item("kind", "onchain")?;
+                d.set_item("txid", PyBytes::new(py, txid))?;
+                let outputs = outputs
+                    .iter()
+                    .map(|o| {
+                        let od = PyDict::new(py);
+                        od.set_item("script_pubkey", PyBytes::new(py, &o.script_pubkey))?;
+                        od.set_item("address", &o.address)?;
+                        od.set_item("amount_sat", o.amount_sat)?;
+                        Ok(od)
+                    })
+                    .collect::<PyResult<Vec<&PyDict>>>()?;
+                d.set_item("outputs", outputs)?;
+                d.set_item("fee_sat", fee_sat)?;
+            }
+            ApprovalRequest::OverLimit {
+                limit_msat,
+                window_secs,
+                destination,
+                spent_msat,
+                amount_msat,
+            } => {
+                d.set_item("kind", "over_limit")?;
+                d.set_item("limit_msat", limit_msat)?;
+                d.set_item("window_secs", window_secs)?;
+                d.set_item("destination", destination)?;
+                d.set_item("spent_msat", spent_msat)?;
+                d.set_item("amount_msat", amount_msat)?;
+            }
+            ApprovalRequest::Unresolved { message } => {
+                d.set_item("kind", "unresolved")?;
+                d.set_item("message", message)?;
+            }
+        }
+        Ok(d)
+    }
+}
+
+#[async_trait::async_trait]
+impl ApprovalHandler for PyApprovalHandler {
+    async fn approve(&self, request: ApprovalRequest) -> bool {
+        let callback = self.callback.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| {
+                let d = PyApprovalHandler::to_dict(py, &request)?;
+                callback.call1(py, (d,))?.extract::<bool>(py)
+            })
+        })
+        .await;
+
+        match res {
+            Ok(Ok(approved)) => approved,
+            Ok(Err(e)) => {
+                log::error!("Approval handler raised an exception, denying: {}", e);
+                false
+            }
+            Err(e) => {
+                log::error!("Approval handler failed, denying: {}", e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::runtime::exec;
+use gl_client::bitcoin::secp256k1::PublicKey;
+use gl_client::wallet::{self, NewInvoice, OnchainAmount, OpenChannel};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+/// Typed wallet operations on a node, see `gl_client::wallet`.
+#[pyclass]
+pub struct Wallet {
+    inner: wallet::Wallet,
+}
+
+impl Wallet {
+    pub fn new(inner: wallet::Wallet) -> Self {
+        Wallet { inner }
+    }
+}
+
+fn error<D: core::fmt::Display>(e: D) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+#[pymethods]
+impl Wallet {
+    fn balance<'a>(&self, py: Python<'a>) -> PyResult<&'a PyDict> {
+        let b = exec(self.inner.clone().balance()).map_err(error)?;
+        let d = PyDict::new(py);
+        d.set_item("onchain_confirmed_msat", b.onchain_confirmed_msat)?;
+        d.set_item("onchain_unconfirmed_msat", b.onchain_unconfirmed_msat)?;
+        d.set_item("onchain_immature_msat", b.onchain_immature_msat)?;
+        d.set_item("onchain_reserved_msat", b.onchain_reserved_msat)?;
+        d.set_item("channel_spendable_msat", b.channel_spendable_msat)?;
+        d.set_item("channel_receivable_msat", b.channel_receivable_msat)?;
+        d.set_item("channel_pending_msat", b.channel_pending_msat)?;
+        d.set_item("total_msat", b.total_msat())?;
+        Ok(d)
+    }
+
+    fn payments<'a>(&self, py: Python<'a>) -> PyResult<&'a PyList> {
+        let payments = exec(self.inner.clone().payments()).map_err(error)?;
+        let l = PyList::empty(py);
+        for p in payments {
+            l.append(payment_to_dict(py, p)?)?;
+        }
+        Ok(l)
+    }
+
+    fn pay<'a>(
+        &self,
+        py: Python<'a>,
+        input: &str,
+        amount_msat: Option<u64>,
+    ) -> PyResult<&'a PyDict> {
+        let payment = exec(self.inner.clone().pay(input, amount_msat)).map_err(error)?;
+        payment_to_dict(py, payment)
+    }
+
+    fn create_invoice<'a>(
+        &self,
+        py: Python<'a>,
+        label: String,
+        description: String,
+        amount_msat: Option<u64>,
+        expiry: Option<u64>,
+    ) -> PyResult<&'a PyDict> {
+        let invoice = NewInvoice {
+            label,
+            description,
+            amount_msat,
+            expiry,
+        };
+        let invoice = exec(self.inner.clone().create_invoice(invoice)).map_err(error)?;
+        invoice_to_dict(py, invoice)
+    }
+
+    fn invoice<'a>(&self, py: Python<'a>, label: &str) -> PyResult<Option<&'a PyDict>> {
+        exec(self.inner.clone().invoice(label))
+            .map_err(error)?
+            .map(|i| invoice_to_dict(py, i))
+            .transpose()
+    }
+
+    fn invoices<'a>(&self, py: Python<'a>) -> PyResult<&'a PyList> {
+        let invoices = exec(self.inner.clone().invoices()).map_err(error)?;
+        let l = PyList::empty(py);
+        for i in invoices {
+            l.append(invoice_to_dict(py, i)?)?;
+        }
+        Ok(l)
+    }
+
+    fn wait_invoice<'a>(&self, py: Python<'a>, label: &str) -> PyResult<&'a PyDict> {
+        let invoice = exec(self.inner.clone().wait_invoice(label)).map_err(error)?;
+        invoice_to_dict(py, invoice)
+    }
+
+    fn cancel_invoice(&self, label: &str) -> PyResult<()> {
+        exec(self.inner.clone().cancel_invoice(label)).map_err(error)
+    }
+
+    fn fee_estimates<'a>(&self, py: Python<'a>) -> PyResult<&'a PyDict> {
+        let f = exec(self.inner.clone().fee_estimates()).map_err(error)?;
+        let d = PyDict::new(py);
+        d.set_item("min_acceptable", f.min_acceptable)?;
+        d.set_item("opening", f.opening)?;
+        d.set_item("mutual_close", f.mutual_close)?;
+        let estimates = PyList::empty(py);
+        for e in f.estimates {
+            let ed = PyDict::new(py);
+            ed.set_item("blocks", e.blocks)?;
+            ed.set_item("perkb", e.perkb)?;
+            estimates.append(ed)?;
+        }
+        d.set_item("estimates", estimates)?;
+        Ok(d)
+    }
+
+    fn channels<'a>(&self, py: Python<'a>) -> PyResult<&'a PyList> {
+        let channels = exec(self.inner.clone().channels()).map_err(error)?;
+        let l = PyList::empty(py);
+        for c in channels {
+            let d = PyDict::new(py);
+            d.set_item("peer_id", PyBytes::new(py, &c.peer_id))?;
+            d.set_item("peer_connected", c.peer_connected)?;
+            d.set_item(
+                "state",
+                match c.state {
+                    wallet::ChannelState::Opening => "opening",
+                    wallet::ChannelState::Open => "open",
+                    wallet::ChannelState::Closing => "closing",
+                    wallet::ChannelState::Closed => "closed",
+                },
+            )?;
+            d.set_item("channel_id", c.channel_id.map(|i| PyBytes::new(py, &i)))?;
+            d.set_item("short_channel_id", c.short_channel_id)?;
+            d.set_item("funding_txid", c.funding_txid.map(|i| PyBytes::new(py, &i)))?;
+            d.set_item("total_msat", c.total_msat)?;
+            d.set_item("to_us_msat", c.to_us_msat)?;
+            d.set_item("spendable_msat", c.spendable_msat)?;
+            d.set_item("receivable_msat", c.receivable_msat)?;
+            l.append(d)?;
+        }
+        Ok(l)
+    }
+
+    #[pyo3(signature = (peer_id, address, amount_sat, announce))]
+    fn open_channel<'a>(
+        &self,
+        py: Python<'a>,
+        peer_id: Vec<u8>,
+        address: Option<String>,
+        amount_sat: Option<u64>,
+        announce: bool,
+    ) -> PyResult<&'a PyDict> {
+        let open = OpenChannel {
+            peer_id: PublicKey::from_slice(&peer_id).map_err(error)?,
+            address,
+            amount: amount_sat.map_or(OnchainAmount::All, OnchainAmount::Sat),
+            push_msat: None,
+            feerate: None,
+            announce,
+        };
+        let res = exec(self.inner.clone().open_channel(open)).map_err(error)?;
+        let d = PyDict::new(py);
+        d.set_item("channel_id", PyBytes::new(py, &res.channel_id))?;
+        d.set_item("txid", PyBytes::new(py, &res.txid))?;
+        d.set_item("outnum", res.outnum)?;
+        Ok(d)
+    }
+
+    fn close_channel<'a>(
+        &self,
+        py: Python<'a>,
+        id: &str,
+        destination: Option<String>,
+    ) -> PyResult<&'a PyDict> {
+        let res = exec(self.inner.clone().close_channel(id, destination)).map_err(error)?;
+        let d = PyDict::new(py);
+        d.set_item(
+            "close_type",
+            match res.close_type {
+                wallet::CloseType::Mutual => "mutual",
+                wallet::CloseType::Unilateral => "unilateral",
+                wallet::CloseType::Unopened => "unopened",
+            },
+        )?;
+        d.set_item("txid", res.txid.map(|t| PyBytes::new(py, &t)))?;
+        Ok(d)
+    }
+}
+
+fn payment_to_dict(py: Python<'_>, p: wallet::Payment) -> PyResult<&PyDict> {
+    let d = PyDict::new(py);
+    d.set_item(
+        "direction",
+        match p.direction {
+            wallet::Direction::Incoming => "incoming",
+            wallet::Direction::Outgoing => "outgoing",
+        },
+    )?;
+    d.set_item(
+        "status",
+        match p.status {
+            wallet::PaymentStatus::Pending => "pending",
+            wallet::PaymentStatus::Complete => "complete",
+            wallet::PaymentStatus::Failed => "failed",
+        },
+    )?;
+    d.set_item("payment_hash", PyBytes::new(py, &p.payment_hash))?;
+    d.set_item("amount_msat", p.amount_msat)?;
+    d.set_item("fee_msat", p.fee_msat)?;
+    d.set_item("preimage", p.preimage.map(|i| PyBytes::new(py, &i)))?;
+    d.set_item("description", p.description)?;
+    d.set_item("invoice", p.invoice)?;
+    d.set_item("destination", p.destination.map(|i| PyBytes::new(py, &i)))?;
+    d.set_item("timestamp", p.timestamp)?;
+    Ok(d)
+}
+
+fn invoice_to_dict(py: Python<'_>, i: wallet::Invoice) -> PyResult<&PyDict> {
+    let d = PyDict::new(py);
+    d.set_item("label", i.label)?;
+    d.set_item(
+        "status",
+        match i.status {
+            wallet::InvoiceStatus::Unpaid => "unpaid",
+            wallet::InvoiceStatus::Paid => "paid",
+            wallet::InvoiceStatus::Expired => "expired",
+        },
+    )?;
+    d.set_item("invoice", i.invoice)?;
+    d.set_item("payment_hash", PyBytes::new(py, &i.payment_hash))?;
+    d.set_item("description", i.description)?;
+    d.set_item("amount_msat", i.amount_msat)?;
+    d.set_item("amount_received_msat", i.amount_received_msat)?;
+    d.set_item("expires_at", i.expires_at)?;
+    d.set_item("paid_at", i.paid_at)?;
+    d.set_item("preimage", i.preimage.map(|p| PyBytes::new(py, &p)))?;
+    Ok(d)
+}
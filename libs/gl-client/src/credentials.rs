@@ -39,6 +39,10 @@ pub enum Error {
     ReadFromFileError(#[from] std::io::Error),
     #[error("could not fetch default nobody credentials: {}", .0)]
     FetchDefaultNobodyCredentials(#[source] anyhow::Error),
+    #[error("could not encrypt credentials: {}", .0)]
+    EncryptCredentialsError(String),
+    #[error("could not decrypt credentials: {}", .0)]
+    DecryptCredentialsError(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -148,10 +152,51 @@ pub struct Device {
 impl Device {
     /// Creates a new set of `Device` credentials from the given
     /// credentials data blob. It defaults to the nobody credentials set.
+    ///
+    /// Encrypted credentials are decrypted with the passphrase in the
+    /// `GL_CREDENTIALS_PASSPHRASE` environment variable. Use
+    /// `try_from_bytes` to fail if that is not possible, rather than
+    /// falling back to the nobody credentials.
     pub fn from_bytes(data: impl AsRef<[u8]>) -> Self {
+        Self::try_from_bytes(data).unwrap_or_else(|e| {
+            log::warn!("{}", e);
+            Self::default()
+        })
+    }
+
+    /// Like `from_bytes`, but fails if the credentials are encrypted
+    /// and `GL_CREDENTIALS_PASSPHRASE` is not set or does not decrypt
+    /// them. Use `from_bytes_with_key` to pass the key explicitly.
+    pub fn try_from_bytes(data: impl AsRef<[u8]>) -> Result<Self> {
+        let data = data.as_ref();
+        if !is_encrypted(data) {
+            return Ok(Self::from_plaintext(data));
+        }
+        let passphrase = std::env::var(PASSPHRASE_ENV).map_err(|_| {
+            Error::DecryptCredentialsError(format!(
+                "credentials are encrypted, but {} is not set",
+                PASSPHRASE_ENV
+            ))
+        })?;
+        Self::from_bytes_with_key(data, &CredentialsKey::Passphrase(passphrase))
+    }
+
+    /// Creates a new set of `Device` credentials from a credentials
+    /// data blob encrypted with `key`. Unencrypted blobs are accepted
+    /// as well, so existing credentials can be migrated by loading
+    /// them here and storing them with `to_encrypted_bytes`.
+    pub fn from_bytes_with_key(data: impl AsRef<[u8]>, key: &CredentialsKey) -> Result<Self> {
+        let data = data.as_ref();
+        if !is_encrypted(data) {
+            return Ok(Self::from_plaintext(data));
+        }
+        Ok(Self::from_plaintext(&decrypt(data, key)?))
+    }
+
+    fn from_plaintext(data: &[u8]) -> Self {
         let mut creds = Self::default();
-        log::trace!("Build authenticated credentials from: {:?}", data.as_ref());
-        if let Ok(data) = model::Data::try_from(data.as_ref()) {
+        log::trace!("Build authenticated credentials from: {:?}", data);
+        if let Ok(data) = model::Data::try_from(data) {
             creds.version = data.version;
             if let Some(cert) = data.cert {
                 creds.cert = cert
@@ -242,6 +287,12 @@ impl Device {
     pub fn to_bytes(&self) -> Vec<u8> {
         self.to_owned().into()
     }
+
+    /// Like `to_bytes`, but encrypted with `key`, so that a leaked
+    /// credentials file does not give access to the node.
+    pub fn to_encrypted_bytes(&self, key: &CredentialsKey) -> Result<Vec<u8>> {
+        encrypt(&self.to_bytes(), key)
+    }
 }
 
 impl TlsConfigProvider for Device {
@@ -337,6 +388,167 @@ mod model {
     }
 }
 
+/// The environment variable `Device::from_bytes` takes the passphrase
+/// for encrypted credentials from.
+pub const PASSPHRASE_ENV: &str = "GL_CREDENTIALS_PASSPHRASE";
+
+/// Encrypted credentials start with these bytes. They cannot start an
+/// unencrypted credentials blob, since 0x47 is an invalid protobuf tag.
+const ENC_MAGIC: &[u8; 4] = b"GLCE";
+const ENC_VERSION: u8 = 1;
+const KDF_SCRYPT: u8 = 1;
+const KDF_SEED: u8 = 2;
+/// Magic, version, KDF, scrypt `log_n`, `r` and `p`, salt, nonce.
+const ENC_HEADER_LEN: usize = 4 + 1 + 1 + 1 + 4 + 4 + 16 + 12;
+/// Tag of the AEAD, appended to the ciphertext.
+const ENC_TAG_LEN: usize = 16;
+/// Recommended scrypt parameters for interactive logins, using 32MiB.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+/// The most expensive scrypt parameters we accept when decrypting,
+/// using 1GiB. The parameters are read from the blob, so we must not
+/// let it make us allocate arbitrary amounts of memory.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 1;
+
+/// The key to encrypt credentials with at rest.
+#[derive(Clone)]
+pub enum CredentialsKey {
+    /// A passphrase chosen by the user, stretched with scrypt.
+    Passphrase(String),
+    /// The seed of the node, for devices that have it anyway.
+    Seed(Vec<u8>),
+}
+
+/// Whether `data` is an encrypted credentials blob.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENC_MAGIC)
+}
+
+/// Encrypts a credentials blob with `key`, using ChaCha20-Poly1305
+/// and a key derived with scrypt from a passphrase, or with
+/// HMAC-SHA256 from a seed.
+pub fn encrypt(data: &[u8], key: &CredentialsKey) -> Result<Vec<u8>> {
+    encrypt_with_cost(data, key, SCRYPT_LOG_N)
+}
+
+fn encrypt_with_cost(data: &[u8], key: &CredentialsKey, log_n: u8) -> Result<Vec<u8>> {
+    use chacha20poly1305::{aead::Aead, aead::Payload, ChaCha20Poly1305, KeyInit};
+
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let kdf = match key {
+        CredentialsKey::Passphrase(_) => KDF_SCRYPT,
+        CredentialsKey::Seed(_) => KDF_SEED,
+    };
+
+    let mut out = Vec::with_capacity(ENC_HEADER_LEN + data.len() + ENC_TAG_LEN);
+    out.extend_from_slice(ENC_MAGIC);
+    out.push(ENC_VERSION);
+    out.push(kdf);
+    out.push(log_n);
+    out.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    out.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+
+    let secret = derive_key(key, &salt, log_n, SCRYPT_R, SCRYPT_P)
+        .map_err(|e| Error::EncryptCredentialsError(e.to_string()))?;
+    let ciphertext = ChaCha20Poly1305::new(&secret.into())
+        .encrypt(
+            &nonce.into(),
+            Payload {
+                msg: data,
+                aad: &out,
+            },
+        )
+        .map_err(|e| Error::EncryptCredentialsError(e.to_string()))?;
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypts a credentials blob created by `encrypt`.
+pub fn decrypt(data: &[u8], key: &CredentialsKey) -> Result<Vec<u8>> {
+    use chacha20poly1305::{aead::Aead, aead::Payload, ChaCha20Poly1305, KeyInit};
+    use Error::DecryptCredentialsError;
+
+    if !is_encrypted(data) {
+        return Err(DecryptCredentialsError("not encrypted".to_string()));
+    }
+    if data.len() < ENC_HEADER_LEN + ENC_TAG_LEN {
+        return Err(DecryptCredentialsError("truncated".to_string()));
+    }
+    let (header, ciphertext) = data.split_at(ENC_HEADER_LEN);
+    if header[4] != ENC_VERSION {
+        return Err(DecryptCredentialsError(format!(
+            "version {} is not supported by this client version {}",
+            header[4], ENC_VERSION
+        )));
+    }
+    let kdf = header[5];
+    let log_n = header[6];
+    let r = u32::from_be_bytes(header[7..11].try_into().unwrap());
+    let p = u32::from_be_bytes(header[11..15].try_into().unwrap());
+    let salt = &header[15..31];
+    let nonce: [u8; 12] = header[31..43].try_into().unwrap();
+
+    if kdf == KDF_SCRYPT && (log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P) {
+        return Err(DecryptCredentialsError(format!(
+            "scrypt parameters log_n={}, r={}, p={} exceed the limits",
+            log_n, r, p
+        )));
+    }
+
+    match (kdf, key) {
+        (KDF_SCRYPT, CredentialsKey::Passphrase(_)) | (KDF_SEED, CredentialsKey::Seed(_)) => {}
+        (KDF_SCRYPT, _) => return Err(DecryptCredentialsError("needs a passphrase".to_string())),
+        (KDF_SEED, _) => return Err(DecryptCredentialsError("needs the seed".to_string())),
+        (k, _) => return Err(DecryptCredentialsError(format!("unknown KDF {}", k))),
+    }
+
+    let secret =
+        derive_key(key, salt, log_n, r, p).map_err(|e| DecryptCredentialsError(e.to_string()))?;
+    ChaCha20Poly1305::new(&secret.into())
+        .decrypt(
+            &nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| DecryptCredentialsError("wrong key or corrupted data".to_string()))
+}
+
+fn derive_key(
+    key: &CredentialsKey,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> anyhow::Result<[u8; 32]> {
+    use lightning_signer::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+    use lightning_signer::bitcoin::hashes::{sha256, Hash, HashEngine};
+
+    let mut secret = [0u8; 32];
+    match key {
+        CredentialsKey::Passphrase(passphrase) => {
+            let params = scrypt::Params::new(log_n, r, p, secret.len())
+                .map_err(|e| anyhow::anyhow!("invalid scrypt parameters: {}", e))?;
+            scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut secret)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        CredentialsKey::Seed(seed) => {
+            let mut engine = HmacEngine::<sha256::Hash>::new(seed);
+            engine.input(b"greenlight/credentials");
+            engine.input(salt);
+            secret = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+        }
+    }
+    Ok(secret)
+}
+
 /// Tries to load nobody credentials from a file that is passed by an envvar and
 /// defaults to the nobody cert and key paths that have been set during build-
 /// time.
@@ -397,4 +609,77 @@ mod tests {
         assert!(data.ca.is_some_and(|d| d == vec![95, 94]));
         assert!(data.rune.is_some_and(|d| d == *"non_functional_rune"));
     }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let data = b"some credentials".to_vec();
+        let passphrase = CredentialsKey::Passphrase("correct horse".to_string());
+        let enc = encrypt_with_cost(&data, &passphrase, 4).unwrap();
+        assert!(is_encrypted(&enc));
+        assert!(!is_encrypted(&data));
+        assert_eq!(decrypt(&enc, &passphrase).unwrap(), data);
+
+        let wrong = CredentialsKey::Passphrase("battery staple".to_string());
+        assert!(decrypt(&enc, &wrong).is_err());
+        assert!(decrypt(&enc, &CredentialsKey::Seed(vec![1; 32])).is_err());
+
+        let mut tampered = enc.clone();
+        tampered[6] += 1;
+        assert!(decrypt(&tampered, &passphrase).is_err());
+
+        // Too expensive parameters are rejected before deriving the
+        // key.
+        let mut expensive = enc.clone();
+        expensive[6] = 40;
+        assert!(decrypt(&expensive, &passphrase)
+            .unwrap_err()
+            .to_string()
+            .contains("exceed the limits"));
+
+        let seed = CredentialsKey::Seed(vec![1; 32]);
+        let enc = encrypt(&data, &seed).unwrap();
+        assert_eq!(decrypt(&enc, &seed).unwrap(), data);
+        assert!(decrypt(&enc, &CredentialsKey::Seed(vec![2; 32])).is_err());
+    }
+
+    #[test]
+    fn test_device_migration() {
+        let device = Device::with(vec![99, 98], vec![97, 96], "rune").with_ca(vec![95, 94]);
+        let seed = CredentialsKey::Seed(vec![1; 32]);
+
+        // Unencrypted credentials still load, and can be stored
+        // encrypted.
+        let loaded = Device::from_bytes_with_key(device.to_bytes(), &seed).unwrap();
+        let enc = loaded.to_encrypted_bytes(&seed).unwrap();
+
+        let loaded = Device::from_bytes_with_key(&enc, &seed).unwrap();
+        assert_eq!(loaded.cert, device.cert);
+        assert_eq!(loaded.key, device.key);
+        assert_eq!(loaded.ca, device.ca);
+        assert_eq!(loaded.rune, device.rune);
+    }
+
+    #[test]
+    fn test_try_from_bytes() {
+        let device = Device::with(vec![99, 98], vec![97, 96], "rune");
+        let enc = device
+            .to_encrypted_bytes(&CredentialsKey::Passphrase("correct horse".to_string()))
+            .unwrap();
+
+        std::env::remove_var(PASSPHRASE_ENV);
+        assert!(Device::try_from_bytes(&enc).is_err());
+        std::env::set_var(PASSPHRASE_ENV, "battery staple");
+        assert!(Device::try_from_bytes(&enc).is_err());
+        // The infallible version falls back to the nobody credentials.
+        assert!(Device::from_bytes(&enc).rune.is_empty());
+
+        std::env::set_var(PASSPHRASE_ENV, "correct horse");
+        assert_eq!(Device::try_from_bytes(&enc).unwrap().rune, "rune");
+        std::env::remove_var(PASSPHRASE_ENV);
+
+        assert_eq!(
+            Device::try_from_bytes(device.to_bytes()).unwrap().cert,
+            device.cert
+        );
+    }
 }
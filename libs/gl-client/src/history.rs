@@ -0,0 +1,791 @@
+//! One activity feed for everything that moved the node's funds.
+//!
+//! The node keeps invoices, sent payments, on-chain transactions and
+//! forwards in separate lists. `History` merges them into a single
+//! feed of `Activity` entries with stable ids, ordered by the time
+//! they were created:
+//!
+//!  - invoices, including incoming keysends, from `ListInvoices`
+//!  - outgoing payments and keysends from `ListSendPays`, with the
+//!    parts of a payment attempt combined into one entry
+//!  - wallet transactions from `ListTransactions`, with the amount
+//!    computed from the outputs in `ListFunds`
+//!  - forwards from `ListForwards`, with the fee as amount
+//!
+//! `History::sync` only fetches what changed since the last sync,
+//! using the `created_index` and `updated_index` of invoices,
+//! sendpays and forwards, and reports new entries and status
+//! transitions. Transactions have no such index, so they are only
+//! re-scanned when a new block arrived, or after
+//! `History::rescan_transactions`, and compared against the known
+//! ones. The node does not know when a transaction happened, so it
+//! is stamped with the time `History` first saw it: persist the
+//! history with `to_bytes` and `from_bytes` to keep those times and
+//! resume syncing later.
+
+use crate::lightning_invoice::Bolt11Invoice;
+use crate::node::ClnClient;
+use crate::pb::cln;
+use crate::wallet::Direction;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Entries fetched per call while syncing.
+const SYNC_BATCH: u32 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityKind {
+    /// A payment to one of our invoices or offers, or a payment
+    /// request that is still open.
+    Invoice,
+    /// A payment we sent to an invoice or offer.
+    Payment,
+    /// A spontaneous payment, sent or received.
+    Keysend,
+    /// A transaction of the on-chain wallet.
+    OnChain,
+    /// A payment routed through the node.
+    Forward,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityStatus {
+    Pending,
+    Complete,
+    Failed,
+    /// An invoice that expired unpaid.
+    Expired,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Activity {
+    /// Stable across syncs, e.g., `invoice:<label>` or
+    /// `onchain:<txid>`.
+    pub id: String,
+    pub kind: ActivityKind,
+    pub direction: Direction,
+    pub status: ActivityStatus,
+    /// How much our balance changes, or changed, by this activity,
+    /// excluding fees. For forwards this is the fee we earned.
+    pub amount_msat: u64,
+    /// Fees we paid, if known.
+    pub fee_msat: Option<u64>,
+    pub payment_hash: Option<Vec<u8>>,
+    pub preimage: Option<Vec<u8>>,
+    pub txid: Option<Vec<u8>>,
+    pub description: Option<String>,
+    /// The BOLT11 or BOLT12 invoice, if there was one.
+    pub invoice: Option<String>,
+    /// The recipient of outgoing payments.
+    pub destination: Option<Vec<u8>>,
+    /// When the activity started, in seconds since the epoch. The
+    /// node does not know when transactions happened, so for them
+    /// this is when `History` first saw them.
+    pub timestamp: u64,
+    /// When an invoice was paid.
+    pub completed_at: Option<u64>,
+}
+
+/// A new activity, or a change of a known one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivityChange {
+    pub activity: Activity,
+    /// `None` if the activity is new.
+    pub previous_status: Option<ActivityStatus>,
+}
+
+/// The highest `created_index` and `updated_index` seen per list.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub invoices_created: u64,
+    pub invoices_updated: u64,
+    pub sendpays_created: u64,
+    pub sendpays_updated: u64,
+    pub forwards_created: u64,
+    pub forwards_updated: u64,
+    /// The block height when the transactions were last scanned.
+    #[serde(default)]
+    pub blockheight: Option<u32>,
+}
+
+/// A part of an outgoing payment, as returned by `ListSendPays`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SendPart {
+    payment_hash: Vec<u8>,
+    groupid: u64,
+    status: ActivityStatus,
+    amount_msat: Option<u64>,
+    amount_sent_msat: u64,
+    destination: Option<Vec<u8>>,
+    created_at: u64,
+    invoice: Option<String>,
+    description: Option<String>,
+    preimage: Option<Vec<u8>>,
+}
+
+impl From<cln::ListsendpaysPayments> for SendPart {
+    fn from(p: cln::ListsendpaysPayments) -> Self {
+        use cln::listsendpays_payments::ListsendpaysPaymentsStatus;
+        let status = match p.status() {
+            ListsendpaysPaymentsStatus::Pending => ActivityStatus::Pending,
+            ListsendpaysPaymentsStatus::Complete => ActivityStatus::Complete,
+            ListsendpaysPaymentsStatus::Failed => ActivityStatus::Failed,
+        };
+        SendPart {
+            status,
+            payment_hash: p.payment_hash,
+            groupid: p.groupid,
+            amount_msat: p.amount_msat.map(|a| a.msat),
+            amount_sent_msat: p.amount_sent_msat.map(|a| a.msat).unwrap_or_default(),
+            destination: p.destination,
+            created_at: p.created_at,
+            invoice: p.bolt11.or(p.bolt12),
+            description: p.description,
+            preimage: p.payment_preimage,
+        }
+    }
+}
+
+impl SendPart {
+    fn activity_id(&self) -> String {
+        format!("pay:{}:{}", hex::encode(&self.payment_hash), self.groupid)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    cursor: SyncCursor,
+    entries: HashMap<String, Activity>,
+    /// Parts of outgoing payments by their `id`, to recombine a
+    /// payment when one of its parts changes.
+    parts: HashMap<u64, SendPart>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn cursor(&self) -> &SyncCursor {
+        &self.cursor
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Activity> {
+        self.entries.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<History> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Up to `limit` activities, newest first, starting after the
+    /// activity with id `before`, i.e., the last one of the previous
+    /// page. The page is empty if there is no activity `before`.
+    pub fn page(&self, before: Option<&str>, limit: usize) -> Vec<Activity> {
+        let mut entries: Vec<&Activity> = self.entries.values().collect();
+        entries.sort_by(|a, b| (b.timestamp, &b.id).cmp(&(a.timestamp, &a.id)));
+        let start = match before {
+            Some(id) => match entries.iter().position(|a| a.id == id) {
+                Some(i) => i + 1,
+                None => return vec![],
+            },
+            None => 0,
+        };
+        entries
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Scan the wallet transactions on the next sync, even if there
+    /// was no new block, e.g., to pick up a transaction we just
+    /// broadcast.
+    pub fn rescan_transactions(&mut self) {
+        self.cursor.blockheight = None;
+    }
+
+    /// Fetch what changed on the node since the last sync, and
+    /// return new activities and those whose status or amounts
+    /// changed. If the sync fails the history is left unchanged, so
+    /// the changes are reported by the next sync.
+    pub async fn sync(&mut self, node: &mut ClnClient) -> Result<Vec<ActivityChange>> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let mut next = self.clone();
+        let mut changes = vec![];
+        next.sync_invoices(node, &mut changes).await?;
+        next.sync_sendpays(node, &mut changes).await?;
+        next.sync_forwards(node, &mut changes).await?;
+        next.sync_transactions(node, now, &mut changes).await?;
+        *self = next;
+        Ok(changes)
+    }
+
+    async fn sync_invoices(
+        &mut self,
+        node: &mut ClnClient,
+        changes: &mut Vec<ActivityChange>,
+    ) -> Result<()> {
+        use cln::listinvoices_request::ListinvoicesIndex;
+        for index in [ListinvoicesIndex::Created, ListinvoicesIndex::Updated] {
+            let mut cursor = match index {
+                ListinvoicesIndex::Created => self.cursor.invoices_created,
+                ListinvoicesIndex::Updated => self.cursor.invoices_updated,
+            };
+            loop {
+                let start = cursor;
+                let invoices = node
+                    .list_invoices(cln::ListinvoicesRequest {
+                        index: Some(index.into()),
+                        start: Some(cursor + 1),
+                        limit: Some(SYNC_BATCH),
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_inner()
+                    .invoices;
+                let done = invoices.len() < SYNC_BATCH as usize;
+                for i in invoices {
+                    let next = match index {
+                        ListinvoicesIndex::Created => i.created_index,
+                        ListinvoicesIndex::Updated => i.updated_index,
+                    };
+                    cursor = cursor.max(next.unwrap_or_default());
+                    changes.extend(self.apply(invoice_activity(i)));
+                }
+                // Without indexes we would fetch the same batch again.
+                if done || cursor == start {
+                    break;
+                }
+            }
+            match index {
+                ListinvoicesIndex::Created => self.cursor.invoices_created = cursor,
+                ListinvoicesIndex::Updated => self.cursor.invoices_updated = cursor,
+            }
+        }
+        Ok(())
+    }
+
+    async fn sync_sendpays(
+        &mut self,
+        node: &mut ClnClient,
+        changes: &mut Vec<ActivityChange>,
+    ) -> Result<()> {
+        use cln::listsendpays_request::ListsendpaysIndex;
+        let mut touched = HashSet::new();
+        for index in [ListsendpaysIndex::Created, ListsendpaysIndex::Updated] {
+            let mut cursor = match index {
+                ListsendpaysIndex::Created => self.cursor.sendpays_created,
+                ListsendpaysIndex::Updated => self.cursor.sendpays_updated,
+            };
+            loop {
+                let start = cursor;
+                let payments = node
+                    .list_send_pays(cln::ListsendpaysRequest {
+                        index: Some(index.into()),
+                        start: Some(cursor + 1),
+                        limit: Some(SYNC_BATCH),
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_inner()
+                    .payments;
+                let done = payments.len() < SYNC_BATCH as usize;
+                for p in payments {
+                    let next = match index {
+                        ListsendpaysIndex::Created => p.created_index,
+                        ListsendpaysIndex::Updated => p.updated_index,
+                    };
+                    cursor = cursor.max(next.unwrap_or_default());
+                    let id = p.id;
+                    let part = SendPart::from(p);
+                    touched.insert(part.activity_id());
+                    self.parts.insert(id, part);
+                }
+                // Without indexes we would fetch the same batch again.
+                if done || cursor == start {
+                    break;
+                }
+            }
+            match index {
+                ListsendpaysIndex::Created => self.cursor.sendpays_created = cursor,
+                ListsendpaysIndex::Updated => self.cursor.sendpays_updated = cursor,
+            }
+        }
+
+        let mut groups: HashMap<String, Vec<&SendPart>> = HashMap::new();
+        for part in self.parts.values() {
+            let id = part.activity_id();
+            if touched.contains(&id) {
+                groups.entry(id).or_default().push(part);
+            }
+        }
+        let activities: Vec<Activity> = groups
+            .into_iter()
+            .map(|(id, parts)| payment_activity(id, &parts))
+            .collect();
+        for a in activities {
+            changes.extend(self.apply(a));
+        }
+        Ok(())
+    }
+
+    async fn sync_forwards(
+        &mut self,
+        node: &mut ClnClient,
+        changes: &mut Vec<ActivityChange>,
+    ) -> Result<()> {
+        use cln::listforwards_request::ListforwardsIndex;
+        for index in [ListforwardsIndex::Created, ListforwardsIndex::Updated] {
+            let mut cursor = match index {
+                ListforwardsIndex::Created => self.cursor.forwards_created,
+                ListforwardsIndex::Updated => self.cursor.forwards_updated,
+            };
+            loop {
+                let start = cursor;
+                let forwards = node
+                    .list_forwards(cln::ListforwardsRequest {
+                        index: Some(index.into()),
+                        start: Some(cursor + 1),
+                        limit: Some(SYNC_BATCH),
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_inner()
+                    .forwards;
+                let done = forwards.len() < SYNC_BATCH as usize;
+                for f in forwards {
+                    let next = match index {
+                        ListforwardsIndex::Created => f.created_index,
+                        ListforwardsIndex::Updated => f.updated_index,
+                    };
+                    cursor = cursor.max(next.unwrap_or_default());
+                    changes.extend(self.apply(forward_activity(f)));
+                }
+                // Without indexes we would fetch the same batch again.
+                if done || cursor == start {
+                    break;
+                }
+            }
+            match index {
+                ListforwardsIndex::Created => self.cursor.forwards_created = cursor,
+                ListforwardsIndex::Updated => self.cursor.forwards_updated = cursor,
+            }
+        }
+        Ok(())
+    }
+
+    async fn sync_transactions(
+        &mut self,
+        node: &mut ClnClient,
+        now: u64,
+        changes: &mut Vec<ActivityChange>,
+    ) -> Result<()> {
+        let tip = node
+            .getinfo(cln::GetinfoRequest {})
+            .await?
+            .into_inner()
+            .blockheight;
+        if self.cursor.blockheight == Some(tip) {
+            return Ok(());
+        }
+        let outputs = node
+            .list_funds(cln::ListfundsRequest { spent: Some(true) })
+            .await?
+            .into_inner()
+            .outputs;
+        let ours: HashMap<(Vec<u8>, u32), u64> = outputs
+            .into_iter()
+            .map(|o| {
+                (
+                    (o.txid, o.output),
+                    o.amount_msat.map(|a| a.msat).unwrap_or_default(),
+                )
+            })
+            .collect();
+        let transactions = node
+            .list_transactions(cln::ListtransactionsRequest {})
+            .await?
+            .into_inner()
+            .transactions;
+        for tx in transactions {
+            if let Some(a) = transaction_activity(&tx, &ours, now) {
+                changes.extend(self.apply(a));
+            }
+        }
+        self.cursor.blockheight = Some(tip);
+        Ok(())
+    }
+
+    /// Add or update an activity. The timestamp of known activities
+    /// is kept, so they do not move around in the feed.
+    fn apply(&mut self, mut activity: Activity) -> Option<ActivityChange> {
+        let previous_status = match self.entries.get(&activity.id) {
+            Some(known) => {
+                activity.timestamp = known.timestamp;
+                if *known == activity {
+                    return None;
+                }
+                Some(known.status)
+            }
+            None => None,
+        };
+        self.entries.insert(activity.id.clone(), activity.clone());
+        Some(ActivityChange {
+            activity,
+            previous_status,
+        })
+    }
+}
+
+fn invoice_activity(i: cln::ListinvoicesInvoices) -> Activity {
+    use cln::listinvoices_invoices::ListinvoicesInvoicesStatus;
+    let status = match i.status() {
+        ListinvoicesInvoicesStatus::Unpaid => ActivityStatus::Pending,
+        ListinvoicesInvoicesStatus::Paid => ActivityStatus::Complete,
+        ListinvoicesInvoicesStatus::Expired => ActivityStatus::Expired,
+    };
+    // The keysend plugin records incoming keysends as invoices.
+    let kind = match i.label.starts_with("keysend-") {
+        true => ActivityKind::Keysend,
+        false => ActivityKind::Invoice,
+    };
+    let created_at = i
+        .bolt11
+        .as_deref()
+        .and_then(|b| Bolt11Invoice::from_str(b).ok())
+        .map(|b| b.duration_since_epoch().as_secs());
+    Activity {
+        id: format!("invoice:{}", i.label),
+        kind,
+        direction: Direction::Incoming,
+        status,
+        amount_msat: i
+            .amount_received_msat
+            .or(i.amount_msat)
+            .map(|a| a.msat)
+            .unwrap_or_default(),
+        fee_msat: None,
+        payment_hash: Some(i.payment_hash),
+        preimage: i.payment_preimage,
+        txid: None,
+        description: i.description,
+        invoice: i.bolt11.or(i.bolt12),
+        destination: None,
+        timestamp: created_at.or(i.paid_at).unwrap_or(i.expires_at),
+        completed_at: i.paid_at,
+    }
+}
+
+/// Combine the parts of one payment attempt. The attempt succeeded
+/// if any part did, and its amount is that of the parts that did not
+/// fail.
+fn payment_activity(id: String, parts: &[&SendPart]) -> Activity {
+    let statuses: Vec<ActivityStatus> = parts.iter().map(|p| p.status).collect();
+    let status = if statuses.contains(&ActivityStatus::Complete) {
+        ActivityStatus::Complete
+    } else if statuses.contains(&ActivityStatus::Pending) {
+        ActivityStatus::Pending
+    } else {
+        ActivityStatus::Failed
+    };
+    let counted: Vec<&&SendPart> = match status {
+        ActivityStatus::Failed => parts.iter().collect(),
+        _ => parts
+            .iter()
+            .filter(|p| p.status != ActivityStatus::Failed)
+            .collect(),
+    };
+    let (amount_msat, amount_sent_msat) = match status {
+        // Failed parts may have been retried with the same amount.
+        ActivityStatus::Failed => counted
+            .iter()
+            .map(|p| (p.amount_msat.unwrap_or_default(), p.amount_sent_msat))
+            .max()
+            .unwrap_or_default(),
+        _ => counted.iter().fold((0, 0), |(a, s), p| {
+            (
+                a + p.amount_msat.unwrap_or_default(),
+                s + p.amount_sent_msat,
+            )
+        }),
+    };
+    let first = parts
+        .iter()
+        .min_by_key(|p| p.created_at)
+        .expect("a payment has at least one part");
+    let kind = match first.invoice {
+        Some(_) => ActivityKind::Payment,
+        None => ActivityKind::Keysend,
+    };
+    Activity {
+        id,
+        kind,
+        direction: Direction::Outgoing,
+        status,
+        amount_msat,
+        fee_msat: match status {
+            ActivityStatus::Complete => Some(amount_sent_msat.saturating_sub(amount_msat)),
+            _ => None,
+        },
+        payment_hash: Some(first.payment_hash.clone()),
+        preimage: parts.iter().find_map(|p| p.preimage.clone()),
+        txid: None,
+        description: first.description.clone(),
+        invoice: first.invoice.clone(),
+        destination: first.destination.clone(),
+        timestamp: first.created_at,
+        completed_at: None,
+    }
+}
+
+fn forward_activity(f: cln::ListforwardsForwards) -> Activity {
+    use cln::listforwards_forwards::ListforwardsForwardsStatus;
+    let status = match f.status() {
+        ListforwardsForwardsStatus::Offered => ActivityStatus::Pending,
+        ListforwardsForwardsStatus::Settled => ActivityStatus::Complete,
+        ListforwardsForwardsStatus::LocalFailed | ListforwardsForwardsStatus::Failed => {
+            ActivityStatus::Failed
+        }
+    };
+    let id = match f.created_index {
+        Some(i) => format!("forward:{}", i),
+        None => format!(
+            "forward:{}:{}",
+            f.in_channel,
+            f.in_htlc_id.unwrap_or_default()
+        ),
+    };
+    Activity {
+        id,
+        kind: ActivityKind::Forward,
+        direction: Direction::Incoming,
+        status,
+        amount_msat: f.fee_msat.map(|a| a.msat).unwrap_or_default(),
+        fee_msat: None,
+        payment_hash: None,
+        preimage: None,
+        txid: None,
+        description: None,
+        invoice: None,
+        destination: None,
+        timestamp: f.received_time as u64,
+        completed_at: None,
+    }
+}
+
+/// The change of our on-chain balance by `tx`: our outputs it
+/// creates, minus our outputs it spends. `ours` maps our outputs,
+/// spent or not, to their amount.
+fn transaction_activity(
+    tx: &cln::ListtransactionsTransactions,
+    ours: &HashMap<(Vec<u8>, u32), u64>,
+    timestamp: u64,
+) -> Option<Activity> {
+    let received: u64 = tx
+        .outputs
+        .iter()
+        .filter_map(|o| ours.get(&(tx.hash.clone(), o.index)))
+        .sum();
+    let spent: u64 = tx
+        .inputs
+        .iter()
+        .filter_map(|i| ours.get(&(i.txid.clone(), i.index)))
+        .sum();
+    let (direction, amount_msat) = match received.checked_sub(spent) {
+        Some(0) => return None,
+        Some(gain) => (Direction::Incoming, gain),
+        None => (Direction::Outgoing, spent - received),
+    };
+    Some(Activity {
+        id: format!("onchain:{}", hex::encode(&tx.hash)),
+        kind: ActivityKind::OnChain,
+        direction,
+        status: match tx.blockheight {
+            0 => ActivityStatus::Pending,
+            _ => ActivityStatus::Complete,
+        },
+        amount_msat,
+        fee_msat: None,
+        payment_hash: None,
+        preimage: None,
+        txid: Some(tx.hash.clone()),
+        description: None,
+        invoice: None,
+        destination: None,
+        timestamp,
+        completed_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(status: ActivityStatus, amount: u64, sent: u64, created_at: u64) -> SendPart {
+        SendPart {
+            payment_hash: vec![1; 32],
+            groupid: 1,
+            status,
+            amount_msat: Some(amount),
+            amount_sent_msat: sent,
+            destination: None,
+            created_at,
+            invoice: Some("lnbc1".to_owned()),
+            description: None,
+            preimage: None,
+        }
+    }
+
+    #[test]
+    fn test_payment_parts() {
+        use ActivityStatus::*;
+        // A failed part retried, and two parts of an MPP.
+        let parts = [
+            part(Failed, 600, 610, 10),
+            part(Complete, 600, 605, 11),
+            part(Complete, 400, 402, 11),
+        ];
+        let a = payment_activity("pay".to_owned(), &parts.iter().collect::<Vec<_>>());
+        assert_eq!(a.status, Complete);
+        assert_eq!(a.amount_msat, 1000);
+        assert_eq!(a.fee_msat, Some(7));
+        assert_eq!(a.timestamp, 10);
+        assert_eq!(a.kind, ActivityKind::Payment);
+
+        let parts = [part(Failed, 600, 610, 10), part(Pending, 600, 605, 11)];
+        let a = payment_activity("pay".to_owned(), &parts.iter().collect::<Vec<_>>());
+        assert_eq!(a.status, Pending);
+        assert_eq!(a.amount_msat, 600);
+        assert_eq!(a.fee_msat, None);
+
+        let mut keysend = part(Failed, 600, 610, 10);
+        keysend.invoice = None;
+        let parts = [keysend.clone(), keysend];
+        let a = payment_activity("pay".to_owned(), &parts.iter().collect::<Vec<_>>());
+        assert_eq!(a.status, Failed);
+        assert_eq!(a.amount_msat, 600);
+        assert_eq!(a.kind, ActivityKind::Keysend);
+    }
+
+    #[test]
+    fn test_transaction_direction() {
+        let tx =
+            |hash: u8, inputs: Vec<(u8, u32)>, outputs: usize| cln::ListtransactionsTransactions {
+                hash: vec![hash; 32],
+                blockheight: 100,
+                inputs: inputs
+                    .into_iter()
+                    .map(|(txid, index)| cln::ListtransactionsTransactionsInputs {
+                        txid: vec![txid; 32],
+                        index,
+                        ..Default::default()
+                    })
+                    .collect(),
+                outputs: (0..outputs)
+                    .map(|index| cln::ListtransactionsTransactionsOutputs {
+                        index: index as u32,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+        let mut ours = HashMap::new();
+        // We received 10k in output 1 of tx 1, and spent it in tx 2
+        // with 3k change in output 0.
+        ours.insert((vec![1; 32], 1), 10_000);
+        ours.insert((vec![2; 32], 0), 3_000);
+
+        let received = transaction_activity(&tx(1, vec![(9, 0)], 2), &ours, 5).unwrap();
+        assert_eq!(received.direction, Direction::Incoming);
+        assert_eq!(received.amount_msat, 10_000);
+        assert_eq!(received.status, ActivityStatus::Complete);
+
+        let sent = transaction_activity(&tx(2, vec![(1, 1)], 2), &ours, 5).unwrap();
+        assert_eq!(sent.direction, Direction::Outgoing);
+        assert_eq!(sent.amount_msat, 7_000);
+
+        assert!(transaction_activity(&tx(3, vec![(9, 1)], 1), &ours, 5).is_none());
+    }
+
+    #[test]
+    fn test_transitions_and_pages() {
+        let activity = |id: &str, status, timestamp| Activity {
+            id: id.to_owned(),
+            kind: ActivityKind::Invoice,
+            direction: Direction::Incoming,
+            status,
+            amount_msat: 1000,
+            fee_msat: None,
+            payment_hash: None,
+            preimage: None,
+            txid: None,
+            description: None,
+            invoice: None,
+            destination: None,
+            timestamp,
+            completed_at: None,
+        };
+        let mut history = History::new();
+        let change = history
+            .apply(activity("a", ActivityStatus::Pending, 10))
+            .unwrap();
+        assert_eq!(change.previous_status, None);
+        assert!(history
+            .apply(activity("a", ActivityStatus::Pending, 10))
+            .is_none());
+
+        // The status changes, the timestamp stays.
+        let change = history
+            .apply(activity("a", ActivityStatus::Complete, 99))
+            .unwrap();
+        assert_eq!(change.previous_status, Some(ActivityStatus::Pending));
+        assert_eq!(history.get("a").unwrap().timestamp, 10);
+
+        history.apply(activity("b", ActivityStatus::Complete, 20));
+        history.apply(activity("c", ActivityStatus::Complete, 20));
+        history.apply(activity("d", ActivityStatus::Complete, 5));
+        let ids = |page: Vec<Activity>| page.into_iter().map(|a| a.id).collect::<Vec<_>>();
+        assert_eq!(ids(history.page(None, 2)), vec!["c", "b"]);
+        assert_eq!(ids(history.page(Some("b"), 2)), vec!["a", "d"]);
+        assert!(history.page(Some("d"), 2).is_empty());
+        assert!(history.page(Some("unknown"), 2).is_empty());
+
+        let restored = History::from_bytes(&history.to_bytes().unwrap()).unwrap();
+        assert_eq!(ids(restored.page(None, 10)), ids(history.page(None, 10)));
+    }
+
+    #[test]
+    fn test_transaction_rescan() {
+        // Cursors persisted before the block height was tracked
+        // scan the transactions on the next sync.
+        let cursor: SyncCursor = serde_json::from_str(
+            r#"{"invoices_created":1,"invoices_updated":1,"sendpays_created":0,
+                "sendpays_updated":0,"forwards_created":0,"forwards_updated":0}"#,
+        )
+        .unwrap();
+        assert_eq!(cursor.blockheight, None);
+
+        let mut history = History::new();
+        history.cursor.blockheight = Some(100);
+        history.rescan_transactions();
+        assert_eq!(history.cursor().blockheight, None);
+    }
+}
@@ -32,6 +32,18 @@ pub mod persist;
 
 pub mod lnurl;
 
+/// Parse invoices, offers, LNURLs, addresses and the like, and pay
+/// them.
+pub mod payment_input;
+
+/// A typed wallet API over the node: balance, payments, invoices,
+/// fees and channels.
+pub mod wallet;
+
+/// A single, incrementally synced feed of invoices, payments,
+/// on-chain transactions and forwards.
+pub mod history;
+
 /// The pairing service that pairs signer-less clients with existing
 /// signers.
 pub mod pairing;
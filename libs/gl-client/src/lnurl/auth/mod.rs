@@ -0,0 +1,220 @@
+//! LNURL-auth (LUD-04) with the linking keys derived from the node's
+//! keys as described in LUD-05.
+
+use anyhow::{anyhow, ensure, Result};
+use lightning_signer::bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use lightning_signer::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use lightning_signer::bitcoin::hashes::{sha256, Hash, HashEngine};
+use lightning_signer::bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use reqwest::Url;
+
+/// The purpose of the LUD-05 derivation paths.
+const LNURL_AUTH_PURPOSE: u32 = 138;
+
+/// A `login` request decoded from an LNURL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoginRequest {
+    /// The decoded URL, which the signature is appended to.
+    pub url: String,
+    /// The domain the linking key is derived for.
+    pub domain: String,
+    /// The challenge to sign.
+    pub k1: [u8; 32],
+    /// One of `register`, `login`, `link` or `auth`, if the service
+    /// told us.
+    pub action: Option<String>,
+}
+
+pub fn parse_login_request(url: &str) -> Result<LoginRequest> {
+    let parsed = Url::parse(url)?;
+    let query = |name: &str| {
+        parsed
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+
+    ensure!(
+        query("tag").as_deref() == Some("login"),
+        "Expected tag to say 'login'"
+    );
+    let k1 = query("k1").ok_or_else(|| anyhow!("Missing k1 in login request"))?;
+    let k1: [u8; 32] = hex::decode(&k1)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| anyhow!("k1 is not 32 hex encoded bytes: {}", k1))?;
+    let domain = parsed
+        .host_str()
+        .ok_or_else(|| anyhow!("Login request has no domain: {}", url))?
+        .to_string();
+
+    Ok(LoginRequest {
+        url: url.to_string(),
+        domain,
+        k1,
+        action: query("action"),
+    })
+}
+
+/// The indices that, below `m/138'`, derive the linking key for
+/// `domain` from the hashing key `m/138'/0`.
+fn linking_key_indices(hashing_key: &SecretKey, domain: &str) -> [u32; 4] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(&hashing_key.secret_bytes());
+    engine.input(domain.as_bytes());
+    let material = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+
+    let mut indices = [0u32; 4];
+    for (i, chunk) in material.chunks(4).take(4).enumerate() {
+        indices[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    indices
+}
+
+/// Derive the linking key for `domain` from the wallet's `master`
+/// key.
+pub fn linking_key(master: &Xpriv, domain: &str) -> Result<SecretKey> {
+    let secp = Secp256k1::new();
+    let purpose = ChildNumber::from_hardened_idx(LNURL_AUTH_PURPOSE)?;
+    let hashing_path = DerivationPath::from(vec![purpose, ChildNumber::from_normal_idx(0)?]);
+    let hashing_key = master.derive_priv(&secp, &hashing_path)?.private_key;
+
+    let mut path = vec![purpose];
+    path.extend(
+        linking_key_indices(&hashing_key, domain)
+            .into_iter()
+            .map(ChildNumber::from),
+    );
+    Ok(master
+        .derive_priv(&secp, &DerivationPath::from(path))?
+        .private_key)
+}
+
+/// Sign the challenge `k1` with `key`. Returns the DER encoded
+/// signature and the compressed public key of `key`.
+pub fn sign_challenge(key: &SecretKey, k1: &[u8; 32]) -> (Vec<u8>, Vec<u8>) {
+    let secp = Secp256k1::new();
+    let sig = secp.sign_ecdsa(&Message::from_digest(*k1), key);
+    let pubkey = PublicKey::from_secret_key(&secp, key);
+    (sig.serialize_der().to_vec(), pubkey.serialize().to_vec())
+}
+
+pub fn build_login_callback_url(request: &LoginRequest, sig: &[u8], key: &[u8]) -> Result<String> {
+    let mut url = Url::parse(&request.url)?;
+    url.query_pairs_mut()
+        .append_pair("sig", &hex::encode(sig))
+        .append_pair("key", &hex::encode(key));
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials;
+    use crate::lnurl::models::{MockLnUrlHttpClient, OkResponse};
+    use crate::lnurl::LNURL;
+    use crate::signer::Signer;
+    use bech32::{ToBase32, Variant};
+    use lightning_signer::bitcoin::secp256k1::ecdsa::Signature;
+    use lightning_signer::bitcoin::Network;
+
+    const K1: &str = "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e";
+
+    #[test]
+    fn test_linking_key_indices() {
+        // Test vector from LUD-05.
+        let hashing_key = SecretKey::from_slice(
+            &hex::decode("7d417a6a5e9a6a4a879aeaba11a11838764c8fa2b959c242d43dea682b3e409b")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            linking_key_indices(&hashing_key, "site.com"),
+            [1588488367, 2659270754, 38110259, 4136336762]
+        );
+    }
+
+    #[test]
+    fn test_parse_login_request() {
+        let url = format!("https://site.com/auth?tag=login&k1={K1}&action=login");
+        let req = parse_login_request(&url).unwrap();
+        assert_eq!(req.domain, "site.com");
+        assert_eq!(hex::encode(req.k1), K1);
+        assert_eq!(req.action.as_deref(), Some("login"));
+
+        assert!(parse_login_request(&format!("https://site.com?tag=withdraw&k1={K1}")).is_err());
+        assert!(parse_login_request("https://site.com?tag=login&k1=abcd").is_err());
+        assert!(parse_login_request("https://site.com?tag=login").is_err());
+    }
+
+    #[test]
+    fn test_sign_challenge() {
+        let master = Xpriv::new_master(Network::Bitcoin, &[1u8; 32]).unwrap();
+        let key = linking_key(&master, "site.com").unwrap();
+        assert_eq!(key, linking_key(&master, "site.com").unwrap());
+        assert_ne!(key, linking_key(&master, "other.com").unwrap());
+
+        let req = parse_login_request(&format!("https://site.com?tag=login&k1={K1}")).unwrap();
+        let (sig, pubkey) = sign_challenge(&key, &req.k1);
+        let secp = Secp256k1::new();
+        secp.verify_ecdsa(
+            &Message::from_digest(req.k1),
+            &Signature::from_der(&sig).unwrap(),
+            &PublicKey::from_slice(&pubkey).unwrap(),
+        )
+        .unwrap();
+
+        let callback = Url::parse(&build_login_callback_url(&req, &sig, &pubkey).unwrap()).unwrap();
+        let query: Vec<(String, String)> = callback.query_pairs().into_owned().collect();
+        assert_eq!(query[0], ("tag".to_string(), "login".to_string()));
+        assert_eq!(query[2], ("sig".to_string(), hex::encode(&sig)));
+        assert_eq!(query[3], ("key".to_string(), hex::encode(&pubkey)));
+    }
+
+    #[tokio::test]
+    async fn test_lnurl_login() {
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let url = format!("https://site.com/auth?tag=login&k1={K1}");
+        let lnurl = bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32).unwrap();
+
+        let (sig, key) = signer.sign_lnurl_auth("site.com", &[0xe2; 32]).unwrap();
+        let (_, same_key) = signer.sign_lnurl_auth("site.com", &[0x01; 32]).unwrap();
+        assert_eq!(key, same_key);
+
+        let mut mock_http_client = MockLnUrlHttpClient::new();
+        mock_http_client
+            .expect_send_login_callback()
+            .times(2)
+            .returning(move |callback| {
+                let query: Vec<(String, String)> = Url::parse(callback)
+                    .unwrap()
+                    .query_pairs()
+                    .into_owned()
+                    .collect();
+                assert_eq!(query[2].0, "sig");
+                assert_eq!(query[3], ("key".to_string(), hex::encode(&key)));
+                let status = match query[2].1 == hex::encode(&sig) {
+                    true => "OK",
+                    false => "ERROR",
+                };
+                Box::pin(futures::future::ready(Ok(OkResponse {
+                    status: status.to_string(),
+                    reason: None,
+                })))
+            });
+
+        let lnurl_client = LNURL::new(mock_http_client);
+        // Only the challenge we signed above is accepted by the mock.
+        assert!(lnurl_client.login(&lnurl, &signer).await.is_err());
+        let url = format!(
+            "https://site.com/auth?tag=login&k1={}",
+            hex::encode([0xe2; 32])
+        );
+        let lnurl = bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32).unwrap();
+        assert!(lnurl_client.login(&lnurl, &signer).await.is_ok());
+    }
+}
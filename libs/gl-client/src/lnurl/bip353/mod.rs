@@ -0,0 +1,319 @@
+//! Human-readable payment destinations, e.g., `₿alice@example.com`,
+//! as described in BIP-353.
+//!
+//! The payment instructions are a `bitcoin:` URI in a DNSSEC signed
+//! TXT record at `alice.user._bitcoin-payment.example.com`. We build
+//! a proof of the record through a `DnsResolver`, and verify it
+//! ourselves, so we don't need to trust the resolver.
+//!
+//! The exception is a name that does not exist, or has no TXT
+//! records at all: we don't check proofs of nonexistence (NSEC or
+//! NSEC3), so `NoSuchName` is only as trustworthy as the resolver. A
+//! resolver could use it to hide a BIP-353 record and make callers
+//! fall back to LNURL.
+
+mod uri;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose;
+use base64::Engine;
+use dnssec_prover::query::{ProofBuilder, ProofBuildingError, QueryBuf};
+use dnssec_prover::rr::{Name, RR, TXT_TYPE};
+use dnssec_prover::ser::parse_rr_stream;
+use dnssec_prover::validation::verify_rr_stream;
+use log::debug;
+use mockall::automock;
+use std::fmt;
+use std::str::FromStr;
+
+pub use uri::{BitcoinUri, PaymentInstruction};
+
+/// The DNS-over-HTTPS endpoint `DohResolver` uses by default.
+pub const DEFAULT_DOH_URL: &str = "https://1.1.1.1/dns-query";
+
+/// A name like `₿alice@example.com`. The `₿` is optional.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HumanReadableName {
+    pub user: String,
+    pub domain: String,
+}
+
+impl HumanReadableName {
+    /// The name of the TXT record with the payment instructions.
+    pub fn dns_name(&self) -> String {
+        format!("{}.user._bitcoin-payment.{}.", self.user, self.domain)
+    }
+
+    /// The same name, read as a LUD-16 lightning address.
+    pub fn lightning_address(&self) -> String {
+        format!("{}@{}", self.user, self.domain)
+    }
+}
+
+impl FromStr for HumanReadableName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix('₿').unwrap_or(s);
+        let (user, domain) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("{} is not a human-readable name", s))?;
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        ensure!(
+            valid(user) && valid(domain.trim_end_matches('.')),
+            "{} is not a human-readable name",
+            s
+        );
+        Ok(HumanReadableName {
+            user: user.to_lowercase(),
+            domain: domain.trim_end_matches('.').to_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for HumanReadableName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "₿{}@{}", self.user, self.domain)
+    }
+}
+
+/// Sends DNS queries to a recursive resolver. The resolver does not
+/// need to be trusted, the answers are checked against DNSSEC.
+#[async_trait]
+#[automock]
+pub trait DnsResolver {
+    /// Send `query`, a DNS message in wire format, and return the
+    /// response in wire format.
+    async fn query(&self, query: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// A `DnsResolver` using DNS-over-HTTPS (RFC 8484).
+pub struct DohResolver {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl DohResolver {
+    pub fn new() -> DohResolver {
+        DohResolver::with_url(DEFAULT_DOH_URL)
+    }
+
+    pub fn with_url(url: &str) -> DohResolver {
+        DohResolver {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+        }
+    }
+}
+
+impl Default for DohResolver {
+    fn default() -> Self {
+        DohResolver::new()
+    }
+}
+
+#[async_trait]
+impl DnsResolver for DohResolver {
+    async fn query(&self, query: Vec<u8>) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[("dns", general_purpose::URL_SAFE_NO_PAD.encode(query))])
+            .header("Accept", "application/dns-message")
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// The resolver answered that the name, or its TXT records, do not
+/// exist. This is not backed by a DNSSEC proof, see the module
+/// documentation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoSuchName(pub HumanReadableName);
+
+impl fmt::Display for NoSuchName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} does not exist", self.0)
+    }
+}
+
+impl std::error::Error for NoSuchName {}
+
+/// Payment instructions resolved from a `HumanReadableName`.
+#[derive(Clone, Debug)]
+pub struct Resolution {
+    pub uri: BitcoinUri,
+    /// The DNSSEC proof of the record (RFC 9102), which can be handed
+    /// to others to prove whom we paid.
+    pub proof: Vec<u8>,
+}
+
+/// Look up the payment instructions for `name`. Returns `None` if
+/// the name provably has TXT records but none of them is a BIP-353
+/// record. Fails with `NoSuchName` if the resolver claims the name
+/// or its TXT records do not exist, e.g., because the domain only
+/// serves LNURL, since we can't verify that. `now` is in seconds
+/// since the UNIX epoch.
+pub async fn resolve<R: DnsResolver>(
+    resolver: &R,
+    name: &HumanReadableName,
+    now: u64,
+) -> Result<Option<Resolution>> {
+    let dns_name = Name::try_from(name.dns_name())
+        .map_err(|_| anyhow!("{} is not a valid DNS name", name.dns_name()))?;
+    let proof = match build_txt_proof(resolver, &dns_name).await? {
+        Some(p) => p,
+        None => return Err(NoSuchName(name.clone()).into()),
+    };
+
+    let records = verify_txt_proof(&proof, &dns_name, now)?;
+    if records.is_empty() {
+        debug!("{} has no TXT records", name);
+        return Err(NoSuchName(name.clone()).into());
+    }
+    let records: Vec<String> = records
+        .into_iter()
+        .filter(|r| r.len() >= 8 && r[..8].eq_ignore_ascii_case(b"bitcoin:"))
+        .map(|r| String::from_utf8(r).map_err(|_| anyhow!("Record is not valid UTF-8")))
+        .collect::<Result<_>>()?;
+    match records.as_slice() {
+        [] => Ok(None),
+        [record] => Ok(Some(Resolution {
+            uri: BitcoinUri::parse(record)?,
+            proof,
+        })),
+        _ => bail!("{} has more than one bitcoin: record", name),
+    }
+}
+
+/// Ask `resolver` for the TXT records at `name`, and everything
+/// needed to prove them from the DNSSEC root. Returns `None` if the
+/// name does not exist.
+async fn build_txt_proof<R: DnsResolver>(resolver: &R, name: &Name) -> Result<Option<Vec<u8>>> {
+    let (mut builder, query) = ProofBuilder::new(name, TXT_TYPE);
+    let mut queries = vec![query];
+    while builder.awaiting_responses() {
+        let query = queries
+            .pop()
+            .ok_or_else(|| anyhow!("DNSSEC proof builder ran out of queries"))?;
+        let response = resolver.query(query.into_vec()).await?;
+        ensure!(
+            response.len() <= u16::MAX as usize,
+            "DNS response is too long"
+        );
+        let mut buf = QueryBuf::new_zeroed(0);
+        buf.extend_from_slice(&response);
+        match builder.process_response(&buf) {
+            Ok(new_queries) => queries.extend(new_queries),
+            Err(ProofBuildingError::NoSuchName) => {
+                debug!("{} does not exist", name);
+                return Ok(None);
+            }
+            Err(e) => bail!("Failed to build a DNSSEC proof for {}: {}", name, e),
+        }
+    }
+    let (proof, _ttl) = builder
+        .finish_proof()
+        .map_err(|_| anyhow!("Too many queries to prove {}", name))?;
+    Ok(Some(proof))
+}
+
+/// Verify `proof` up to the DNSSEC root and return the contents of
+/// the TXT records at `name`.
+pub fn verify_txt_proof(proof: &[u8], name: &Name, now: u64) -> Result<Vec<Vec<u8>>> {
+    let rrs = parse_rr_stream(proof).map_err(|_| anyhow!("Malformed DNSSEC proof"))?;
+    let verified =
+        verify_rr_stream(&rrs).map_err(|e| anyhow!("DNSSEC validation failed: {:?}", e))?;
+    ensure!(
+        verified.valid_from <= now && now <= verified.expires,
+        "DNSSEC signatures are only valid from {} to {}",
+        verified.valid_from,
+        verified.expires
+    );
+    Ok(verified
+        .resolve_name(name)
+        .into_iter()
+        .filter_map(|rr| match rr {
+            RR::Txt(txt) => Some(txt.data.as_vec()),
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A proof of the BIP-353 record of `₿matt@mattcorallo.com`,
+    /// valid from 1709047250 to 1709359258.
+    const PROOF: &str = include_str!("testdata/matt.user._bitcoin-payment.mattcorallo.com.hex");
+    const NOW: u64 = 1709100000;
+
+    fn name() -> HumanReadableName {
+        "₿matt@mattcorallo.com".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(
+            name(),
+            HumanReadableName {
+                user: "matt".to_string(),
+                domain: "mattcorallo.com".to_string(),
+            }
+        );
+        assert_eq!(
+            "Matt@MattCorallo.com".parse::<HumanReadableName>().unwrap(),
+            name()
+        );
+        assert_eq!(
+            name().dns_name(),
+            "matt.user._bitcoin-payment.mattcorallo.com."
+        );
+        assert_eq!(name().to_string(), "₿matt@mattcorallo.com");
+        assert!("lnbc1".parse::<HumanReadableName>().is_err());
+        assert!("@example.com".parse::<HumanReadableName>().is_err());
+        assert!("a b@example.com".parse::<HumanReadableName>().is_err());
+    }
+
+    #[test]
+    fn test_verify_txt_proof() {
+        let proof = hex::decode(PROOF.trim()).unwrap();
+        let dns_name = Name::try_from(name().dns_name()).unwrap();
+        let records = verify_txt_proof(&proof, &dns_name, NOW).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].starts_with(b"bitcoin:?b12=lno1"));
+
+        // Expired signatures.
+        assert!(verify_txt_proof(&proof, &dns_name, 1709359259).is_err());
+
+        // A tampered record.
+        let mut tampered = proof.clone();
+        let i = proof.windows(4).position(|w| w == b"lno1").unwrap();
+        tampered[i + 10] ^= 1;
+        assert!(verify_txt_proof(&tampered, &dns_name, NOW).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_no_such_name() {
+        let mut resolver = MockDnsResolver::new();
+        resolver.expect_query().times(1).returning(|query| {
+            // Answer with NXDOMAIN.
+            let mut response = query.clone();
+            response[2] = 0x81;
+            response[3] = 0x83;
+            Box::pin(async move { Ok(response) })
+        });
+        let err = resolve(&resolver, &name(), NOW).await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&NoSuchName(name())));
+    }
+}
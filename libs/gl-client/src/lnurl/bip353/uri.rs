@@ -0,0 +1,158 @@
+//! `bitcoin:` URIs (BIP-21, with the BIP-321 extensions) as found in
+//! BIP-353 records.
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+/// A way to pay a `BitcoinUri`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaymentInstruction {
+    /// A BOLT12 offer, to fetch an invoice from.
+    Offer(String),
+    /// A BOLT11 invoice.
+    Invoice(String),
+    OnChain {
+        address: String,
+        amount_msat: Option<u64>,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitcoinUri {
+    /// The on-chain address, BIP-321 allows URIs without one.
+    pub address: Option<String>,
+    pub amount_msat: Option<u64>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    /// A BOLT11 invoice from the `lightning` parameter.
+    pub lightning: Option<String>,
+    /// A BOLT12 offer from the `lno` parameter.
+    pub offer: Option<String>,
+}
+
+impl BitcoinUri {
+    pub fn parse(uri: &str) -> Result<BitcoinUri> {
+        let scheme = uri.get(..8).unwrap_or_default();
+        ensure!(
+            scheme.eq_ignore_ascii_case("bitcoin:"),
+            "Not a bitcoin: URI: {}",
+            uri
+        );
+        let (address, query) = match uri[8..].split_once('?') {
+            Some((a, q)) => (a, q),
+            None => (&uri[8..], ""),
+        };
+
+        let mut res = BitcoinUri {
+            address: Some(address.to_string()).filter(|a| !a.is_empty()),
+            ..Default::default()
+        };
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            let key = key.to_lowercase();
+            let value = value.to_string();
+            match key.as_str() {
+                "amount" => res.amount_msat = Some(parse_btc_amount(&value)?),
+                "label" => res.label = Some(value),
+                "message" => res.message = Some(value),
+                "lightning" => res.lightning = Some(value),
+                // `b12` is what earlier drafts of BIP-353 used.
+                "lno" | "b12" => res.offer = Some(value),
+                k if k.starts_with("req-") => {
+                    bail!("Unsupported required parameter {} in bitcoin: URI", k)
+                }
+                _ => {}
+            }
+        }
+        ensure!(
+            res.address.is_some() || res.lightning.is_some() || res.offer.is_some(),
+            "The bitcoin: URI has no way to pay it"
+        );
+        Ok(res)
+    }
+
+    /// The best way to pay this URI: an offer, then an invoice, then
+    /// on-chain.
+    pub fn preferred(&self) -> PaymentInstruction {
+        if let Some(offer) = &self.offer {
+            PaymentInstruction::Offer(offer.clone())
+        } else if let Some(invoice) = &self.lightning {
+            PaymentInstruction::Invoice(invoice.clone())
+        } else {
+            PaymentInstruction::OnChain {
+                address: self.address.clone().unwrap_or_default(),
+                amount_msat: self.amount_msat,
+            }
+        }
+    }
+}
+
+/// Parse a decimal amount of BTC, e.g., `0.0005`, into millisatoshis.
+fn parse_btc_amount(amount: &str) -> Result<u64> {
+    let invalid = || anyhow!("Invalid amount {} in bitcoin: URI", amount);
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || fraction.len() > 8
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| invalid())?
+    };
+    let fraction: u64 = format!("{:0<8}", fraction).parse().map_err(|_| invalid())?;
+    whole
+        .checked_mul(100_000_000)
+        .and_then(|s| s.checked_add(fraction))
+        .and_then(|s| s.checked_mul(1_000))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bitcoin_uri() {
+        let uri = BitcoinUri::parse(
+            "BITCOIN:bc1qexample?amount=0.0005&label=Alice&lightning=lnbc1&req-foo-unknown=1",
+        );
+        assert!(uri.is_err());
+
+        let uri = BitcoinUri::parse(
+            "bitcoin:bc1qexample?amount=1.5&message=For%20coffee&lightning=lnbc1",
+        )
+        .unwrap();
+        assert_eq!(uri.address.as_deref(), Some("bc1qexample"));
+        assert_eq!(uri.amount_msat, Some(150_000_000_000));
+        assert_eq!(uri.message.as_deref(), Some("For coffee"));
+        assert_eq!(
+            uri.preferred(),
+            PaymentInstruction::Invoice("lnbc1".to_string())
+        );
+
+        let uri = BitcoinUri::parse("bitcoin:?lno=lno1qq&lightning=lnbc1").unwrap();
+        assert_eq!(uri.address, None);
+        assert_eq!(
+            uri.preferred(),
+            PaymentInstruction::Offer("lno1qq".to_string())
+        );
+
+        let uri = BitcoinUri::parse("bitcoin:bc1qexample?amount=.001").unwrap();
+        assert_eq!(
+            uri.preferred(),
+            PaymentInstruction::OnChain {
+                address: "bc1qexample".to_string(),
+                amount_msat: Some(100_000_000),
+            }
+        );
+
+        assert!(BitcoinUri::parse("bitcoin:").is_err());
+        assert!(BitcoinUri::parse("lightning:lnbc1").is_err());
+        assert!(BitcoinUri::parse("bitcoin:bc1qexample?amount=0.000000001").is_err());
+        assert!(BitcoinUri::parse("bitcoin:bc1qexample?amount=-1").is_err());
+    }
+}
@@ -1,19 +1,56 @@
-mod models;
+pub(crate) mod auth;
+pub mod bip353;
+pub(crate) mod models;
 mod pay;
-mod utils;
+pub mod server;
+pub(crate) mod utils;
 mod withdraw;
 
+pub use self::models::{
+    LnUrlHttpClearnetClient, PayerData, PayerDataAuth, SuccessAction, VerifyResponse,
+};
 use self::models::{
     LnUrlHttpClient, PayRequestCallbackResponse, PayRequestResponse, WithdrawRequestResponse,
 };
+pub use self::pay::{PayOptions, PayResult, SuccessActionResult};
 use self::utils::{parse_invoice, parse_lnurl};
+use crate::lightning::offers::offer::Offer;
 use crate::node::ClnClient;
-use crate::pb::cln::{amount_or_any, Amount, AmountOrAny};
-use anyhow::{anyhow, Result};
-use models::LnUrlHttpClearnetClient;
-use pay::{resolve_lnurl_to_invoice, validate_invoice_from_callback_response};
+use crate::pb::cln::{
+    amount_or_any, waitinvoice_response::WaitinvoiceStatus, Amount, AmountOrAny,
+    WaitinvoiceResponse,
+};
+use crate::signer::Signer;
+use anyhow::{anyhow, bail, ensure, Result};
+use auth::{build_login_callback_url, parse_login_request};
+use bip353::{DnsResolver, HumanReadableName, PaymentInstruction};
+use pay::{process_success_action, resolve_lnurl_pay, validate_invoice_from_callback_response};
+use std::str::FromStr;
+use std::time::Duration;
 use url::Url;
-use withdraw::{build_withdraw_request_callback_url, parse_withdraw_request_response_from_url};
+use withdraw::{
+    build_withdraw_request_callback_url, ensure_amount_is_withdrawable,
+    parse_withdraw_request_response_from_url,
+};
+
+pub use auth::LoginRequest;
+
+/// How often `LNURL::wait_for_settlement` polls the verify URL.
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long `LNURL::withdraw` waits for the service to pay our
+/// invoice.
+pub const DEFAULT_WITHDRAW_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An invoice to pay, obtained for a human-readable name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedInvoice {
+    /// A BOLT11 or BOLT12 invoice, ready to be passed to `pay`.
+    pub invoice: String,
+    /// The amount to pass to `pay`. Only set if the invoice does not
+    /// have one.
+    pub amount_msat: Option<u64>,
+}
 
 pub struct LNURL<T: LnUrlHttpClient> {
     http_client: T,
@@ -67,15 +104,73 @@ impl<T: LnUrlHttpClient> LNURL<T> {
         lnurl: &str,
         amount_msats: u64,
         node: &mut ClnClient,
-    ) -> Result<tonic::Response<crate::pb::cln::PayResponse>> {
-        let invoice = resolve_lnurl_to_invoice(&self.http_client, lnurl, amount_msats).await?;
+    ) -> Result<PayResult> {
+        self.pay_with_options(lnurl, amount_msats, &PayOptions::default(), node)
+            .await
+    }
+
+    /// Pay `amount_msats` to the LNURL or lightning address `lnurl`,
+    /// sending along the comment and payer data in `options`.
+    pub async fn pay_with_options(
+        &self,
+        lnurl: &str,
+        amount_msats: u64,
+        options: &PayOptions,
+        node: &mut ClnClient,
+    ) -> Result<PayResult> {
+        let callback_response =
+            resolve_lnurl_pay(&self.http_client, lnurl, amount_msats, options).await?;
+
+        let payment = node
+            .pay(crate::pb::cln::PayRequest {
+                bolt11: callback_response.pr.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
 
-        node.pay(crate::pb::cln::PayRequest {
-            bolt11: invoice.to_string(),
-            ..Default::default()
+        let success_action = callback_response
+            .success_action
+            .map(|a| process_success_action(a, &payment.payment_preimage));
+        Ok(PayResult {
+            payment,
+            success_action,
+            verify: callback_response.verify,
         })
-        .await
-        .map_err(|e| anyhow!(e))
+    }
+
+    /// Ask the service whether the invoice behind the LUD-21 `verify`
+    /// URL was paid.
+    pub async fn verify(&self, verify_url: &str) -> Result<VerifyResponse> {
+        let response = self.http_client.get_verify_response(verify_url).await?;
+        ensure!(
+            response.status == "OK",
+            "Verify failed: {}",
+            response.reason.clone().unwrap_or(response.status)
+        );
+        Ok(response)
+    }
+
+    /// Poll the LUD-21 `verify` URL until the service reports the
+    /// invoice as settled, or fail after `timeout`.
+    pub async fn wait_for_settlement(
+        &self,
+        verify_url: &str,
+        timeout: Duration,
+    ) -> Result<VerifyResponse> {
+        let poll = async {
+            loop {
+                let response = self.verify(verify_url).await?;
+                if response.settled {
+                    return Ok(response);
+                }
+                tokio::time::sleep(VERIFY_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(timeout, poll)
+            .await
+            .map_err(|_| anyhow!("Payment was not settled within {:?}", timeout))?
     }
 
     pub async fn get_withdraw_request_response(
@@ -98,13 +193,38 @@ impl<T: LnUrlHttpClient> LNURL<T> {
         Ok(withdrawal_request_response)
     }
 
+    /// Withdraw `amount_msats` from the service behind `lnurl`, and
+    /// wait up to `DEFAULT_WITHDRAW_TIMEOUT` for it to pay us.
     pub async fn withdraw(
         &self,
         lnurl: &str,
         amount_msats: u64,
         node: &mut ClnClient,
-    ) -> Result<()> {
+    ) -> Result<WaitinvoiceResponse> {
+        self.withdraw_with_timeout(lnurl, amount_msats, DEFAULT_WITHDRAW_TIMEOUT, node)
+            .await
+    }
+
+    /// Withdraw `amount_msats` from the service behind `lnurl`.
+    /// Returns the paid invoice once the service paid it, or fails if
+    /// the service rejects the invoice or does not pay it within
+    /// `timeout`.
+    pub async fn withdraw_with_timeout(
+        &self,
+        lnurl: &str,
+        amount_msats: u64,
+        timeout: Duration,
+        node: &mut ClnClient,
+    ) -> Result<WaitinvoiceResponse> {
         let withdraw_request_response = self.get_withdraw_request_response(lnurl).await?;
+        ensure_amount_is_withdrawable(&withdraw_request_response, amount_msats)?;
+
+        // Labels must be unique, and the same LNURL may be used
+        // more than once.
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis();
+        let label = format!("lnurl-withdraw-{}-{}", withdraw_request_response.k1, now);
 
         let amount = AmountOrAny {
             value: Some(amount_or_any::Value::Amount(Amount { msat: amount_msats })),
@@ -112,6 +232,7 @@ impl<T: LnUrlHttpClient> LNURL<T> {
         let invoice = node
             .invoice(crate::pb::cln::InvoiceRequest {
                 amount_msat: Some(amount),
+                label: label.clone(),
                 description: withdraw_request_response.default_description.clone(),
                 ..Default::default()
             })
@@ -122,10 +243,143 @@ impl<T: LnUrlHttpClient> LNURL<T> {
         let callback_url =
             build_withdraw_request_callback_url(&withdraw_request_response, invoice.bolt11)?;
 
-        let _ = self
+        let response = self
             .http_client
-            .send_invoice_for_withdraw_request(&callback_url);
+            .send_invoice_for_withdraw_request(&callback_url)
+            .await?;
+        ensure!(
+            response.status == "OK",
+            "The service rejected the withdrawal: {}",
+            response.reason.unwrap_or(response.status)
+        );
+
+        let paid = tokio::time::timeout(
+            timeout,
+            node.wait_invoice(crate::pb::cln::WaitinvoiceRequest {
+                label: label.clone(),
+            }),
+        )
+        .await
+        .map_err(|_| anyhow!("Invoice {} was not paid within {:?}", label, timeout))?
+        .map_err(|e| anyhow!(e))?
+        .into_inner();
+        ensure!(
+            paid.status == WaitinvoiceStatus::Paid as i32,
+            "Invoice {} expired before it was paid",
+            label
+        );
+        Ok(paid)
+    }
 
+    pub fn get_login_request(&self, lnurl: &str) -> Result<LoginRequest> {
+        parse_login_request(&parse_lnurl(lnurl)?)
+    }
+
+    /// Log into the service behind the `login` LNURL `lnurl` with a
+    /// linking key derived from the keys of `signer`.
+    pub async fn login(&self, lnurl: &str, signer: &Signer) -> Result<()> {
+        let request = self.get_login_request(lnurl)?;
+        let (sig, key) = signer.sign_lnurl_auth(&request.domain, &request.k1)?;
+        let callback_url = build_login_callback_url(&request, &sig, &key)?;
+
+        let response = self.http_client.send_login_callback(&callback_url).await?;
+        ensure!(
+            response.status == "OK",
+            "Login to {} failed: {}",
+            request.domain,
+            response.reason.unwrap_or(response.status)
+        );
         Ok(())
     }
+
+    /// Get an invoice for `amount_msats` to the human-readable name
+    /// `name`, e.g., `₿alice@example.com`. The BIP-353 record of the
+    /// name is looked up through `resolver`, and if the name has none
+    /// and `lnurl_fallback` is set it is paid as a lightning address
+    /// instead. The amount may be omitted if the payment instructions
+    /// carry one.
+    ///
+    /// Note that the fallback trusts `resolver` when it says the name
+    /// does not exist, see [`bip353::NoSuchName`].
+    pub async fn get_invoice_for_name<R: DnsResolver>(
+        &self,
+        resolver: &R,
+        name: &str,
+        amount_msats: Option<u64>,
+        lnurl_fallback: bool,
+        node: &mut ClnClient,
+    ) -> Result<ResolvedInvoice> {
+        let name: HumanReadableName = name.parse()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let require_amount =
+            || amount_msats.ok_or_else(|| anyhow!("An amount is required to pay {}", name));
+
+        let resolution = match bip353::resolve(resolver, &name, now).await {
+            Ok(Some(r)) => r,
+            Ok(None) if !lnurl_fallback => bail!("{} has no BIP-353 record", name),
+            Err(e) if !lnurl_fallback || !e.is::<bip353::NoSuchName>() => return Err(e),
+            _ => {
+                let callback_response = resolve_lnurl_pay(
+                    &self.http_client,
+                    &name.lightning_address(),
+                    require_amount()?,
+                    &PayOptions::default(),
+                )
+                .await?;
+                return Ok(ResolvedInvoice {
+                    invoice: callback_response.pr,
+                    amount_msat: None,
+                });
+            }
+        };
+
+        match resolution.uri.preferred() {
+            PaymentInstruction::Offer(offer) => {
+                let parsed = Offer::from_str(&offer)
+                    .map_err(|e| anyhow!("Invalid offer for {}: {:?}", name, e))?;
+                let amount_msat = match parsed.amount() {
+                    Some(_) => None,
+                    None => Some(require_amount()?),
+                };
+                let invoice = node
+                    .fetch_invoice(crate::pb::cln::FetchinvoiceRequest {
+                        offer,
+                        amount_msat: amount_msat.map(|msat| Amount { msat }),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| anyhow!(e))?
+                    .into_inner();
+                Ok(ResolvedInvoice {
+                    invoice: invoice.invoice,
+                    amount_msat: None,
+                })
+            }
+            PaymentInstruction::Invoice(invoice) => {
+                let amount_msat = match parse_invoice(&invoice)?.amount_milli_satoshis() {
+                    Some(a) => {
+                        ensure!(
+                            amount_msats.is_none_or(|m| m == a),
+                            "The invoice of {} is for {} msat",
+                            name,
+                            a
+                        );
+                        None
+                    }
+                    None => Some(require_amount()?),
+                };
+                Ok(ResolvedInvoice {
+                    invoice,
+                    amount_msat,
+                })
+            }
+            PaymentInstruction::OnChain { address, .. } => Err(anyhow!(
+                "{} only accepts on-chain payments, to {}",
+                name,
+                address
+            )),
+        }
+    }
 }
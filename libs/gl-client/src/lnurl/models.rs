@@ -15,23 +15,117 @@ pub struct PayRequestResponse {
     pub min_sendable: u64,
     pub tag: String,
     pub metadata: String,
+    /// LUD-12: the maximum length of a comment, if the service
+    /// accepts comments.
+    #[serde(
+        rename = "commentAllowed",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub comment_allowed: Option<u64>,
+    /// LUD-18: the payer data the service asks for.
+    #[serde(rename = "payerData", default, skip_serializing_if = "Option::is_none")]
+    pub payer_data: Option<PayerDataSpec>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct PayRequestCallbackResponse {
     pub pr: String,
+    #[serde(default)]
     pub routes: Vec<String>,
+    /// LUD-09: what to show the user once the invoice is paid.
+    #[serde(
+        rename = "successAction",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub success_action: Option<SuccessAction>,
+    /// LUD-21: where to check whether the invoice was paid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct OkResponse {
-    status: String,
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PayerDataField {
+    #[serde(default)]
+    pub mandatory: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PayerDataAuthField {
+    #[serde(default)]
+    pub mandatory: bool,
+    pub k1: String,
+}
+
+/// The payer data a service asks for (LUD-18).
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PayerDataSpec {
+    pub name: Option<PayerDataField>,
+    pub pubkey: Option<PayerDataField>,
+    pub identifier: Option<PayerDataField>,
+    pub email: Option<PayerDataField>,
+    pub auth: Option<PayerDataAuthField>,
+}
+
+/// A LNURL-auth signature over the `k1` from `PayerDataAuthField`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PayerDataAuth {
+    pub key: String,
+    pub k1: String,
+    pub sig: String,
+}
+
+/// Information about the payer sent along with a payment (LUD-18).
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PayerData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<PayerDataAuth>,
+}
+
+/// An action to perform once the invoice is paid (LUD-09, LUD-10).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    /// A message encrypted with the payment preimage.
+    Aes {
+        description: String,
+        ciphertext: String,
+        iv: String,
+    },
+}
+
+/// The response of a LUD-21 `verify` URL.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct VerifyResponse {
+    pub status: String,
+    #[serde(default)]
+    pub settled: bool,
+    pub preimage: Option<String>,
+    pub pr: Option<String>,
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct ErrorResponse {
-    status: String,
-    reason: String,
+pub struct OkResponse {
+    pub status: String,
+    /// Set by services that respond with an error status.
+    pub reason: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -57,6 +151,8 @@ pub trait LnUrlHttpClient {
     ) -> Result<PayRequestCallbackResponse>;
     async fn get_withdrawal_request_response(&self, url: &str) -> Result<WithdrawRequestResponse>;
     async fn send_invoice_for_withdraw_request(&self, url: &str) -> Result<OkResponse>;
+    async fn send_login_callback(&self, url: &str) -> Result<OkResponse>;
+    async fn get_verify_response(&self, url: &str) -> Result<VerifyResponse>;
 }
 
 pub struct LnUrlHttpClearnetClient {
@@ -82,6 +178,12 @@ impl LnUrlHttpClearnetClient {
     }
 }
 
+impl Default for LnUrlHttpClearnetClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl LnUrlHttpClient for LnUrlHttpClearnetClient {
     async fn get_pay_request_response(&self, lnurl: &str) -> Result<PayRequestResponse> {
@@ -102,4 +204,12 @@ impl LnUrlHttpClient for LnUrlHttpClearnetClient {
     async fn send_invoice_for_withdraw_request(&self, url: &str) -> Result<OkResponse>{
         self.get::<OkResponse>(url).await
     }
+
+    async fn send_login_callback(&self, url: &str) -> Result<OkResponse> {
+        self.get::<OkResponse>(url).await
+    }
+
+    async fn get_verify_response(&self, url: &str) -> Result<VerifyResponse> {
+        self.get::<VerifyResponse>(url).await
+    }
 }
@@ -1,9 +1,13 @@
+mod success_action;
+
 use super::models;
 use super::utils::parse_lnurl;
 
 use crate::lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
 use crate::lnurl::{
-    models::{LnUrlHttpClient, PayRequestCallbackResponse, PayRequestResponse},
+    models::{
+        LnUrlHttpClient, PayRequestCallbackResponse, PayRequestResponse, PayerData, PayerDataSpec,
+    },
     utils::parse_invoice,
 };
 
@@ -12,11 +16,38 @@ use log::debug;
 use reqwest::Url;
 use sha256;
 
-pub async fn resolve_lnurl_to_invoice<T: LnUrlHttpClient>(
+pub use success_action::{process_success_action, SuccessActionResult};
+
+/// Optional information sent along with a payment.
+#[derive(Clone, Debug, Default)]
+pub struct PayOptions {
+    /// LUD-12: a comment for the recipient.
+    pub comment: Option<String>,
+    /// LUD-18: information about the payer. Only the fields the
+    /// service asks for are sent.
+    pub payer_data: Option<PayerData>,
+}
+
+/// The outcome of a LNURL payment.
+#[derive(Debug)]
+pub struct PayResult {
+    pub payment: crate::pb::cln::PayResponse,
+    /// What the service wants us to show the user, already decrypted
+    /// with the payment preimage if needed.
+    pub success_action: Option<SuccessActionResult>,
+    /// LUD-21: where to check whether the service received the
+    /// payment. See `LNURL::wait_for_settlement`.
+    pub verify: Option<String>,
+}
+
+/// Request an invoice for `amount_msats` from the service behind
+/// `lnurl_identifier`, and check that it is the invoice we asked for.
+pub async fn resolve_lnurl_pay<T: LnUrlHttpClient>(
     http_client: &T,
     lnurl_identifier: &str,
     amount_msats: u64,
-) -> Result<String> {
+    options: &PayOptions,
+) -> Result<PayRequestCallbackResponse> {
     let url = match is_lnurl(lnurl_identifier) {
         true => parse_lnurl(lnurl_identifier)?,
         false => parse_lightning_address(lnurl_identifier)?,
@@ -28,19 +59,36 @@ pub async fn resolve_lnurl_to_invoice<T: LnUrlHttpClient>(
         http_client.get_pay_request_response(&url).await?;
 
     validate_pay_request_response(lnurl_identifier, &lnurl_pay_request_response, amount_msats)?;
-
-    let callback_url = build_callback_url(&lnurl_pay_request_response, amount_msats)?;
+    validate_comment(&lnurl_pay_request_response, options.comment.as_deref())?;
+    let payer_data = filter_payer_data(
+        lnurl_pay_request_response.payer_data.as_ref(),
+        options.payer_data.as_ref(),
+    )?
+    .map(|p| serde_json::to_string(&p))
+    .transpose()?;
+
+    let callback_url = build_callback_url(
+        &lnurl_pay_request_response,
+        amount_msats,
+        options.comment.as_deref(),
+        payer_data.as_deref(),
+    )?;
     let callback_response: PayRequestCallbackResponse = http_client
         .get_pay_request_callback_response(&callback_url)
         .await?;
 
     let invoice = parse_invoice(&callback_response.pr)?;
-    validate_invoice_from_callback_response(
-        &invoice,
-        amount_msats,
-        &lnurl_pay_request_response.metadata,
-    )?;
-    Ok(invoice.to_string())
+    // LUD-18: the invoice commits to the payer data as well.
+    let metadata = format!(
+        "{}{}",
+        lnurl_pay_request_response.metadata,
+        payer_data.unwrap_or_default()
+    );
+    validate_invoice_from_callback_response(&invoice, amount_msats, &metadata)?;
+    if let Some(action) = &callback_response.success_action {
+        success_action::validate_success_action(action, &lnurl_pay_request_response.callback)?;
+    }
+    Ok(callback_response)
 }
 
 fn is_lnurl(lnurl_identifier: &str) -> bool {
@@ -145,13 +193,106 @@ fn extract_description(lnurl_pay_request_response: &PayRequestResponse) -> Resul
 fn build_callback_url(
     lnurl_pay_request_response: &models::PayRequestResponse,
     amount: u64,
+    comment: Option<&str>,
+    payer_data: Option<&str>,
 ) -> Result<String> {
     let mut url = Url::parse(&lnurl_pay_request_response.callback)?;
     url.query_pairs_mut()
         .append_pair("amount", &amount.to_string());
+    if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+        url.query_pairs_mut().append_pair("comment", comment);
+    }
+    if let Some(payer_data) = payer_data {
+        url.query_pairs_mut().append_pair("payerdata", payer_data);
+    }
     Ok(url.to_string())
 }
 
+// LUD-12: comments are only sent to services that accept them
+fn validate_comment(
+    lnurl_pay_request_response: &PayRequestResponse,
+    comment: Option<&str>,
+) -> Result<()> {
+    let length = comment.map(|c| c.chars().count()).unwrap_or(0);
+    if length == 0 {
+        return Ok(());
+    }
+
+    match lnurl_pay_request_response.comment_allowed {
+        Some(allowed) if allowed > 0 => ensure!(
+            length as u64 <= allowed,
+            "Comment must be {} characters or less",
+            allowed
+        ),
+        _ => return Err(anyhow!("The service does not accept comments")),
+    }
+    Ok(())
+}
+
+// LUD-18: only send the payer data the service asks for, and fail if
+// we lack a field the service requires
+fn filter_payer_data(
+    spec: Option<&PayerDataSpec>,
+    payer_data: Option<&PayerData>,
+) -> Result<Option<PayerData>> {
+    let spec = match spec {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let empty = PayerData::default();
+    let payer_data = payer_data.unwrap_or(&empty);
+
+    fn field<T: Clone>(
+        name: &str,
+        mandatory: Option<bool>,
+        value: &Option<T>,
+    ) -> Result<Option<T>> {
+        match mandatory {
+            None => Ok(None),
+            Some(true) if value.is_none() => {
+                Err(anyhow!("The service requires the payer's {}", name))
+            }
+            Some(_) => Ok(value.clone()),
+        }
+    }
+
+    let filtered = PayerData {
+        name: field(
+            "name",
+            spec.name.as_ref().map(|f| f.mandatory),
+            &payer_data.name,
+        )?,
+        pubkey: field(
+            "pubkey",
+            spec.pubkey.as_ref().map(|f| f.mandatory),
+            &payer_data.pubkey,
+        )?,
+        identifier: field(
+            "identifier",
+            spec.identifier.as_ref().map(|f| f.mandatory),
+            &payer_data.identifier,
+        )?,
+        email: field(
+            "email",
+            spec.email.as_ref().map(|f| f.mandatory),
+            &payer_data.email,
+        )?,
+        auth: field(
+            "auth",
+            spec.auth.as_ref().map(|f| f.mandatory),
+            &payer_data.auth,
+        )?,
+    };
+    if let (Some(auth), Some(spec)) = (&filtered.auth, &spec.auth) {
+        ensure!(
+            auth.k1 == spec.k1,
+            "The payer's auth does not sign the k1 of the service"
+        );
+    }
+
+    Ok(Some(filtered).filter(|p| *p != empty))
+}
+
 // Validates the pay request response for expected values
 fn ensure_amount_is_within_range(
     lnurl_pay_request_response: &PayRequestResponse,
@@ -217,6 +358,17 @@ mod tests {
 
     use super::*;
 
+    async fn resolve_lnurl_to_invoice<T: LnUrlHttpClient>(
+        http_client: &T,
+        lnurl_identifier: &str,
+        amount_msats: u64,
+    ) -> Result<String> {
+        let options = PayOptions::default();
+        let callback_response =
+            resolve_lnurl_pay(http_client, lnurl_identifier, amount_msats, &options).await?;
+        Ok(parse_invoice(&callback_response.pr)?.to_string())
+    }
+
     fn convert_to_async_return_value<T: Send + 'static>(
         value: T,
     ) -> Pin<Box<dyn std::future::Future<Output = T> + Send>> {
@@ -404,4 +556,123 @@ mod tests {
             _ => panic!("Expected an error, amount specified is greater than maxSendable"),
         }
     }
+
+    fn pay_request_response(extra: &str) -> PayRequestResponse {
+        serde_json::from_str(&format!("{{ \"callback\": \"https://cipherpunk.com/lnurlp/cb/1\", \"maxSendable\": 100000, \"minSendable\": 100, \"tag\": \"payRequest\", \"metadata\": \"[]\"{} }}", extra)).unwrap()
+    }
+
+    #[test]
+    fn test_validate_comment() {
+        let response = pay_request_response(", \"commentAllowed\": 5");
+        assert!(validate_comment(&response, None).is_ok());
+        assert!(validate_comment(&response, Some("hello")).is_ok());
+        assert!(validate_comment(&response, Some("hello!")).is_err());
+
+        let response = pay_request_response("");
+        assert!(validate_comment(&response, Some("")).is_ok());
+        assert!(validate_comment(&response, Some("hi")).is_err());
+    }
+
+    #[test]
+    fn test_filter_payer_data() {
+        let response = pay_request_response(
+            ", \"payerData\": { \"name\": { \"mandatory\": false }, \"email\": { \"mandatory\": true } }",
+        );
+        let spec = response.payer_data.as_ref();
+        let payer_data = PayerData {
+            name: Some("Satoshi".to_string()),
+            pubkey: Some("02ab".to_string()),
+            email: Some("satoshi@example.com".to_string()),
+            ..Default::default()
+        };
+
+        // Fields the service did not ask for are dropped.
+        let filtered = filter_payer_data(spec, Some(&payer_data)).unwrap().unwrap();
+        assert_eq!(filtered.pubkey, None);
+        assert_eq!(
+            serde_json::to_string(&filtered).unwrap(),
+            "{\"name\":\"Satoshi\",\"email\":\"satoshi@example.com\"}"
+        );
+
+        // Mandatory fields must be present.
+        let no_email = PayerData {
+            email: None,
+            ..payer_data.clone()
+        };
+        assert!(filter_payer_data(spec, Some(&no_email)).is_err());
+
+        // Nothing is sent to services that don't ask for payer data.
+        assert_eq!(filter_payer_data(None, Some(&payer_data)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_build_callback_url_with_comment_and_payer_data() {
+        let response = pay_request_response("");
+        let url =
+            build_callback_url(&response, 1000, Some("thanks!"), Some("{\"name\":\"S\"}")).unwrap();
+        let query: Vec<(String, String)> = Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(
+            query,
+            vec![
+                ("amount".to_string(), "1000".to_string()),
+                ("comment".to_string(), "thanks!".to_string()),
+                ("payerdata".to_string(), "{\"name\":\"S\"}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_callback_response_with_success_action() {
+        let response: PayRequestCallbackResponse = serde_json::from_str(
+            "{\"pr\": \"lnbc1\", \"successAction\": { \"tag\": \"message\", \"message\": \"Thanks\" }, \"verify\": \"https://cipherpunk.com/verify/1\"}",
+        )
+        .unwrap();
+        assert_eq!(
+            response.success_action,
+            Some(models::SuccessAction::Message {
+                message: "Thanks".to_string()
+            })
+        );
+        assert_eq!(
+            response.verify.as_deref(),
+            Some("https://cipherpunk.com/verify/1")
+        );
+        assert!(response.routes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_settlement() {
+        use crate::lnurl::LNURL;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut mock_http_client = MockLnUrlHttpClient::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        mock_http_client
+            .expect_get_verify_response()
+            .returning(move |_url| {
+                let settled = c.fetch_add(1, Ordering::SeqCst) > 0;
+                let x = serde_json::from_str(&format!(
+                "{{\"status\": \"OK\", \"settled\": {}, \"preimage\": null, \"pr\": \"lnbc1\"}}",
+                settled
+            ))
+                .unwrap();
+                convert_to_async_return_value(Ok(x))
+            });
+
+        let lnurl = LNURL::new(mock_http_client);
+        let url = "https://cipherpunk.com/verify/1";
+        assert!(!lnurl.verify(url).await.unwrap().settled);
+        let response = lnurl
+            .wait_for_settlement(url, std::time::Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(response.settled);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,195 @@
+//! Success actions (LUD-09) and their AES encrypted variant (LUD-10).
+
+use crate::lnurl::models::SuccessAction;
+use aes::cipher::generic_array::GenericArray;
+use aes::{Aes256, BlockDecrypt, NewBlockCipher};
+use anyhow::{anyhow, ensure, Result};
+use base64::engine::general_purpose;
+use base64::Engine;
+use reqwest::Url;
+
+/// The maximum length of messages and descriptions.
+const MAX_TEXT_LEN: usize = 144;
+
+/// The maximum length of the base64 encoded ciphertext of an AES
+/// success action.
+const MAX_CIPHERTEXT_LEN: usize = 4096;
+
+/// A success action ready to be shown to the user.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SuccessActionResult {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    /// The decrypted message of an AES success action.
+    Aes {
+        description: String,
+        plaintext: String,
+    },
+    /// An AES success action we could not decrypt. The payment went
+    /// through nonetheless.
+    AesFailed {
+        description: String,
+        reason: String,
+    },
+}
+
+/// Check that `action` follows LUD-09 and LUD-10, and that a URL
+/// action points to the same domain as `callback`.
+pub fn validate_success_action(action: &SuccessAction, callback: &str) -> Result<()> {
+    match action {
+        SuccessAction::Message { message } => {
+            ensure!(
+                message.chars().count() <= MAX_TEXT_LEN,
+                "Success action message is longer than {} characters",
+                MAX_TEXT_LEN
+            );
+        }
+        SuccessAction::Url { description, url } => {
+            ensure!(
+                description.chars().count() <= MAX_TEXT_LEN,
+                "Success action description is longer than {} characters",
+                MAX_TEXT_LEN
+            );
+            let domain = Url::parse(callback)?.host_str().map(str::to_string);
+            ensure!(
+                Url::parse(url)?.host_str().map(str::to_string) == domain,
+                "Success action URL {} is not on the domain of the callback",
+                url
+            );
+        }
+        SuccessAction::Aes {
+            description,
+            ciphertext,
+            iv,
+        } => {
+            ensure!(
+                description.chars().count() <= MAX_TEXT_LEN,
+                "Success action description is longer than {} characters",
+                MAX_TEXT_LEN
+            );
+            ensure!(
+                ciphertext.len() <= MAX_CIPHERTEXT_LEN,
+                "Success action ciphertext is longer than {} characters",
+                MAX_CIPHERTEXT_LEN
+            );
+            ensure!(iv.len() == 24, "Success action iv is not 16 bytes");
+        }
+    }
+    Ok(())
+}
+
+/// Turn `action` into something to show the user, decrypting it with
+/// the payment `preimage` if needed.
+pub fn process_success_action(action: SuccessAction, preimage: &[u8]) -> SuccessActionResult {
+    match action {
+        SuccessAction::Message { message } => SuccessActionResult::Message { message },
+        SuccessAction::Url { description, url } => SuccessActionResult::Url { description, url },
+        SuccessAction::Aes {
+            description,
+            ciphertext,
+            iv,
+        } => match decrypt(preimage, &ciphertext, &iv) {
+            Ok(plaintext) => SuccessActionResult::Aes {
+                description,
+                plaintext,
+            },
+            Err(e) => SuccessActionResult::AesFailed {
+                description,
+                reason: e.to_string(),
+            },
+        },
+    }
+}
+
+/// Decrypt the base64 encoded `ciphertext` with AES-256-CBC and
+/// PKCS#7 padding, using the preimage as the key.
+fn decrypt(preimage: &[u8], ciphertext: &str, iv: &str) -> Result<String> {
+    ensure!(preimage.len() == 32, "Preimage is not 32 bytes long");
+    let ciphertext = general_purpose::STANDARD.decode(ciphertext)?;
+    let iv = general_purpose::STANDARD.decode(iv)?;
+    ensure!(iv.len() == 16, "iv is not 16 bytes long");
+    ensure!(
+        !ciphertext.is_empty() && ciphertext.len().is_multiple_of(16),
+        "Ciphertext is not a multiple of the block size"
+    );
+
+    let cipher = Aes256::new(GenericArray::from_slice(preimage));
+    let mut previous = iv.as_slice();
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    for chunk in ciphertext.chunks(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+        plaintext.extend(block.iter().zip(previous).map(|(b, p)| b ^ p));
+        previous = chunk;
+    }
+
+    let padding = *plaintext.last().unwrap() as usize;
+    ensure!(
+        (1..=16).contains(&padding)
+            && plaintext[plaintext.len() - padding..]
+                .iter()
+                .all(|b| *b as usize == padding),
+        "Invalid padding, is the preimage correct?"
+    );
+    plaintext.truncate(plaintext.len() - padding);
+    String::from_utf8(plaintext).map_err(|e| anyhow!("Plaintext is not valid UTF-8: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aes(ciphertext: &str, iv: &str) -> SuccessAction {
+        SuccessAction::Aes {
+            description: "Your voucher".to_string(),
+            ciphertext: ciphertext.to_string(),
+            iv: iv.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_aes_success_action() {
+        let action = aes(
+            "TBv0yZLEM2jegFqBqLR1LWW329kcnCHNch7LeoPuTh8=",
+            "AAECAwQFBgcICQoLDA0ODw==",
+        );
+        validate_success_action(&action, "https://site.com/cb").unwrap();
+
+        assert_eq!(
+            process_success_action(action.clone(), &[0x42; 32]),
+            SuccessActionResult::Aes {
+                description: "Your voucher".to_string(),
+                plaintext: "Your voucher code is 1234-5678".to_string(),
+            }
+        );
+        assert!(matches!(
+            process_success_action(action, &[0x43; 32]),
+            SuccessActionResult::AesFailed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_validate_success_action() {
+        let url = |url: &str| SuccessAction::Url {
+            description: "Receipt".to_string(),
+            url: url.to_string(),
+        };
+        assert!(
+            validate_success_action(&url("https://site.com/r/1"), "https://site.com/cb").is_ok()
+        );
+        assert!(
+            validate_success_action(&url("https://evil.com/r/1"), "https://site.com/cb").is_err()
+        );
+
+        let message = SuccessAction::Message {
+            message: "x".repeat(MAX_TEXT_LEN + 1),
+        };
+        assert!(validate_success_action(&message, "https://site.com/cb").is_err());
+        assert!(validate_success_action(&aes("AAAA", "AAAA"), "https://site.com/cb").is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! A LNURL-pay (LUD-06) and Lightning Address (LUD-16) server that
+//! mints the invoices on the node.
+//!
+//! `LnurlPayService` is a `tower::Service` that can be embedded in
+//! any hyper based server, or run on its own with
+//! `LnurlPayService::serve`. It serves two endpoints:
+//!
+//! - `/.well-known/lnurlp/<user>`: the pay request for the Lightning
+//!   Address `<user>@<domain>`.
+//! - `/lnurlp/<user>/callback?amount=<msat>[&comment=<text>]`: mints
+//!   an invoice committing to the metadata of the pay request.
+
+use super::models::{OkResponse, PayRequestCallbackResponse, PayRequestResponse};
+use crate::node::ClnClient;
+use crate::pb::cln::{amount_or_any, Amount, AmountOrAny, InvoiceRequest};
+use anyhow::{anyhow, Result};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::{debug, info};
+use reqwest::Url;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+const WELL_KNOWN_PREFIX: &str = "/.well-known/lnurlp/";
+const CALLBACK_PREFIX: &str = "/lnurlp/";
+const CALLBACK_SUFFIX: &str = "/callback";
+
+/// What the server accepts and how it presents itself.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// The public URL of the server, e.g., `https://example.com`.
+    base_url: String,
+    /// The domain of the Lightning Addresses, taken from `base_url`.
+    domain: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    /// LUD-12: the maximum length of comments, 0 to refuse comments.
+    pub comment_allowed: u64,
+    /// The users to serve. If empty, any user name is served.
+    pub users: Vec<String>,
+}
+
+impl ServerConfig {
+    /// Serve payments of 1 sat up to 1M sat, without comments, for
+    /// any user name.
+    pub fn new(base_url: &str) -> Result<Self> {
+        let domain = Url::parse(base_url)?
+            .host_str()
+            .ok_or_else(|| anyhow!("Base URL {} has no domain", base_url))?
+            .to_string();
+        Ok(ServerConfig {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            domain,
+            min_sendable: 1_000,
+            max_sendable: 1_000_000_000,
+            comment_allowed: 0,
+            users: vec![],
+        })
+    }
+
+    pub fn with_sendable(mut self, min_msat: u64, max_msat: u64) -> Self {
+        self.min_sendable = min_msat;
+        self.max_sendable = max_msat;
+        self
+    }
+
+    pub fn with_comment_allowed(mut self, length: u64) -> Self {
+        self.comment_allowed = length;
+        self
+    }
+
+    pub fn with_users(mut self, users: Vec<String>) -> Self {
+        self.users = users.into_iter().map(|u| u.to_lowercase()).collect();
+        self
+    }
+
+    /// The metadata of the pay request for `user`. The invoices
+    /// commit to its hash, so it must not change between the pay
+    /// request and the callback.
+    fn metadata(&self, user: &str) -> String {
+        let address = format!("{}@{}", user, self.domain);
+        serde_json::json!([
+            ["text/plain", format!("Payment to {}", address)],
+            ["text/identifier", address],
+        ])
+        .to_string()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ServerError {
+    NotFound(String),
+    BadRequest(String),
+    Node(String),
+}
+
+impl ServerError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ServerError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServerError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ServerError::Node(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn reason(self) -> String {
+        match self {
+            ServerError::NotFound(r) | ServerError::BadRequest(r) | ServerError::Node(r) => r,
+        }
+    }
+}
+
+/// Serves the pay requests of Lightning Addresses on `config.domain`
+/// with invoices from `node`.
+#[derive(Clone)]
+pub struct LnurlPayService {
+    node: ClnClient,
+    config: Arc<ServerConfig>,
+}
+
+impl LnurlPayService {
+    pub fn new(node: ClnClient, config: ServerConfig) -> Self {
+        LnurlPayService {
+            node,
+            config: Arc::new(config),
+        }
+    }
+
+    /// Serve HTTP requests on `addr` until the future is dropped.
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let service = self.clone();
+            async move { Ok::<_, Infallible>(service) }
+        });
+        info!("Serving LNURL-pay requests on {}", addr);
+        hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+        Ok(())
+    }
+
+    fn check_user(&self, user: &str) -> Result<String, ServerError> {
+        let user = user.to_lowercase();
+        // LUD-16 restricts user names to these characters.
+        let valid = !user.is_empty()
+            && user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c));
+        if !valid || !(self.config.users.is_empty() || self.config.users.contains(&user)) {
+            return Err(ServerError::NotFound(format!("Unknown user {}", user)));
+        }
+        Ok(user)
+    }
+
+    /// The pay request for the Lightning Address of `user`.
+    pub fn pay_request(&self, user: &str) -> Result<PayRequestResponse, ServerError> {
+        let user = self.check_user(user)?;
+        let config = &self.config;
+        Ok(PayRequestResponse {
+            callback: format!(
+                "{}{}{}{}",
+                config.base_url, CALLBACK_PREFIX, user, CALLBACK_SUFFIX
+            ),
+            max_sendable: config.max_sendable,
+            min_sendable: config.min_sendable,
+            tag: "payRequest".to_string(),
+            metadata: config.metadata(&user),
+            comment_allowed: Some(config.comment_allowed).filter(|c| *c > 0),
+            payer_data: None,
+        })
+    }
+
+    /// Mint an invoice for `amount_msat` to `user`. The comment, if
+    /// any, is stored in the label of the invoice.
+    pub async fn callback(
+        &self,
+        user: &str,
+        amount_msat: u64,
+        comment: Option<&str>,
+    ) -> Result<PayRequestCallbackResponse, ServerError> {
+        let user = self.check_user(user)?;
+        let config = &self.config;
+        if amount_msat < config.min_sendable || amount_msat > config.max_sendable {
+            return Err(ServerError::BadRequest(format!(
+                "Amount must be between {} and {}",
+                config.min_sendable, config.max_sendable
+            )));
+        }
+        let comment = comment.filter(|c| !c.is_empty());
+        if let Some(comment) = comment {
+            if comment.chars().count() as u64 > config.comment_allowed {
+                return Err(ServerError::BadRequest(format!(
+                    "Comment must be {} characters or less",
+                    config.comment_allowed
+                )));
+            }
+        }
+
+        let label = invoice_label(&user, comment);
+
+        let invoice = self
+            .node
+            .clone()
+            .invoice(InvoiceRequest {
+                amount_msat: Some(AmountOrAny {
+                    value: Some(amount_or_any::Value::Amount(Amount { msat: amount_msat })),
+                }),
+                label,
+                // The invoice only carries the hash of the metadata.
+                description: config.metadata(&user),
+                deschashonly: Some(true),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| ServerError::Node(e.message().to_string()))?
+            .into_inner();
+
+        Ok(PayRequestCallbackResponse {
+            pr: invoice.bolt11,
+            routes: vec![],
+            success_action: None,
+            verify: None,
+        })
+    }
+
+    async fn handle(self, request: Request<Body>) -> Response<Body> {
+        debug!("Handling LNURL-pay request {}", request.uri());
+        let path = request.uri().path().to_string();
+        let query: Vec<(String, String)> = request
+            .uri()
+            .query()
+            .map(|q| {
+                url::form_urlencoded::parse(q.as_bytes())
+                    .into_owned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let param = |name: &str| {
+            query
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+
+        let result = if request.method() != Method::GET {
+            Err(ServerError::BadRequest(
+                "Only GET requests are supported".to_string(),
+            ))
+        } else if let Some(user) = path.strip_prefix(WELL_KNOWN_PREFIX) {
+            self.pay_request(user).map(|r| json(&r))
+        } else if let Some(user) = path
+            .strip_prefix(CALLBACK_PREFIX)
+            .and_then(|p| p.strip_suffix(CALLBACK_SUFFIX))
+        {
+            match param("amount").map(|a| a.parse::<u64>()) {
+                Some(Ok(amount)) => self
+                    .callback(user, amount, param("comment"))
+                    .await
+                    .map(|r| json(&r)),
+                _ => Err(ServerError::BadRequest(
+                    "Missing or invalid amount".to_string(),
+                )),
+            }
+        } else {
+            Err(ServerError::NotFound(format!("Nothing at {}", path)))
+        };
+
+        result.unwrap_or_else(|e| {
+            let status = e.status();
+            let mut response = json(&OkResponse {
+                status: "ERROR".to_string(),
+                reason: Some(e.reason()),
+            });
+            *response.status_mut() = status;
+            response
+        })
+    }
+}
+
+/// The label of an invoice minted for `user`. Labels must be unique,
+/// and two requests can arrive within the same millisecond, so the
+/// timestamp is followed by a random suffix.
+fn invoice_label(user: &str, comment: Option<&str>) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let nonce: u64 = rand::random();
+    match comment {
+        Some(c) => format!("lnurlp-{}-{}-{:016x}: {}", user, now, nonce, c),
+        None => format!("lnurlp-{}-{}-{:016x}", user, now, nonce),
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::from(serde_json::to_vec(value).unwrap_or_default()))
+        .unwrap()
+}
+
+impl tower::Service<Request<Body>> for LnurlPayService {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move { Ok(service.handle(request).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::{Nobody, TlsConfigProvider};
+    use crate::node::service::AuthLayer;
+    use tower::Service;
+
+    /// A service whose node is never reached.
+    fn service(config: ServerConfig) -> LnurlPayService {
+        let key = Nobody::default().tls_config().private_key.unwrap();
+        let channel = tonic::transport::Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+        let auth = tower::Layer::layer(&AuthLayer::new(key, String::new()).unwrap(), channel);
+        LnurlPayService::new(ClnClient::new(auth), config)
+    }
+
+    async fn get(service: &mut LnurlPayService, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = service.call(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_pay_request() {
+        let config = ServerConfig::new("https://example.com/")
+            .unwrap()
+            .with_comment_allowed(20)
+            .with_users(vec!["Alice".to_string()]);
+        let mut service = service(config);
+
+        let (status, body) = get(&mut service, "/.well-known/lnurlp/alice").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["tag"], "payRequest");
+        assert_eq!(
+            body["callback"],
+            "https://example.com/lnurlp/alice/callback"
+        );
+        assert_eq!(body["commentAllowed"], 20);
+        let response: PayRequestResponse = serde_json::from_value(body).unwrap();
+        let metadata: Vec<Vec<String>> = serde_json::from_str(&response.metadata).unwrap();
+        assert_eq!(metadata[1], vec!["text/identifier", "alice@example.com"]);
+
+        let (status, body) = get(&mut service, "/.well-known/lnurlp/bob").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["status"], "ERROR");
+        let (status, _) = get(&mut service, "/somewhere/else").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_callback_validation() {
+        let config = ServerConfig::new("https://example.com")
+            .unwrap()
+            .with_sendable(1_000, 10_000)
+            .with_comment_allowed(5);
+        let mut service = service(config);
+
+        for uri in [
+            "/lnurlp/alice/callback",
+            "/lnurlp/alice/callback?amount=abc",
+            "/lnurlp/alice/callback?amount=999",
+            "/lnurlp/alice/callback?amount=10001",
+            "/lnurlp/alice/callback?amount=1000&comment=toolong",
+        ] {
+            let (status, body) = get(&mut service, uri).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(body["status"], "ERROR");
+        }
+
+        let (status, _) = get(&mut service, "/lnurlp/al%20ice/callback?amount=1000").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_invoice_label() {
+        let labels: std::collections::HashSet<String> =
+            (0..100).map(|_| invoice_label("alice", None)).collect();
+        assert_eq!(labels.len(), 100);
+        assert!(labels.iter().all(|l| l.starts_with("lnurlp-alice-")));
+        assert!(invoice_label("alice", Some("thanks")).ends_with(": thanks"));
+    }
+}
@@ -16,6 +16,32 @@ pub fn build_withdraw_request_callback_url(
     Ok(url.to_string())
 }
 
+// Validates the amount against the range accepted by the service
+pub fn ensure_amount_is_withdrawable(
+    withdraw_request_response: &WithdrawRequestResponse,
+    amount_msats: u64,
+) -> Result<()> {
+    if withdraw_request_response.tag != "withdrawRequest" {
+        return Err(anyhow!("Expected tag to say 'withdrawRequest'"));
+    }
+
+    if amount_msats < withdraw_request_response.min_withdrawable {
+        return Err(anyhow!(
+            "Amount must be {} or greater",
+            withdraw_request_response.min_withdrawable
+        ));
+    }
+
+    if amount_msats > withdraw_request_response.max_withdrawable {
+        return Err(anyhow!(
+            "Amount must be {} or less",
+            withdraw_request_response.max_withdrawable
+        ));
+    }
+
+    Ok(())
+}
+
 fn convert_value_field_from_str_to_u64(
     value: &mut Map<String, Value>,
     field_name: &str,
@@ -96,6 +122,25 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_ensure_amount_is_withdrawable() {
+        let withdraw_request = parse_withdraw_request_response_from_url("https://cipherpunk.com?tag=withdrawRequest&callback=cipherpunk.com&k1=42&minWithdrawable=1000&maxWithdrawable=5000&defaultDescription=").unwrap();
+
+        assert!(ensure_amount_is_withdrawable(&withdraw_request, 1000).is_ok());
+        assert!(ensure_amount_is_withdrawable(&withdraw_request, 5000).is_ok());
+        assert!(ensure_amount_is_withdrawable(&withdraw_request, 999)
+            .unwrap_err()
+            .to_string()
+            .contains("1000 or greater"));
+        assert!(ensure_amount_is_withdrawable(&withdraw_request, 5001)
+            .unwrap_err()
+            .to_string()
+            .contains("5000 or less"));
+
+        let pay_request = parse_withdraw_request_response_from_url("https://cipherpunk.com?tag=payRequest&callback=cipherpunk.com&k1=42&minWithdrawable=1000&maxWithdrawable=5000&defaultDescription=").unwrap();
+        assert!(ensure_amount_is_withdrawable(&pay_request, 1000).is_err());
+    }
+
     #[test]
     fn test_parse_withdraw_request_response_from_url() {
         let withdraw_request = parse_withdraw_request_response_from_url("https://cipherpunk.com?tag=withdraw&callback=cipherpunk.com&k1=42&minWithdrawable=1&maxWithdrawable=100&defaultDescription=");
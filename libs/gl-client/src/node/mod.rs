@@ -81,6 +81,15 @@ impl Node {
     where
         C: GrpcClient,
     {
+        let chan = self.channel(node_uri)?;
+        let chan = ServiceBuilder::new()
+            .layer(self.auth_layer()?)
+            .service(chan);
+
+        Ok(C::new_with_inner(chan, self.decoding_message_size()))
+    }
+
+    fn channel(&self, node_uri: String) -> Result<Channel> {
         let node_uri = Uri::from_maybe_shared(node_uri)?;
         info!("Connecting to node at {}", node_uri);
 
@@ -103,15 +112,6 @@ impl Node {
             tls
         };
 
-        let layer = match tls.private_key {
-            Some(k) => service::AuthLayer::new(k, self.rune.clone())?,
-            None => {
-                return Err(anyhow!(
-                    "Cannot connect a node::Client without first configuring its identity"
-                ))
-            }
-        };
-
         let chan = tonic::transport::Endpoint::from_shared(node_uri.to_string())?
             .tls_config(tls.inner)?
             .tcp_keepalive(Some(crate::TCP_KEEPALIVE))
@@ -119,19 +119,26 @@ impl Node {
             .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
             .keep_alive_while_idle(true)
             .connect_lazy();
-        let chan = ServiceBuilder::new().layer(layer).service(chan);
+        Ok(chan)
+    }
 
-        let size = self
-            .max_decoding_message_size
-            .unwrap_or(DEFAULT_MAX_DECODING_MESSAGE_SIZE);
+    fn auth_layer(&self) -> Result<service::AuthLayer> {
+        match &self.tls.private_key {
+            Some(k) => service::AuthLayer::new(k.clone(), self.rune.clone()),
+            None => Err(anyhow!(
+                "Cannot connect a node::Client without first configuring its identity"
+            )),
+        }
+    }
 
-        Ok(C::new_with_inner(chan, size))
+    fn decoding_message_size(&self) -> usize {
+        self.max_decoding_message_size
+            .unwrap_or(DEFAULT_MAX_DECODING_MESSAGE_SIZE)
     }
 
-    pub async fn schedule_with_uri<C>(self, scheduler_uri: String) -> Result<C>
-    where
-        C: GrpcClient,
-    {
+    /// Asks the scheduler at `scheduler_uri` to schedule the node,
+    /// and returns a channel to it.
+    async fn schedule_channel(&self, scheduler_uri: String) -> Result<Channel> {
         debug!(
             "Contacting scheduler at {} to get the node address",
             scheduler_uri
@@ -152,7 +159,56 @@ impl Node {
 
         debug!("Node scheduled at {}", node_info.grpc_uri);
 
-        self.connect(node_info.grpc_uri).await
+        self.channel(node_info.grpc_uri)
+    }
+
+    pub async fn schedule_with_uri<C>(self, scheduler_uri: String) -> Result<C>
+    where
+        C: GrpcClient,
+    {
+        let chan = self.schedule_channel(scheduler_uri).await?;
+        let chan = ServiceBuilder::new()
+            .layer(self.auth_layer()?)
+            .service(chan);
+
+        Ok(C::new_with_inner(chan, self.decoding_message_size()))
+    }
+
+    /// Like `schedule_with_uri`, but when the node goes offline the
+    /// client schedules it again, reconnects and retries the calls
+    /// `policy` allows.
+    pub async fn schedule_reconnecting_with_uri<C>(
+        self,
+        scheduler_uri: String,
+        policy: reconnect::RetryPolicy,
+    ) -> Result<C>
+    where
+        C: GrpcClient,
+    {
+        let chan = self.schedule_channel(scheduler_uri.clone()).await?;
+        let node = self.clone();
+        let reconnect = reconnect::ReconnectLayer::new(
+            move || {
+                let node = node.clone();
+                let scheduler_uri = scheduler_uri.clone();
+                async move { node.schedule_channel(scheduler_uri).await }
+            },
+            policy,
+        );
+        let chan = ServiceBuilder::new()
+            .layer(self.auth_layer()?)
+            .layer(reconnect)
+            .service(chan);
+
+        Ok(C::new_with_inner(chan, self.decoding_message_size()))
+    }
+
+    pub async fn schedule_reconnecting<C>(self, policy: reconnect::RetryPolicy) -> Result<C>
+    where
+        C: GrpcClient,
+    {
+        let uri = utils::scheduler_uri();
+        self.schedule_reconnecting_with_uri(uri, policy).await
     }
 
     pub async fn schedule<C>(self) -> Result<C>
@@ -165,7 +221,9 @@ impl Node {
 }
 
 mod generic;
+pub mod reconnect;
 pub mod service;
+pub mod stream;
 pub use generic::GenericClient;
 
 mod stasher {
@@ -0,0 +1,406 @@
+//! Reschedule the node and retry calls when it goes offline.
+//!
+//! Nodes are descheduled when idle, and calls to them then fail
+//! with a transport error or `Unavailable`. The `ReconnectService`
+//! sits between the `AuthService` and the channel: it asks the
+//! scheduler to schedule the node again, switches to the new
+//! `grpc_uri`, and retries the call if it is safe to do so.
+//!
+//! A call is retried if we could not connect to the node, so it
+//! never left the client, or if the node answered `Unavailable` and
+//! the `RetryPolicy` allows it. Calls that fail in any other way may
+//! have reached the node, and are not retried.
+
+use anyhow::Result;
+use http::{Request, Response};
+use http_body::Body as _;
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tonic::body::BoxBody;
+use tonic::transport::{Body, Channel};
+use tower::{Layer, Service, ServiceExt};
+
+/// Calls that only read from the node, and can be sent again after
+/// the node answered `Unavailable`.
+pub const RETRYABLE: &[&str] = &[
+    "/cln.Node/Getinfo",
+    "/cln.Node/ListPeers",
+    "/cln.Node/ListFunds",
+    "/cln.Node/ListChannels",
+    "/cln.Node/CheckMessage",
+    "/cln.Node/ListDatastore",
+    "/cln.Node/ListInvoices",
+    "/cln.Node/ListSendPays",
+    "/cln.Node/ListTransactions",
+    "/cln.Node/ListNodes",
+    "/cln.Node/ListPeerChannels",
+    "/cln.Node/ListClosedChannels",
+    "/cln.Node/DecodePay",
+    "/cln.Node/Decode",
+    "/cln.Node/Feerates",
+    "/cln.Node/GetRoute",
+    "/cln.Node/ListForwards",
+    "/cln.Node/ListPays",
+    "/cln.Node/ListHtlcs",
+];
+
+/// Losing the connection is the expected outcome of stopping the
+/// node, so it must not schedule the node again.
+const STOP: &str = "/cln.Node/Stop";
+
+/// Which calls are retried after the node answered `Unavailable`,
+/// and how often.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    retryable: HashSet<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            retryable: RETRYABLE.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retry the call with the gRPC `path`, e.g., `/cln.Node/Invoice`,
+    /// after the node answered `Unavailable`. Only use this for calls
+    /// that are safe to send twice.
+    pub fn retryable(mut self, path: impl Into<String>) -> Self {
+        self.retryable.insert(path.into());
+        self
+    }
+
+    /// Don't retry the call with the gRPC `path`, even if it is in
+    /// `RETRYABLE`.
+    pub fn non_retryable(mut self, path: &str) -> Self {
+        self.retryable.remove(path);
+        self
+    }
+
+    pub fn is_retryable(&self, path: &str) -> bool {
+        self.retryable.contains(path)
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+type Connector =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<Channel>> + Send>> + Send + Sync>;
+
+/// Wraps a channel in a `ReconnectService` that calls `connector` to
+/// schedule the node and connect to it again when it is offline.
+#[derive(Clone)]
+pub struct ReconnectLayer {
+    connector: Connector,
+    policy: RetryPolicy,
+}
+
+impl ReconnectLayer {
+    pub fn new<F, Fut>(connector: F, policy: RetryPolicy) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Channel>> + Send + 'static,
+    {
+        ReconnectLayer {
+            connector: Arc::new(move || Box::pin(connector())),
+            policy,
+        }
+    }
+}
+
+impl Layer<Channel> for ReconnectLayer {
+    type Service = ReconnectService;
+
+    fn layer(&self, inner: Channel) -> Self::Service {
+        ReconnectService {
+            current: Arc::new(Mutex::new(Current {
+                channel: inner.clone(),
+                generation: 0,
+            })),
+            inner,
+            reconnect: Some((self.connector.clone(), self.policy.clone())),
+        }
+    }
+}
+
+struct Current {
+    channel: Channel,
+    /// Incremented on each reconnect, so that calls failing at the
+    /// same time reschedule only once.
+    generation: u64,
+}
+
+#[derive(Clone)]
+pub struct ReconnectService {
+    current: Arc<Mutex<Current>>,
+    /// The channel used when not reconnecting.
+    inner: Channel,
+    reconnect: Option<(Connector, RetryPolicy)>,
+}
+
+impl ReconnectService {
+    /// A service that just passes calls to `channel`.
+    pub fn direct(channel: Channel) -> Self {
+        ReconnectService {
+            current: Arc::new(Mutex::new(Current {
+                channel: channel.clone(),
+                generation: 0,
+            })),
+            inner: channel,
+            reconnect: None,
+        }
+    }
+}
+
+impl Service<Request<BoxBody>> for ReconnectService {
+    type Response = Response<Body>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.reconnect {
+            // Calls wait for the current channel to be ready.
+            Some(_) => Poll::Ready(Ok(())),
+            None => self.inner.poll_ready(cx).map_err(Into::into),
+        }
+    }
+
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
+        let (connector, policy) = match self.reconnect.clone() {
+            Some(r) => r,
+            None => {
+                // See `AuthService::call` on why we swap the channel.
+                let clone = self.inner.clone();
+                let mut inner = std::mem::replace(&mut self.inner, clone);
+                return Box::pin(async move { Ok(inner.call(request).await?) });
+            }
+        };
+        let current = self.current.clone();
+
+        Box::pin(async move {
+            let path = request.uri().path().to_string();
+            // Streamed requests cannot be replayed.
+            if path == STOP || request.headers().contains_key(super::stream::STREAM_HEADER) {
+                let mut channel = current.lock().await.channel.clone();
+                return Ok(channel.ready().await?.call(request).await?);
+            }
+            let retryable = policy.is_retryable(&path);
+            let (parts, mut body) = request.into_parts();
+            let data = body.data().await.transpose()?.unwrap_or_default();
+
+            let mut attempt = 1;
+            loop {
+                let (mut channel, generation) = {
+                    let c = current.lock().await;
+                    (c.channel.clone(), c.generation)
+                };
+                let mut request = Request::builder()
+                    .method(parts.method.clone())
+                    .uri(parts.uri.clone())
+                    .version(parts.version)
+                    .body(BoxBody::from(super::stasher::StashBody::new(data.clone())))?;
+                *request.headers_mut() = parts.headers.clone();
+
+                let res = match channel.ready().await {
+                    Ok(c) => c.call(request).await,
+                    Err(e) => Err(e),
+                };
+                let retry = match &res {
+                    // We never reached the node, so it is safe to
+                    // send the request again.
+                    Err(e) if is_connect_error(e) => true,
+                    // The call may have been processed.
+                    Err(_) => false,
+                    Ok(r) if is_unavailable(r) => retryable,
+                    Ok(_) => return Ok(res?),
+                };
+
+                debug!("Node is offline when calling {}, rescheduling", path);
+                if let Err(e) = reconnect(&current, &connector, generation).await {
+                    warn!("Could not reschedule the node: {}", e);
+                }
+                if !retry || attempt >= policy.max_attempts {
+                    return Ok(res?);
+                }
+                tokio::time::sleep(policy.backoff(attempt - 1)).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// Whether the node answered with `Unavailable` right away.
+fn is_unavailable(response: &Response<Body>) -> bool {
+    let code = tonic::Code::Unavailable as i32;
+    response
+        .headers()
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == code.to_string())
+}
+
+/// Whether `e` was caused by failing to connect to the node, i.e.,
+/// the request was never sent.
+fn is_connect_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if e.downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_connect())
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+async fn reconnect(current: &Mutex<Current>, connector: &Connector, generation: u64) -> Result<()> {
+    let mut c = current.lock().await;
+    if c.generation != generation {
+        // Someone else reconnected already.
+        return Ok(());
+    }
+    c.channel = connector().await?;
+    c.generation += 1;
+    debug!("Reconnected to the node");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable("/cln.Node/Getinfo"));
+        assert!(!policy.is_retryable("/cln.Node/Pay"));
+        assert!(!policy.is_retryable("/cln.Node/Invoice"));
+
+        let policy = policy
+            .retryable("/cln.Node/Invoice")
+            .non_retryable("/cln.Node/Getinfo");
+        assert!(policy.is_retryable("/cln.Node/Invoice"));
+        assert!(!policy.is_retryable("/cln.Node/Getinfo"));
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    /// A node that answers the first `unavailable` calls with
+    /// `Unavailable`, and counts the calls it gets.
+    async fn serve(unavailable: usize) -> (Channel, Arc<std::sync::Mutex<usize>>) {
+        let calls = Arc::new(std::sync::Mutex::new(0));
+        let make = {
+            let calls = calls.clone();
+            hyper::service::make_service_fn(move |_| {
+                let calls = calls.clone();
+                async move {
+                    Ok::<_, std::convert::Infallible>(tower::service_fn(
+                        move |_req: Request<hyper::Body>| {
+                            let mut calls = calls.lock().unwrap();
+                            *calls += 1;
+                            let status = match *calls <= unavailable {
+                                true => tonic::Code::Unavailable,
+                                false => tonic::Code::Ok,
+                            };
+                            let res = Response::builder()
+                                .header("content-type", "application/grpc")
+                                .header("grpc-status", (status as i32).to_string())
+                                .body(hyper::Body::empty())
+                                .unwrap();
+                            async move { Ok::<_, std::convert::Infallible>(res) }
+                        },
+                    ))
+                }
+            })
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            hyper::Server::from_tcp(listener)
+                .unwrap()
+                .http2_only(true)
+                .serve(make),
+        );
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect_lazy();
+        (channel, calls)
+    }
+
+    /// Call `path` on a `ReconnectService` that starts out with
+    /// `channel` and reconnects to `node`, and return the gRPC status.
+    async fn call(channel: Channel, node: Channel, path: &str) -> String {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut svc = ReconnectLayer::new(
+            move || {
+                let node = node.clone();
+                async move { Ok(node) }
+            },
+            policy,
+        )
+        .layer(channel);
+        let request = Request::builder()
+            .uri(format!("http://localhost{}", path))
+            .body(BoxBody::from(super::super::stasher::StashBody::new(
+                bytes::Bytes::from_static(b"request"),
+            )))
+            .unwrap();
+        let res = svc.ready().await.unwrap().call(request).await.unwrap();
+        res.headers()["grpc-status"].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_retry_unavailable() {
+        let unavailable = (tonic::Code::Unavailable as i32).to_string();
+
+        // Reads are retried on the new node.
+        let (node, calls) = serve(1).await;
+        assert_eq!(call(node.clone(), node, "/cln.Node/Getinfo").await, "0");
+        assert_eq!(*calls.lock().unwrap(), 2);
+
+        // Anything else may have been processed, so it isn't.
+        let (node, calls) = serve(1).await;
+        assert_eq!(call(node.clone(), node, "/cln.Node/Pay").await, unavailable);
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_connect_error() {
+        // Nothing listens here.
+        let offline = tonic::transport::Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+
+        // The request never left, so even a payment is retried.
+        let (node, calls) = serve(0).await;
+        assert_eq!(call(offline, node, "/cln.Node/Pay").await, "0");
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+}
@@ -2,14 +2,19 @@ use anyhow::{anyhow, Result};
 use http::{Request, Response};
 use log::{debug, trace};
 use rustls_pemfile as pemfile;
+use std::collections::HashSet;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use tonic::body::BoxBody;
 use tonic::transport::Body;
 use tonic::transport::Channel;
 use tower::{Layer, Service};
 
+use super::reconnect::ReconnectService;
+use super::stream;
+
 use ring::signature::KeyPair;
 use ring::{
     rand,
@@ -19,6 +24,7 @@ use ring::{
 pub struct AuthLayer {
     key: Vec<u8>,
     rune: String,
+    streaming: Arc<HashSet<String>>,
 }
 
 impl AuthLayer {
@@ -44,7 +50,23 @@ impl AuthLayer {
             Err(e) => return Err(anyhow!("Could not decide keypair from PEM string: {}", e)),
         };
 
-        Ok(AuthLayer { key, rune })
+        let streaming = stream::STREAMING_METHODS
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        Ok(AuthLayer {
+            key,
+            rune,
+            streaming: Arc::new(streaming),
+        })
+    }
+
+    /// Sign requests to the method at `path` message by message, as
+    /// needed for client-streaming and bidirectional calls. See
+    /// [`super::stream`].
+    pub fn with_streaming_method(mut self, path: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.streaming).insert(path.into());
+        self
     }
 }
 
@@ -52,10 +74,19 @@ impl Layer<Channel> for AuthLayer {
     type Service = AuthService;
 
     fn layer(&self, inner: Channel) -> Self::Service {
+        self.layer(ReconnectService::direct(inner))
+    }
+}
+
+impl Layer<ReconnectService> for AuthLayer {
+    type Service = AuthService;
+
+    fn layer(&self, inner: ReconnectService) -> Self::Service {
         AuthService {
             key: self.key.clone(),
             inner,
             rune: self.rune.clone(),
+            streaming: self.streaming.clone(),
         }
     }
 }
@@ -64,8 +95,9 @@ impl Layer<Channel> for AuthLayer {
 pub struct AuthService {
     // PKCS#8 formatted private key
     key: Vec<u8>,
-    inner: Channel,
+    inner: ReconnectService,
     rune: String,
+    streaming: Arc<HashSet<String>>,
 }
 impl Service<Request<BoxBody>> for AuthService {
     type Response = Response<Body>;
@@ -93,6 +125,7 @@ impl Service<Request<BoxBody>> for AuthService {
         .unwrap();
 
         let rune = self.rune.clone();
+        let streaming = self.streaming.contains(request.uri().path());
 
         Box::pin(async move {
             use bytes::BufMut;
@@ -107,6 +140,32 @@ impl Service<Request<BoxBody>> for AuthService {
 
             let (mut parts, mut body) = request.into_parts();
 
+            if streaming {
+                // The messages are signed as they are sent, see
+                // `super::stream`.
+                let time: u64 = time.try_into()?;
+                let mut ts = vec![];
+                ts.put_u64(time);
+                let pubkey = engine.encode(keypair.public_key().as_ref());
+                parts
+                    .headers
+                    .insert("glauthpubkey", pubkey.parse().unwrap());
+                parts
+                    .headers
+                    .insert("glts", engine.encode(ts).parse().unwrap());
+                parts
+                    .headers
+                    .insert("glrune", rune.parse().expect("Could not parse rune"));
+                parts
+                    .headers
+                    .insert(stream::STREAM_HEADER, "1".parse().unwrap());
+
+                let body = signed_stream(body, keypair, time);
+                let request = Request::from_parts(parts, body);
+                debug!("Sending streamed request {:?}", request);
+                return inner.call(request).await;
+            }
+
             let data = body.data().await.unwrap().unwrap();
 
             // Copy used to create the signature (payload + associated data)
@@ -156,3 +215,52 @@ impl Service<Request<BoxBody>> for AuthService {
         })
     }
 }
+
+/// Prefixes each message of `body` with its signature.
+fn signed_stream(body: BoxBody, keypair: EcdsaKeyPair, timestamp: u64) -> BoxBody {
+    use http_body::Body as _;
+    hyper::Body::wrap_stream(signed_messages(body, keypair, timestamp))
+        .map_err(|e| tonic::Status::from_error(Box::new(e)))
+        .boxed_unsync()
+}
+
+fn signed_messages(
+    mut body: BoxBody,
+    keypair: EcdsaKeyPair,
+    timestamp: u64,
+) -> impl futures::Stream<Item = Result<bytes::Bytes, tonic::Status>> + Send {
+    use bytes::BufMut;
+    use tonic::codegen::Body;
+
+    async_stream::try_stream! {
+        let rng = rand::SystemRandom::new();
+        let mut splitter = stream::MessageSplitter::default();
+        let mut seq = 0;
+        while let Some(data) = body.data().await {
+            for message in splitter.push(&data?) {
+                let sig = keypair
+                    .sign(&rng, &stream::signed_data(&message, timestamp, seq))
+                    .map_err(|_| tonic::Status::internal("could not sign message"))?;
+                seq += 1;
+
+                let mut envelope = bytes::BytesMut::with_capacity(sig.as_ref().len() + message.len());
+                envelope.put_slice(sig.as_ref());
+                envelope.put_slice(&message);
+                yield envelope.freeze();
+            }
+        }
+        if !splitter.is_empty() {
+            Err(tonic::Status::internal("body ends with a partial message"))?;
+        }
+
+        // Mark the end, so the node can tell if the stream was cut.
+        let marker = stream::end_marker(seq);
+        let sig = keypair
+            .sign(&rng, &stream::signed_data(&marker, timestamp, seq))
+            .map_err(|_| tonic::Status::internal("could not sign end marker"))?;
+        let mut envelope = bytes::BytesMut::with_capacity(sig.as_ref().len() + marker.len());
+        envelope.put_slice(sig.as_ref());
+        envelope.put_slice(&marker);
+        yield envelope.freeze();
+    }
+}
@@ -0,0 +1,318 @@
+//! Signing of streamed request bodies.
+//!
+//! Unary requests are signed as a whole, with the signature in the
+//! `glauthsig` header. That does not work for client-streaming and
+//! bidirectional calls, since the headers are sent before the
+//! messages. Instead the `AuthService` marks these requests with the
+//! `glstream` header, and prefixes each gRPC message in the body with
+//! a signature over the message, the timestamp of the request and the
+//! sequence number of the message:
+//!
+//! ```text
+//! signature (64 bytes) || gRPC message (5 byte prefix + payload)
+//! ```
+//!
+//! The signature covers `"glstream" || timestamp || seq || message`.
+//! After the last message the client sends a signed end marker with
+//! the number of messages, so that the node can tell a complete
+//! stream from one that was cut short:
+//!
+//! ```text
+//! signature (64 bytes) || 0xff || count (8 bytes)
+//! ```
+//!
+//! The marker is signed like a message at position `count`. Streams
+//! without it are rejected.
+//!
+//! Signatures of unary requests cover `message || timestamp`, and
+//! since a gRPC message starts with a compression flag of 0 or 1 the
+//! two can't be mistaken for one another. The node verifies and
+//! strips the signatures with an `EnvelopeDecoder` before handing the
+//! messages to the service, and passes the sequence number on to the
+//! signer in `PendingRequest::stream_seq`.
+
+use anyhow::{anyhow, ensure, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+
+/// Header marking a request whose messages are signed one by one.
+pub const STREAM_HEADER: &str = "glstream";
+
+/// Client-streaming and bidirectional methods of the node. Requests
+/// to these are signed message by message.
+pub const STREAMING_METHODS: &[&str] = &[];
+
+pub const SIGNATURE_LEN: usize = 64;
+const GRPC_PREFIX_LEN: usize = 5;
+
+/// First byte of the end marker. gRPC messages start with a
+/// compression flag of 0 or 1, so the two can't be confused.
+const END_FLAG: u8 = 0xff;
+const END_MARKER_LEN: usize = 9;
+
+/// Domain separation of the signatures of streamed messages.
+const SIGNATURE_TAG: &[u8] = b"glstream";
+
+/// The data the signature of the `seq`th message covers.
+pub fn signed_data(message: &[u8], timestamp: u64, seq: u64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(SIGNATURE_TAG.len() + 16 + message.len());
+    data.put_slice(SIGNATURE_TAG);
+    data.put_u64(timestamp);
+    data.put_u64(seq);
+    data.put_slice(message);
+    data
+}
+
+/// The end marker of a stream of `count` messages. Its signature
+/// covers `signed_data(&end_marker(count), timestamp, count)`.
+pub fn end_marker(count: u64) -> Vec<u8> {
+    let mut marker = Vec::with_capacity(END_MARKER_LEN);
+    marker.put_u8(END_FLAG);
+    marker.put_u64(count);
+    marker
+}
+
+/// Splits a body into gRPC messages, which may span or share data
+/// frames.
+#[derive(Default)]
+pub(crate) struct MessageSplitter {
+    buf: BytesMut,
+}
+
+impl MessageSplitter {
+    /// Adds `data` and returns the messages that are now complete.
+    pub(crate) fn push(&mut self, data: &[u8]) -> Vec<Bytes> {
+        self.buf.put_slice(data);
+        let mut messages = vec![];
+        while let Some(len) = message_len(&self.buf, 0) {
+            if self.buf.len() < len {
+                break;
+            }
+            messages.push(self.buf.split_to(len).freeze());
+        }
+        messages
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+/// The length of the gRPC message starting at `offset`, including its
+/// prefix, if the prefix is there.
+fn message_len(buf: &[u8], offset: usize) -> Option<usize> {
+    let mut prefix = buf.get(offset..offset + GRPC_PREFIX_LEN)?;
+    prefix.advance(1);
+    Some(GRPC_PREFIX_LEN + prefix.get_u32() as usize)
+}
+
+/// A message from a streamed request, with a verified signature.
+#[derive(Clone, Debug)]
+pub struct SignedMessage {
+    /// The gRPC message, including its prefix.
+    pub message: Bytes,
+    pub signature: Vec<u8>,
+    /// The timestamp of the request.
+    pub timestamp: u64,
+    /// The position of the message in the stream.
+    pub seq: u64,
+}
+
+/// Verifies and unwraps the signed messages of a streamed request.
+pub struct EnvelopeDecoder {
+    pubkey: Vec<u8>,
+    timestamp: u64,
+    seq: u64,
+    max_message_size: usize,
+    buf: BytesMut,
+    ended: bool,
+}
+
+impl EnvelopeDecoder {
+    pub fn new(pubkey: Vec<u8>, timestamp: u64, max_message_size: usize) -> Self {
+        EnvelopeDecoder {
+            pubkey,
+            timestamp,
+            seq: 0,
+            max_message_size,
+            buf: BytesMut::new(),
+            ended: false,
+        }
+    }
+
+    /// Adds `data` from the body and returns the messages that are
+    /// now complete. Fails if a signature does not match, so the
+    /// stream must be aborted.
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<SignedMessage>> {
+        self.buf.put_slice(data);
+        let mut messages = vec![];
+        loop {
+            ensure!(
+                !self.ended || self.buf.is_empty(),
+                "data after the end of the stream"
+            );
+            if self.buf.get(SIGNATURE_LEN) == Some(&END_FLAG) {
+                if self.buf.len() < SIGNATURE_LEN + END_MARKER_LEN {
+                    break;
+                }
+                let signature = self.buf.split_to(SIGNATURE_LEN);
+                let marker = self.buf.split_to(END_MARKER_LEN);
+                ensure!(
+                    marker[..] == end_marker(self.seq)[..],
+                    "stream ends after {} messages, but the marker disagrees",
+                    self.seq
+                );
+                self.verify(&marker, &signature)
+                    .map_err(|_| anyhow!("signature of the end marker does not match"))?;
+                self.ended = true;
+                continue;
+            }
+            let len = match message_len(&self.buf, SIGNATURE_LEN) {
+                Some(len) => len,
+                None => break,
+            };
+            ensure!(
+                len <= self.max_message_size,
+                "message {} exceeds size limit",
+                self.seq
+            );
+            if self.buf.len() < SIGNATURE_LEN + len {
+                break;
+            }
+            let signature = self.buf.split_to(SIGNATURE_LEN).to_vec();
+            let message = self.buf.split_to(len).freeze();
+            self.verify(&message, &signature)
+                .map_err(|_| anyhow!("signature of message {} does not match", self.seq))?;
+            messages.push(SignedMessage {
+                message,
+                signature,
+                timestamp: self.timestamp,
+                seq: self.seq,
+            });
+            self.seq += 1;
+        }
+        Ok(messages)
+    }
+
+    /// Verifies the signature of `data` at the current position.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<()> {
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &self.pubkey)
+            .verify(&signed_data(data, self.timestamp, self.seq), signature)
+            .map_err(|_| anyhow!("invalid signature"))
+    }
+
+    /// Checks that the body ended with the end marker, and not in the
+    /// middle of a message.
+    pub fn finish(&self) -> Result<()> {
+        ensure!(self.buf.is_empty(), "body ends with a partial message");
+        ensure!(self.ended, "body ends without the end marker");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    fn message(payload: &[u8]) -> Vec<u8> {
+        let mut m = vec![0];
+        m.put_u32(payload.len() as u32);
+        m.put_slice(payload);
+        m
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let mut body = message(b"hello");
+        body.extend(message(b""));
+        body.extend(message(b"world"));
+
+        let mut splitter = MessageSplitter::default();
+        let mut messages = splitter.push(&body[..3]);
+        assert!(messages.is_empty());
+        messages.extend(splitter.push(&body[3..13]));
+        messages.extend(splitter.push(&body[13..]));
+        assert!(splitter.is_empty());
+        assert_eq!(messages.len(), 3);
+        assert_eq!(&messages[2][..], &message(b"world")[..]);
+    }
+
+    #[test]
+    fn test_decode_envelopes() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let keypair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        let pubkey = keypair.public_key().as_ref().to_vec();
+
+        let messages = [message(b"first"), message(b"second")];
+        let mut body = vec![];
+        for (seq, m) in messages.iter().enumerate() {
+            let sig = keypair
+                .sign(&rng, &signed_data(m, 1000, seq as u64))
+                .unwrap();
+            body.extend_from_slice(sig.as_ref());
+            body.extend_from_slice(m);
+        }
+        let end = |count: u64| {
+            let marker = end_marker(count);
+            let sig = keypair
+                .sign(&rng, &signed_data(&marker, 1000, count))
+                .unwrap();
+            [sig.as_ref(), &marker[..]].concat()
+        };
+        let complete = [&body[..], &end(2)[..]].concat();
+
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        let mut decoded = decoder.push(&complete[..70]).unwrap();
+        decoded.extend(decoder.push(&complete[70..]).unwrap());
+        decoder.finish().unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(&decoded[1].message[..], &messages[1][..]);
+        assert_eq!((decoded[1].timestamp, decoded[1].seq), (1000, 1));
+
+        // Reordered messages fail, since the sequence number is signed.
+        let first_len = SIGNATURE_LEN + messages[0].len();
+        let mut reordered = body[first_len..].to_vec();
+        reordered.extend_from_slice(&body[..first_len]);
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        assert!(decoder.push(&reordered).is_err());
+
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        decoder.push(&body[..first_len + 10]).unwrap();
+        assert!(decoder.finish().is_err());
+
+        // A stream cut after a message, without the end marker or
+        // with a marker for a different count, fails.
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        decoder.push(&body).unwrap();
+        assert!(decoder.finish().is_err());
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        decoder.push(&body[..first_len]).unwrap();
+        assert!(decoder.push(&end(2)).is_err());
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        let mut forged = end(1);
+        forged[SIGNATURE_LEN + 8] = 2;
+        decoder.push(&body[..first_len]).unwrap();
+        assert!(decoder.push(&forged).is_err());
+
+        // Nothing may follow the end marker.
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), 1000, 1024);
+        assert!(decoder
+            .push(&[&complete[..], &messages[0][..]].concat())
+            .is_err());
+
+        let mut decoder = EnvelopeDecoder::new(pubkey, 1000, 8);
+        assert!(decoder.push(&body).is_err());
+    }
+
+    #[test]
+    fn test_signed_data() {
+        // Shifting the sequence number into the timestamp changes
+        // the signed data.
+        assert_ne!(signed_data(b"m", 1000, 1), signed_data(b"m", 1001, 0));
+        assert!(signed_data(b"m", 1000, 1).starts_with(b"glstream"));
+    }
+}
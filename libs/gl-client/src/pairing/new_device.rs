@@ -2,6 +2,7 @@ use super::PairingSessionData;
 use crate::{
     credentials::{Device, TlsConfigProvider},
     pb::scheduler::{pairing_client::PairingClient, PairDeviceRequest},
+    runes::RuneTemplate,
     tls::{self, TlsConfig},
 };
 use log::debug;
@@ -79,6 +80,13 @@ impl Client<Connected> {
         let device_id = hex::encode(device_cert.get_key_pair().public_key_raw());
         let csr = device_cert.serialize_request_pem()?;
 
+        // Restrictions may also name predefined rule sets, e.g.,
+        // `invoice-only,expires=30d`.
+        let restrictions = match restrictions.parse::<RuneTemplate>() {
+            Ok(t) => t.encode()?,
+            Err(_) => restrictions.to_string(),
+        };
+
         // Restrictions should always contain the pubkey field to bind them to
         // the private key of the device.
         let mut restriction = format!("pubkey={}", device_id.clone());
@@ -0,0 +1,471 @@
+//! Parse whatever a user pasted or scanned to pay someone.
+//!
+//! `PaymentInput::parse` classifies BIP-21 URIs, BOLT11 invoices,
+//! BOLT12 offers and invoices, LNURLs, lightning addresses, node ids
+//! and on-chain addresses, and checks that they are meant for the
+//! network of the node. `PaymentInput::pay` then makes the matching
+//! call on the node.
+
+use crate::bitcoin::address::NetworkUnchecked;
+use crate::bitcoin::constants::ChainHash;
+use crate::bitcoin::secp256k1::PublicKey;
+use crate::bitcoin::{Address, Network};
+use crate::lightning::offers::offer::Offer;
+use crate::lnurl::bip353::{BitcoinUri, DohResolver, HumanReadableName};
+use crate::lnurl::{LnUrlHttpClearnetClient, LNURL};
+use crate::node::ClnClient;
+use crate::pb::cln;
+use anyhow::{anyhow, bail, ensure, Result};
+use bech32::{ToBase32, Variant};
+use std::str::FromStr;
+
+/// What an LNURL is for, if we can tell without fetching it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LnUrlKind {
+    Pay,
+    Withdraw,
+    Auth,
+    Channel,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaymentInput {
+    Bolt11 {
+        invoice: String,
+        /// The amount of the invoice, or the amount of the BIP-21 URI
+        /// the invoice came with.
+        amount_msat: Option<u64>,
+    },
+    Bolt12Offer {
+        offer: String,
+        amount_msat: Option<u64>,
+    },
+    /// A BOLT12 invoice. These are not decoded, the node checks them
+    /// when paying.
+    Bolt12Invoice { invoice: String },
+    LnUrl {
+        /// The LNURL in bech32, LUD-17 URLs are converted.
+        lnurl: String,
+        /// The decoded URL.
+        url: String,
+        kind: Option<LnUrlKind>,
+    },
+    /// A lightning address or BIP-353 name, e.g., `alice@example.com`.
+    LightningAddress(HumanReadableName),
+    /// A node to pay with keysend.
+    NodeId { node_id: PublicKey },
+    OnChain {
+        address: String,
+        amount_msat: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    },
+}
+
+impl PaymentInput {
+    /// Classify and decode `input`, and check that it can be paid by
+    /// a node on `network`.
+    pub fn parse(input: &str, network: Network) -> Result<PaymentInput> {
+        let input = input.trim();
+        let input = strip_prefix_ignore_case(input, "lightning:").unwrap_or(input);
+        let lower = input.to_lowercase();
+
+        if lower.starts_with("bitcoin:") {
+            return parse_bip21(input, network);
+        }
+        for (scheme, kind) in [
+            ("lnurlp://", LnUrlKind::Pay),
+            ("lnurlw://", LnUrlKind::Withdraw),
+            ("keyauth://", LnUrlKind::Auth),
+            ("lnurlc://", LnUrlKind::Channel),
+        ] {
+            if let Some(rest) = strip_prefix_ignore_case(input, scheme) {
+                return parse_lud17(rest, kind);
+            }
+        }
+        if lower.starts_with("lnurl1") {
+            return parse_lnurl(input);
+        }
+        if lower.starts_with("lno1") {
+            return parse_offer(input, network, None);
+        }
+        if lower.starts_with("lni1") {
+            return Ok(PaymentInput::Bolt12Invoice {
+                invoice: input.to_string(),
+            });
+        }
+        if lower.starts_with("ln") {
+            return parse_bolt11(input, network, None);
+        }
+        if input.contains('@') {
+            return Ok(PaymentInput::LightningAddress(input.parse()?));
+        }
+        if input.len() == 66 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(PaymentInput::NodeId {
+                node_id: PublicKey::from_str(input)?,
+            });
+        }
+        match input.parse::<Address<NetworkUnchecked>>() {
+            Ok(address) => {
+                let address = address
+                    .require_network(network)
+                    .map_err(|_| anyhow!("Address {} is not for {}", input, network))?;
+                Ok(PaymentInput::OnChain {
+                    address: address.to_string(),
+                    amount_msat: None,
+                    label: None,
+                    message: None,
+                })
+            }
+            Err(_) => bail!("Unrecognized payment input {}", input),
+        }
+    }
+
+    /// Like `parse`, checking against the network `node` runs on.
+    pub async fn parse_for_node(input: &str, node: &mut ClnClient) -> Result<PaymentInput> {
+        let info = node
+            .getinfo(cln::GetinfoRequest {})
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner();
+        let network = info
+            .network
+            .parse::<Network>()
+            .map_err(|_| anyhow!("Node reported unknown network {}", info.network))?;
+        PaymentInput::parse(input, network)
+    }
+
+    /// Pay this input with `node`. `amount_msat` is only needed if the
+    /// input does not specify the amount. On-chain addresses are not
+    /// paid here, use `withdraw` for those. See
+    /// `LNURL::get_invoice_for_name` for `lnurl_fallback`.
+    pub async fn pay(
+        &self,
+        node: &mut ClnClient,
+        amount_msat: Option<u64>,
+        lnurl_fallback: bool,
+    ) -> Result<cln::PayResponse> {
+        let require_amount =
+            |a: Option<u64>| a.ok_or_else(|| anyhow!("An amount is required to pay this"));
+        let (bolt11, amount_msat) = match self {
+            PaymentInput::Bolt11 {
+                invoice,
+                amount_msat: amount,
+            } => {
+                let has_amount = crate::lnurl::utils::parse_invoice(invoice)?
+                    .amount_milli_satoshis()
+                    .is_some();
+                match has_amount {
+                    true => (invoice.clone(), None),
+                    false => (
+                        invoice.clone(),
+                        Some(require_amount(amount_msat.or(*amount))?),
+                    ),
+                }
+            }
+            PaymentInput::Bolt12Offer {
+                offer,
+                amount_msat: amount,
+            } => {
+                let amount = match amount {
+                    Some(_) => None,
+                    None => Some(require_amount(amount_msat)?),
+                };
+                let invoice = node
+                    .fetch_invoice(cln::FetchinvoiceRequest {
+                        offer: offer.clone(),
+                        amount_msat: amount.map(|msat| cln::Amount { msat }),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| anyhow!(e))?
+                    .into_inner();
+                (invoice.invoice, None)
+            }
+            PaymentInput::Bolt12Invoice { invoice } => (invoice.clone(), None),
+            PaymentInput::LnUrl { lnurl, kind, .. } => {
+                ensure!(
+                    matches!(kind, None | Some(LnUrlKind::Pay)),
+                    "This LNURL is for {:?}, not for paying",
+                    kind.unwrap()
+                );
+                let lnurl_client = LNURL::new(LnUrlHttpClearnetClient::new());
+                let result = lnurl_client
+                    .pay(lnurl, require_amount(amount_msat)?, node)
+                    .await?;
+                return Ok(result.payment);
+            }
+            PaymentInput::LightningAddress(name) => {
+                let resolved = LNURL::new(LnUrlHttpClearnetClient::new())
+                    .get_invoice_for_name(
+                        &DohResolver::new(),
+                        &name.to_string(),
+                        amount_msat,
+                        lnurl_fallback,
+                        node,
+                    )
+                    .await?;
+                (resolved.invoice, resolved.amount_msat)
+            }
+            PaymentInput::NodeId { node_id } => {
+                let res = node
+                    .key_send(cln::KeysendRequest {
+                        destination: node_id.serialize().to_vec(),
+                        amount_msat: Some(cln::Amount {
+                            msat: require_amount(amount_msat)?,
+                        }),
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| anyhow!(e))?
+                    .into_inner();
+                return Ok(cln::PayResponse {
+                    payment_preimage: res.payment_preimage,
+                    destination: res.destination,
+                    payment_hash: res.payment_hash,
+                    created_at: res.created_at,
+                    parts: res.parts,
+                    amount_msat: res.amount_msat,
+                    amount_sent_msat: res.amount_sent_msat,
+                    warning_partial_completion: res.warning_partial_completion,
+                    status: cln::pay_response::PayStatus::Complete as i32,
+                });
+            }
+            PaymentInput::OnChain { address, .. } => {
+                bail!("{} is an on-chain address, use withdraw to pay it", address)
+            }
+        };
+
+        Ok(node
+            .pay(cln::PayRequest {
+                bolt11,
+                amount_msat: amount_msat.map(|msat| cln::Amount { msat }),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| anyhow!(e))?
+            .into_inner())
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(p) if p.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+/// A BIP-21 URI is paid with its offer if it has one, then with its
+/// invoice, and only then on-chain.
+fn parse_bip21(input: &str, network: Network) -> Result<PaymentInput> {
+    let uri = BitcoinUri::parse(input)?;
+    if let Some(offer) = &uri.offer {
+        return parse_offer(offer, network, uri.amount_msat);
+    }
+    if let Some(lightning) = &uri.lightning {
+        // Some wallets put LNURLs in the `lightning` parameter.
+        if lightning.to_lowercase().starts_with("lnurl") {
+            return parse_lnurl(lightning);
+        }
+        return parse_bolt11(lightning, network, uri.amount_msat);
+    }
+    let address = uri.address.unwrap_or_default();
+    let checked = address
+        .parse::<Address<NetworkUnchecked>>()?
+        .require_network(network)
+        .map_err(|_| anyhow!("Address {} is not for {}", address, network))?;
+    Ok(PaymentInput::OnChain {
+        address: checked.to_string(),
+        amount_msat: uri.amount_msat,
+        label: uri.label,
+        message: uri.message,
+    })
+}
+
+fn parse_bolt11(input: &str, network: Network, amount_msat: Option<u64>) -> Result<PaymentInput> {
+    let invoice = crate::lnurl::utils::parse_invoice(input)?;
+    ensure!(
+        invoice.network() == network,
+        "Invoice is for {}, not for {}",
+        invoice.network(),
+        network
+    );
+    Ok(PaymentInput::Bolt11 {
+        invoice: input.to_string(),
+        amount_msat: invoice.amount_milli_satoshis().or(amount_msat),
+    })
+}
+
+fn parse_offer(input: &str, network: Network, amount_msat: Option<u64>) -> Result<PaymentInput> {
+    let offer = Offer::from_str(input).map_err(|e| anyhow!("Invalid offer: {:?}", e))?;
+    ensure!(
+        offer.supports_chain(ChainHash::using_genesis_block(network)),
+        "Offer is not for {}",
+        network
+    );
+    let amount = match offer.amount() {
+        Some(crate::lightning::offers::offer::Amount::Bitcoin { amount_msats }) => {
+            Some(amount_msats)
+        }
+        // Offers in other currencies are converted by the node.
+        _ => amount_msat,
+    };
+    Ok(PaymentInput::Bolt12Offer {
+        offer: input.to_string(),
+        amount_msat: amount,
+    })
+}
+
+fn parse_lnurl(input: &str) -> Result<PaymentInput> {
+    let url = crate::lnurl::utils::parse_lnurl(input)?;
+    let parsed = url::Url::parse(&url)?;
+    let kind = match parsed
+        .query_pairs()
+        .find(|(k, _)| k == "tag")
+        .map(|(_, v)| v.to_string())
+        .as_deref()
+    {
+        Some("login") => Some(LnUrlKind::Auth),
+        Some("withdrawRequest") => Some(LnUrlKind::Withdraw),
+        Some("payRequest") => Some(LnUrlKind::Pay),
+        Some("channelRequest") => Some(LnUrlKind::Channel),
+        _ => None,
+    };
+    Ok(PaymentInput::LnUrl {
+        lnurl: input.to_lowercase(),
+        url,
+        kind,
+    })
+}
+
+/// LUD-17: `lnurlp://example.com/...` stands for the https URL, or
+/// the http URL for onion services.
+fn parse_lud17(rest: &str, kind: LnUrlKind) -> Result<PaymentInput> {
+    let host = rest.split(['/', '?', ':']).next().unwrap_or_default();
+    ensure!(!host.is_empty(), "LNURL has no domain");
+    let scheme = match host.ends_with(".onion") {
+        true => "http",
+        false => "https",
+    };
+    let url = format!("{}://{}", scheme, rest);
+    url::Url::parse(&url)?;
+    let lnurl = bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32)?;
+    Ok(PaymentInput::LnUrl {
+        lnurl,
+        url,
+        kind: Some(kind),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVOICE: &str = "lnbc1u1pjv9qrvsp5e5wwexctzp9yklcrzx448c68q2a7kma55cm67ruajjwfkrswnqvqpp55x6mmz8ch6nahrcuxjsjvs23xkgt8eu748nukq463zhjcjk4s65shp5dd6hc533r655wtyz63jpf6ja08srn6rz6cjhwsjuyckrqwanhjtsxqzjccqpjrzjqw6lfdpjecp4d5t0gxk5khkrzfejjxyxtxg5exqsd95py6rhwwh72rpgrgqq3hcqqgqqqqlgqqqqqqgq9q9qxpqysgq95njz4sz6h7r2qh7txnevcrvg0jdsfpe72cecmjfka8mw5nvm7tydd0j34ps2u9q9h6v5u8h3vxs8jqq5fwehdda6a8qmpn93fm290cquhuc6r";
+    const OFFER: &str = "lno1qsgqmqvgm96frzdg8m0gc6nzeqffvzsqzrxqy32afmr3jn9ggkwg3egfwch2hy0l6jut6vfd8vpsc3h89l6u3dm4q2d6nuamav3w27xvdmv3lpgklhg7l5teypqz9l53hj7zvuaenh34xqsz2sa967yzqkylfu9xtcd5ymcmfp32h083e805y7jfd236w9afhavqqvl8uyma7x77yun4ehe9pnhu2gekjguexmxpqjcr2j822xr7q34p078gzslf9wpwz5y57alxu99s0z2ql0kfqvwhzycqq45ehh58xnfpuek80hw6spvwrvttjrrq9pphh0dpydh06qqspp5uq4gpyt6n9mwexde44qv7lstzzq60nr40ff38u27un6y53aypmx0p4qruk2tf9mjwqlhxak4znvna5y";
+    // From LUD-01.
+    const LNURL: &str = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+    const ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const NODE_ID: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn parse(input: &str) -> Result<PaymentInput> {
+        PaymentInput::parse(input, Network::Bitcoin)
+    }
+
+    #[test]
+    fn test_parse_lightning() {
+        let bolt11 = PaymentInput::Bolt11 {
+            invoice: INVOICE.to_string(),
+            amount_msat: Some(100_000),
+        };
+        assert_eq!(parse(INVOICE).unwrap(), bolt11);
+        assert_eq!(parse(&format!("lightning:{}", INVOICE)).unwrap(), bolt11);
+        assert_eq!(
+            parse(&format!(
+                "bitcoin:{}?amount=0.001&lightning={}",
+                ADDRESS, INVOICE
+            ))
+            .unwrap(),
+            bolt11
+        );
+        assert!(PaymentInput::parse(INVOICE, Network::Regtest).is_err());
+
+        assert!(matches!(
+            parse(&format!("bitcoin:?lno={}&lightning={}", OFFER, INVOICE)).unwrap(),
+            PaymentInput::Bolt12Offer { offer, .. } if offer == OFFER
+        ));
+        assert!(matches!(
+            parse(OFFER).unwrap(),
+            PaymentInput::Bolt12Offer { .. }
+        ));
+        assert!(PaymentInput::parse(OFFER, Network::Regtest).is_err());
+        assert!(matches!(
+            parse("lni1qqq").unwrap(),
+            PaymentInput::Bolt12Invoice { .. }
+        ));
+
+        assert_eq!(
+            parse(NODE_ID).unwrap(),
+            PaymentInput::NodeId {
+                node_id: PublicKey::from_str(NODE_ID).unwrap(),
+            }
+        );
+        assert_eq!(
+            parse("₿Alice@example.com").unwrap(),
+            PaymentInput::LightningAddress("alice@example.com".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_lnurl() {
+        let url = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+        assert_eq!(
+            parse(LNURL).unwrap(),
+            PaymentInput::LnUrl {
+                lnurl: LNURL.to_lowercase(),
+                url: url.to_string(),
+                kind: None,
+            }
+        );
+        assert_eq!(
+            parse(&format!("lightning:{}", LNURL)).unwrap(),
+            parse(LNURL).unwrap()
+        );
+
+        match parse("lnurlw://service.com/withdraw?k1=abc").unwrap() {
+            PaymentInput::LnUrl { lnurl, url, kind } => {
+                assert_eq!(url, "https://service.com/withdraw?k1=abc");
+                assert_eq!(kind, Some(LnUrlKind::Withdraw));
+                assert_eq!(crate::lnurl::utils::parse_lnurl(&lnurl).unwrap(), url);
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(matches!(
+            parse("lnurlp://abcdef.onion/pay").unwrap(),
+            PaymentInput::LnUrl { url, .. } if url == "http://abcdef.onion/pay"
+        ));
+    }
+
+    #[test]
+    fn test_parse_onchain() {
+        assert_eq!(
+            parse(&format!(
+                "bitcoin:{}?amount=0.001&label=Shop",
+                ADDRESS.to_uppercase()
+            ))
+            .unwrap(),
+            PaymentInput::OnChain {
+                address: ADDRESS.to_string(),
+                amount_msat: Some(100_000_000),
+                label: Some("Shop".to_string()),
+                message: None,
+            }
+        );
+        assert!(matches!(
+            parse(ADDRESS).unwrap(),
+            PaymentInput::OnChain { .. }
+        ));
+        assert!(PaymentInput::parse(ADDRESS, Network::Regtest).is_err());
+        assert!(parse("hello").is_err());
+    }
+}
@@ -9,9 +9,14 @@ use lightning_signer::persist::{Error, Persist, SignerId};
 use lightning_signer::policy::validator::ValidatorFactory;
 use lightning_signer::SendSync;
 use log::{trace, warn};
+use ring::hmac;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -21,6 +26,55 @@ const CHANNEL_PREFIX: &str = "channels";
 const ALLOWLIST_PREFIX: &str = "allowlists";
 const TRACKER_PREFIX: &str = "trackers";
 
+/// Prefixes of the values the signer keeps for itself, rather than
+/// for VLS, e.g., the spending ledger and the rune registry. The
+/// node stores them like any other value, but they carry a MAC keyed
+/// by the signer, so the node can neither forge nor modify them. The
+/// signer may change these without a request from the node, e.g.,
+/// when revoking a rune, so the node can lag behind on them. They
+/// are checked for rollbacks against the versions we last sent to
+/// the node instead, see `MemoryPersister::rollbacks`.
+const SIGNER_VALUE_PREFIXES: &[&str] = &["spending", "runes"];
+
+/// Whether `key` is one of the values the signer keeps for itself.
+pub(crate) fn is_signer_value(key: &str) -> bool {
+    SIGNER_VALUE_PREFIXES.contains(&key.split('/').next().unwrap_or_default())
+}
+
+/// The envelope of a signer value. The MAC covers the key and the
+/// version too, so the node can't move a value to another key, or
+/// replay an old value with a bumped version.
+#[derive(Serialize, Deserialize)]
+struct SignedValue {
+    mac: String,
+    value: serde_json::Value,
+}
+
+fn value_mac(secret: &[u8; 32], key: &str, version: u64, value: &serde_json::Value) -> hmac::Tag {
+    let mut ctx = hmac::Context::with_key(&hmac::Key::new(hmac::HMAC_SHA256, secret));
+    ctx.update(key.as_bytes());
+    ctx.update(&[0]);
+    ctx.update(&version.to_be_bytes());
+    ctx.update(&serde_json::to_vec(value).unwrap_or_default());
+    ctx.sign()
+}
+
+/// Return the value of a signer value entry, if its MAC is valid.
+fn open_value(
+    secret: &[u8; 32],
+    key: &str,
+    version: u64,
+    value: &serde_json::Value,
+) -> Option<serde_json::Value> {
+    let signed: SignedValue = serde_json::from_value(value.clone()).ok()?;
+    let mac = hex::decode(&signed.mac).ok()?;
+    let expected = value_mac(secret, key, version, &signed.value);
+    match ring::constant_time::verify_slices_are_equal(expected.as_ref(), &mac) {
+        Ok(()) => Some(signed.value),
+        Err(_) => None,
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct State {
     values: BTreeMap<String, (u64, serde_json::Value)>,
@@ -198,6 +252,27 @@ impl Display for StateChange {
     }
 }
 
+/// An entry the node sent us that is older than the version we have
+/// already seen for the same key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateRollback {
+    pub key: String,
+    /// The highest version we have seen for this key.
+    pub seen: u64,
+    /// The version the node sent us.
+    pub received: u64,
+}
+
+impl Display for StateRollback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "StateRollback[{}]: seen_version={}, received_version={}",
+            self.key, self.seen, self.received
+        )
+    }
+}
+
 impl State {
     pub fn new() -> Self {
         State {
@@ -241,6 +316,80 @@ impl State {
         Ok(res)
     }
 
+    /// Compare `other` against the versions we have seen so far, and
+    /// return all entries that would roll our state back. Versions
+    /// only ever increase, so a lower version than ours means that
+    /// whoever sent `other` is replaying an old state.
+    pub fn rollbacks(&self, other: &State) -> Vec<StateRollback> {
+        other
+            .values
+            .iter()
+            .filter_map(|(key, (newver, _))| match self.values.get(key) {
+                Some((oldver, _)) if oldver > newver => Some(StateRollback {
+                    key: key.clone(),
+                    seen: *oldver,
+                    received: *newver,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Return the keys of the signer values in `self` whose MAC does
+    /// not verify with `secret`, i.e., that were not written by us.
+    pub(crate) fn unauthenticated(&self, secret: &[u8; 32]) -> Vec<String> {
+        self.values
+            .iter()
+            .filter(|(key, _)| is_signer_value(key))
+            .filter(|(key, (version, value))| open_value(secret, key, *version, value).is_none())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Get a value the signer keeps in its state in addition to the
+    /// VLS entries. Values that fail to authenticate are ignored.
+    pub(crate) fn get_value<T: serde::de::DeserializeOwned>(
+        &self,
+        key: &str,
+        secret: &[u8; 32],
+    ) -> Option<T> {
+        let (version, value) = self.values.get(key)?;
+        let value = match open_value(secret, key, *version, value) {
+            Some(v) => v,
+            None => {
+                warn!("Ignoring unauthenticated state entry {}", key);
+                return None;
+            }
+        };
+        match serde_json::from_value(value) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("Ignoring malformed state entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Insert or replace a value, bumping its version so it gets
+    /// synced to the node, and authenticate it with `secret`.
+    pub(crate) fn put_value<T: Serialize>(
+        &mut self,
+        key: &str,
+        value: &T,
+        secret: &[u8; 32],
+    ) -> anyhow::Result<()> {
+        let value = serde_json::to_value(value)?;
+        let version = self.values.get(key).map(|v| v.0 + 1).unwrap_or(0);
+        let mac = value_mac(secret, key, version, &value);
+        let signed = SignedValue {
+            mac: hex::encode(mac.as_ref()),
+            value,
+        };
+        self.values
+            .insert(key.to_string(), (version, serde_json::to_value(signed)?));
+        Ok(())
+    }
+
     pub fn diff(&self, other: &State) -> anyhow::Result<Vec<StateChange>> {
         Ok(other
             .values
@@ -286,21 +435,233 @@ impl From<Vec<crate::pb::SignerStateEntry>> for State {
     }
 }
 
+/// A durable backend for the signer `State`. The signer keeps its
+/// working copy in memory, and writes it through to the store
+/// whenever it changes, so that it survives restarts and can be used
+/// to check the state the node sends us against what we have seen
+/// before.
+pub trait StateStore: Send + Sync {
+    /// Load the last stored `State`, or `None` if nothing has been
+    /// stored yet.
+    fn load(&self) -> anyhow::Result<Option<State>>;
+
+    /// Replace the stored `State` with `state`. Implementations must
+    /// ensure that a crash never leaves a partially written state
+    /// behind.
+    fn store(&self, state: &State) -> anyhow::Result<()>;
+}
+
+/// A `StateStore` that keeps the state as a JSON file on the local
+/// filesystem. Writes go to a temporary file first, which is synced
+/// and then atomically renamed over the previous version, so readers
+/// see either the old or the new state, never a mix of both.
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+impl FileStateStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileStateStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        PathBuf::from(tmp)
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self) -> anyhow::Result<Option<State>> {
+        let data = match std::fs::read(&self.path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let state: State = serde_json::from_slice(&data)?;
+        trace!(
+            "Loaded {} signer state entries from {}",
+            state.values.len(),
+            self.path.display()
+        );
+        Ok(Some(state))
+    }
+
+    fn store(&self, state: &State) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(state)?;
+        let tmp = self.tmp_path();
+        {
+            let mut f = File::create(&tmp)?;
+            f.write_all(&data)?;
+            f.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)?;
+
+        // Sync the directory too, otherwise the rename itself may
+        // not survive a crash.
+        if let Some(dir) = self.path.parent() {
+            if let Ok(d) = File::open(dir) {
+                let _ = d.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
+
 pub(crate) struct MemoryPersister {
     state: Arc<Mutex<State>>,
+    store: Option<Arc<dyn StateStore>>,
+    /// Authenticates the signer values, see `SIGNER_VALUE_PREFIXES`.
+    secret: [u8; 32],
+    /// The versions of the signer values we last sent to the node.
+    /// The node may lag behind our state, but it must never go back
+    /// on these.
+    reported: Arc<Mutex<BTreeMap<String, u64>>>,
+    /// Whether the state changed since we last wrote it to the
+    /// store.
+    dirty: AtomicBool,
 }
 
 impl MemoryPersister {
-    pub fn new() -> Self {
+    pub fn new(secret: [u8; 32]) -> Self {
         let state = Arc::new(Mutex::new(State {
             values: BTreeMap::new(),
         }));
-        MemoryPersister { state }
+        MemoryPersister {
+            state,
+            store: None,
+            secret,
+            reported: Arc::default(),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a persister that is initialized from, and writes any
+    /// change through to, the given `store`.
+    pub fn with_store(store: Arc<dyn StateStore>, secret: [u8; 32]) -> anyhow::Result<Self> {
+        let state = store.load()?.unwrap_or_else(State::new);
+        Ok(MemoryPersister {
+            state: Arc::new(Mutex::new(state)),
+            store: Some(store),
+            secret,
+            reported: Arc::default(),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// The signer values in `state` that were not written by us.
+    pub(crate) fn unauthenticated(&self, state: &State) -> Vec<String> {
+        state.unauthenticated(&self.secret)
+    }
+
+    /// Return the entries in `other` that are older than what we
+    /// have seen. VLS values are compared against our state, signer
+    /// values against what we sent to the node.
+    pub(crate) fn rollbacks(&self, other: &State) -> Vec<StateRollback> {
+        let mut seen = State::new();
+        seen.values = self
+            .state
+            .lock()
+            .unwrap()
+            .values
+            .iter()
+            .filter(|(key, _)| !is_signer_value(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        for (key, version) in self.reported.lock().unwrap().iter() {
+            seen.values
+                .insert(key.clone(), (*version, serde_json::Value::Null));
+        }
+        seen.rollbacks(other)
+    }
+
+    /// Remember the versions of the signer values in `state`, which
+    /// is about to be sent to the node.
+    pub(crate) fn mark_reported(&self, state: &State) {
+        let mut reported = self.reported.lock().unwrap();
+        for (key, (version, _)) in state.values.iter().filter(|(k, _)| is_signer_value(k)) {
+            reported.insert(key.clone(), *version);
+        }
     }
 
     pub fn state(&self) -> Arc<Mutex<State>> {
         self.state.clone()
     }
+
+    /// Note that the state was changed through `state()`, so the
+    /// next `flush` writes it.
+    pub(crate) fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Write the current state to the backing store, if any, and if
+    /// it changed since the last write. Must not be called while
+    /// holding the state lock.
+    pub fn flush(&self) -> Result<(), Error> {
+        let store = match &self.store {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let state = self.state.lock().unwrap().clone();
+        store.store(&state).map_err(|e| {
+            // Try again on the next flush.
+            self.mark_dirty();
+            Error::Internal(format!("failed to store signer state: {}", e))
+        })
+    }
+
+    /// Write a change we just made through to the store.
+    fn write_through(&self) -> Result<(), Error> {
+        self.mark_dirty();
+        self.flush()
+    }
+
+    pub(crate) fn get_value<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.state.lock().unwrap().get_value(key, &self.secret)
+    }
+
+    /// Get a value we wrote from `state`, e.g., one the node sent us.
+    pub(crate) fn get_value_from<T: serde::de::DeserializeOwned>(
+        &self,
+        state: &State,
+        key: &str,
+    ) -> Option<T> {
+        state.get_value(key, &self.secret)
+    }
+
+    /// Update a value in the state and write it through to the
+    /// backing store.
+    pub(crate) fn put_value<T: Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .put_value(key, value, &self.secret)?;
+        Ok(self.write_through()?)
+    }
+
+    /// Read, modify and write back a value while holding the state
+    /// lock, so concurrent updates don't get lost. `f` returns whether
+    /// it changed the value, and the value is only written if it did.
+    pub(crate) fn update_value<T, F>(&self, key: &str, f: F) -> anyhow::Result<()>
+    where
+        T: Serialize + serde::de::DeserializeOwned + Default,
+        F: FnOnce(&mut T) -> bool,
+    {
+        {
+            let mut state = self.state.lock().unwrap();
+            let mut value: T = state.get_value(key, &self.secret).unwrap_or_default();
+            if !f(&mut value) {
+                return Ok(());
+            }
+            state.put_value(key, &value, &self.secret)?;
+        }
+        Ok(self.write_through()?)
+    }
 }
 
 impl SendSync for MemoryPersister {}
@@ -320,14 +681,15 @@ impl Persist for MemoryPersister {
                 network: config.network.to_string(),
             },
             state.into(),
-        )
+        )?;
+        self.write_through()
     }
 
     fn delete_channel(&self, node_id: &PublicKey, channel: &ChannelId) -> Result<(), Error> {
         let node_channel_id = vls_persist::model::NodeChannelId::new(node_id, &channel);
         let id = hex::encode(node_channel_id.0);
         self.state.lock().unwrap().delete_channel(&id);
-        Ok(())
+        self.write_through()
     }
 
     fn update_node(
@@ -336,7 +698,8 @@ impl Persist for MemoryPersister {
         state: &NodeState,
     ) -> Result<(), Error> {
         let key = hex::encode(node_id.serialize());
-        self.state.lock().unwrap().update_node(&key, state.into())
+        self.state.lock().unwrap().update_node(&key, state.into())?;
+        self.write_through()
     }
 
     fn delete_node(
@@ -344,7 +707,8 @@ impl Persist for MemoryPersister {
         node_id: &lightning_signer::bitcoin::secp256k1::PublicKey,
     ) -> Result<(), Error> {
         let key = hex::encode(node_id.serialize());
-        self.state.lock().unwrap().delete_node(&key)
+        self.state.lock().unwrap().delete_node(&key)?;
+        self.write_through()
     }
 
     fn new_channel(
@@ -365,7 +729,8 @@ impl Persist for MemoryPersister {
         };
         let id = hex::encode(id.0);
 
-        self.state.lock().unwrap().insert_channel(&id, entry)
+        self.state.lock().unwrap().insert_channel(&id, entry)?;
+        self.write_through()
     }
 
     fn update_channel(
@@ -383,7 +748,8 @@ impl Persist for MemoryPersister {
             enforcement_state: channel.enforcement_state.clone(),
             blockheight: None,
         };
-        self.state.lock().unwrap().update_channel(&id, entry)
+        self.state.lock().unwrap().update_channel(&id, entry)?;
+        self.write_through()
     }
 
     fn get_channel(
@@ -404,7 +770,8 @@ impl Persist for MemoryPersister {
         self.state
             .lock()
             .unwrap()
-            .new_chain_tracker(node_id, tracker)
+            .new_chain_tracker(node_id, tracker)?;
+        self.write_through()
     }
 
     fn update_tracker(
@@ -415,11 +782,13 @@ impl Persist for MemoryPersister {
         let key = hex::encode(node_id.serialize());
         let key = format!("{TRACKER_PREFIX}/{key}");
 
-        let mut state = self.state.lock().unwrap();
-        let v = state.values.get_mut(&key).unwrap();
-        let tracker: vls_persist::model::ChainTrackerEntry = tracker.into();
-        *v = (v.0 + 1, serde_json::to_value(tracker).unwrap());
-        Ok(())
+        {
+            let mut state = self.state.lock().unwrap();
+            let v = state.values.get_mut(&key).unwrap();
+            let tracker: vls_persist::model::ChainTrackerEntry = tracker.into();
+            *v = (v.0 + 1, serde_json::to_value(tracker).unwrap());
+        }
+        self.write_through()
     }
 
     fn get_tracker(
@@ -458,18 +827,20 @@ impl Persist for MemoryPersister {
         let key = hex::encode(node_id.serialize());
         let key = format!("{ALLOWLIST_PREFIX}/{key}");
 
-        let mut state = self.state.lock().unwrap();
-        match state.values.get_mut(&key) {
-            Some(v) => {
-                *v = (v.0 + 1, serde_json::to_value(allowlist).unwrap());
-            }
-            None => {
-                state
-                    .values
-                    .insert(key, (0u64, serde_json::to_value(allowlist).unwrap()));
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.values.get_mut(&key) {
+                Some(v) => {
+                    *v = (v.0 + 1, serde_json::to_value(allowlist).unwrap());
+                }
+                None => {
+                    state
+                        .values
+                        .insert(key, (0u64, serde_json::to_value(allowlist).unwrap()));
+                }
             }
         }
-        Ok(())
+        self.write_through()
     }
 
     fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<std::string::String>, Error> {
@@ -562,7 +933,8 @@ impl Persist for MemoryPersister {
         Ok(nodes)
     }
     fn clear_database(&self) -> Result<(), Error> {
-        self.state.lock().unwrap().clear()
+        self.state.lock().unwrap().clear()?;
+        self.write_through()
     }
 
     fn signer_id(&self) -> SignerId {
@@ -572,3 +944,194 @@ impl Persist for MemoryPersister {
         [0u8; 16]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let store = FileStateStore::new(&path);
+        assert!(store.load().unwrap().is_none());
+
+        let mut state = State::new();
+        state
+            .values
+            .insert("nodes/abc".to_string(), (3, serde_json::json!({"a": 1})));
+        store.store(&state).unwrap();
+
+        // The temporary file must have been renamed into place.
+        assert!(!store.tmp_path().exists());
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.values.get("nodes/abc").unwrap().0, 3);
+
+        // Overwriting replaces the previous version.
+        state.values.get_mut("nodes/abc").unwrap().0 = 4;
+        store.store(&state).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.values.get("nodes/abc").unwrap().0, 4);
+    }
+
+    #[test]
+    fn test_rollbacks() {
+        let mut local = State::new();
+        local
+            .values
+            .insert("channels/a".to_string(), (5, serde_json::json!({})));
+        local
+            .values
+            .insert("channels/b".to_string(), (2, serde_json::json!({})));
+
+        let mut remote = State::new();
+        remote
+            .values
+            .insert("channels/a".to_string(), (4, serde_json::json!({})));
+        remote
+            .values
+            .insert("channels/b".to_string(), (3, serde_json::json!({})));
+        remote
+            .values
+            .insert("channels/c".to_string(), (0, serde_json::json!({})));
+
+        assert_eq!(
+            local.rollbacks(&remote),
+            vec![StateRollback {
+                key: "channels/a".to_string(),
+                seen: 5,
+                received: 4,
+            }]
+        );
+        remote.values.get_mut("channels/a").unwrap().0 = 5;
+        assert!(local.rollbacks(&remote).is_empty());
+    }
+
+    #[test]
+    fn test_signer_value_rollbacks() {
+        let persister = MemoryPersister::new([1u8; 32]);
+        let registry = |version| {
+            let mut state = State::new();
+            state.values.insert(
+                "runes/registry".to_string(),
+                (version, serde_json::json!({})),
+            );
+            state
+        };
+        persister.put_value("runes/registry", &1).unwrap();
+        persister.mark_reported(&persister.state().lock().unwrap());
+        persister.put_value("runes/registry", &2).unwrap();
+
+        // The node may lag behind on values the signer changed on
+        // its own, but not go back on what it was sent.
+        assert!(persister.rollbacks(&registry(0)).is_empty());
+        persister.mark_reported(&persister.state().lock().unwrap());
+        assert_eq!(
+            persister.rollbacks(&registry(0)),
+            vec![StateRollback {
+                key: "runes/registry".to_string(),
+                seen: 1,
+                received: 0,
+            }]
+        );
+        assert!(persister.rollbacks(&registry(1)).is_empty());
+    }
+
+    #[test]
+    fn test_signer_values() {
+        let secret = [1u8; 32];
+        let mut state = State::new();
+        state
+            .put_value("spending/ledger", &vec![1, 2, 3], &secret)
+            .unwrap();
+        state
+            .values
+            .insert("channels/a".to_string(), (0, serde_json::json!({})));
+        assert_eq!(
+            state.get_value::<Vec<u32>>("spending/ledger", &secret),
+            Some(vec![1, 2, 3])
+        );
+        assert!(state.unauthenticated(&secret).is_empty());
+
+        // A different key, i.e., another seed, can't read it.
+        assert_eq!(
+            state.get_value::<Vec<u32>>("spending/ledger", &[2u8; 32]),
+            None
+        );
+        assert_eq!(state.unauthenticated(&[2u8; 32]), vec!["spending/ledger"]);
+
+        // Neither changing the value nor bumping the version keeps
+        // the MAC valid.
+        let mut forged = state.clone();
+        let entry = forged.values.get_mut("spending/ledger").unwrap();
+        entry.1["value"] = serde_json::json!([]);
+        assert_eq!(forged.unauthenticated(&secret), vec!["spending/ledger"]);
+        assert_eq!(
+            forged.get_value::<Vec<u32>>("spending/ledger", &secret),
+            None
+        );
+
+        let mut forged = state.clone();
+        forged.values.get_mut("spending/ledger").unwrap().0 += 1;
+        assert_eq!(forged.unauthenticated(&secret), vec!["spending/ledger"]);
+
+        // Nor does moving it to another key.
+        let mut forged = state.clone();
+        let entry = forged.values.remove("spending/ledger").unwrap();
+        forged.values.insert("runes/registry".to_string(), entry);
+        assert_eq!(forged.unauthenticated(&secret), vec!["runes/registry"]);
+    }
+
+    #[test]
+    fn test_persister_writes_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(FileStateStore::new(dir.path().join("state.json")));
+        let persister = MemoryPersister::with_store(store.clone(), [0u8; 32]).unwrap();
+        persister.clear_database().unwrap();
+        persister
+            .state()
+            .lock()
+            .unwrap()
+            .values
+            .insert("nodes/abc".to_string(), (0, serde_json::json!({})));
+        persister.mark_dirty();
+        persister.flush().unwrap();
+
+        let persister = MemoryPersister::with_store(store, [0u8; 32]).unwrap();
+        assert!(persister
+            .state()
+            .lock()
+            .unwrap()
+            .values
+            .contains_key("nodes/abc"));
+    }
+
+    /// The store is only written when the state changed.
+    #[test]
+    fn test_persister_writes_changes_only() {
+        #[derive(Default)]
+        struct CountingStore(Mutex<usize>);
+        impl StateStore for CountingStore {
+            fn load(&self) -> anyhow::Result<Option<State>> {
+                Ok(None)
+            }
+            fn store(&self, _state: &State) -> anyhow::Result<()> {
+                *self.0.lock().unwrap() += 1;
+                Ok(())
+            }
+        }
+        let store = Arc::new(CountingStore::default());
+        let writes = || *store.0.lock().unwrap();
+        let persister = MemoryPersister::with_store(store.clone(), [0u8; 32]).unwrap();
+
+        persister.flush().unwrap();
+        assert_eq!(writes(), 0);
+        persister.clear_database().unwrap();
+        assert_eq!(writes(), 1);
+        persister.flush().unwrap();
+        assert_eq!(writes(), 1);
+        persister.mark_dirty();
+        persister.flush().unwrap();
+        assert_eq!(writes(), 2);
+    }
+}
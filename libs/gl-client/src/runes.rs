@@ -1,6 +1,11 @@
 use runeauth::{Alternative, Check, Condition, ConditionChecker, Restriction, Rune, RuneError};
 use std::fmt::Display;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The longest period a rate limit can refer to. The signer only
+/// remembers the uses of a rune for this long.
+pub const MAX_RATE_PERIOD: Duration = Duration::from_secs(24 * 3600);
 
 /// Represents an entity that can provide restrictions.
 ///
@@ -54,7 +59,7 @@ impl RuneFactory {
 }
 
 /// Predefined rule sets to generate `Restriction`s from.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DefRules<'a> {
     /// Represents a rule set where only read operations are allowed. This
     /// translates to a `Restriction` that is "method^Get|method^List".
@@ -62,6 +67,26 @@ pub enum DefRules<'a> {
     /// Represents a rule set where only the `pay` method is allowed. This
     /// translates to a `Restriction` that is "method=pay".
     Pay,
+    /// Represents a rule set for receiving payments: creating, listing and
+    /// waiting for invoices, and streaming incoming payments.
+    InvoiceOnly,
+    /// Represents a rule set for monitoring a node: read operations and
+    /// streaming incoming payments and logs.
+    Watch,
+    /// Represents a rule set for on-chain funds: generating addresses,
+    /// listing and withdrawing funds.
+    Onchain,
+    /// Represents a rule set for receiving payments through an LSP, i.e.,
+    /// invoices with a just-in-time channel.
+    Lsp,
+    /// The rune expires after the given duration, counted from when the
+    /// restriction is generated. This translates to a `Restriction` that is
+    /// "time<[now + duration]".
+    Expiring(Duration),
+    /// At most `n` requests per period. This translates to a `Restriction`
+    /// that is "rate=[n]/[period in seconds]". Periods are capped at
+    /// `MAX_RATE_PERIOD`.
+    RateLimited(u32, Duration),
     /// A special rule that adds the alternatives of the given `DefRules`
     /// in a disjunctive set. Example: Add(vec![ReadOnly, Pay]) translates
     /// to a `Restriction` that is "method^Get|method^List|method=pay".
@@ -93,6 +118,51 @@ impl<'a> Restrictor for DefRules<'a> {
                     .unwrap()];
                 Ok(a)
             }
+            DefRules::InvoiceOnly => methods(&[
+                "invoice",
+                "lspinvoice",
+                "listinvoices",
+                "waitinvoice",
+                "waitanyinvoice",
+                "streamincoming",
+            ]),
+            DefRules::Watch => {
+                let a = vec![Restriction::new(vec![
+                    alternative("method", Condition::BeginsWith, "get")?,
+                    alternative("method", Condition::BeginsWith, "list")?,
+                    alternative("method", Condition::Equal, "streamincoming")?,
+                    alternative("method", Condition::Equal, "streamlog")?,
+                ])?];
+                Ok(a)
+            }
+            DefRules::Onchain => methods(&["newaddr", "listfunds", "withdraw"]),
+            DefRules::Lsp => methods(&["lspinvoice", "listinvoices", "streamincoming"]),
+            DefRules::Expiring(duration) => {
+                let expiry = (SystemTime::now() + duration)
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| RuneError::Unknown(e.to_string()))?
+                    .as_secs();
+                let a = vec![Restriction::new(vec![alternative(
+                    "time",
+                    Condition::IntLT,
+                    &expiry.to_string(),
+                )?])?];
+                Ok(a)
+            }
+            DefRules::RateLimited(n, period) => {
+                if period.is_zero() || period > MAX_RATE_PERIOD {
+                    return Err(RuneError::ValueError(format!(
+                        "rate period must be between 1s and {}s",
+                        MAX_RATE_PERIOD.as_secs()
+                    )));
+                }
+                let a = vec![Restriction::new(vec![alternative(
+                    "rate",
+                    Condition::Equal,
+                    &format!("{}/{}", n, period.as_secs()),
+                )?])?];
+                Ok(a)
+            }
             DefRules::Add(rules) => {
                 let alt_set =
                     rules
@@ -118,6 +188,12 @@ impl<'a> Display for DefRules<'a> {
         match self {
             DefRules::ReadOnly => write!(f, "readonly"),
             DefRules::Pay => write!(f, "pay"),
+            DefRules::InvoiceOnly => write!(f, "invoice-only"),
+            DefRules::Watch => write!(f, "watch"),
+            DefRules::Onchain => write!(f, "onchain"),
+            DefRules::Lsp => write!(f, "lsp"),
+            DefRules::Expiring(d) => write!(f, "expires={}", fmt_duration(*d)),
+            DefRules::RateLimited(n, d) => write!(f, "rate={}/{}", n, fmt_duration(*d)),
             DefRules::Add(rules) => {
                 write!(
                     f,
@@ -135,6 +211,145 @@ impl<'a> Display for DefRules<'a> {
     }
 }
 
+impl FromStr for DefRules<'static> {
+    type Err = RuneError;
+
+    /// Parses the name of a rule set, as printed by `Display`, e.g.,
+    /// "invoice-only", "expires=30d" or "rate=10/1h".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, arg) = match s.split_once('=') {
+            Some((n, a)) => (n.trim(), Some(a.trim())),
+            None => (s, None),
+        };
+        match (name, arg) {
+            ("readonly", None) => Ok(DefRules::ReadOnly),
+            ("pay", None) => Ok(DefRules::Pay),
+            ("invoice-only", None) => Ok(DefRules::InvoiceOnly),
+            ("watch", None) => Ok(DefRules::Watch),
+            ("onchain", None) => Ok(DefRules::Onchain),
+            ("lsp", None) => Ok(DefRules::Lsp),
+            ("expires", Some(d)) => Ok(DefRules::Expiring(parse_duration(d)?)),
+            ("rate", Some(r)) => {
+                // A plain number is per minute, like in CLN's runes.
+                let (n, period) = match r.split_once('/') {
+                    Some((n, p)) => (n, parse_duration(p)?),
+                    None => (r, Duration::from_secs(60)),
+                };
+                let n = n
+                    .trim()
+                    .parse()
+                    .map_err(|_| RuneError::ValueError(format!("invalid rate {}", r)))?;
+                Ok(DefRules::RateLimited(n, period))
+            }
+            _ => Err(RuneError::ValueError(format!("unknown rule set {}", s))),
+        }
+    }
+}
+
+/// A combination of predefined rule sets in a compact form, e.g.,
+/// "invoice-only,expires=30d". The methods allowed by the rule sets
+/// are combined, so "watch,pay" allows the methods of both, while
+/// "expires" and "rate" restrict whatever is allowed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuneTemplate {
+    rules: Vec<DefRules<'static>>,
+}
+
+impl RuneTemplate {
+    /// Encode the restrictions in the form `create_rune` and the pairing
+    /// flows accept, e.g., "method=pay|method^list&time<1700000000".
+    pub fn encode(&self) -> Result<String, RuneError> {
+        Ok(self
+            .generate()?
+            .iter()
+            .map(|r| r.encode())
+            .collect::<Vec<String>>()
+            .join("&"))
+    }
+}
+
+impl FromStr for RuneTemplate {
+    type Err = RuneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split(',')
+            .filter(|r| !r.trim().is_empty())
+            .map(DefRules::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if rules.is_empty() {
+            return Err(RuneError::ValueError("empty rune template".to_string()));
+        }
+        Ok(RuneTemplate { rules })
+    }
+}
+
+impl Display for RuneTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rules: Vec<String> = self.rules.iter().map(|r| r.to_string()).collect();
+        write!(f, "{}", rules.join(","))
+    }
+}
+
+impl Restrictor for &RuneTemplate {
+    fn generate(self) -> Result<Vec<Restriction>, RuneError> {
+        let (limits, methods): (Vec<DefRules>, Vec<DefRules>) = self
+            .rules
+            .iter()
+            .partition(|r| matches!(r, DefRules::Expiring(_) | DefRules::RateLimited(..)));
+        let mut res = match methods.len() {
+            0 => vec![],
+            1 => methods[0].generate()?,
+            _ => DefRules::Add(&methods).generate()?,
+        };
+        for l in limits {
+            res.append(&mut l.generate()?);
+        }
+        Ok(res)
+    }
+}
+
+/// Restriction allowing any of the given methods.
+fn methods(methods: &[&str]) -> Result<Vec<Restriction>, RuneError> {
+    let alts = methods
+        .iter()
+        .map(|m| alternative("method", Condition::Equal, m))
+        .collect::<Result<Vec<Alternative>, RuneError>>()?;
+    Ok(vec![Restriction::new(alts)?])
+}
+
+/// Parses a duration such as "30d", "12h", "15m" or "60s". A plain
+/// number is in seconds.
+fn parse_duration(s: &str) -> Result<Duration, RuneError> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let num: u64 = num
+        .parse()
+        .map_err(|_| RuneError::ValueError(format!("invalid duration {}", s)))?;
+    let secs = match unit {
+        "s" => num,
+        "m" => num * 60,
+        "h" => num * 3600,
+        "d" => num * 24 * 3600,
+        _ => return Err(RuneError::ValueError(format!("invalid duration {}", s))),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// Formats a duration in the largest unit that represents it exactly.
+fn fmt_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    [(24 * 3600, "d"), (3600, "h"), (60, "m")]
+        .iter()
+        .find(|(u, _)| secs != 0 && secs.is_multiple_of(*u))
+        .map(|(u, n)| format!("{}{}", secs / u, n))
+        .unwrap_or_else(|| format!("{}s", secs))
+}
+
 /// Creates an `Alternative` based on the provided field, condition, and value.
 ///
 /// This function is a shorthand for creating new `Alternative` entities
@@ -165,7 +380,28 @@ pub struct Context {
     pub unique_id: String,
     // The timestamp associated with the request.
     pub time: SystemTime,
-    // Todo (nepet): Add param field that uses enum or serde to store the params  of a call.
+    // The parameters of the call.
+    pub params: Params,
+    // Earlier requests authorized by the same rune, in seconds since the
+    // epoch, to check rate limits against.
+    pub uses: Vec<u64>,
+}
+
+/// The parameters of a call that restrictions can refer to. Fields
+/// that are not set are missing from the context, so only the `!`
+/// condition passes on them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Params {
+    /// The amount the call sends or requests, in millisatoshi. For
+    /// payments this is the amount passed along with the call, or the
+    /// invoice amount if there is none.
+    pub amount_msat: Option<u64>,
+    /// The node id or address the call sends funds to.
+    pub destination: Option<String>,
+    /// The label of the payment or invoice.
+    pub label: Option<String>,
+    /// The amount encoded in the invoice being paid, in millisatoshi.
+    pub bolt11_amount: Option<u64>,
 }
 
 /// Implementation of the `Check` trait for the `Context` struct, allowing it to
@@ -181,6 +417,9 @@ impl Check for Context {
     ///
     /// * `Ok(())` if the check is successful, an `Err` containing a `RuneError` otherwise.
     fn check_alternative(&self, alt: &Alternative) -> anyhow::Result<(), RuneError> {
+        if alt.get_field() == "rate" && alt.get_condition() == Condition::Equal {
+            return self.check_rate(alt);
+        }
         let value = match alt.get_field().as_str() {
             "" => self.unique_id.clone(),
             "method" => self.method.clone(),
@@ -193,18 +432,56 @@ impl Check for Context {
                 })?
                 .as_secs()
                 .to_string(),
+            "amount_msat" => opt_string(&self.params.amount_msat),
+            "destination" => opt_string(&self.params.destination),
+            "label" => opt_string(&self.params.label),
+            "bolt11_amount" => opt_string(&self.params.bolt11_amount),
             _ => String::new(), // If we don't know the field we can not set it!
         };
         ConditionChecker { value }.check_alternative(alt)
     }
 }
 
+impl Context {
+    /// Checks a "rate=[n]/[period]" alternative, i.e., that fewer than `n`
+    /// requests used the rune within the last `period` seconds. A rate
+    /// without a period is per minute.
+    fn check_rate(&self, alt: &Alternative) -> anyhow::Result<(), RuneError> {
+        let value = alt.get_value();
+        let invalid = || RuneError::ValueError(format!("rate: invalid value {}", value));
+        let (n, period) = match value.split_once('/') {
+            Some((n, p)) => (n, p.parse::<u64>().map_err(|_| invalid())?),
+            None => (value.as_str(), 60),
+        };
+        let n: usize = n.parse().map_err(|_| invalid())?;
+        let now = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| RuneError::Unknown(e.to_string()))?
+            .as_secs();
+        let recent = self.uses.iter().filter(|t| *t + period > now).count();
+        if recent < n {
+            Ok(())
+        } else {
+            Err(RuneError::ValueError(format!(
+                "rate: exceeded {} requests per {}s",
+                n, period
+            )))
+        }
+    }
+}
+
+/// Missing values are represented by an empty string.
+fn opt_string<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Context, DefRules, RuneFactory};
+    use super::{Context, DefRules, Params, Restrictor, RuneFactory, RuneTemplate};
     use base64::{engine::general_purpose, Engine as _};
     use runeauth::{Alternative, Condition, Restriction, Rune};
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn test_carve_readonly_rune() {
@@ -326,6 +603,8 @@ mod tests {
             pubkey: String::from("020000000000000000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r1.are_restrictions_met(ctx).is_ok());
         // Check with method="ListFunds", pubkey=020000000000000000
@@ -334,6 +613,8 @@ mod tests {
             pubkey: String::from("020000000000000000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r1.are_restrictions_met(ctx).is_ok());
         // Check with method="GetInfo", pubkey=""
@@ -342,6 +623,8 @@ mod tests {
             pubkey: String::new(),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r2.are_restrictions_met(ctx).is_ok());
         // Check with method="GetInfo", pubkey="020000000000000000"
@@ -350,6 +633,8 @@ mod tests {
             pubkey: String::from("020000000000000000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r2.are_restrictions_met(ctx).is_ok());
         // Check with method="GetInfo", pubkey=""
@@ -358,6 +643,8 @@ mod tests {
             pubkey: String::new(),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r3.are_restrictions_met(ctx).is_ok());
         // Check with method="", pubkey="020000"
@@ -366,6 +653,8 @@ mod tests {
             pubkey: String::from("020000000000000000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r4.are_restrictions_met(ctx).is_ok());
 
@@ -376,6 +665,8 @@ mod tests {
             pubkey: String::from("030000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r1.are_restrictions_met(ctx).is_err());
         // Check with method="ListFunds", pubkey=030000, wrong method.
@@ -384,6 +675,8 @@ mod tests {
             pubkey: String::from("030000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r2.are_restrictions_met(ctx).is_err());
         // Check with pubkey=030000, pubkey present.
@@ -392,6 +685,8 @@ mod tests {
             pubkey: String::from("030000"),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r3.are_restrictions_met(ctx).is_err());
         // Check with method="GetInfo", method present.
@@ -400,7 +695,108 @@ mod tests {
             pubkey: String::new(),
             time: SystemTime::now(),
             unique_id: String::new(),
+            params: Params::default(),
+            uses: vec![],
         };
         assert!(r4.are_restrictions_met(ctx).is_err());
     }
+
+    #[test]
+    fn test_param_restrictions() {
+        let mr = Rune::new_master_rune(&[0; 32], vec![], None, None).unwrap();
+        let rune = Rune::new(
+            mr.authcode(),
+            vec![
+                Restriction::try_from("amount_msat<100000").unwrap(),
+                Restriction::try_from("label^pos-|label!").unwrap(),
+            ],
+            None,
+            None,
+        )
+        .unwrap();
+        let ctx = |amount_msat: Option<u64>, label: Option<&str>| Context {
+            method: String::from("pay"),
+            pubkey: String::new(),
+            time: SystemTime::now(),
+            unique_id: String::new(),
+            params: Params {
+                amount_msat,
+                label: label.map(|l| l.to_string()),
+                ..Default::default()
+            },
+            uses: vec![],
+        };
+
+        assert!(rune.are_restrictions_met(ctx(Some(99_999), None)).is_ok());
+        assert!(rune
+            .are_restrictions_met(ctx(Some(1_000), Some("pos-1")))
+            .is_ok());
+        assert!(rune.are_restrictions_met(ctx(Some(100_000), None)).is_err());
+        assert!(rune
+            .are_restrictions_met(ctx(Some(1_000), Some("web-1")))
+            .is_err());
+        // A cap on the amount does not pass if the amount is unknown.
+        assert!(rune.are_restrictions_met(ctx(None, None)).is_err());
+    }
+
+    #[test]
+    fn test_rune_template() {
+        let t: RuneTemplate = "invoice-only, pay,expires=30d,rate=10/1h".parse().unwrap();
+        assert_eq!(t.to_string(), "invoice-only,pay,expires=30d,rate=10/1h");
+
+        let encoded = t.encode().unwrap();
+        let restrs: Vec<&str> = encoded.split('&').collect();
+        assert_eq!(restrs.len(), 3);
+        assert!(restrs[0].starts_with("method=invoice|"));
+        assert!(restrs[0].ends_with("|method=pay"));
+        assert!(restrs[1].starts_with("time<"));
+        assert_eq!(restrs[2], "rate=10/3600");
+
+        assert_eq!(
+            "rate=5".parse::<DefRules>().unwrap(),
+            DefRules::RateLimited(5, Duration::from_secs(60))
+        );
+        assert!("method=pay".parse::<RuneTemplate>().is_err());
+        assert!("expires=30x".parse::<RuneTemplate>().is_err());
+        assert!("rate=1/2d"
+            .parse::<RuneTemplate>()
+            .unwrap()
+            .encode()
+            .is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mr = Rune::new_master_rune(&[0; 32], vec![], None, None).unwrap();
+        let rune = Rune::new(
+            mr.authcode(),
+            DefRules::RateLimited(2, Duration::from_secs(60))
+                .generate()
+                .unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let ctx = |uses: Vec<u64>| Context {
+            method: String::from("pay"),
+            pubkey: String::new(),
+            time: SystemTime::now(),
+            unique_id: String::new(),
+            params: Params::default(),
+            uses,
+        };
+
+        assert!(rune.are_restrictions_met(ctx(vec![now - 10])).is_ok());
+        assert!(rune
+            .are_restrictions_met(ctx(vec![now - 10, now - 5]))
+            .is_err());
+        // Uses outside of the period don't count.
+        assert!(rune
+            .are_restrictions_met(ctx(vec![now - 100, now - 5]))
+            .is_ok());
+    }
 }
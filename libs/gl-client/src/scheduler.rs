@@ -435,6 +435,18 @@ where
             .await
     }
 
+    /// Like `node`, but the client schedules the node again when it
+    /// goes offline, and retries the calls `policy` allows. See
+    /// [`node::reconnect`].
+    pub async fn node_reconnecting<T>(&self, policy: node::reconnect::RetryPolicy) -> Result<T>
+    where
+        T: GrpcClient,
+    {
+        node::Node::new(self.creds.node_id()?, self.creds.clone())?
+            .schedule_reconnecting_with_uri(self.grpc_uri.clone(), policy)
+            .await
+    }
+
     pub async fn get_node_info(&self, wait: bool) -> Result<pb::scheduler::NodeInfoResponse> {
         Ok(self
             .client
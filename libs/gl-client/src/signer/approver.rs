@@ -1,4 +1,11 @@
+use super::limits::LimitExceeded;
+use async_trait::async_trait;
+use lightning_signer::bitcoin::hashes::Hash;
+use lightning_signer::bitcoin::{Address, Network, Psbt};
+use lightning_signer::invoice::{Invoice, InvoiceAttributes};
 use lightning_signer::prelude::SendSync;
+use std::sync::Arc;
+use std::time::Duration;
 use vls_protocol_signer::approver::Approve;
 
 // An approver that will collect any request it gets and files a
@@ -46,3 +53,244 @@ impl<A: Approve> Approve for ReportingApprover<A> {
     }
 }
 impl<A: Approve> SendSync for ReportingApprover<A> {}
+
+/// Default time we wait for the user to answer an approval request
+/// before denying it.
+pub const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An output of an on-chain transaction that does not go to one of
+/// our own wallet addresses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnchainOutput {
+    pub script_pubkey: Vec<u8>,
+    /// The address for `script_pubkey`, if it has a standard form.
+    pub address: Option<String>,
+    pub amount_sat: u64,
+}
+
+/// A request the signer could not match up with a signed request
+/// from the user, and that needs an explicit approval before we
+/// sign it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApprovalRequest {
+    /// Pay a BOLT11 or BOLT12 invoice.
+    Invoice {
+        payee: Vec<u8>,
+        payment_hash: Vec<u8>,
+        amount_msat: u64,
+        description: Option<String>,
+    },
+    /// Send a keysend payment. The destination is not always known.
+    Keysend {
+        destination: Option<Vec<u8>>,
+        payment_hash: Vec<u8>,
+        amount_msat: u64,
+    },
+    /// Send funds on-chain to one or more external outputs.
+    Onchain {
+        txid: Vec<u8>,
+        outputs: Vec<OnchainOutput>,
+        /// Unknown if the PSBT lacks the amount of an input.
+        fee_sat: Option<u64>,
+    },
+    /// A payment that would exceed one of the spending limits.
+    OverLimit {
+        limit_msat: u64,
+        window_secs: u64,
+        /// Set for per-destination limits.
+        destination: Option<String>,
+        spent_msat: u64,
+        amount_msat: u64,
+    },
+    /// Any other request, with a textual description of the message.
+    Unresolved { message: String },
+}
+
+impl From<&LimitExceeded> for ApprovalRequest {
+    fn from(e: &LimitExceeded) -> Self {
+        ApprovalRequest::OverLimit {
+            limit_msat: e.limit.limit_msat,
+            window_secs: e.limit.window_secs,
+            destination: e.destination.clone(),
+            spent_msat: e.spent_msat,
+            amount_msat: e.amount_msat,
+        }
+    }
+}
+
+impl ApprovalRequest {
+    pub(crate) fn from_invoice(inv: &Invoice) -> Self {
+        ApprovalRequest::Invoice {
+            payee: inv.payee_pub_key().serialize().to_vec(),
+            payment_hash: inv.payment_hash().0.to_vec(),
+            amount_msat: inv.amount_milli_satoshis(),
+            description: inv.description(),
+        }
+    }
+
+    /// Describe the outputs of `psbt` that leave the wallet. Outputs
+    /// to our own wallet carry their derivation path.
+    pub(crate) fn from_psbt(psbt: &Psbt, network: Network) -> Self {
+        let tx = &psbt.unsigned_tx;
+        let outputs = tx
+            .output
+            .iter()
+            .zip(psbt.outputs.iter())
+            .filter(|(_, o)| o.bip32_derivation.is_empty() && o.tap_key_origins.is_empty())
+            .map(|(o, _)| OnchainOutput {
+                script_pubkey: o.script_pubkey.to_bytes(),
+                address: Address::from_script(&o.script_pubkey, network)
+                    .ok()
+                    .map(|a| a.to_string()),
+                amount_sat: o.value.to_sat(),
+            })
+            .collect();
+        ApprovalRequest::Onchain {
+            txid: tx.compute_txid().to_byte_array().to_vec(),
+            outputs,
+            fee_sat: psbt.fee().ok().map(|f| f.to_sat()),
+        }
+    }
+}
+
+/// Lets an application decide on requests the signer cannot justify
+/// on its own, e.g., by asking the user for confirmation. Requests
+/// that are not approved within the configured timeout are denied.
+#[async_trait]
+pub trait ApprovalHandler: Send + Sync {
+    async fn approve(&self, request: ApprovalRequest) -> bool;
+}
+
+/// Asks an `ApprovalHandler`, and denies if it does not answer in
+/// time.
+#[derive(Clone)]
+pub(crate) struct UserApprover {
+    handler: Arc<dyn ApprovalHandler>,
+    timeout: Duration,
+}
+
+impl UserApprover {
+    pub fn new(handler: Arc<dyn ApprovalHandler>, timeout: Duration) -> Self {
+        UserApprover { handler, timeout }
+    }
+
+    pub async fn ask(&self, request: ApprovalRequest) -> bool {
+        log::info!("Asking for approval of {:?}", request);
+        match tokio::time::timeout(self.timeout, self.handler.approve(request)).await {
+            Ok(approved) => approved,
+            Err(_) => {
+                log::warn!("Approval timed out after {:?}, denying", self.timeout);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(bool, Duration);
+
+    #[async_trait]
+    impl ApprovalHandler for Fixed {
+        async fn approve(&self, _request: ApprovalRequest) -> bool {
+            tokio::time::sleep(self.1).await;
+            self.0
+        }
+    }
+
+    fn keysend() -> ApprovalRequest {
+        ApprovalRequest::Keysend {
+            destination: None,
+            payment_hash: vec![0u8; 32],
+            amount_msat: 1000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_approver() {
+        let approve = UserApprover::new(
+            Arc::new(Fixed(true, Duration::ZERO)),
+            Duration::from_secs(5),
+        );
+        assert!(approve.ask(keysend()).await);
+
+        let deny = UserApprover::new(
+            Arc::new(Fixed(false, Duration::ZERO)),
+            Duration::from_secs(5),
+        );
+        assert!(!deny.ask(keysend()).await);
+    }
+
+    #[tokio::test]
+    async fn test_user_approver_timeout() {
+        let slow = UserApprover::new(
+            Arc::new(Fixed(true, Duration::from_secs(10))),
+            Duration::from_millis(50),
+        );
+        assert!(!slow.ask(keysend()).await);
+    }
+
+    #[test]
+    fn test_onchain_request() {
+        use lightning_signer::bitcoin::absolute::LockTime;
+        use lightning_signer::bitcoin::bip32::{DerivationPath, Fingerprint};
+        use lightning_signer::bitcoin::secp256k1::{Secp256k1, SecretKey};
+        use lightning_signer::bitcoin::transaction::Version;
+        use lightning_signer::bitcoin::{Amount, ScriptBuf, Transaction, TxIn, TxOut};
+        use std::str::FromStr;
+
+        let dest = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        let out = |sat: u64, script: ScriptBuf| TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: script,
+        };
+        let dest_script = Address::from_str(dest)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                out(10_000, dest_script.clone()),
+                out(5_000, dest_script.clone()),
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        // The second output is change to our wallet.
+        let key = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        psbt.outputs[1]
+            .bip32_derivation
+            .insert(key, (Fingerprint::from([0; 4]), DerivationPath::master()));
+
+        let txid = psbt.unsigned_tx.compute_txid().to_byte_array().to_vec();
+        let expected_outputs = vec![OnchainOutput {
+            script_pubkey: dest_script.to_bytes(),
+            address: Some(dest.to_string()),
+            amount_sat: 10_000,
+        }];
+        assert_eq!(
+            ApprovalRequest::from_psbt(&psbt, Network::Bitcoin),
+            ApprovalRequest::Onchain {
+                txid: txid.clone(),
+                outputs: expected_outputs.clone(),
+                fee_sat: None,
+            }
+        );
+
+        psbt.inputs[0].witness_utxo = Some(out(15_500, dest_script));
+        assert_eq!(
+            ApprovalRequest::from_psbt(&psbt, Network::Bitcoin),
+            ApprovalRequest::Onchain {
+                txid,
+                outputs: expected_outputs,
+                fee_sat: Some(500),
+            }
+        );
+    }
+}
@@ -0,0 +1,478 @@
+//! Local audit journal of the requests the signer processed.
+//!
+//! Every request the signer signs or rejects is appended to the
+//! journal along with the user requests that justified it. Each
+//! entry commits to its predecessor with an HMAC keyed by a secret
+//! derived from the signer secret, so entries can't be edited, or
+//! the journal rewritten, without the seed. The journal also keeps a
+//! head, authenticated the same way, with the first and the last
+//! entry it holds, so entries can't be cut from either end unnoticed.
+//! What remains possible is to restore an older copy of the journal
+//! along with its head.
+
+use super::report::{rotate_files, rotated_path};
+use anyhow::{anyhow, Result};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The hash preceding the first entry of a journal.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Used to derive the journal key from the signer secret.
+pub(crate) const AUDIT_KEY_DERIVATION: &str = "gl-audit-journal";
+
+/// A signed user request that was pending when the signer handled
+/// a request, i.e., the authority the signer acted on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditContext {
+    /// The grpc method, e.g., `/cln.Node/Pay`.
+    pub uri: String,
+    /// Hex-encoded public key of the client that signed the request.
+    pub pubkey: String,
+    /// Unique id of the rune that authorized the request, if any.
+    pub rune_id: Option<String>,
+    /// The decoded request.
+    pub request: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Signed,
+    Rejected { reason: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    /// The signer message type, e.g., `SignWithdrawal`.
+    pub request: String,
+    pub context: Vec<AuditContext>,
+    /// The value the request sends out of the wallet, if any.
+    pub amount_msat: Option<u64>,
+    pub outcome: AuditOutcome,
+    /// Hash of the previous entry.
+    pub prev_hash: String,
+    /// HMAC of this entry, covering all fields above.
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self, key: &hmac::Key) -> String {
+        let mut e = self.clone();
+        e.hash = String::new();
+        let data = serde_json::to_vec(&e).expect("audit entries serialize");
+        hex::encode(hmac::sign(key, &data).as_ref())
+    }
+}
+
+/// The range of entries a journal holds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditHead {
+    /// Sequence number of the oldest entry that was not rotated out.
+    pub first_seq: u64,
+    /// Sequence number and hash of the last entry.
+    pub last_seq: u64,
+    pub last_hash: String,
+    /// HMAC of the fields above.
+    pub mac: String,
+}
+
+impl AuditHead {
+    fn new(first_seq: u64, last: &AuditEntry, key: &hmac::Key) -> Self {
+        let mut head = AuditHead {
+            first_seq,
+            last_seq: last.seq,
+            last_hash: last.hash.clone(),
+            mac: String::new(),
+        };
+        head.mac = head.compute_mac(key);
+        head
+    }
+
+    fn compute_mac(&self, key: &hmac::Key) -> String {
+        let data = format!("{}:{}:{}", self.first_seq, self.last_seq, self.last_hash);
+        hex::encode(hmac::sign(key, data.as_bytes()).as_ref())
+    }
+}
+
+/// Where the journal is kept. Implementations must only ever append,
+/// and only drop the oldest entries.
+pub trait AuditLog: Send + Sync {
+    /// Append `entry`. If older entries were dropped to make room,
+    /// returns the sequence number of the oldest entry still kept.
+    fn append(&self, entry: &AuditEntry) -> Result<Option<u64>>;
+    fn entries(&self) -> Result<Vec<AuditEntry>>;
+    fn head(&self) -> Result<Option<AuditHead>>;
+    fn set_head(&self, head: &AuditHead) -> Result<()>;
+}
+
+/// Keeps the journal in a file with one JSON entry per line, and
+/// its head in `<path>.head`. Once the file reaches `max_bytes` it is
+/// rotated to `<path>.1`, and so on, keeping `keep` rotated files.
+/// The entries of older files are dropped.
+pub struct FileAuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    lock: Mutex<()>,
+}
+
+impl FileAuditLog {
+    pub fn new(path: impl AsRef<Path>, max_bytes: u64, keep: usize) -> Self {
+        FileAuditLog {
+            path: path.as_ref().to_path_buf(),
+            max_bytes,
+            keep,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn head_path(&self) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(".head");
+        p.into()
+    }
+
+    fn read(path: &Path) -> Result<Vec<AuditEntry>> {
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        BufReader::new(f)
+            .lines()
+            .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()))
+            .map(|l| Ok(serde_json::from_str(&l?)?))
+            .collect()
+    }
+
+    fn read_all(&self) -> Result<Vec<AuditEntry>> {
+        let mut entries = vec![];
+        for i in (1..=self.keep).rev() {
+            entries.extend(Self::read(&rotated_path(&self.path, i))?);
+        }
+        entries.extend(Self::read(&self.path)?);
+        Ok(entries)
+    }
+}
+
+impl AuditLog for FileAuditLog {
+    fn append(&self, entry: &AuditEntry) -> Result<Option<u64>> {
+        let _guard = self.lock.lock().unwrap();
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        let rotate = size >= self.max_bytes;
+        if rotate {
+            rotate_files(&self.path, self.keep)?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        f.write_all(&line)?;
+        f.sync_data()?;
+        match rotate {
+            true => Ok(self.read_all()?.first().map(|e| e.seq)),
+            false => Ok(None),
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>> {
+        let _guard = self.lock.lock().unwrap();
+        self.read_all()
+    }
+
+    fn head(&self) -> Result<Option<AuditHead>> {
+        match fs::read(self.head_path()) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_head(&self, head: &AuditHead) -> Result<()> {
+        // Replace the head in one step, so it is never half written.
+        let mut tmp = self.head_path().into_os_string();
+        tmp.push(".tmp");
+        let mut f = File::create(&tmp)?;
+        f.write_all(&serde_json::to_vec(head)?)?;
+        f.sync_data()?;
+        fs::rename(&tmp, self.head_path())?;
+        Ok(())
+    }
+}
+
+/// Keeps the journal in memory only.
+#[derive(Default)]
+pub struct MemoryAuditLog {
+    entries: Mutex<Vec<AuditEntry>>,
+    head: Mutex<Option<AuditHead>>,
+}
+
+impl AuditLog for MemoryAuditLog {
+    fn append(&self, entry: &AuditEntry) -> Result<Option<u64>> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(None)
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>> {
+        Ok(self.entries.lock().unwrap().clone())
+    }
+
+    fn head(&self) -> Result<Option<AuditHead>> {
+        Ok(self.head.lock().unwrap().clone())
+    }
+
+    fn set_head(&self, head: &AuditHead) -> Result<()> {
+        *self.head.lock().unwrap() = Some(head.clone());
+        Ok(())
+    }
+}
+
+/// Check that `entries` form an unbroken chain under `key`, from the
+/// first to the last entry recorded in `head`.
+pub(crate) fn verify_chain(
+    entries: &[AuditEntry],
+    head: Option<&AuditHead>,
+    key: &hmac::Key,
+) -> Result<()> {
+    let head = match head {
+        Some(h) => h,
+        None if entries.is_empty() => return Ok(()),
+        None => return Err(anyhow!("the journal has no head")),
+    };
+    if head.compute_mac(key) != head.mac {
+        return Err(anyhow!("the head of the journal was modified"));
+    }
+    let mut seq = head.first_seq;
+    let mut prev = match entries.first() {
+        Some(e) if e.seq == 0 => GENESIS_HASH.to_string(),
+        Some(e) => e.prev_hash.clone(),
+        None => return Err(anyhow!("entries {} and later are missing", seq)),
+    };
+    for e in entries.iter() {
+        if e.seq != seq {
+            return Err(anyhow!("entry {} has sequence number {}", seq, e.seq));
+        }
+        if e.prev_hash != prev {
+            return Err(anyhow!("entry {} does not follow its predecessor", e.seq));
+        }
+        if e.compute_hash(key) != e.hash {
+            return Err(anyhow!("entry {} was modified", e.seq));
+        }
+        prev = e.hash.clone();
+        seq += 1;
+    }
+    if seq != head.last_seq + 1 || prev != head.last_hash {
+        return Err(anyhow!("entries {} and later are missing", seq));
+    }
+    Ok(())
+}
+
+/// Selects entries from the journal. Unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct AuditQuery {
+    /// Only entries at or after this time, in seconds since the epoch.
+    pub since: Option<u64>,
+    /// Only entries before this time, in seconds since the epoch.
+    pub until: Option<u64>,
+    /// Only entries for this signer message type.
+    pub request: Option<String>,
+    /// Only rejected requests.
+    pub rejected_only: bool,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, e: &AuditEntry) -> bool {
+        !matches!(self.since, Some(t) if e.timestamp < t)
+            && !matches!(self.until, Some(t) if e.timestamp >= t)
+            && !matches!(&self.request, Some(r) if r != &e.request)
+            && (!self.rejected_only || e.outcome != AuditOutcome::Signed)
+    }
+}
+
+/// Appends entries to an `AuditLog`, linking each to the previous.
+pub(crate) struct AuditJournal {
+    log: Arc<dyn AuditLog>,
+    key: hmac::Key,
+    /// The head as of the last entry we appended.
+    head: Mutex<Option<AuditHead>>,
+}
+
+impl AuditJournal {
+    /// `secret` is the key of the journal, derived from the signer
+    /// secret.
+    pub fn new(log: Arc<dyn AuditLog>, secret: &[u8]) -> Self {
+        AuditJournal {
+            log,
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+            head: Mutex::new(None),
+        }
+    }
+
+    pub fn append(
+        &self,
+        timestamp: u64,
+        request: String,
+        context: Vec<AuditContext>,
+        amount_msat: Option<u64>,
+        outcome: AuditOutcome,
+    ) -> Result<AuditEntry> {
+        let mut head = self.head.lock().unwrap();
+        // Pick up where the log left off the first time we append,
+        // but only from a head we wrote.
+        if head.is_none() {
+            *head = self.log.head()?;
+            verify_chain(&self.log.entries()?, head.as_ref(), &self.key)?;
+        }
+        let (first_seq, seq, prev_hash) = match head.as_ref() {
+            Some(h) => (h.first_seq, h.last_seq + 1, h.last_hash.clone()),
+            None => (0, 0, GENESIS_HASH.to_string()),
+        };
+
+        let mut entry = AuditEntry {
+            seq,
+            timestamp,
+            request,
+            context,
+            amount_msat,
+            outcome,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash(&self.key);
+        let first_seq = self.log.append(&entry)?.unwrap_or(first_seq);
+        let new_head = AuditHead::new(first_seq, &entry, &self.key);
+        self.log.set_head(&new_head)?;
+        *head = Some(new_head);
+        Ok(entry)
+    }
+
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let entries = self.log.entries()?;
+        verify_chain(&entries, self.log.head()?.as_ref(), &self.key)?;
+        Ok(entries.into_iter().filter(|e| query.matches(e)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = &[7; 32];
+
+    fn append(j: &AuditJournal, timestamp: u64, outcome: AuditOutcome) {
+        j.append(
+            timestamp,
+            "SignWithdrawal".to_string(),
+            vec![],
+            None,
+            outcome,
+        )
+        .unwrap();
+    }
+
+    fn verify(entries: &[AuditEntry], log: &dyn AuditLog) -> Result<()> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET);
+        verify_chain(entries, log.head().unwrap().as_ref(), &key)
+    }
+
+    #[test]
+    fn test_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = Arc::new(FileAuditLog::new(
+            dir.path().join("audit.jsonl"),
+            u64::MAX,
+            0,
+        ));
+        let journal = AuditJournal::new(log.clone(), SECRET);
+        append(&journal, 10, AuditOutcome::Signed);
+        append(
+            &journal,
+            20,
+            AuditOutcome::Rejected {
+                reason: "no".to_string(),
+            },
+        );
+
+        // A new journal continues the chain.
+        let journal = AuditJournal::new(log.clone(), SECRET);
+        append(&journal, 30, AuditOutcome::Signed);
+
+        let all = journal.query(&AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2].prev_hash, all[1].hash);
+
+        let rejected = journal
+            .query(&AuditQuery {
+                rejected_only: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].timestamp, 20);
+
+        let mut tampered = all.clone();
+        tampered[1].outcome = AuditOutcome::Signed;
+        assert!(verify(&tampered, log.as_ref()).is_err());
+        tampered.remove(1);
+        assert!(verify(&tampered, log.as_ref()).is_err());
+
+        // Entries can't be cut from either end.
+        assert!(verify(&all[1..], log.as_ref()).is_err());
+        assert!(verify(&all[..2], log.as_ref()).is_err());
+
+        // Nor can the journal be rewritten without the key.
+        let mut rewritten = all.clone();
+        rewritten[2].outcome = AuditOutcome::Rejected {
+            reason: "no".to_string(),
+        };
+        rewritten[2].hash = {
+            use lightning_signer::bitcoin::hashes::{sha256, Hash};
+            let mut e = rewritten[2].clone();
+            e.hash = String::new();
+            sha256::Hash::hash(&serde_json::to_vec(&e).unwrap()).to_string()
+        };
+        assert!(verify(&rewritten, log.as_ref()).is_err());
+
+        // A journal keyed by another secret doesn't verify.
+        let other = AuditJournal::new(log.clone(), &[8; 32]);
+        assert!(other.query(&AuditQuery::default()).is_err());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        // Every entry goes to a new file.
+        let log = Arc::new(FileAuditLog::new(&path, 1, 2));
+        let journal = AuditJournal::new(log.clone(), SECRET);
+        for t in 0..4 {
+            append(&journal, t, AuditOutcome::Signed);
+        }
+        assert!(dir.path().join("audit.jsonl.2").exists());
+        assert!(!dir.path().join("audit.jsonl.3").exists());
+
+        // The first entry was dropped, the rest still form a chain.
+        let entries = journal.query(&AuditQuery::default()).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        let mut tampered = entries.clone();
+        tampered.remove(1);
+        assert!(verify(&tampered, log.as_ref()).is_err());
+
+        // Removing the oldest file is noticed.
+        fs::remove_file(dir.path().join("audit.jsonl.2")).unwrap();
+        assert!(journal.query(&AuditQuery::default()).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use super::approver::UserApprover;
+use super::audit::{AuditJournal, AuditLog, AUDIT_KEY_DERIVATION};
+use super::pairing::PairingQueue;
+use super::report::{ReportSink, SchedulerReportSink};
+use super::rune_registry::RuneUses;
+use super::{
+    ApprovalHandler, Signer, SignerPolicy, GITHASH, RUNE_DERIVATION_SECRET, RUNE_VERSION,
+    STATE_DERIVATION_SECRET, VERSION,
+};
+use crate::credentials::TlsConfigProvider;
+use crate::persist::{MemoryPersister, StateStore};
+use crate::tls::TlsConfig;
+use anyhow::{anyhow, Result};
+use lightning_signer::bitcoin::Network;
+use lightning_signer::node::NodeServices;
+use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+use lightning_signer::signer::ClockStartingTimeFactory;
+use lightning_signer::util::clock::StandardClock;
+use lightning_signer::util::crypto_utils;
+use log::{info, trace};
+use runeauth::Rune;
+use std::sync::Arc;
+use std::time::Duration;
+use vls_protocol::msgs::{DeBolt, HsmdInitReplyV4, SerBolt};
+use vls_protocol_signer::handler;
+
+/// Configures and creates a `Signer`. Anything that is not set
+/// explicitly uses the same defaults as `Signer::new`.
+pub struct SignerBuilder {
+    secret: Vec<u8>,
+    network: Network,
+    tls: TlsConfig,
+    policy: SignerPolicy,
+    store: Option<Arc<dyn StateStore>>,
+    approval: Option<(Arc<dyn ApprovalHandler>, Duration)>,
+    audit: Option<Arc<dyn AuditLog>>,
+    reporter: Option<Arc<dyn ReportSink>>,
+    pairing: Option<Duration>,
+}
+
+impl SignerBuilder {
+    pub fn new<T>(secret: Vec<u8>, network: Network, creds: T) -> Self
+    where
+        T: TlsConfigProvider,
+    {
+        SignerBuilder {
+            secret,
+            network,
+            tls: creds.tls_config(),
+            policy: SignerPolicy::default(),
+            store: None,
+            approval: None,
+            audit: None,
+            reporter: None,
+            pairing: None,
+        }
+    }
+
+    /// Use `policy` instead of the default policy profile.
+    pub fn with_policy(mut self, policy: SignerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Load the signer state from, and write any change through to,
+    /// `store`. Without a store the state is only kept in memory.
+    pub fn with_state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Ask `handler` to approve requests the signer cannot resolve
+    /// on its own. See `Signer::with_approval_handler`.
+    pub fn with_approval_handler(
+        mut self,
+        handler: Arc<dyn ApprovalHandler>,
+        timeout: Duration,
+    ) -> Self {
+        self.approval = Some((handler, timeout));
+        self
+    }
+
+    /// Record every request the signer signs or rejects in `log`.
+    pub fn with_audit_log(mut self, log: Arc<dyn AuditLog>) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Deliver rejection reports to `sink` instead of uploading them
+    /// to the scheduler.
+    pub fn with_report_sink(mut self, sink: Arc<dyn ReportSink>) -> Self {
+        self.reporter = Some(sink);
+        self
+    }
+
+    /// Hold pairing requests until the app answers them. See
+    /// `Signer::with_pairing_queue`.
+    pub fn with_pairing_queue(mut self, timeout: Duration) -> Self {
+        self.pairing = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> Result<Signer> {
+        info!("Initializing signer for {VERSION} ({GITHASH}) (VLS)");
+        let mut sec: [u8; 32] = [0; 32];
+        sec.copy_from_slice(&self.secret[0..32]);
+        let network = self.network;
+
+        // The persister takes care of persisting metadata across
+        // restarts. The values the signer keeps for itself are
+        // authenticated with a key derived from the seed, so the node
+        // can't tamper with them.
+        let state_secret = crypto_utils::hkdf_sha256(&sec, STATE_DERIVATION_SECRET.as_bytes(), &[]);
+        let persister = Arc::new(match self.store {
+            Some(store) => MemoryPersister::with_store(store, state_secret)?,
+            None => MemoryPersister::new(state_secret),
+        });
+
+        let policy = self.policy.to_simple_policy(network);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
+        let starting_time_factory = ClockStartingTimeFactory::new();
+        let clock = Arc::new(StandardClock());
+
+        let services = NodeServices {
+            validator_factory,
+            starting_time_factory,
+            persister: persister.clone(),
+            trusted_oracle_pubkeys: vec![],
+            clock,
+        };
+
+        let mut handler = handler::HandlerBuilder::new(network, 0, services.clone(), sec)
+            .build()
+            .map_err(|e| anyhow!("building root_handler: {:?}", e))?;
+
+        // Calling init on the `InitHandler` from above puts it into a
+        // state that it can be upgraded into the `RootHandler` that
+        // we need for the rest of the run.
+        let init = Signer::initmsg(&mut handler)?;
+
+        let init = HsmdInitReplyV4::from_vec(init)
+            .map_err(|e| anyhow!("Failed to parse init message as HsmdInitReplyV4: {:?}", e))?;
+
+        let id = init.node_id.0.to_vec();
+        let init = init.as_vec();
+
+        // Init master rune. We create the rune seed from the nodes
+        // seed by deriving a hardened key tagged with "rune secret".
+        let rune_secret = crypto_utils::hkdf_sha256(&sec, RUNE_DERIVATION_SECRET.as_bytes(), &[]);
+        let mr = Rune::new_master_rune(&rune_secret, vec![], None, Some(RUNE_VERSION.to_string()))?;
+
+        trace!("Initialized signer for node_id={}", hex::encode(&id));
+        Ok(Signer {
+            secret: sec,
+            master_rune: mr,
+            services,
+            tls: self.tls,
+            id,
+            init,
+            network,
+            state: persister.state(),
+            persister,
+            approver: self
+                .approval
+                .map(|(handler, timeout)| UserApprover::new(handler, timeout)),
+            spending_limits: self.policy.spending_limits,
+            over_limit: self.policy.over_limit,
+            audit: self.audit.map(|log| {
+                let key = crypto_utils::hkdf_sha256(&sec, AUDIT_KEY_DERIVATION.as_bytes(), &[]);
+                Arc::new(AuditJournal::new(log, &key))
+            }),
+            reporter: self
+                .reporter
+                .unwrap_or_else(|| Arc::new(SchedulerReportSink::default())),
+            rune_uses: Arc::new(RuneUses::default()),
+            pairing: self
+                .pairing
+                .map(|timeout| Arc::new(PairingQueue::new(timeout))),
+        })
+    }
+}
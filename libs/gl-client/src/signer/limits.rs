@@ -0,0 +1,386 @@
+//! Spending limits enforced by the signer.
+//!
+//! VLS only knows about the requests it signs, not about what the
+//! user intended to spend over time. The signer therefore tracks
+//! outgoing payments and on-chain transfers in a ledger that is
+//! persisted along with the rest of the signer state, and refuses
+//! to sign once a limit would be exceeded. Since the limits are
+//! checked in the signer they hold even if the node or an API key
+//! is compromised: the ledger carries a MAC only the signer can
+//! create, so the node can't alter it. Without a local `StateStore`
+//! a restarted signer has to trust the node to hand it the latest
+//! ledger though.
+
+use super::model::Request;
+use lightning_signer::bitcoin::{Address, Network};
+use lightning_signer::invoice::{Invoice, InvoiceAttributes};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+use vls_protocol::msgs::Message;
+
+/// Key under which the ledger is stored in the signer state.
+pub(crate) const SPENDING_LEDGER_KEY: &str = "spending/ledger";
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+
+/// Caps the amount sent within a sliding window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendingLimit {
+    pub limit_msat: u64,
+    /// Length of the window in seconds.
+    pub window_secs: u64,
+    /// Apply the limit to each destination separately, instead of
+    /// to the sum of all payments.
+    #[serde(default)]
+    pub per_destination: bool,
+}
+
+impl SpendingLimit {
+    pub fn hourly(limit_msat: u64) -> Self {
+        SpendingLimit {
+            limit_msat,
+            window_secs: HOUR,
+            per_destination: false,
+        }
+    }
+
+    pub fn daily(limit_msat: u64) -> Self {
+        SpendingLimit {
+            limit_msat,
+            window_secs: DAY,
+            per_destination: false,
+        }
+    }
+
+    pub fn per_destination(mut self) -> Self {
+        self.per_destination = true;
+        self
+    }
+}
+
+/// What to do with a request that would exceed a limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    /// Refuse to sign.
+    #[default]
+    Deny,
+    /// Ask the approval handler, and refuse if there is none.
+    Ask,
+}
+
+/// A request that would exceed one of the configured limits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LimitExceeded {
+    pub limit: SpendingLimit,
+    /// The destination the limit applies to, for per-destination
+    /// limits.
+    pub destination: Option<String>,
+    /// Amount already spent within the window.
+    pub spent_msat: u64,
+    /// Amount the request would add.
+    pub amount_msat: u64,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sending {}msat on top of {}msat exceeds the limit of {}msat per {}s",
+            self.amount_msat, self.spent_msat, self.limit.limit_msat, self.limit.window_secs
+        )?;
+        if let Some(d) = &self.destination {
+            write!(f, " to {}", d)?;
+        }
+        Ok(())
+    }
+}
+
+/// An invoice we were asked to pay, but can't tell how much it sends:
+/// either it has no amount and there is no matching `pay` request, or
+/// we can't parse it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownAmount {
+    pub bolt11: String,
+}
+
+impl Display for UnknownAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot tell the amount paid to {}", self.bolt11)
+    }
+}
+
+/// An outgoing payment or on-chain transfer we signed off on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Spend {
+    /// The payment hash, or the spent outpoint, used to recognize
+    /// retries of the same payment.
+    pub id: String,
+    pub destination: String,
+    pub amount_msat: u64,
+    pub timestamp: u64,
+}
+
+/// The limits in force and the spends they are checked against.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SpendingLedger {
+    pub limits: Vec<SpendingLimit>,
+    pub spends: Vec<Spend>,
+}
+
+impl SpendingLedger {
+    /// Drop `spends` that were already recorded, e.g., because the
+    /// node retries a payment.
+    pub fn unrecorded(&self, spends: Vec<Spend>) -> Vec<Spend> {
+        spends
+            .into_iter()
+            .filter(|s| !self.spends.iter().any(|o| o.id == s.id))
+            .collect()
+    }
+
+    fn spent(&self, limit: &SpendingLimit, destination: Option<&str>, now: u64) -> u64 {
+        self.spends
+            .iter()
+            .filter(|s| s.timestamp + limit.window_secs > now)
+            .filter(|s| !matches!(destination, Some(d) if d != s.destination))
+            .map(|s| s.amount_msat)
+            .sum()
+    }
+
+    /// Check whether recording `spends` would exceed any limit.
+    pub fn check(&self, spends: &[Spend], now: u64) -> Result<(), LimitExceeded> {
+        for limit in self.limits.iter() {
+            let mut destinations: Vec<Option<&str>> = if limit.per_destination {
+                spends
+                    .iter()
+                    .map(|s| Some(s.destination.as_str()))
+                    .collect()
+            } else {
+                vec![None]
+            };
+            destinations.sort_unstable();
+            destinations.dedup();
+
+            for destination in destinations {
+                let amount_msat = spends
+                    .iter()
+                    .filter(|s| !matches!(destination, Some(d) if d != s.destination))
+                    .map(|s| s.amount_msat)
+                    .sum::<u64>();
+                let spent_msat = self.spent(limit, destination, now);
+                if spent_msat.saturating_add(amount_msat) > limit.limit_msat {
+                    return Err(LimitExceeded {
+                        limit: *limit,
+                        destination: destination.map(|d| d.to_string()),
+                        spent_msat,
+                        amount_msat,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `spends` and forget about spends that are outside of all
+    /// windows.
+    pub fn record(&mut self, spends: Vec<Spend>, now: u64) {
+        let window = self.limits.iter().map(|l| l.window_secs).max().unwrap_or(0);
+        self.spends.retain(|s| s.timestamp + window > now);
+        self.spends.extend(spends);
+    }
+}
+
+/// Extract the outgoing value of a signer request. Zero-amount
+/// invoices take their amount from the pending `pay` request, and
+/// without one the amount is unknown, as is that of invoices we can't
+/// parse.
+pub(crate) fn spends(
+    msg: &Message,
+    reqs: &[Request],
+    network: Network,
+    now: u64,
+) -> Result<Vec<Spend>, UnknownAmount> {
+    Ok(match msg {
+        Message::PreapproveInvoice(m) => {
+            let bolt11 = match std::str::from_utf8(&m.invstring.0) {
+                Ok(s) => s,
+                Err(_) => {
+                    return Err(UnknownAmount {
+                        bolt11: String::from_utf8_lossy(&m.invstring.0).into_owned(),
+                    })
+                }
+            };
+            let invoice = match Invoice::from_str(bolt11) {
+                Ok(i) => i,
+                Err(e) => {
+                    log::warn!("Could not parse invoice to track spending: {:?}", e);
+                    return Err(UnknownAmount {
+                        bolt11: bolt11.to_string(),
+                    });
+                }
+            };
+            let amount_msat = match invoice.amount_milli_satoshis() {
+                0 => reqs
+                    .iter()
+                    .filter_map(|r| match r {
+                        Request::Pay(p) if p.bolt11 == bolt11 => {
+                            p.amount_msat.as_ref().map(|a| a.msat)
+                        }
+                        Request::TrampolinePay(p) if p.bolt11 == bolt11 => Some(p.amount_msat),
+                        _ => None,
+                    })
+                    .max()
+                    .ok_or_else(|| UnknownAmount {
+                        bolt11: bolt11.to_string(),
+                    })?,
+                a => a,
+            };
+            vec![Spend {
+                id: hex::encode(invoice.payment_hash().0),
+                destination: invoice.payee_pub_key().to_string(),
+                amount_msat,
+                timestamp: now,
+            }]
+        }
+        Message::PreapproveKeysend(m) => vec![Spend {
+            id: hex::encode(m.payment_hash.0),
+            destination: hex::encode(m.destination.0),
+            amount_msat: m.amount_msat,
+            timestamp: now,
+        }],
+        Message::SignWithdrawal(m) => {
+            let psbt = &m.psbt.0.psbt.inner;
+            let txid = psbt.unsigned_tx.compute_txid();
+            psbt.unsigned_tx
+                .output
+                .iter()
+                .zip(psbt.outputs.iter())
+                .enumerate()
+                // Outputs to our own wallet carry their derivation
+                // path, everything else leaves the wallet.
+                .filter(|(_, (_, o))| o.bip32_derivation.is_empty() && o.tap_key_origins.is_empty())
+                .map(|(i, (txo, _))| Spend {
+                    id: format!("{}:{}", txid, i),
+                    destination: Address::from_script(&txo.script_pubkey, network)
+                        .map(|a| a.to_string())
+                        .unwrap_or_else(|_| hex::encode(txo.script_pubkey.as_bytes())),
+                    amount_msat: txo.value.to_sat() * 1000,
+                    timestamp: now,
+                })
+                .collect()
+        }
+        _ => vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::model::cln::{Amount, PayRequest};
+    use lightning_signer::bitcoin::hashes::{sha256, Hash};
+    use lightning_signer::bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning_signer::lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+    use std::time::Duration;
+    use vls_protocol::msgs::PreapproveInvoice;
+    use vls_protocol::serde_bolt::WireString;
+
+    fn spend(id: &str, destination: &str, amount_msat: u64, timestamp: u64) -> Spend {
+        Spend {
+            id: id.to_string(),
+            destination: destination.to_string(),
+            amount_msat,
+            timestamp,
+        }
+    }
+
+    fn zero_amount_invoice() -> String {
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Bitcoin)
+            .description("coffee".to_string())
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([42; 32]))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .expiry_time(Duration::from_secs(600))
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_zero_amount_invoice() {
+        let bolt11 = zero_amount_invoice();
+        let msg = Message::PreapproveInvoice(PreapproveInvoice {
+            invstring: WireString(bolt11.clone().into_bytes()),
+        });
+        assert_eq!(
+            spends(&msg, &[], Network::Bitcoin, 0),
+            Err(UnknownAmount {
+                bolt11: bolt11.clone()
+            })
+        );
+
+        // The amount comes from the `pay` request instead.
+        let pay = Request::Pay(PayRequest {
+            bolt11,
+            amount_msat: Some(Amount { msat: 5000 }),
+            ..Default::default()
+        });
+        let spends = spends(&msg, &[pay], Network::Bitcoin, 0).unwrap();
+        assert_eq!(spends[0].amount_msat, 5000);
+    }
+
+    #[test]
+    fn test_unparseable_invoice() {
+        // Invoices we can't parse don't slip past the limits.
+        for invstring in [b"lnbc1garbage".to_vec(), vec![0xff, 0xfe]] {
+            let msg = Message::PreapproveInvoice(PreapproveInvoice {
+                invstring: WireString(invstring.clone()),
+            });
+            assert_eq!(
+                spends(&msg, &[], Network::Bitcoin, 0),
+                Err(UnknownAmount {
+                    bolt11: String::from_utf8_lossy(&invstring).into_owned()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut ledger = SpendingLedger {
+            limits: vec![SpendingLimit::hourly(10_000)],
+            spends: vec![],
+        };
+        ledger.record(vec![spend("a", "x", 6_000, 0)], 0);
+
+        assert!(ledger.check(&[spend("b", "y", 4_000, 10)], 10).is_ok());
+        let err = ledger.check(&[spend("b", "y", 5_000, 10)], 10).unwrap_err();
+        assert_eq!(err.spent_msat, 6_000);
+        assert_eq!(err.amount_msat, 5_000);
+
+        // An hour later the first spend no longer counts.
+        assert!(ledger.check(&[spend("b", "y", 5_000, HOUR)], HOUR).is_ok());
+        ledger.record(vec![spend("b", "y", 5_000, HOUR)], HOUR);
+        assert_eq!(ledger.spends.len(), 1);
+    }
+
+    #[test]
+    fn test_per_destination() {
+        let mut ledger = SpendingLedger {
+            limits: vec![SpendingLimit::daily(10_000).per_destination()],
+            spends: vec![],
+        };
+        ledger.record(vec![spend("a", "x", 8_000, 0)], 0);
+        assert!(ledger.check(&[spend("b", "y", 8_000, 1)], 1).is_ok());
+        let err = ledger.check(&[spend("b", "x", 8_000, 1)], 1).unwrap_err();
+        assert_eq!(err.destination, Some("x".to_string()));
+
+        // Retries of a recorded payment are not counted twice.
+        assert!(ledger
+            .unrecorded(vec![spend("a", "x", 8_000, 2)])
+            .is_empty());
+    }
+}
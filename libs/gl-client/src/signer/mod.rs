@@ -19,14 +19,14 @@ use bytes::BufMut;
 use http::uri::InvalidUri;
 use lightning_signer::bitcoin::hashes::Hash;
 use lightning_signer::bitcoin::secp256k1::PublicKey;
+use lightning_signer::bitcoin::Address;
 use lightning_signer::bitcoin::Network;
 use lightning_signer::node::NodeServices;
-use lightning_signer::policy::filter::FilterRule;
-use lightning_signer::util::crypto_utils;
 use log::{debug, error, info, trace, warn};
 use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
 use runeauth::{Condition, Restriction, Rune, RuneError};
 use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::SystemTime;
@@ -35,23 +35,48 @@ use tokio::time::{sleep, Duration};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::{Endpoint, Uri};
 use tonic::{Code, Request};
-use vls_protocol::msgs::{DeBolt, HsmdInitReplyV4};
 use vls_protocol::serde_bolt::Octets;
 use vls_protocol_signer::approver::{Approve, MemoApprover};
 use vls_protocol_signer::handler;
 use vls_protocol_signer::handler::Handler;
 
 mod approver;
+mod audit;
 mod auth;
+mod builder;
+mod limits;
 pub mod model;
+mod pairing;
+mod policy;
+mod pool;
 mod report;
 mod resolve;
+mod rune_registry;
+
+pub use approver::{ApprovalHandler, ApprovalRequest, OnchainOutput, DEFAULT_APPROVAL_TIMEOUT};
+pub use audit::{
+    AuditContext, AuditEntry, AuditHead, AuditLog, AuditOutcome, AuditQuery, FileAuditLog,
+    MemoryAuditLog,
+};
+pub use builder::SignerBuilder;
+pub use limits::{LimitAction, LimitExceeded, SpendingLimit, UnknownAmount};
+pub use pairing::{PairingDecision, PairingRequest, DEFAULT_PAIRING_TIMEOUT};
+pub use policy::{PolicyRule, RuleAction, SignerPolicy, VelocityInterval, VelocityLimit};
+pub use pool::{NodeHealth, NodeStatus, SignerPool};
+pub use report::{
+    FileReportSink, NoopReportSink, ReportSink, RingBufferReportSink, SchedulerReportSink,
+};
+pub use resolve::FieldMismatch;
+pub use rune_registry::RuneInfo;
 
 const VERSION: &str = "v25.12";
 const GITHASH: &str = env!("GIT_HASH");
 const RUNE_VERSION: &str = "gl0";
 // This is the same derivation key that is used by core lightning itself.
 const RUNE_DERIVATION_SECRET: &str = "gl-commando";
+// Derives the key authenticating the values the signer keeps in its
+// state, e.g., the spending ledger.
+const STATE_DERIVATION_SECRET: &str = "gl-signer-state";
 
 #[derive(Clone)]
 pub struct Signer {
@@ -66,6 +91,20 @@ pub struct Signer {
 
     network: Network,
     state: Arc<Mutex<crate::persist::State>>,
+    persister: Arc<crate::persist::MemoryPersister>,
+
+    /// Asked to approve requests we cannot resolve on our own.
+    approver: Option<approver::UserApprover>,
+    /// Limits configured in the policy. These take precedence over
+    /// the limits recorded in the spending ledger.
+    spending_limits: Option<Vec<SpendingLimit>>,
+    over_limit: LimitAction,
+    audit: Option<Arc<audit::AuditJournal>>,
+    reporter: Arc<dyn ReportSink>,
+    rune_uses: Arc<rune_registry::RuneUses>,
+    /// Holds pairing requests until the user answers them. Without
+    /// it every pairing request with a valid rune is approved.
+    pairing: Option<Arc<pairing::PairingQueue>>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -102,6 +141,30 @@ pub enum Error {
 
     #[error("could not approve pairing request: {0}")]
     ApprovePairingRequestError(String),
+
+    #[error("node attempted to roll back signer state: {0:?}")]
+    StateRollback(Vec<crate::persist::StateRollback>),
+
+    #[error("node sent signer state we did not write: {0:?}")]
+    UnauthenticatedState(Vec<String>),
+
+    #[error(
+        "invoice does not match the request: {}",
+        .0.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(", ")
+    )]
+    InvoiceMismatch(Vec<FieldMismatch>),
+
+    #[error(
+        "transaction does not match the request: {}",
+        .0.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(", ")
+    )]
+    TransactionMismatch(Vec<FieldMismatch>),
+
+    #[error("spending limit exceeded: {0}")]
+    SpendingLimit(LimitExceeded),
+
+    #[error("spending limits can't be checked: {0}")]
+    UnknownAmount(UnknownAmount),
 }
 
 impl Signer {
@@ -109,116 +172,110 @@ impl Signer {
     where
         T: TlsConfigProvider,
     {
-        use lightning_signer::policy::{
-            filter::PolicyFilter, simple_validator::SimpleValidatorFactory,
-        };
-        use lightning_signer::signer::ClockStartingTimeFactory;
-        use lightning_signer::util::clock::StandardClock;
-
-        info!("Initializing signer for {VERSION} ({GITHASH}) (VLS)");
-        let mut sec: [u8; 32] = [0; 32];
-        sec.copy_from_slice(&secret[0..32]);
-
-        // The persister takes care of persisting metadata across
-        // restarts
-        let persister = Arc::new(crate::persist::MemoryPersister::new());
-        let mut policy =
-            lightning_signer::policy::simple_validator::make_default_simple_policy(network);
-
-        policy.filter = PolicyFilter::default();
-        policy.filter.merge(PolicyFilter {
-            // TODO: Remove once we have fully switched over to zero-fee anchors
-            rules: vec![
-                FilterRule::new_warn("policy-channel-safe-type-anchors"),
-                FilterRule::new_warn("policy-routing-balanced"),
-                FilterRule::new_warn("policy-commitment-retry-same"),
-            ],
-        });
-
-        // TODO Remove this once VLS has implemented the fee budget
-        // per payment, rather than the fee budget per HTLC.
-        // Ref: https://github.com/Blockstream/greenlight/issues/538
-        {
-            policy.max_feerate_per_kw = 150_000;
-            policy.filter.merge(PolicyFilter {
-                rules: vec![
-                    FilterRule::new_warn("policy-commitment-fee-range"),
-                    FilterRule::new_warn("policy-mutual-fee-range"),
-                ],
-            });
-        }
+        SignerBuilder::new(secret, network, creds).build()
+    }
 
-        policy.filter.merge(PolicyFilter {
-            // TODO: Remove once we have implemented zero invoice support
-            rules: vec![
-                FilterRule::new_warn("policy-routing-balanced"),
-                FilterRule::new_warn("policy-htlc-fee-range"),
-            ],
-        });
+    /// Create a new signer whose state is kept in `store`. The
+    /// signer loads the last known state on startup, and writes any
+    /// change through to the store before responding to the node,
+    /// so a restarted signer does not depend on the node to tell it
+    /// what the state was.
+    pub fn new_with_store<T>(
+        secret: Vec<u8>,
+        network: Network,
+        creds: T,
+        store: Arc<dyn crate::persist::StateStore>,
+    ) -> Result<Signer, anyhow::Error>
+    where
+        T: TlsConfigProvider,
+    {
+        SignerBuilder::new(secret, network, creds)
+            .with_state_store(store)
+            .build()
+    }
 
-        // TODO: Remove once we found the desync issue
-        policy.filter.merge(PolicyFilter {
-            rules: vec![
-                // "policy failure: get_per_commitment_secret: cannot
-                // revoke commitment_number 312 when
-                // next_holder_commit_num is 313"
-                FilterRule::new_warn("policy-revoke-new-commitment-signed"),
-            ],
-        });
+    /// Ask `handler` to approve any request that we cannot match up
+    /// with a signed request from the user, instead of relying on
+    /// the default decision. Requests that are not approved within
+    /// `timeout` are denied.
+    pub fn with_approval_handler(
+        mut self,
+        handler: Arc<dyn ApprovalHandler>,
+        timeout: Duration,
+    ) -> Signer {
+        self.approver = Some(approver::UserApprover::new(handler, timeout));
+        self
+    }
 
-        // Increase the invoices limit. Results in a larger state, but
-        // bumping into this is rather annoying.
-        policy.max_invoices = 10_000usize;
+    /// Queue pairing requests until they are answered with
+    /// `decide_pairing`, instead of approving them right away.
+    /// Requests that are not answered within `timeout` are denied.
+    pub fn with_pairing_queue(mut self, timeout: Duration) -> Signer {
+        self.pairing = Some(Arc::new(pairing::PairingQueue::new(timeout)));
+        self
+    }
 
-        // Relaxed max_routing_fee since we no longer have the
-        // presplitter which was causing the HTLCs to be smaller.
-        policy.max_routing_fee_msat = 1_000_000;
+    /// The pairing requests waiting for an answer. Always empty if
+    /// the signer has no pairing queue.
+    pub fn pending_pairings(&self) -> Vec<PairingRequest> {
+        match &self.pairing {
+            Some(queue) => queue.list(now_secs()),
+            None => vec![],
+        }
+    }
 
-        let validator_factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
-        let starting_time_factory = ClockStartingTimeFactory::new();
-        let clock = Arc::new(StandardClock());
+    /// Answer the pending pairing request from `device_id`. Fails if
+    /// there is no such request, e.g., because it expired.
+    pub fn decide_pairing(&self, device_id: &str, decision: PairingDecision) -> Result<()> {
+        let queue = self
+            .pairing
+            .as_ref()
+            .ok_or_else(|| anyhow!("no pairing queue configured"))?;
+        if !queue.decide(device_id, decision) {
+            return Err(anyhow!("no pending pairing request from {device_id}"));
+        }
+        Ok(())
+    }
 
-        let services = NodeServices {
-            validator_factory,
-            starting_time_factory,
-            persister: persister.clone(),
-            trusted_oracle_pubkeys: vec![],
-            clock,
-        };
+    /// Return the entries of the audit journal matching `query`,
+    /// after checking that the journal has not been tampered with.
+    pub fn audit(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        match &self.audit {
+            Some(journal) => journal.query(query),
+            None => Err(anyhow!("no audit log configured")),
+        }
+    }
 
-        let mut handler = handler::HandlerBuilder::new(network, 0 as u64, services.clone(), sec)
-            .build()
-            .map_err(|e| anyhow!("building root_handler: {:?}", e))?;
+    /// List the runes issued by this signer, including revoked ones.
+    pub fn list_runes(&self) -> Vec<RuneInfo> {
+        self.rune_registry().runes
+    }
 
-        // Calling init on the `InitHandler` from above puts it into a
-        // state that it can be upgraded into the `RootHandler` that
-        // we need for the rest of the run.
-        let init = Signer::initmsg(&mut handler)?;
+    /// Revoke the rune with the unique id `id`, and all runes carved
+    /// from it. Requests authorized by a revoked rune are rejected.
+    pub fn revoke_rune(&self, id: u64) -> Result<()> {
+        self.persister.update_value(
+            rune_registry::RUNE_REGISTRY_KEY,
+            |r: &mut rune_registry::RuneRegistry| {
+                r.revoke(id);
+                true
+            },
+        )
+    }
 
-        let init = HsmdInitReplyV4::from_vec(init).map_err(|e| {
-            anyhow!("Failed to parse init message as HsmdInitReplyV4: {:?}", e)
-        })?;
+    fn rune_registry(&self) -> rune_registry::RuneRegistry {
+        self.persister
+            .get_value(rune_registry::RUNE_REGISTRY_KEY)
+            .unwrap_or_default()
+    }
 
-        let id = init.node_id.0.to_vec();
-        use vls_protocol::msgs::SerBolt;
-        let init = init.as_vec();
-
-        // Init master rune. We create the rune seed from the nodes
-        // seed by deriving a hardened key tagged with "rune secret".
-        let rune_secret = crypto_utils::hkdf_sha256(&sec, RUNE_DERIVATION_SECRET.as_bytes(), &[]);
-        let mr = Rune::new_master_rune(&rune_secret, vec![], None, Some(RUNE_VERSION.to_string()))?;
-
-        trace!("Initialized signer for node_id={}", hex::encode(&id));
-        Ok(Signer {
-            secret: sec,
-            master_rune: mr,
-            services,
-            tls: creds.tls_config(),
-            id,
-            init,
-            network,
-            state: persister.state(),
-        })
+    /// Start configuring a new signer, e.g., to customize its
+    /// policy.
+    pub fn builder<T>(secret: Vec<u8>, network: Network, creds: T) -> SignerBuilder
+    where
+        T: TlsConfigProvider,
+    {
+        SignerBuilder::new(secret, network, creds)
     }
 
     fn init_handler(&self) -> Result<handler::InitHandler, anyhow::Error> {
@@ -300,19 +357,7 @@ impl Signer {
             .into_iter()
             .filter(|r| !r.pubkey.is_empty() && !r.signature.is_empty() && !r.rune.is_empty())
             .map(|r| {
-                let pk = UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &r.pubkey);
-                let mut data = r.request.clone();
-
-                // If we have a timestamp associated we must add it to
-                // the payload being checked. Same thing happens on
-                // the client too.
-                if r.timestamp != 0 {
-                    data.put_u64(r.timestamp);
-                }
-
-                pk.verify(&data, &r.signature)
-                    .map_err(|e| anyhow!("signature verification failed: {}", e))?;
-
+                verify_request_signature(&r)?;
                 self.verify_rune(r.clone())
                     .map(|_| r)
                     .map_err(|e| anyhow!("rune verification failed: {}", e))
@@ -323,7 +368,7 @@ impl Signer {
     /// Verifies that the public key of the request and the signers rune version
     /// match the corresponding restrictions of the rune.
     fn verify_rune(&self, request: crate::pb::PendingRequest) -> Result<(), anyhow::Error> {
-        let rune64 = general_purpose::URL_SAFE.encode(request.rune);
+        let rune64 = general_purpose::URL_SAFE.encode(&request.rune);
         let rune = Rune::from_base64(&rune64)?;
 
         // A valid gl-rune must contain a pubkey field as this  is bound to the
@@ -333,15 +378,21 @@ impl Signer {
             return Err(anyhow!("rune is missing pubkey field"));
         }
 
-        // Currently we only use a 0 unique_id and a pubkey field to allow
-        // for delegation in the future but we could also set the public
-        // key as the unique_id in the future and add a method that allows
-        // to create new empty runes.
+        // Each rune gets a random unique id when it is created,
+        // which allows us to revoke it later on. Runes issued before
+        // that all have the id 0.
+        let authcode = rune.authcode();
         let unique_id = rune.get_id();
-        let ver_id = match unique_id {
+        let ver_id = match &unique_id {
             Some(id) => format!("{}-{}", id, RUNE_VERSION),
             None => String::default(),
         };
+        let rune_id = unique_id.and_then(|id| id.parse::<u64>().ok());
+        if let Some(id) = rune_id {
+            if self.rune_registry().is_revoked(id) {
+                return Err(anyhow!("rune {} has been revoked", id));
+            }
+        }
 
         // Check that the request points to `cln.Node`.
         let mut parts = request.uri.split('/');
@@ -369,17 +420,46 @@ impl Signer {
             }
         };
 
+        // Restrictions on the call parameters fail if the request
+        // can not be decoded, since the parameters are missing then.
+        let params = match decode_request(request.clone()) {
+            Ok(r) => rune_params(&r),
+            Err(e) => {
+                debug!("could not decode request while verifying rune: {}", e);
+                runes::Params::default()
+            }
+        };
+
+        let time = SystemTime::now();
+        let now = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        // The same pending request is verified with every signer
+        // request it accompanies, so it only counts once against
+        // rate limits.
         let ctx = runes::Context {
             method,
             pubkey: hex::encode(request.pubkey),
-            time: SystemTime::now(),
+            time,
             unique_id: ver_id,
+            params,
+            uses: self.rune_uses.get(&authcode, &request.signature, now),
         };
 
-        match self.master_rune.check_with_reason(&rune64, ctx) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
+        self.master_rune.check_with_reason(&rune64, ctx)?;
+        self.rune_uses.record(authcode, request.signature, now);
+
+        if let Some(id) = rune_id {
+            let res = self.persister.update_value(
+                rune_registry::RUNE_REGISTRY_KEY,
+                |r: &mut rune_registry::RuneRegistry| r.touch(id, now),
+            );
+            if let Err(e) = res {
+                warn!("Failed to record use of rune {}: {}", id, e);
+            }
         }
+        Ok(())
     }
 
     /// Given the URI of the running node, connect to it and stream
@@ -439,13 +519,14 @@ impl Signer {
                     // Report the rejection to the server for debugging
                     // Note: process_request already reports some errors, but we ensure
                     // all errors are reported here as a safety net
-                    report::Reporter::report(crate::pb::scheduler::SignerRejection {
-                        msg: format!("Error in run_once loop: {:?}", e),
-                        request: Some(req.clone()),
-                        git_version: GITHASH.to_string(),
-                        node_id: self.node_id(),
-                    })
-                    .await;
+                    self.reporter
+                        .report(crate::pb::scheduler::SignerRejection {
+                            msg: format!("Error in run_once loop: {:?}", e),
+                            request: Some(req.clone()),
+                            git_version: GITHASH.to_string(),
+                            node_id: self.node_id(),
+                        })
+                        .await;
 
                     let response = HsmResponse {
                         raw: vec![],
@@ -476,28 +557,296 @@ impl Signer {
             reqs
         );
 
+        // Funding outputs can only be told apart by their outpoint,
+        // which we learn when the channel is set up.
+        let funding = match msg {
+            vls_protocol::msgs::Message::SignWithdrawal(_) => PublicKey::from_slice(&self.id)
+                .ok()
+                .and_then(|p| self.services.persister.get_node_channels(&p).ok())
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(_, c)| c.channel_setup.map(|s| s.funding_outpoint))
+                .collect(),
+            _ => vec![],
+        };
+
         // Quick path out of here: we can't find a resolution for a
         // request, then abort!
-        Resolver::try_resolve(msg, &reqs)?;
+        Resolver::try_resolve(msg, reqs, &funding)?;
 
         Ok(())
     }
 
+    /// Check the value `msg` sends out of the wallet against the
+    /// spending limits, and return the spends to record once it is
+    /// signed.
+    async fn check_spending(
+        &self,
+        msg: &vls_protocol::msgs::Message,
+        ctxrequests: &[model::Request],
+        req: &HsmRequest,
+    ) -> Result<Vec<limits::Spend>, Error> {
+        let mut ledger: limits::SpendingLedger = self
+            .persister
+            .get_value(limits::SPENDING_LEDGER_KEY)
+            .unwrap_or_default();
+        // Configured limits replace the recorded ones, otherwise the
+        // recorded limits stay in force.
+        match &self.spending_limits {
+            Some(limits) if &ledger.limits != limits => {
+                ledger.limits = limits.clone();
+                self.persister
+                    .put_value(limits::SPENDING_LEDGER_KEY, &ledger)
+                    .map_err(Error::Other)?;
+            }
+            _ => {}
+        }
+        if ledger.limits.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let spends = match limits::spends(msg, ctxrequests, self.network, now) {
+            Ok(spends) => ledger.unrecorded(spends),
+            Err(e) => {
+                // Without an amount we can't check the limits, so
+                // only the user can let this through.
+                warn!("Request spends an unknown amount: {}", e);
+                self.reporter
+                    .report(crate::pb::scheduler::SignerRejection {
+                        msg: e.to_string(),
+                        request: Some(req.clone()),
+                        git_version: GITHASH.to_string(),
+                        node_id: self.node_id(),
+                    })
+                    .await;
+                let approved = match (self.over_limit, &self.approver) {
+                    (LimitAction::Ask, Some(approver)) => {
+                        approver.ask(approval_request(msg, self.network).0).await
+                    }
+                    _ => false,
+                };
+                return match approved {
+                    true => Ok(vec![]),
+                    false => Err(Error::UnknownAmount(e)),
+                };
+            }
+        };
+        if spends.is_empty() {
+            return Ok(vec![]);
+        }
+
+        if let Err(e) = ledger.check(&spends, now) {
+            warn!("Request exceeds spending limit: {}", e);
+            self.reporter
+                .report(crate::pb::scheduler::SignerRejection {
+                    msg: e.to_string(),
+                    request: Some(req.clone()),
+                    git_version: GITHASH.to_string(),
+                    node_id: self.node_id(),
+                })
+                .await;
+
+            let approved = match (self.over_limit, &self.approver) {
+                (LimitAction::Ask, Some(approver)) => approver.ask((&e).into()).await,
+                _ => false,
+            };
+            if !approved {
+                return Err(Error::SpendingLimit(e));
+            }
+        }
+        Ok(spends)
+    }
+
+    /// Add `spends` to the spending ledger, after the request that
+    /// makes them was signed.
+    #[allow(clippy::result_large_err)]
+    fn record_spending(&self, spends: Vec<limits::Spend>) -> Result<(), Error> {
+        if spends.is_empty() {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.persister
+            .update_value(
+                limits::SPENDING_LEDGER_KEY,
+                |ledger: &mut limits::SpendingLedger| {
+                    let spends = ledger.unrecorded(spends);
+                    ledger.record(spends, now);
+                    true
+                },
+            )
+            .map_err(Error::Other)
+    }
+
     async fn process_request(&self, req: HsmRequest) -> Result<HsmResponse, Error> {
+        let verified: Vec<crate::pb::PendingRequest> = self
+            .check_request_auth(req.requests.clone())
+            .into_iter()
+            .filter_map(|r| r.ok())
+            .collect();
+        let res = self.handle_request(req.clone(), verified.clone()).await;
+        self.record_audit(&req, verified, &res);
+        res
+    }
+
+    /// Append the outcome of `req` to the audit journal, if any,
+    /// along with the `verified` user requests that justified it.
+    fn record_audit(
+        &self,
+        req: &HsmRequest,
+        verified: Vec<crate::pb::PendingRequest>,
+        res: &Result<HsmResponse, Error>,
+    ) {
+        let journal = match &self.audit {
+            Some(j) => j,
+            None => return,
+        };
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut context = vec![];
+        let mut ctxrequests = vec![];
+        for r in verified {
+            let rune_id = Rune::from_base64(&general_purpose::URL_SAFE.encode(&r.rune))
+                .ok()
+                .and_then(|r| r.get_id());
+            let (uri, pubkey) = (r.uri.clone(), hex::encode(&r.pubkey));
+            let request = match decode_request(r) {
+                Ok(d) => {
+                    let s = format!("{:?}", d);
+                    ctxrequests.push(d);
+                    s
+                }
+                Err(e) => format!("undecodable: {}", e),
+            };
+            context.push(AuditContext {
+                uri,
+                pubkey,
+                rune_id,
+                request,
+            });
+        }
+
+        let msg = vls_protocol::msgs::from_vec(req.raw.clone()).ok();
+        let spends = msg
+            .as_ref()
+            .map(|m| limits::spends(m, &ctxrequests, self.network, now));
+        let outcome = match res {
+            Ok(_) => AuditOutcome::Signed,
+            Err(e) => AuditOutcome::Rejected {
+                reason: e.to_string(),
+            },
+        };
+        // The `Debug` representation starts with the variant name.
+        let request = msg
+            .as_ref()
+            .map(|m| format!("{:?}", m))
+            .and_then(|m| m.split('(').next().map(|n| n.to_string()))
+            .unwrap_or_else(|| "Unknown".to_string());
+        let amount_msat = spends
+            .and_then(|s| s.ok())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.iter().map(|s| s.amount_msat).sum());
+
+        if let Err(e) = journal.append(now, request, context, amount_msat, outcome) {
+            error!("Failed to append to audit journal: {}", e);
+        }
+    }
+
+    /// Process `req`, justified by the `verified` user requests from
+    /// its context.
+    async fn handle_request(
+        &self,
+        req: HsmRequest,
+        verified: Vec<crate::pb::PendingRequest>,
+    ) -> Result<HsmResponse, Error> {
         debug!("Processing request {:?}", req);
         let diff: crate::persist::State = req.signer_state.clone().into();
 
+        // Only we can write the values we keep for ourselves, e.g.,
+        // the spending ledger, so the node must not be able to change
+        // them.
+        let forged = self.persister.unauthenticated(&diff);
+        if !forged.is_empty() {
+            error!("Refusing request with forged signer state: {:?}", forged);
+            self.reporter
+                .report(crate::pb::scheduler::SignerRejection {
+                    msg: format!("Refusing request with forged signer state: {:?}", forged),
+                    request: Some(req.clone()),
+                    git_version: GITHASH.to_string(),
+                    node_id: self.node_id(),
+                })
+                .await;
+            return Err(Error::UnauthenticatedState(forged));
+        }
+
+        let rollbacks = self.persister.rollbacks(&diff);
+        if !rollbacks.is_empty() {
+            // The node sent us an entry that is older than one we
+            // have already seen. This is either a bug or the node
+            // trying to replay an old state, either way we must not
+            // sign based on it.
+            let msg = rollbacks
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            error!("Refusing request with outdated signer state: {}", msg);
+            self.reporter
+                .report(crate::pb::scheduler::SignerRejection {
+                    msg: format!("Refusing request with outdated signer state: {}", msg),
+                    request: Some(req.clone()),
+                    git_version: GITHASH.to_string(),
+                    node_id: self.node_id(),
+                })
+                .await;
+            return Err(Error::StateRollback(rollbacks));
+        }
+
+        // Whichever version of the rune registry wins the merge, we
+        // must neither lose the runes we issued before learning the
+        // registry of the node, nor the revocations it knows about.
+        let local_runes = self.rune_registry();
+        let node_runes: Option<rune_registry::RuneRegistry> = self
+            .persister
+            .get_value_from(&diff, rune_registry::RUNE_REGISTRY_KEY);
+
         let prestate = {
             debug!("Updating local signer state with state from node");
-            let mut state = self.state.lock().map_err(|e| {
-                Error::Other(anyhow!("Failed to acquire state lock: {:?}", e))
-            })?;
-            state.merge(&diff).map_err(|e| {
-                Error::Other(anyhow!("Failed to merge signer state: {:?}", e))
-            })?;
+            let mut state = self
+                .state
+                .lock()
+                .map_err(|e| Error::Other(anyhow!("Failed to acquire state lock: {:?}", e)))?;
+            let changes = state
+                .merge(&diff)
+                .map_err(|e| Error::Other(anyhow!("Failed to merge signer state: {:?}", e)))?;
+            if !changes.is_empty() {
+                self.persister.mark_dirty();
+            }
             trace!("Processing request {}", hex::encode(&req.raw));
             state.clone()
         };
+        self.persister
+            .update_value(
+                rune_registry::RUNE_REGISTRY_KEY,
+                |r: &mut rune_registry::RuneRegistry| {
+                    let local = r.absorb(&local_runes);
+                    let node = node_runes.as_ref().is_some_and(|n| r.absorb(n));
+                    local || node
+                },
+            )
+            .map_err(|e| Error::Other(anyhow!("Failed to store signer state: {:?}", e)))?;
+        self.persister
+            .flush()
+            .map_err(|e| Error::Other(anyhow!("Failed to store signer state: {:?}", e)))?;
 
         // The first two bytes represent the message type. Check that
         // it is not a `sign-message` request (type 23).
@@ -511,10 +860,8 @@ impl Signer {
             }
         }
 
-        let ctxrequests: Vec<model::Request> = self
-            .check_request_auth(req.requests.clone())
+        let ctxrequests: Vec<model::Request> = verified
             .into_iter()
-            .filter_map(|r| r.ok())
             .map(|r| decode_request(r))
             .filter_map(|r| match r {
                 Ok(r) => Some(r),
@@ -532,40 +879,70 @@ impl Signer {
             serde_json::to_string(&prestate).unwrap_or_else(|_| "<failed to serialize>".to_string())
         );
 
+        let mut user_approvals = vec![];
         if let Err(e) = self.authenticate_request(&msg, &ctxrequests) {
-            report::Reporter::report(crate::pb::scheduler::SignerRejection {
-                msg: e.to_string(),
-                request: Some(req.clone()),
-                git_version: GITHASH.to_string(),
-                node_id: self.node_id(),
-            })
-            .await;
-            #[cfg(not(feature = "permissive"))]
-            return Err(Error::Resolver(req.raw, ctxrequests));
+            self.reporter
+                .report(crate::pb::scheduler::SignerRejection {
+                    msg: e.to_string(),
+                    request: Some(req.clone()),
+                    git_version: GITHASH.to_string(),
+                    node_id: self.node_id(),
+                })
+                .await;
+
+            // Give the user a chance to approve the request before
+            // rejecting it.
+            if let Some(approver) = &self.approver {
+                let (request, approval) = approval_request(&msg, self.network);
+                if !approver.ask(request).await {
+                    return Err(e);
+                }
+                user_approvals.extend(approval);
+            } else {
+                #[cfg(not(feature = "permissive"))]
+                return Err(e);
+            }
         };
 
-        // If present, add the close_to_addr to the allowlist
-        for parsed_request in ctxrequests.iter() {
-            match parsed_request {
-                model::Request::GlConfig(gl_config) => {
-                    let pubkey = PublicKey::from_slice(&self.id);
-                    match pubkey {
-                        Ok(p) => {
-                            let _ = self
-                                .services
-                                .persister
-                                .update_node_allowlist(&p, vec![gl_config.close_to_addr.clone()]);
-                        }
-                        Err(e) => debug!("Could not parse public key {:?}: {:?}", self.id, e),
+        let spends = self.check_spending(&msg, &ctxrequests, &req).await?;
+
+        // If present, add the close_to addresses to the allowlist,
+        // so we can close to them later on.
+        let close_to: Vec<String> = ctxrequests
+            .iter()
+            .filter_map(|r| match r {
+                model::Request::GlConfig(gl_config) => Some(&gl_config.close_to_addr),
+                model::Request::FundChannel(model::cln::FundchannelRequest {
+                    close_to: Some(addr),
+                    ..
+                }) => Some(addr),
+                _ => None,
+            })
+            .filter(|addr| !addr.is_empty())
+            .filter(|addr| {
+                match Address::from_str(addr).and_then(|a| a.require_network(self.network)) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("Not allowlisting close_to address {}: {}", addr, e);
+                        false
                     }
                 }
-                _ => {}
+            })
+            .cloned()
+            .collect();
+        if !close_to.is_empty() {
+            match PublicKey::from_slice(&self.id) {
+                Ok(p) => {
+                    let _ = self.services.persister.update_node_allowlist(&p, close_to);
+                }
+                Err(e) => debug!("Could not parse public key {:?}: {:?}", self.id, e),
             }
         }
 
         use auth::Authorizer;
         let auth = auth::GreenlightAuthorizer {};
-        let approvals = auth.authorize(&ctxrequests).map_err(|e| Error::Auth(e))?;
+        let mut approvals = auth.authorize(&ctxrequests).map_err(Error::Auth)?;
+        approvals.extend(user_approvals);
         debug!("Current approvals: {:?}", approvals);
 
         let approver = Arc::new(MemoApprover::new(approver::ReportingApprover::new(
@@ -580,14 +957,18 @@ impl Signer {
         log::trace!("Updating state from context");
         if let Err(e) = update_state_from_context(&ctxrequests, &root_handler) {
             error!("Failed to update state from context: {:?}", e);
-            report::Reporter::report(crate::pb::scheduler::SignerRejection {
-                msg: format!("Failed to update state from context: {:?}", e),
-                request: Some(req.clone()),
-                git_version: GITHASH.to_string(),
-                node_id: self.node_id(),
-            })
-            .await;
-            return Err(Error::Other(anyhow!("Failed to update state from context: {:?}", e)));
+            self.reporter
+                .report(crate::pb::scheduler::SignerRejection {
+                    msg: format!("Failed to update state from context: {:?}", e),
+                    request: Some(req.clone()),
+                    git_version: GITHASH.to_string(),
+                    node_id: self.node_id(),
+                })
+                .await;
+            return Err(Error::Other(anyhow!(
+                "Failed to update state from context: {:?}",
+                e
+            )));
         }
         log::trace!("State updated");
 
@@ -612,22 +993,25 @@ impl Signer {
         let response = match response {
             Ok(r) => r,
             Err(e) => {
-                report::Reporter::report(crate::pb::scheduler::SignerRejection {
-                    msg: format!("{:?}", e),
-                    request: Some(req.clone()),
-                    git_version: GITHASH.to_string(),
-                    node_id: self.node_id(),
-                })
-                .await;
+                self.reporter
+                    .report(crate::pb::scheduler::SignerRejection {
+                        msg: format!("{:?}", e),
+                        request: Some(req.clone()),
+                        git_version: GITHASH.to_string(),
+                        node_id: self.node_id(),
+                    })
+                    .await;
                 return Err(Error::Other(anyhow!("processing request: {e:?}")));
             }
         };
+        self.record_spending(spends)?;
 
         let signer_state: Vec<crate::pb::SignerStateEntry> = {
             debug!("Serializing state changes to report to node");
             let state = self.state.lock().map_err(|e| {
                 Error::Other(anyhow!("Failed to acquire state lock for serialization: {:?}", e))
             })?;
+            self.persister.mark_reported(&state);
             state.clone().into()
         };
         Ok(HsmResponse {
@@ -782,6 +1166,26 @@ impl Signer {
     async fn init_scheduler(
         &self,
         scheduler_uri: String,
+    ) -> Result<SchedulerClient<tonic::transport::channel::Channel>> {
+        let mut scheduler = self.connect_scheduler(scheduler_uri)?;
+
+        // Upgrade node if necessary.
+        // If it fails due to connection error, sleep and retry. Re-throw all other errors.
+        while let Err(e) = self.maybe_upgrade(&mut scheduler).await {
+            match e.code() {
+                Code::Unavailable => {
+                    debug!("Cannot connect to scheduler, sleeping and retrying");
+                    sleep(Duration::from_secs(3)).await;
+                }
+                _ => Err(Error::Upgrade(e))?,
+            }
+        }
+        Ok(scheduler)
+    }
+
+    fn connect_scheduler(
+        &self,
+        scheduler_uri: String,
     ) -> Result<SchedulerClient<tonic::transport::channel::Channel>> {
         info!("Connecting to scheduler at {scheduler_uri}");
 
@@ -792,47 +1196,37 @@ impl Signer {
             .keep_alive_timeout(crate::TCP_KEEPALIVE_TIMEOUT)
             .keep_alive_while_idle(true)
             .connect_lazy();
-        let mut scheduler = SchedulerClient::new(channel);
-
-        // Upgrade node if necessary.
-        // If it fails due to connection error, sleep and retry. Re-throw all other errors.
-        loop {
-            let call_start = tokio::time::Instant::now();
-            debug!("Sending maybe_upgrade to {}", self.version());
-            #[allow(deprecated)]
-            let res = scheduler
-                .maybe_upgrade(UpgradeRequest {
-                    initmsg: self.init.clone(),
-                    signer_version: self.version().to_owned(),
-                    startupmsgs: self
-                        .get_startup_messages()
-                        .into_iter()
-                        .map(|s| s.into())
-                        .collect(),
-                })
-                .await;
-            debug!(
-                "Server returned {:?} after {}s",
-                res,
-                call_start.elapsed().as_secs()
-            );
+        Ok(SchedulerClient::new(channel))
+    }
 
-            match res {
-                Err(e) => match e.code() {
-                    Code::Unavailable => {
-                        debug!("Cannot connect to scheduler, sleeping and retrying");
-                        sleep(Duration::from_secs(3)).await;
-                        continue;
-                    }
-                    _ => Err(Error::Upgrade(e))?,
-                },
-                Ok(r) => {
-                    debug!("Server reports version {}", r.into_inner().old_version)
-                }
-            }
-            break;
-        }
-        Ok(scheduler)
+    /// Tell the scheduler which signer version we run, so it can
+    /// upgrade the node if necessary.
+    async fn maybe_upgrade(
+        &self,
+        scheduler: &mut SchedulerClient<tonic::transport::channel::Channel>,
+    ) -> Result<(), tonic::Status> {
+        let call_start = tokio::time::Instant::now();
+        debug!("Sending maybe_upgrade to {}", self.version());
+        #[allow(deprecated)]
+        let res = scheduler
+            .maybe_upgrade(UpgradeRequest {
+                initmsg: self.init.clone(),
+                signer_version: self.version().to_owned(),
+                startupmsgs: self
+                    .get_startup_messages()
+                    .into_iter()
+                    .map(|s| s.into())
+                    .collect(),
+            })
+            .await;
+        debug!(
+            "Server returned {:?} after {}s",
+            res,
+            call_start.elapsed().as_secs()
+        );
+        let r = res?;
+        debug!("Server reports version {}", r.into_inner().old_version);
+        Ok(())
     }
 
     /// The core signer loop. Connects to the signer and keeps the connection alive.
@@ -904,6 +1298,48 @@ impl Signer {
         Ok(())
     }
 
+    /// Like `run_forever_with_uri`, but returns the first error
+    /// instead of retrying, so that a supervisor can report it and
+    /// decide when to try again.
+    pub(crate) async fn run_until_error(&self, scheduler_uri: String) -> Result<(), anyhow::Error> {
+        let mut scheduler = self.connect_scheduler(scheduler_uri)?;
+        self.maybe_upgrade(&mut scheduler)
+            .await
+            .map_err(Error::Upgrade)?;
+        tokio::select! {
+            res = self.run_node_until_error(scheduler.clone()) => res,
+            res = self.run_once_scheduler(scheduler) => res,
+        }
+    }
+
+    /// Wait for the node to be scheduled and process its requests,
+    /// until the scheduler or the node fails.
+    async fn run_node_until_error(
+        &self,
+        mut scheduler: SchedulerClient<tonic::transport::Channel>,
+    ) -> Result<(), anyhow::Error> {
+        loop {
+            let node_info = scheduler
+                .get_node_info(NodeInfoRequest {
+                    node_id: self.id.clone(),
+                    wait: true,
+                })
+                .await?
+                .into_inner();
+
+            if node_info.grpc_uri.is_empty() {
+                trace!("Got an empty GRPC URI, node is not scheduled, sleeping and retrying");
+                sleep(Duration::from_millis(1000)).await;
+                continue;
+            }
+
+            // The node stopping is not a failure, it'll get
+            // scheduled again.
+            self.run_once(Uri::from_maybe_shared(node_info.grpc_uri)?)
+                .await?;
+        }
+    }
+
     async fn run_forever_scheduler(
         &self,
         scheduler: SchedulerClient<tonic::transport::Channel>,
@@ -911,6 +1347,8 @@ impl Signer {
         loop {
             if let Err(e) = self.run_once_scheduler(scheduler.clone()).await {
                 warn!("Error running schduler, trying again: {e}");
+                // Don't spin, other signers may share this runtime.
+                sleep(Duration::from_millis(1000)).await;
             }
         }
     }
@@ -937,12 +1375,17 @@ impl Signer {
                     trace!("Processing scheduler request {}", req_id);
                     match msg.request {
                         Some(signer_request::Request::ApprovePairing(req)) => {
-                            if let Err(e) = self
-                                .process_pairing_approval(req_id, req, sender.clone())
-                                .await
-                            {
-                                debug!("Could not process pairing approval: {:?}", e);
-                            }
+                            // Pairing requests may wait for the user, so
+                            // don't hold up the stream while they do.
+                            let signer = self.clone();
+                            let sender = sender.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) =
+                                    signer.process_pairing_approval(req_id, req, sender).await
+                                {
+                                    debug!("Could not process pairing approval: {:?}", e);
+                                }
+                            });
                         }
                         None => {
                             debug!("Received an empty signing request");
@@ -992,13 +1435,36 @@ impl Signer {
             request: vec![],
             uri: "/cln.Node/ApprovePairing".to_string(),
             signature: req.sig,
-            pubkey: req.pubkey,
+            pubkey: req.pubkey.clone(),
             timestamp: req.timestamp,
             rune,
+            stream_seq: None,
         })?;
 
-        let restrs: Vec<Vec<&str>> = req
-            .restrictions
+        let restrictions = match &self.pairing {
+            Some(queue) => {
+                let request = PairingRequest {
+                    device_id: req.device_id.clone(),
+                    device_name: req.device_name.clone(),
+                    restrictions: req.restrictions.clone(),
+                    pubkey: req.pubkey.clone(),
+                    expires_at: 0,
+                };
+                queue
+                    .submit(request, now_secs())
+                    .await
+                    .restrictions(&req.restrictions)?
+                    .ok_or_else(|| {
+                        Error::ApprovePairingRequestError(format!(
+                            "pairing request from {} was denied",
+                            req.device_id
+                        ))
+                    })?
+            }
+            None => req.restrictions.clone(),
+        };
+
+        let restrs: Vec<Vec<&str>> = restrictions
             .split('&')
             .map(|s| s.split('|').collect::<Vec<&str>>())
             .collect();
@@ -1022,6 +1488,29 @@ impl Signer {
         Ok(())
     }
 
+    /// Sign the LNURL-auth challenge `k1` from `domain` with the
+    /// linking key for `domain`, derived as described in LUD-05.
+    /// Returns the DER encoded signature and the linking public key.
+    pub fn sign_lnurl_auth(&self, domain: &str, k1: &[u8; 32]) -> Result<(Vec<u8>, Vec<u8>)> {
+        use lightning_signer::bitcoin::bip32::Xpriv;
+        use lightning_signer::util::crypto_utils::hkdf_sha256;
+
+        // The same master key the signer uses for the on-chain wallet.
+        let seed = hkdf_sha256(&self.secret, "bip32 seed".as_bytes(), &[]);
+        let master = Xpriv::new_master(self.network, &seed)?;
+        let key = crate::lnurl::auth::linking_key(&master, domain)?;
+        Ok(crate::lnurl::auth::sign_challenge(&key, k1))
+    }
+
+    /// Log into the service behind the LNURL-auth `lnurl`. See
+    /// `LNURL::login`.
+    pub async fn lnurl_auth(&self, lnurl: &str) -> Result<()> {
+        use crate::lnurl::{models::LnUrlHttpClearnetClient, LNURL};
+        LNURL::new(LnUrlHttpClearnetClient::new())
+            .login(lnurl, self)
+            .await
+    }
+
     // TODO See comment on `sign_device_key`.
     pub fn sign_challenge(&self, challenge: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
         if challenge.len() != 32 {
@@ -1111,6 +1600,8 @@ impl Signer {
     ///
     /// If a `rune` is supplied the restrictions are added to this rune. This
     /// way one can invoke a rune that only allows for a subset of commands.
+    /// Otherwise the new rune gets a unique id, under which it is listed by
+    /// `list_runes` and can be revoked with `revoke_rune`.
     ///
     /// `restrictions` is a vector of restrictions where each restriction itself
     /// is a vector of one ore more alternatives.
@@ -1127,6 +1618,14 @@ impl Signer {
     /// - #: a comment, ignored, e.g. dumb example#.
     /// - !: only passes if the name does not exist. e.g. something!.  Every other operator except # fails if name does not exist!
     ///
+    /// Besides `method`, `pubkey` and `time`, restrictions can refer to
+    /// the parameters of the call:
+    ///
+    /// - `amount_msat`: the amount sent or requested, e.g. amount_msat<100000.
+    /// - `destination`: the node id or address funds are sent to.
+    /// - `label`: the label of a payment or invoice, e.g. label^pos-.
+    /// - `bolt11_amount`: the amount of the invoice being paid.
+    ///
     /// # Examples
     /// This creates a fresh rune that is only restricted to a pubkey:
     ///
@@ -1140,6 +1639,10 @@ impl Signer {
     ///
     /// `create_rune("wjEjvKoFJToMLBv4QVbJpSbMoGFlnYVxs8yy40PIBgs9MC1nbDAmcHVia2V5PTAwMDAwMA", vec![vec!["method^list", "method^get"]])`
     ///
+    /// This restricts a rune to payments of less than 10000 sats each:
+    ///
+    /// `create_rune(Some(rune), vec![vec!["method=pay"], vec!["amount_msat<10000000"]])`
+    ///
     pub fn create_rune(
         &self,
         rune: Option<&str>,
@@ -1154,6 +1657,11 @@ impl Signer {
             });
             return Ok(rune.to_base64());
         } else {
+            let description = restrictions
+                .iter()
+                .map(|alts| alts.join("|"))
+                .collect::<Vec<String>>()
+                .join("&");
             let res: Vec<Restriction> = restrictions
                 .into_iter()
                 .map(|alts| {
@@ -1162,10 +1670,6 @@ impl Signer {
                 })
                 .collect::<Result<Vec<Restriction>, RuneError>>()?;
 
-            // New rune, we need a unique id.
-            // FIXME: Add a counter that persists in SSS.
-            let unique_id = 0;
-
             // Check that at least one restriction has a `pubkey` field set.
             let has_pubkey_field = res.iter().any(|r: &Restriction| {
                 r.alternatives
@@ -1176,6 +1680,20 @@ impl Signer {
                 return Err(anyhow!("Missing a restriction on the pubkey"));
             }
 
+            // New rune, we need a unique id.
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let mut unique_id = 0;
+            self.persister.update_value(
+                rune_registry::RUNE_REGISTRY_KEY,
+                |r: &mut rune_registry::RuneRegistry| {
+                    unique_id = r.issue(description, now);
+                    true
+                },
+            )?;
+
             let rune = Rune::new(
                 self.master_rune.authcode(),
                 res,
@@ -1187,6 +1705,35 @@ impl Signer {
     }
 }
 
+/// Check that the client signed `r`, either as a unary request or as
+/// a message of a streamed request, see `crate::node::stream`.
+pub fn verify_request_signature(r: &crate::pb::PendingRequest) -> Result<(), anyhow::Error> {
+    let data = match r.stream_seq {
+        Some(seq) => crate::node::stream::signed_data(&r.request, r.timestamp, seq),
+        None => {
+            let mut data = r.request.clone();
+            // If we have a timestamp associated we must add it to
+            // the payload being checked. Same thing happens on the
+            // client too.
+            if r.timestamp != 0 {
+                data.put_u64(r.timestamp);
+            }
+            data
+        }
+    };
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &r.pubkey)
+        .verify(&data, &r.signature)
+        .map_err(|e| anyhow!("signature verification failed: {}", e))
+}
+
+/// Seconds since the UNIX epoch.
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 /// Look through the context requests and update the state
 /// accordingly. This is useful to modify allowlists and invoice lists
 /// extracted from the authenticated requests.
@@ -1239,6 +1786,65 @@ fn update_state_from_request(
     Ok(())
 }
 
+/// Describe a request the resolver could not match, so we can ask
+/// the user about it. If the VLS policy is going to ask the approver
+/// about the request as well, we also return the approval that lets
+/// it pass once the user approved.
+fn approval_request(
+    msg: &vls_protocol::msgs::Message,
+    network: Network,
+) -> (
+    ApprovalRequest,
+    Option<vls_protocol_signer::approver::Approval>,
+) {
+    use lightning_signer::invoice::Invoice;
+    use lightning_signer::lightning::types::payment::PaymentHash;
+    use std::str::FromStr;
+    use vls_protocol::msgs::Message;
+    use vls_protocol_signer::approver::Approval;
+
+    match msg {
+        Message::PreapproveInvoice(m) => {
+            let invoice = std::str::from_utf8(&m.invstring.0)
+                .ok()
+                .and_then(|s| Invoice::from_str(s).ok());
+            if let Some(invoice) = invoice {
+                return (
+                    ApprovalRequest::from_invoice(&invoice),
+                    Some(Approval::Invoice(invoice)),
+                );
+            }
+        }
+        Message::PreapproveKeysend(m) => {
+            return (
+                ApprovalRequest::Keysend {
+                    destination: Some(m.destination.0.to_vec()),
+                    payment_hash: m.payment_hash.0.to_vec(),
+                    amount_msat: m.amount_msat,
+                },
+                Some(Approval::KeySend(
+                    PaymentHash(m.payment_hash.0),
+                    m.amount_msat,
+                )),
+            );
+        }
+        Message::SignWithdrawal(m) => {
+            let psbt = &m.psbt.0.psbt.inner;
+            return (
+                ApprovalRequest::from_psbt(psbt, network),
+                Some(Approval::Onchain(psbt.unsigned_tx.clone())),
+            );
+        }
+        _ => {}
+    }
+    (
+        ApprovalRequest::Unresolved {
+            message: format!("{:?}", msg),
+        },
+        None,
+    )
+}
+
 /// Used to decode incoming requests into their corresponding protobuf
 /// message. This is used by the E2E verification to verify that
 /// incoming requests match up with the user intent. User intent here
@@ -1251,13 +1857,88 @@ fn decode_request(r: crate::pb::PendingRequest) -> Result<model::Request, anyhow
     // for technical details.
     //
     // Notice that we assume that the compression flag is off.
-    assert_eq!(r.request[0], 0u8);
+    if r.request.len() < 5 || r.request[0] != 0u8 {
+        return Err(anyhow!("request is compressed or truncated"));
+    }
     let payload = &r.request[5..];
 
     crate::signer::model::cln::decode_request(&r.uri, payload)
         .or_else(|_| crate::signer::model::greenlight::decode_request(&r.uri, payload))
 }
 
+/// Extract the parameters of a request that runes can restrict.
+fn rune_params(r: &model::Request) -> runes::Params {
+    use lightning_signer::invoice::{Invoice, InvoiceAttributes};
+    use model::cln::{amount_or_all, amount_or_any};
+    use model::Request;
+
+    let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+    // Zero-amount invoices have no amount.
+    let invoice = |bolt11: &str| match Invoice::from_str(bolt11) {
+        Ok(i) => (
+            Some(i.amount_milli_satoshis()).filter(|a| *a != 0),
+            Some(i.payee_pub_key().to_string()),
+        ),
+        Err(_) => (None, None),
+    };
+
+    let mut p = runes::Params::default();
+    match r {
+        Request::Pay(r) => {
+            (p.bolt11_amount, p.destination) = invoice(&r.bolt11);
+            p.amount_msat = r.amount_msat.as_ref().map(|a| a.msat).or(p.bolt11_amount);
+            p.label = r.label.clone();
+        }
+        Request::TrampolinePay(r) => {
+            (p.bolt11_amount, p.destination) = invoice(&r.bolt11);
+            p.amount_msat = Some(r.amount_msat).filter(|a| *a != 0).or(p.bolt11_amount);
+            p.label = non_empty(&r.label);
+        }
+        Request::SendPay(r) => {
+            if let Some(bolt11) = &r.bolt11 {
+                (p.bolt11_amount, p.destination) = invoice(bolt11);
+            }
+            p.amount_msat = r.amount_msat.as_ref().map(|a| a.msat).or(p.bolt11_amount);
+            p.label = r.label.clone();
+        }
+        Request::KeySend(r) => {
+            p.destination = Some(hex::encode(&r.destination));
+            p.amount_msat = r.amount_msat.as_ref().map(|a| a.msat);
+            p.label = r.label.clone();
+        }
+        Request::Invoice(r) => {
+            p.amount_msat = match r.amount_msat.as_ref().and_then(|a| a.value.as_ref()) {
+                Some(amount_or_any::Value::Amount(a)) => Some(a.msat),
+                _ => None,
+            };
+            p.label = non_empty(&r.label);
+        }
+        Request::LspInvoice(r) => {
+            p.amount_msat = Some(r.amount_msat).filter(|a| *a != 0);
+            p.label = non_empty(&r.label);
+        }
+        Request::Withdraw(r) => {
+            p.destination = Some(r.destination.clone());
+            p.amount_msat = match r.satoshi.as_ref().and_then(|a| a.value.as_ref()) {
+                Some(amount_or_all::Value::Amount(a)) => Some(a.msat),
+                _ => None,
+            };
+        }
+        Request::FundChannel(r) => {
+            p.destination = Some(hex::encode(&r.id));
+            p.amount_msat = match r.amount.as_ref().and_then(|a| a.value.as_ref()) {
+                Some(amount_or_all::Value::Amount(a)) => Some(a.msat),
+                _ => None,
+            };
+        }
+        Request::Close(r) => {
+            p.destination = r.destination.clone();
+        }
+        _ => {}
+    }
+    p
+}
+
 /// A `(request, response)`-tuple passed to the scheduler to allow
 /// signerless startups.
 pub struct StartupMessage {
@@ -1307,6 +1988,358 @@ mod tests {
             .is_err());
     }
 
+    /// A signer backed by a `FileStateStore` must pick up the state
+    /// it stored before a restart.
+    #[test]
+    fn test_file_store_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signer_state.json");
+        let store = Arc::new(crate::persist::FileStateStore::new(&path));
+
+        let signer = Signer::new_with_store(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+            store.clone(),
+        )
+        .unwrap();
+        assert!(path.exists());
+        let keys = |s: &Signer| -> Vec<String> {
+            let entries: Vec<pb::SignerStateEntry> = s.state.lock().unwrap().clone().into();
+            entries.into_iter().map(|e| e.key).collect()
+        };
+        let before = keys(&signer);
+        assert!(before.iter().any(|k| k.starts_with("nodes/")));
+
+        let restarted = Signer::new_with_store(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+            store,
+        )
+        .unwrap();
+        assert_eq!(signer.node_id(), restarted.node_id());
+        assert_eq!(before, keys(&restarted));
+    }
+
+    /// A node sending us an entry that is older than the one we
+    /// already have must be refused.
+    #[tokio::test]
+    async fn test_state_rollback_rejection() {
+        let reports = Arc::new(RingBufferReportSink::new(10));
+        let signer = Signer::builder(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .with_report_sink(reports.clone())
+        .build()
+        .unwrap();
+
+        let mut entries: Vec<pb::SignerStateEntry> = signer.state.lock().unwrap().clone().into();
+        let mut entry = entries.remove(0);
+        signer
+            .state
+            .lock()
+            .unwrap()
+            .merge(
+                &vec![pb::SignerStateEntry {
+                    version: entry.version + 2,
+                    ..entry.clone()
+                }]
+                .into(),
+            )
+            .unwrap();
+        entry.version += 1;
+
+        let res = signer
+            .process_request(HsmRequest {
+                request_id: 0,
+                context: None,
+                raw: vec![],
+                signer_state: vec![entry],
+                requests: Vec::new(),
+            })
+            .await;
+        assert!(matches!(res, Err(Error::StateRollback(r)) if r.len() == 1));
+        assert_eq!(reports.reports().len(), 1);
+    }
+
+    /// Once the node got a newer spending ledger from us it can't go
+    /// back to an older one, even though that one is authentic.
+    #[tokio::test]
+    async fn test_signer_value_rollback_rejection() {
+        use vls_protocol::msgs::SerBolt;
+        let policy = SignerPolicy {
+            spending_limits: Some(vec![SpendingLimit::daily(100_000)]),
+            ..Default::default()
+        };
+        let signer = Signer::builder(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .with_policy(policy)
+        .build()
+        .unwrap();
+        let keysend = |hash: u8, state: Vec<pb::SignerStateEntry>| HsmRequest {
+            request_id: 0,
+            context: None,
+            raw: vls_protocol::msgs::PreapproveKeysend {
+                destination: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+                payment_hash: vls_protocol::model::Sha256([hash; 32]),
+                amount_msat: 1000,
+            }
+            .as_vec(),
+            signer_state: state,
+            requests: Vec::new(),
+        };
+        let ledger = |res: HsmResponse| {
+            res.signer_state
+                .into_iter()
+                .find(|e| e.key == limits::SPENDING_LEDGER_KEY)
+                .unwrap()
+        };
+
+        let stale = ledger(signer.process_request(keysend(1, vec![])).await.unwrap());
+        let current = ledger(signer.process_request(keysend(2, vec![])).await.unwrap());
+        assert!(current.version > stale.version);
+
+        let res = signer.process_request(keysend(3, vec![stale])).await;
+        assert!(matches!(
+            res,
+            Err(Error::StateRollback(r)) if r[0].key == limits::SPENDING_LEDGER_KEY
+        ));
+        assert!(signer
+            .process_request(keysend(3, vec![current]))
+            .await
+            .is_ok());
+    }
+
+    struct FixedApproval(bool);
+
+    #[async_trait::async_trait]
+    impl ApprovalHandler for FixedApproval {
+        async fn approve(&self, request: ApprovalRequest) -> bool {
+            assert!(matches!(
+                request,
+                ApprovalRequest::Keysend {
+                    amount_msat: 1000,
+                    ..
+                }
+            ));
+            self.0
+        }
+    }
+
+    /// An unresolved keysend is passed to the approval handler, which
+    /// gets to decide whether we sign.
+    #[tokio::test]
+    async fn test_approval_handler() {
+        use vls_protocol::msgs::SerBolt;
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let msg = vls_protocol::msgs::PreapproveKeysend {
+            destination: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+            payment_hash: vls_protocol::model::Sha256([1u8; 32]),
+            amount_msat: 1000,
+        }
+        .as_vec();
+        let req = HsmRequest {
+            request_id: 0,
+            context: None,
+            raw: msg,
+            signer_state: vec![],
+            requests: Vec::new(),
+        };
+
+        let deny = signer
+            .clone()
+            .with_approval_handler(Arc::new(FixedApproval(false)), DEFAULT_APPROVAL_TIMEOUT);
+        assert!(matches!(
+            deny.process_request(req.clone()).await,
+            Err(Error::Resolver(_, _))
+        ));
+
+        let approve =
+            signer.with_approval_handler(Arc::new(FixedApproval(true)), DEFAULT_APPROVAL_TIMEOUT);
+        assert!(approve.process_request(req).await.is_ok());
+    }
+
+    /// Keysends are tallied against the spending limits, retries of
+    /// the same payment are only counted once.
+    #[tokio::test]
+    async fn test_spending_limit() {
+        use vls_protocol::msgs::SerBolt;
+        let policy = SignerPolicy {
+            spending_limits: Some(vec![SpendingLimit::daily(1500)]),
+            ..Default::default()
+        };
+        let signer = Signer::builder(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .with_policy(policy)
+        .build()
+        .unwrap();
+        let keysend = |hash: u8| HsmRequest {
+            request_id: 0,
+            context: None,
+            raw: vls_protocol::msgs::PreapproveKeysend {
+                destination: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+                payment_hash: vls_protocol::model::Sha256([hash; 32]),
+                amount_msat: 1000,
+            }
+            .as_vec(),
+            signer_state: vec![],
+            requests: Vec::new(),
+        };
+
+        // Requests we fail to sign don't count.
+        let failing = HsmRequest {
+            context: Some(HsmRequestContext {
+                node_id: vec![0],
+                dbid: 1,
+                capabilities: 0,
+            }),
+            ..keysend(3)
+        };
+        assert!(matches!(
+            signer.process_request(failing).await,
+            Err(Error::Other(_))
+        ));
+
+        assert!(signer.process_request(keysend(1)).await.is_ok());
+        assert!(signer.process_request(keysend(1)).await.is_ok());
+        assert!(matches!(
+            signer.process_request(keysend(2)).await,
+            Err(Error::SpendingLimit(e)) if e.spent_msat == 1000
+        ));
+    }
+
+    /// The node can't tamper with the spending ledger, and a stale
+    /// copy of it doesn't replace the configured limits.
+    #[tokio::test]
+    async fn test_spending_ledger_authenticated() {
+        use vls_protocol::msgs::SerBolt;
+        let signer = |limit_msat: u64| {
+            let policy = SignerPolicy {
+                spending_limits: Some(vec![SpendingLimit::daily(limit_msat)]),
+                ..Default::default()
+            };
+            Signer::builder(
+                vec![0u8; 32],
+                Network::Bitcoin,
+                credentials::Nobody::default(),
+            )
+            .with_policy(policy)
+            .build()
+            .unwrap()
+        };
+        let keysend = |signer: &Signer, hash: u8, state: Vec<pb::SignerStateEntry>| HsmRequest {
+            request_id: 0,
+            context: None,
+            raw: vls_protocol::msgs::PreapproveKeysend {
+                destination: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+                payment_hash: vls_protocol::model::Sha256([hash; 32]),
+                amount_msat: 1000,
+            }
+            .as_vec(),
+            signer_state: state,
+            requests: Vec::new(),
+        };
+        let ledger = |signer: &Signer| -> pb::SignerStateEntry {
+            let entries: Vec<pb::SignerStateEntry> = signer.state.lock().unwrap().clone().into();
+            entries
+                .into_iter()
+                .find(|e| e.key == limits::SPENDING_LEDGER_KEY)
+                .unwrap()
+        };
+
+        // A previous run of the signer, with a higher limit.
+        let old = signer(100_000);
+        assert!(old.process_request(keysend(&old, 1, vec![])).await.is_ok());
+        let stale = ledger(&old);
+
+        // The node replaces the recorded spends.
+        let signer = signer(1500);
+        let mut value: serde_json::Value = serde_json::from_slice(&stale.value).unwrap();
+        value["value"]["spends"] = serde_json::json!([]);
+        let forged = pb::SignerStateEntry {
+            version: stale.version + 1,
+            value: serde_json::to_vec(&value).unwrap(),
+            ..stale.clone()
+        };
+        assert!(matches!(
+            signer.process_request(keysend(&signer, 2, vec![forged])).await,
+            Err(Error::UnauthenticatedState(k)) if k == vec![limits::SPENDING_LEDGER_KEY]
+        ));
+
+        // The stale ledger is authentic, so we keep its spends, but
+        // the configured limit stays in force.
+        assert!(matches!(
+            signer.process_request(keysend(&signer, 2, vec![stale])).await,
+            Err(Error::SpendingLimit(e)) if e.spent_msat == 1000 && e.limit.limit_msat == 1500
+        ));
+    }
+
+    /// Signed and rejected requests both end up in the audit
+    /// journal.
+    #[tokio::test]
+    async fn test_audit_journal() {
+        use vls_protocol::msgs::SerBolt;
+        let signer = Signer::builder(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .with_audit_log(Arc::new(MemoryAuditLog::default()))
+        .build()
+        .unwrap();
+        let keysend = HsmRequest {
+            request_id: 0,
+            context: None,
+            raw: vls_protocol::msgs::PreapproveKeysend {
+                destination: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+                payment_hash: vls_protocol::model::Sha256([1u8; 32]),
+                amount_msat: 1000,
+            }
+            .as_vec(),
+            signer_state: vec![],
+            requests: Vec::new(),
+        };
+        let empty = HsmRequest {
+            raw: vec![],
+            ..keysend.clone()
+        };
+        // Doesn't move any funds, but is recorded all the same.
+        let ecdh = HsmRequest {
+            raw: vls_protocol::msgs::Ecdh {
+                point: vls_protocol::model::PubKey(signer.node_id().try_into().unwrap()),
+            }
+            .as_vec(),
+            ..keysend.clone()
+        };
+        assert!(signer.process_request(keysend).await.is_ok());
+        assert!(signer.process_request(ecdh).await.is_ok());
+        assert!(signer.process_request(empty).await.is_err());
+
+        let entries = signer.audit(&AuditQuery::default()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].request, "PreapproveKeysend");
+        assert_eq!(entries[0].amount_msat, Some(1000));
+        assert_eq!(entries[0].outcome, AuditOutcome::Signed);
+        assert_eq!(entries[1].request, "Ecdh");
+        assert_eq!(entries[1].amount_msat, None);
+        assert_eq!(entries[1].outcome, AuditOutcome::Signed);
+        assert!(matches!(entries[2].outcome, AuditOutcome::Rejected { .. }));
+    }
+
     /// We should reject a signing request with an empty message.
     #[tokio::test]
     async fn test_empty_message() {
@@ -1449,6 +2482,7 @@ mod tests {
             pubkey: pubkey.clone(),
             timestamp: 0,
             rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+            stream_seq: None,
         };
         assert!(signer.verify_rune(r).is_ok());
 
@@ -1461,6 +2495,7 @@ mod tests {
             pubkey: pubkey.clone(),
             timestamp: 0,
             rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+            stream_seq: None,
         };
         assert!(signer.verify_rune(r).is_err());
 
@@ -1474,6 +2509,7 @@ mod tests {
             pubkey: pubkey.clone(),
             timestamp: 0,
             rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+            stream_seq: None,
         };
         assert!(signer.verify_rune(r).is_ok());
 
@@ -1487,10 +2523,242 @@ mod tests {
             pubkey: pubkey.clone(),
             timestamp: 0,
             rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+            stream_seq: None,
+        };
+        assert!(signer.verify_rune(r).is_err());
+    }
+
+    #[test]
+    fn test_rune_checks_params() {
+        use prost::Message;
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let merchant = "02".repeat(33);
+        let dest_rest = format!("destination={}", merchant);
+        let rune = signer
+            .create_rune(
+                None,
+                vec![
+                    vec![&pubkey_rest],
+                    vec!["amount_msat<100000"],
+                    vec![&dest_rest],
+                ],
+            )
+            .unwrap();
+
+        let keysend = |destination: &str, msat: u64| {
+            let payload = model::cln::KeysendRequest {
+                destination: hex::decode(destination).unwrap(),
+                amount_msat: Some(model::cln::Amount { msat }),
+                ..Default::default()
+            }
+            .encode_to_vec();
+            // Uncompressed grpc frame: flag and length prefix.
+            let mut request = vec![0u8];
+            request.put_u32(payload.len() as u32);
+            request.extend(payload);
+            pb::PendingRequest {
+                request,
+                uri: "/cln.Node/KeySend".to_string(),
+                signature: vec![],
+                pubkey: pubkey.clone(),
+                timestamp: 0,
+                rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+                stream_seq: None,
+            }
         };
+
+        assert!(signer.verify_rune(keysend(&merchant, 99_999)).is_ok());
+        assert!(signer.verify_rune(keysend(&merchant, 100_000)).is_err());
+        assert!(signer
+            .verify_rune(keysend(&"03".repeat(33), 1_000))
+            .is_err());
+
+        // Undecodable requests have no parameters to check.
+        let mut r = keysend(&merchant, 1_000);
+        r.request = vec![];
         assert!(signer.verify_rune(r).is_err());
     }
 
+    #[test]
+    fn test_rune_revocation() {
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let phone = signer.create_rune(None, vec![vec![&pubkey_rest]]).unwrap();
+        let tablet = signer.create_rune(None, vec![vec![&pubkey_rest]]).unwrap();
+        let request = |rune: &str| pb::PendingRequest {
+            request: vec![],
+            uri: "/cln.Node/Getinfo".to_string(),
+            signature: vec![],
+            pubkey: pubkey.clone(),
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(rune).unwrap(),
+            stream_seq: None,
+        };
+
+        assert!(signer.verify_rune(request(&phone)).is_ok());
+        let runes = signer.list_runes();
+        assert_eq!(runes.len(), 2);
+        assert_ne!(runes[0].id, runes[1].id);
+        assert!(runes[0].last_used.is_some());
+        assert!(runes[1].last_used.is_none());
+
+        signer.revoke_rune(runes[0].id).unwrap();
+        assert!(signer.verify_rune(request(&phone)).is_err());
+        assert!(signer.verify_rune(request(&tablet)).is_ok());
+        assert!(signer.list_runes()[0].revoked);
+
+        // Runes carved from a revoked rune are revoked too.
+        let carved = signer
+            .create_rune(Some(&phone), vec![vec!["method^get"]])
+            .unwrap();
+        assert!(signer.verify_rune(request(&carved)).is_err());
+    }
+
+    /// The node can't un-revoke runes by editing the registry.
+    #[tokio::test]
+    async fn test_rune_registry_authenticated() {
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let phone = signer.create_rune(None, vec![vec![&pubkey_rest]]).unwrap();
+        signer.revoke_rune(signer.list_runes()[0].id).unwrap();
+
+        let entries: Vec<pb::SignerStateEntry> = signer.state.lock().unwrap().clone().into();
+        let entry = entries
+            .into_iter()
+            .find(|e| e.key == rune_registry::RUNE_REGISTRY_KEY)
+            .unwrap();
+        let mut value: serde_json::Value = serde_json::from_slice(&entry.value).unwrap();
+        value["value"]["revoked"] = serde_json::json!([]);
+        let forged = pb::SignerStateEntry {
+            version: entry.version + 1,
+            value: serde_json::to_vec(&value).unwrap(),
+            ..entry
+        };
+
+        let res = signer
+            .process_request(HsmRequest {
+                request_id: 0,
+                context: None,
+                raw: vec![],
+                signer_state: vec![forged],
+                requests: Vec::new(),
+            })
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::UnauthenticatedState(k)) if k == vec![rune_registry::RUNE_REGISTRY_KEY]
+        ));
+
+        let request = pb::PendingRequest {
+            request: vec![],
+            uri: "/cln.Node/Getinfo".to_string(),
+            signature: vec![],
+            pubkey: pubkey.clone(),
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(&phone).unwrap(),
+            stream_seq: None,
+        };
+        assert!(signer.verify_rune(request).is_err());
+    }
+
+    /// A signer recovered without local state, e.g., after losing
+    /// the phone whose rune was revoked, issues runes that work, and
+    /// keeps them when it learns the registry from the node.
+    #[tokio::test]
+    async fn test_rune_recover_after_revoke() {
+        let signer =
+            |secret| Signer::new(secret, Network::Bitcoin, credentials::Nobody::default()).unwrap();
+        let lost = signer(vec![0u8; 32]);
+        let pubkey = lost.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let phone = lost.create_rune(None, vec![vec![&pubkey_rest]]).unwrap();
+        lost.revoke_rune(lost.list_runes()[0].id).unwrap();
+        let node_state: Vec<pb::SignerStateEntry> = lost.state.lock().unwrap().clone().into();
+
+        let recovered = signer(vec![0u8; 32]);
+        let laptop = recovered
+            .create_rune(None, vec![vec![&pubkey_rest]])
+            .unwrap();
+        let request = |rune: &str| pb::PendingRequest {
+            request: vec![],
+            uri: "/cln.Node/Getinfo".to_string(),
+            signature: vec![],
+            pubkey: pubkey.clone(),
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(rune).unwrap(),
+            stream_seq: None,
+        };
+        assert!(recovered.verify_rune(request(&laptop)).is_ok());
+
+        // The request itself is empty and fails, but the state of the
+        // node has been merged by then.
+        let _ = recovered
+            .process_request(HsmRequest {
+                request_id: 0,
+                context: None,
+                raw: vec![],
+                signer_state: node_state,
+                requests: Vec::new(),
+            })
+            .await;
+        assert!(recovered.verify_rune(request(&laptop)).is_ok());
+        assert!(recovered.verify_rune(request(&phone)).is_err());
+        let runes = recovered.list_runes();
+        assert_eq!(runes.len(), 2);
+        assert!(runes.iter().any(|r| r.revoked));
+        assert!(runes.iter().any(|r| !r.revoked));
+    }
+
+    #[test]
+    fn test_rune_rate_limit() {
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap();
+        let pubkey = signer.node_id();
+        let pubkey_rest = format!("pubkey={}", hex::encode(&pubkey));
+        let rune = signer
+            .create_rune(None, vec![vec![&pubkey_rest], vec!["rate=2/60"]])
+            .unwrap();
+        let request = |signature: u8| pb::PendingRequest {
+            request: vec![],
+            uri: "/cln.Node/Pay".to_string(),
+            signature: vec![signature],
+            pubkey: pubkey.clone(),
+            timestamp: 0,
+            rune: general_purpose::URL_SAFE.decode(&rune).unwrap(),
+            stream_seq: None,
+        };
+
+        assert!(signer.verify_rune(request(1)).is_ok());
+        assert!(signer.verify_rune(request(2)).is_ok());
+        // Verifying a request again doesn't count as another use.
+        assert!(signer.verify_rune(request(1)).is_ok());
+        assert!(signer.verify_rune(request(3)).is_err());
+    }
+
     #[test]
     fn test_empty_rune_is_valid() {
         let creds = credentials::Nobody::default();
@@ -1510,6 +2778,7 @@ mod tests {
                 pubkey,
                 timestamp: 0,
                 rune: general_purpose::URL_SAFE.decode(rune).unwrap(),
+                stream_seq: None,
             })
             .is_ok());
     }
@@ -1533,7 +2802,94 @@ mod tests {
                 pubkey: hex::decode("33aabb").unwrap(),
                 timestamp: 0,
                 rune: general_purpose::URL_SAFE.decode(rune).unwrap(),
+                stream_seq: None,
             })
             .is_err());
     }
+
+    #[tokio::test]
+    async fn test_pairing_queue() {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+        let signer = Signer::new(
+            vec![0u8; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap()
+        .with_pairing_queue(Duration::from_secs(5));
+
+        // The device that approves the pairing, e.g., the app.
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let kp =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        let pubkey = kp.public_key().as_ref().to_vec();
+        let rune = signer
+            .create_rune(
+                None,
+                vec![vec![&format!("pubkey={}", hex::encode(&pubkey))]],
+            )
+            .unwrap();
+
+        let request = |device_id: &str| {
+            let restrictions = format!("pubkey={device_id}");
+            let mut data = vec![];
+            data.put(device_id.as_bytes());
+            data.put_u64(0);
+            data.put(&signer.node_id()[..]);
+            data.put("tablet".as_bytes());
+            data.put(restrictions.as_bytes());
+            ApprovePairingRequest {
+                device_id: device_id.to_string(),
+                timestamp: 0,
+                device_name: "tablet".to_string(),
+                restrictions,
+                sig: kp.sign(&rng, &data).unwrap().as_ref().to_vec(),
+                rune: rune.clone(),
+                pubkey: pubkey.clone(),
+            }
+        };
+
+        let (tx, mut rx) = mpsc::channel(1);
+        let s = signer.clone();
+        let req = request("02aa");
+        let pairing = tokio::spawn(async move { s.process_pairing_approval(1, req, tx).await });
+        while signer.pending_pairings().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let pending = signer.pending_pairings();
+        assert_eq!(pending[0].device_name, "tablet");
+        assert_eq!(pending[0].restrictions, "pubkey=02aa");
+        assert_eq!(pending[0].pubkey, pubkey);
+
+        signer
+            .decide_pairing("02aa", PairingDecision::Narrow("method^list".to_string()))
+            .unwrap();
+        pairing.await.unwrap().unwrap();
+        let res = match rx.recv().await.unwrap().response {
+            Some(signer_response::Response::ApprovePairing(r)) => r,
+            r => panic!("unexpected response {r:?}"),
+        };
+        assert_eq!(res.device_id, "02aa");
+        let issued = Rune::from_base64(&res.rune).unwrap().to_string();
+        assert!(issued.ends_with("pubkey=02aa&method^list"));
+        assert!(signer
+            .decide_pairing("02aa", PairingDecision::Approve)
+            .is_err());
+
+        // Denied requests are not answered.
+        let (tx, mut rx) = mpsc::channel(1);
+        let s = signer.clone();
+        let req = request("02bb");
+        let pairing = tokio::spawn(async move { s.process_pairing_approval(2, req, tx).await });
+        while signer.pending_pairings().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        signer
+            .decide_pairing("02bb", PairingDecision::Deny)
+            .unwrap();
+        assert!(pairing.await.unwrap().is_err());
+        assert!(rx.recv().await.is_none());
+    }
 }
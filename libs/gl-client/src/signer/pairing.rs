@@ -0,0 +1,233 @@
+//! Pairing requests waiting for the user to answer them.
+//!
+//! A new device asks the signer, via the scheduler, for a rune with
+//! the restrictions it needs. With a pairing queue the signer holds
+//! on to these requests until the app approves, narrows or denies
+//! them, instead of approving any request that carries a valid rune.
+
+use crate::runes::RuneTemplate;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Default time a pairing request waits for an answer before it is
+/// denied.
+pub const DEFAULT_PAIRING_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A device asking to be paired with the node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PairingRequest {
+    pub device_id: String,
+    pub device_name: String,
+    /// The restrictions the device asked for, e.g.,
+    /// `method^list|method^get`.
+    pub restrictions: String,
+    /// The public key of the device the request originates from.
+    pub pubkey: Vec<u8>,
+    /// When the request is denied if it was not answered, in seconds
+    /// since the UNIX epoch.
+    pub expires_at: u64,
+}
+
+/// The answer to a `PairingRequest`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PairingDecision {
+    /// Issue a rune with the restrictions the device asked for.
+    Approve,
+    /// Issue a rune with the requested restrictions plus the given
+    /// ones. These are either in rune syntax, e.g.,
+    /// `method^list|method^get&time<1700000000`, or a rune template,
+    /// e.g., `readonly,expires=30d`. Restrictions can only be added,
+    /// so the device never gets more than it asked for.
+    Narrow(String),
+    Deny,
+}
+
+impl PairingDecision {
+    /// The restrictions of the rune to issue for `request`, or `None`
+    /// if it was denied.
+    pub(crate) fn restrictions(&self, request: &str) -> Result<Option<String>> {
+        let extra = match self {
+            PairingDecision::Approve => return Ok(Some(request.to_string())),
+            PairingDecision::Narrow(extra) => match extra.parse::<RuneTemplate>() {
+                Ok(t) => t.encode()?,
+                Err(_) => extra.clone(),
+            },
+            PairingDecision::Deny => return Ok(None),
+        };
+        Ok(Some(
+            [request, extra.as_str()]
+                .into_iter()
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join("&"),
+        ))
+    }
+}
+
+type Pending = (PairingRequest, oneshot::Sender<PairingDecision>);
+
+/// The pairing requests waiting for an answer, keyed by device id.
+pub(crate) struct PairingQueue {
+    timeout: Duration,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl PairingQueue {
+    pub fn new(timeout: Duration) -> Self {
+        PairingQueue {
+            timeout,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The requests that have not been answered and have not expired
+    /// by `now`, oldest first.
+    pub fn list(&self, now: u64) -> Vec<PairingRequest> {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, (r, _)| r.expires_at > now);
+        let mut requests: Vec<PairingRequest> = pending.values().map(|(r, _)| r.clone()).collect();
+        requests.sort_by_key(|r| r.expires_at);
+        requests
+    }
+
+    /// Answer the request from `device_id`. Returns `false` if there
+    /// is no such request, e.g., because it expired.
+    pub fn decide(&self, device_id: &str, decision: PairingDecision) -> bool {
+        let entry = self.pending.lock().unwrap().remove(device_id);
+        match entry {
+            Some((_, tx)) => tx.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Queue a request and wait for the answer. Requests that are not
+    /// answered in time are denied. A new request from the same device
+    /// replaces, and thereby denies, the previous one.
+    pub async fn submit(&self, mut request: PairingRequest, now: u64) -> PairingDecision {
+        let (tx, rx) = oneshot::channel();
+        let device_id = request.device_id.clone();
+        // Round up, so that requests with a timeout below a second are
+        // listed too.
+        let timeout = self.timeout.as_secs() + u64::from(self.timeout.subsec_nanos() > 0);
+        request.expires_at = now + timeout;
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(device_id.clone(), (request, tx));
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(_)) => PairingDecision::Deny,
+            Err(_) => {
+                log::info!("Pairing request from {device_id} expired");
+                let mut pending = self.pending.lock().unwrap();
+                if pending
+                    .get(&device_id)
+                    .is_some_and(|(_, tx)| tx.is_closed())
+                {
+                    pending.remove(&device_id);
+                }
+                PairingDecision::Deny
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn request(device_id: &str) -> PairingRequest {
+        PairingRequest {
+            device_id: device_id.to_string(),
+            device_name: "phone".to_string(),
+            restrictions: "method^list".to_string(),
+            pubkey: vec![2; 33],
+            expires_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_decision_restrictions() {
+        assert_eq!(
+            PairingDecision::Approve
+                .restrictions("method^list")
+                .unwrap(),
+            Some("method^list".to_string())
+        );
+        assert_eq!(
+            PairingDecision::Deny.restrictions("method^list").unwrap(),
+            None
+        );
+        assert_eq!(
+            PairingDecision::Narrow("time<100".to_string())
+                .restrictions("method^list")
+                .unwrap(),
+            Some("method^list&time<100".to_string())
+        );
+        assert_eq!(
+            PairingDecision::Narrow("amount_msat<5".to_string())
+                .restrictions("")
+                .unwrap(),
+            Some("amount_msat<5".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pairing_queue() {
+        let queue = Arc::new(PairingQueue::new(Duration::from_secs(5)));
+        assert!(!queue.decide("dev1", PairingDecision::Approve));
+
+        let q = queue.clone();
+        let waiting = tokio::spawn(async move { q.submit(request("dev1"), 100).await });
+        while queue.list(100).is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let pending = queue.list(100);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].device_id, "dev1");
+        assert_eq!(pending[0].expires_at, 105);
+        // Expired requests are not listed.
+        assert!(queue.list(105).is_empty());
+        assert!(!queue.decide("dev1", PairingDecision::Approve));
+        waiting.abort();
+
+        let q = queue.clone();
+        let waiting = tokio::spawn(async move { q.submit(request("dev2"), 100).await });
+        while queue.list(100).is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let narrow = PairingDecision::Narrow("time<200".to_string());
+        assert!(queue.decide("dev2", narrow.clone()));
+        assert_eq!(waiting.await.unwrap(), narrow);
+        assert!(queue.list(100).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pairing_expiry() {
+        let queue = PairingQueue::new(Duration::from_millis(50));
+        assert_eq!(
+            queue.submit(request("dev1"), 100).await,
+            PairingDecision::Deny
+        );
+        assert!(queue.list(0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pairing_subsecond_timeout() {
+        let queue = Arc::new(PairingQueue::new(Duration::from_millis(500)));
+        let q = queue.clone();
+        let waiting = tokio::spawn(async move { q.submit(request("dev1"), 100).await });
+        while queue.list(100).is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.list(100)[0].expires_at, 101);
+        assert!(queue.decide("dev1", PairingDecision::Approve));
+        assert_eq!(waiting.await.unwrap(), PairingDecision::Approve);
+    }
+}
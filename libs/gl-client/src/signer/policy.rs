@@ -0,0 +1,228 @@
+//! Policy profile for the VLS validator used by the signer.
+//!
+//! The defaults are the policy Greenlight has been running with, and
+//! start from the VLS default policy for the network. Integrators
+//! can tighten or relax individual rules and limits, and load the
+//! whole profile from a file, since `SignerPolicy` has a serde
+//! representation.
+
+use super::limits::{LimitAction, SpendingLimit};
+use anyhow::Result;
+use lightning_signer::bitcoin::Network;
+use lightning_signer::policy::filter::{FilterResult, FilterRule, PolicyFilter};
+use lightning_signer::policy::simple_validator::{make_default_simple_policy, SimplePolicy};
+use lightning_signer::util::velocity::{VelocityControlIntervalType, VelocityControlSpec};
+use serde::{Deserialize, Serialize};
+
+/// What to do when a policy rule is violated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Reject the request.
+    Error,
+    /// Only log a warning and continue.
+    Warn,
+}
+
+/// A filter rule matching a VLS policy tag, such as
+/// `policy-routing-balanced`. Rules are evaluated in order, and the
+/// first matching rule decides the action. Violations that do not
+/// match any rule are errors.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub tag: String,
+    /// Match all tags starting with `tag`.
+    #[serde(default)]
+    pub is_prefix: bool,
+    pub action: RuleAction,
+}
+
+impl PolicyRule {
+    pub fn warn(tag: impl Into<String>) -> Self {
+        PolicyRule {
+            tag: tag.into(),
+            is_prefix: false,
+            action: RuleAction::Warn,
+        }
+    }
+
+    pub fn error(tag: impl Into<String>) -> Self {
+        PolicyRule {
+            tag: tag.into(),
+            is_prefix: false,
+            action: RuleAction::Error,
+        }
+    }
+}
+
+impl From<&PolicyRule> for FilterRule {
+    fn from(r: &PolicyRule) -> FilterRule {
+        FilterRule {
+            tag: r.tag.clone(),
+            is_prefix: r.is_prefix,
+            action: match r.action {
+                RuleAction::Error => FilterResult::Error,
+                RuleAction::Warn => FilterResult::Warn,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VelocityInterval {
+    Hourly,
+    Daily,
+    Unlimited,
+}
+
+/// Limit the amount sent within an interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VelocityLimit {
+    pub limit_msat: u64,
+    pub interval: VelocityInterval,
+}
+
+impl From<VelocityLimit> for VelocityControlSpec {
+    fn from(v: VelocityLimit) -> VelocityControlSpec {
+        VelocityControlSpec {
+            limit_msat: v.limit_msat,
+            interval_type: match v.interval {
+                VelocityInterval::Hourly => VelocityControlIntervalType::Hourly,
+                VelocityInterval::Daily => VelocityControlIntervalType::Daily,
+                VelocityInterval::Unlimited => VelocityControlIntervalType::Unlimited,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignerPolicy {
+    /// Filter rules, evaluated in order, that may demote policy
+    /// violations to warnings.
+    pub rules: Vec<PolicyRule>,
+    pub max_feerate_per_kw: u32,
+    pub max_invoices: usize,
+    pub max_routing_fee_msat: u64,
+    /// Limit on the total amount sent. Uses the VLS default if unset.
+    pub global_velocity_control: Option<VelocityLimit>,
+    /// Limit on the total fees paid. Uses the VLS default if unset.
+    pub fee_velocity_control: Option<VelocityLimit>,
+    /// Limits enforced by the signer on top of the VLS policy. The
+    /// limits are persisted in the signer state, and stay in force
+    /// if unset. An empty list removes them.
+    pub spending_limits: Option<Vec<SpendingLimit>>,
+    /// What to do with requests that exceed a spending limit.
+    pub over_limit: LimitAction,
+}
+
+impl Default for SignerPolicy {
+    fn default() -> Self {
+        SignerPolicy {
+            rules: vec![
+                // TODO: Remove once we have fully switched over to zero-fee anchors
+                PolicyRule::warn("policy-channel-safe-type-anchors"),
+                PolicyRule::warn("policy-routing-balanced"),
+                PolicyRule::warn("policy-commitment-retry-same"),
+                // TODO Remove this once VLS has implemented the fee budget
+                // per payment, rather than the fee budget per HTLC.
+                // Ref: https://github.com/Blockstream/greenlight/issues/538
+                PolicyRule::warn("policy-commitment-fee-range"),
+                PolicyRule::warn("policy-mutual-fee-range"),
+                // TODO: Remove once we have implemented zero invoice support
+                PolicyRule::warn("policy-htlc-fee-range"),
+                // TODO: Remove once we found the desync issue
+                // "policy failure: get_per_commitment_secret: cannot
+                // revoke commitment_number 312 when
+                // next_holder_commit_num is 313"
+                PolicyRule::warn("policy-revoke-new-commitment-signed"),
+            ],
+            // See the fee-range rules above.
+            max_feerate_per_kw: 150_000,
+            // Increase the invoices limit. Results in a larger state, but
+            // bumping into this is rather annoying.
+            max_invoices: 10_000,
+            // Relaxed max_routing_fee since we no longer have the
+            // presplitter which was causing the HTLCs to be smaller.
+            max_routing_fee_msat: 1_000_000,
+            global_velocity_control: None,
+            fee_velocity_control: None,
+            spending_limits: None,
+            over_limit: LimitAction::Deny,
+        }
+    }
+}
+
+impl SignerPolicy {
+    pub fn from_json(s: &str) -> Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add a rule that takes precedence over all existing rules
+    /// matching the same tag.
+    pub fn with_rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.insert(0, rule);
+        self
+    }
+
+    /// Build the VLS policy for `network` from this profile.
+    pub(crate) fn to_simple_policy(&self, network: Network) -> SimplePolicy {
+        let mut policy = make_default_simple_policy(network);
+        policy.filter = PolicyFilter {
+            rules: self.rules.iter().map(|r| r.into()).collect(),
+        };
+        policy.max_feerate_per_kw = self.max_feerate_per_kw;
+        policy.max_invoices = self.max_invoices;
+        policy.max_routing_fee_msat = self.max_routing_fee_msat;
+        if let Some(v) = self.global_velocity_control {
+            policy.global_velocity_control = v.into();
+        }
+        if let Some(v) = self.fee_velocity_control {
+            policy.fee_velocity_control = v.into();
+        }
+        policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_profile() {
+        // Fields that are not mentioned keep their defaults.
+        let p = SignerPolicy::from_json(
+            r#"{"max_invoices": 100, "global_velocity_control": {"limit_msat": 5000, "interval": "daily"}}"#,
+        )
+        .unwrap();
+        assert_eq!(p.max_invoices, 100);
+        assert_eq!(p.rules, SignerPolicy::default().rules);
+
+        let sp = p.to_simple_policy(Network::Bitcoin);
+        assert_eq!(sp.max_invoices, 100);
+        assert_eq!(sp.max_feerate_per_kw, 150_000);
+        assert_eq!(sp.global_velocity_control.limit_msat, 5000);
+
+        let roundtrip = SignerPolicy::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(p, roundtrip);
+    }
+
+    #[test]
+    fn test_rule_precedence() {
+        let p = SignerPolicy::default().with_rule(PolicyRule::error("policy-routing-balanced"));
+        let sp = p.to_simple_policy(Network::Bitcoin);
+        assert_eq!(
+            sp.filter.filter("policy-routing-balanced"),
+            FilterResult::Error
+        );
+        assert_eq!(
+            sp.filter.filter("policy-htlc-fee-range"),
+            FilterResult::Warn
+        );
+    }
+}
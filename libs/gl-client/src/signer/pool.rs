@@ -0,0 +1,316 @@
+//! Run many signers in a single process.
+//!
+//! Each `Signer` in a `SignerPool` runs as its own task on a shared
+//! tokio runtime, under a supervisor that restarts it with an
+//! exponential backoff if it fails. Unlike `Signer::run_forever`,
+//! pooled signers don't retry internally: failing to reach the
+//! scheduler or the node ends the run, and the error is reported in
+//! the node's `NodeStatus` before the restart. Nodes are isolated
+//! from each other: a node that keeps failing only delays its own
+//! restarts.
+//!
+//! Sharing connections between signers is out of scope. Connections
+//! are authenticated with the mTLS identity of the node they belong
+//! to, so each signer keeps its own channels to the scheduler and its
+//! node.
+
+use super::Signer;
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeHealth {
+    /// The signer loops are running.
+    Running,
+    /// The signer failed and is waiting to be restarted.
+    Backoff { until: SystemTime },
+    /// The signer was removed from the pool.
+    Stopped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeStatus {
+    pub node_id: Vec<u8>,
+    pub health: NodeHealth,
+    /// Failures since the signer last ran for a full backoff period.
+    pub failures: u32,
+    pub last_error: Option<String>,
+    pub started_at: Option<SystemTime>,
+}
+
+struct PoolEntry {
+    shutdown: mpsc::Sender<()>,
+    handle: JoinHandle<()>,
+    status: Arc<Mutex<NodeStatus>>,
+}
+
+/// Hosts many signers on one runtime. Signers can be added and
+/// removed at runtime.
+pub struct SignerPool {
+    runtime: Handle,
+    scheduler_uri: String,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    nodes: Mutex<HashMap<Vec<u8>, PoolEntry>>,
+}
+
+impl SignerPool {
+    /// Create an empty pool spawning signers on `runtime`.
+    pub fn new(runtime: Handle) -> Self {
+        SignerPool {
+            runtime,
+            scheduler_uri: crate::utils::scheduler_uri(),
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            nodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_scheduler_uri(mut self, uri: String) -> Self {
+        self.scheduler_uri = uri;
+        self
+    }
+
+    /// Wait at least `min` and at most `max` before restarting a
+    /// failed signer.
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Start running `signer`. Fails if a signer for the same node
+    /// is already in the pool.
+    pub fn add(&self, signer: Signer) -> Result<()> {
+        let node_id = signer.node_id();
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes.contains_key(&node_id) {
+            return Err(anyhow!(
+                "signer for node {} is already running",
+                hex::encode(&node_id)
+            ));
+        }
+
+        let status = Arc::new(Mutex::new(NodeStatus {
+            node_id: node_id.clone(),
+            health: NodeHealth::Running,
+            failures: 0,
+            last_error: None,
+            started_at: None,
+        }));
+        let (shutdown, rx) = mpsc::channel(1);
+        let handle = self.runtime.spawn(supervise(
+            signer,
+            self.scheduler_uri.clone(),
+            self.min_backoff,
+            self.max_backoff,
+            status.clone(),
+            rx,
+        ));
+        info!("Added signer for node {} to pool", hex::encode(&node_id));
+        nodes.insert(
+            node_id,
+            PoolEntry {
+                shutdown,
+                handle,
+                status,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop the signer for `node_id` and remove it from the pool.
+    pub async fn remove(&self, node_id: &[u8]) -> Result<()> {
+        let entry = self
+            .nodes
+            .lock()
+            .unwrap()
+            .remove(node_id)
+            .ok_or_else(|| anyhow!("no signer for node {}", hex::encode(node_id)))?;
+        let _ = entry.shutdown.send(()).await;
+        entry.handle.await?;
+        info!("Removed signer for node {} from pool", hex::encode(node_id));
+        Ok(())
+    }
+
+    /// Stop all signers.
+    pub async fn shutdown(&self) {
+        let node_ids: Vec<Vec<u8>> = self.nodes.lock().unwrap().keys().cloned().collect();
+        for node_id in node_ids {
+            if let Err(e) = self.remove(&node_id).await {
+                warn!("Error stopping signer: {}", e);
+            }
+        }
+    }
+
+    pub fn node_ids(&self) -> Vec<Vec<u8>> {
+        self.nodes.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub fn health(&self, node_id: &[u8]) -> Option<NodeStatus> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(node_id)
+            .map(|e| e.status.lock().unwrap().clone())
+    }
+
+    pub fn status(&self) -> Vec<NodeStatus> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|e| e.status.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+fn backoff(min: Duration, max: Duration, failures: u32) -> Duration {
+    min.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(max)
+}
+
+/// Run `signer` until `shutdown` fires, restarting it with a backoff
+/// whenever its loops exit.
+async fn supervise(
+    signer: Signer,
+    scheduler_uri: String,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    status: Arc<Mutex<NodeStatus>>,
+    mut shutdown: mpsc::Receiver<()>,
+) {
+    let node_id = hex::encode(signer.node_id());
+    loop {
+        let started = SystemTime::now();
+        {
+            let mut s = status.lock().unwrap();
+            s.health = NodeHealth::Running;
+            s.started_at = Some(started);
+        }
+
+        let err = tokio::select! {
+            res = signer.run_until_error(scheduler_uri.clone()) => match res {
+                Ok(()) => "signer loop exited".to_string(),
+                Err(e) => e.to_string(),
+            },
+            _ = shutdown.recv() => break,
+        };
+
+        let delay = {
+            let mut s = status.lock().unwrap();
+            // A signer that ran for a while is considered healthy
+            // again, so it doesn't inherit a long backoff.
+            let ran = started.elapsed().unwrap_or_default();
+            s.failures = if ran > max_backoff { 1 } else { s.failures + 1 };
+            let delay = backoff(min_backoff, max_backoff, s.failures);
+            s.health = NodeHealth::Backoff {
+                until: SystemTime::now() + delay,
+            };
+            s.last_error = Some(err.clone());
+            delay
+        };
+        warn!(
+            "Signer for node {} failed: {}. Restarting in {:?}",
+            node_id, err, delay
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = shutdown.recv() => break,
+        }
+    }
+    debug!("Signer for node {} stopped", node_id);
+    status.lock().unwrap().health = NodeHealth::Stopped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials;
+    use lightning_signer::bitcoin::Network;
+
+    fn signer(seed: u8) -> Signer {
+        Signer::new(
+            vec![seed; 32],
+            Network::Bitcoin,
+            credentials::Nobody::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_backoff() {
+        let (min, max) = (Duration::from_secs(1), Duration::from_secs(10));
+        assert_eq!(backoff(min, max, 1), Duration::from_secs(1));
+        assert_eq!(backoff(min, max, 3), Duration::from_secs(4));
+        assert_eq!(backoff(min, max, 30), max);
+    }
+
+    #[tokio::test]
+    async fn test_add_remove() {
+        // Nothing listens here, so the signers keep failing until
+        // they are removed.
+        let pool = SignerPool::new(Handle::current())
+            .with_scheduler_uri("https://localhost:1".to_string());
+        let (a, b) = (signer(1), signer(2));
+        let (id_a, id_b) = (a.node_id(), b.node_id());
+
+        pool.add(a).unwrap();
+        pool.add(b).unwrap();
+        assert!(pool.add(signer(1)).is_err());
+        assert_eq!(pool.status().len(), 2);
+        assert_ne!(pool.health(&id_a).unwrap().health, NodeHealth::Stopped);
+
+        pool.remove(&id_a).await.unwrap();
+        assert!(pool.health(&id_a).is_none());
+        assert!(pool.remove(&id_a).await.is_err());
+        assert_eq!(pool.node_ids(), vec![id_b]);
+
+        pool.shutdown().await;
+        assert!(pool.node_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_failure() {
+        let pool = SignerPool::new(Handle::current())
+            .with_scheduler_uri("https://localhost:1".to_string())
+            .with_backoff(Duration::from_secs(60), Duration::from_secs(600));
+        let s = signer(1);
+        let id = s.node_id();
+        pool.add(s).unwrap();
+
+        // The connection is refused, and the signer waits out the
+        // backoff instead of retrying on its own.
+        let status = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let status = pool.health(&id).unwrap();
+                if status.health != NodeHealth::Running {
+                    break status;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        match status.health {
+            NodeHealth::Backoff { until } => {
+                assert!(until > SystemTime::now() + Duration::from_secs(50))
+            }
+            h => panic!("unexpected health {:?}", h),
+        }
+        assert_eq!(status.failures, 1);
+        assert!(status.last_error.is_some());
+
+        pool.remove(&id).await.unwrap();
+    }
+}
@@ -11,20 +11,57 @@
 //! the resolver. The information in these reports is already known by
 //! the server and we are attaching most of it just for easier
 //! collation by capturing the full context.
+//!
+//! Deployments that must not ship these payloads to a remote server
+//! can pick a different `ReportSink` when constructing the signer.
 
 use crate::pb;
-pub struct Reporter {}
+use crate::pb::scheduler::SignerRejection;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Receives a report for every request the signer rejects.
+#[async_trait]
+pub trait ReportSink: Send + Sync {
+    async fn report(&self, r: SignerRejection);
+}
+
+/// Uploads reports to the scheduler's debug service. This is the
+/// default.
+pub struct SchedulerReportSink {
+    uri: String,
+    tls: crate::tls::TlsConfig,
+}
+
+impl SchedulerReportSink {
+    pub fn new(uri: String, tls: crate::tls::TlsConfig) -> Self {
+        SchedulerReportSink { uri, tls }
+    }
+}
 
-impl Reporter {
-    pub async fn report(r: pb::scheduler::SignerRejection) {
+impl Default for SchedulerReportSink {
+    fn default() -> Self {
+        SchedulerReportSink::new(crate::utils::scheduler_uri(), crate::tls::TlsConfig::new())
+    }
+}
+
+#[async_trait]
+impl ReportSink for SchedulerReportSink {
+    async fn report(&self, r: SignerRejection) {
         log::warn!("Delivering report {:?}", r);
-        let tls = crate::tls::TlsConfig::new();
-        let uri = crate::utils::scheduler_uri();
-        let channel = tonic::transport::Endpoint::from_shared(uri)
-            .expect("could not configure client")
-            .tls_config(tls.inner.clone())
-            .expect("error configuring client with tls config")
-            .connect_lazy();
+        let channel = match tonic::transport::Endpoint::from_shared(self.uri.clone())
+            .and_then(|e| e.tls_config(self.tls.inner.clone()))
+        {
+            Ok(e) => e.connect_lazy(),
+            Err(e) => {
+                log::error!("could not configure client to report rejection: {}", e);
+                return;
+            }
+        };
 
         let mut client = pb::scheduler::debug_client::DebugClient::new(channel);
         match client.report_signer_rejection(r).await {
@@ -33,3 +70,154 @@ impl Reporter {
         }
     }
 }
+
+/// Appends reports to a local file, one JSON object per line. Once
+/// the file grows beyond `max_bytes` it is rotated to `<path>.1`,
+/// and so on, keeping at most `keep` rotated files.
+pub struct FileReportSink {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    lock: Mutex<()>,
+}
+
+impl FileReportSink {
+    pub fn new(path: impl AsRef<Path>, max_bytes: u64, keep: usize) -> Self {
+        FileReportSink {
+            path: path.as_ref().to_path_buf(),
+            max_bytes,
+            keep,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn write(&self, r: &SignerRejection) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size >= self.max_bytes {
+            rotate_files(&self.path, self.keep)?;
+        }
+        let mut line = serde_json::to_vec(r)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// The `i`th rotated file of `path`, i.e., `<path>.<i>`.
+pub(crate) fn rotated_path(path: &Path, i: usize) -> PathBuf {
+    let mut p = path.to_path_buf().into_os_string();
+    p.push(format!(".{}", i));
+    p.into()
+}
+
+/// Rotate `path` to `<path>.1`, `<path>.1` to `<path>.2`, and so on,
+/// keeping at most `keep` rotated files.
+pub(crate) fn rotate_files(path: &Path, keep: usize) -> std::io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(rotated_path(path, keep));
+    for i in (1..keep).rev() {
+        let from = rotated_path(path, i);
+        if from.exists() {
+            fs::rename(from, rotated_path(path, i + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+#[async_trait]
+impl ReportSink for FileReportSink {
+    async fn report(&self, r: SignerRejection) {
+        if let Err(e) = self.write(&r) {
+            log::error!("could not write rejection report: {}", e);
+        }
+    }
+}
+
+/// Keeps the last `capacity` reports in memory, so the host
+/// application can query them with `reports`.
+pub struct RingBufferReportSink {
+    capacity: usize,
+    reports: Mutex<VecDeque<SignerRejection>>,
+}
+
+impl RingBufferReportSink {
+    pub fn new(capacity: usize) -> Self {
+        RingBufferReportSink {
+            capacity,
+            reports: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// The retained reports, oldest first.
+    pub fn reports(&self) -> Vec<SignerRejection> {
+        self.reports.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl ReportSink for RingBufferReportSink {
+    async fn report(&self, r: SignerRejection) {
+        let mut reports = self.reports.lock().unwrap();
+        if self.capacity == 0 {
+            return;
+        }
+        if reports.len() == self.capacity {
+            reports.pop_front();
+        }
+        reports.push_back(r);
+    }
+}
+
+/// Drops all reports.
+pub struct NoopReportSink;
+
+#[async_trait]
+impl ReportSink for NoopReportSink {
+    async fn report(&self, _r: SignerRejection) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(msg: &str) -> SignerRejection {
+        SignerRejection {
+            msg: msg.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ring_buffer() {
+        let sink = RingBufferReportSink::new(2);
+        for m in ["a", "b", "c"] {
+            sink.report(rejection(m)).await;
+        }
+        let msgs: Vec<String> = sink.reports().into_iter().map(|r| r.msg).collect();
+        assert_eq!(msgs, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reports.jsonl");
+        let sink = FileReportSink::new(&path, 1, 1);
+        for m in ["a", "b", "c"] {
+            sink.report(rejection(m)).await;
+        }
+        // Each report exceeds the size limit, so only the last two
+        // are kept.
+        let current = fs::read_to_string(&path).unwrap();
+        let rotated = fs::read_to_string(rotated_path(&path, 1)).unwrap();
+        assert!(current.contains("\"c\""));
+        assert!(rotated.contains("\"b\""));
+        assert!(!rotated_path(&path, 2).exists());
+    }
+}
@@ -1,8 +1,416 @@
 //! Resolver utilities to match incoming requests against the request
 //! context and find a justifications.
 
-use crate::signer::{model::Request, Error};
-use vls_protocol::msgs::Message;
+use crate::signer::model::cln::{
+    amount_or_all, CloseRequest, FundchannelRequest, InvoiceRequest, OutputDesc, SignpsbtRequest,
+    TxprepareRequest, TxsendRequest, WithdrawRequest,
+};
+use crate::signer::model::{greenlight::LspInvoiceRequest, Request};
+use crate::signer::Error;
+use base64::{engine::general_purpose, Engine as _};
+use lightning_signer::bitcoin::bech32::Fe32;
+use lightning_signer::bitcoin::hashes::{sha256, Hash};
+use lightning_signer::bitcoin::psbt::Psbt;
+use lightning_signer::bitcoin::{Address, OutPoint, ScriptBuf, Transaction, TxOut};
+use lightning_signer::lightning_invoice::RawBolt11Invoice;
+use std::fmt::Display;
+use std::str::FromStr;
+use vls_protocol::msgs::{Message, SignInvoice};
+
+/// The expiry an invoice has if it doesn't specify one, in seconds.
+const DEFAULT_INVOICE_EXPIRY: u64 = 3600;
+
+/// A field in a signer request that does not match the context
+/// request it was compared against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldMismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl FieldMismatch {
+    fn new(field: &str, expected: impl Display, actual: impl Display) -> Self {
+        FieldMismatch {
+            field: field.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+}
+
+impl Display for FieldMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.field, self.expected, self.actual
+        )
+    }
+}
+
+/// The invoice properties we can derive from a context request.
+struct ExpectedInvoice<'a> {
+    description: &'a str,
+    /// Only the hash of the description is committed to.
+    description_hash_only: bool,
+    /// `None` means the invoice must not specify an amount.
+    amount_msat: Option<u64>,
+    expiry: Option<u64>,
+    payment_hash: Option<[u8; 32]>,
+}
+
+impl<'a> From<&'a InvoiceRequest> for ExpectedInvoice<'a> {
+    fn from(r: &'a InvoiceRequest) -> Self {
+        use crate::signer::model::cln::amount_or_any::Value;
+        ExpectedInvoice {
+            description: &r.description,
+            description_hash_only: r.deschashonly.unwrap_or(false),
+            amount_msat: match r.amount_msat.as_ref().and_then(|a| a.value.as_ref()) {
+                Some(Value::Amount(a)) => Some(a.msat),
+                _ => None,
+            },
+            expiry: r.expiry,
+            payment_hash: r
+                .preimage
+                .as_ref()
+                .map(|p| sha256::Hash::hash(p).to_byte_array()),
+        }
+    }
+}
+
+impl<'a> From<&'a LspInvoiceRequest> for ExpectedInvoice<'a> {
+    fn from(r: &'a LspInvoiceRequest) -> Self {
+        ExpectedInvoice {
+            description: &r.description,
+            description_hash_only: false,
+            amount_msat: match r.amount_msat {
+                0 => None,
+                a => Some(a),
+            },
+            expiry: None,
+            payment_hash: None,
+        }
+    }
+}
+
+fn decode_invoice(m: &SignInvoice) -> Result<RawBolt11Invoice, String> {
+    let hrp = std::str::from_utf8(&m.hrp.0).map_err(|e| e.to_string())?;
+    let data = m
+        .u5bytes
+        .0
+        .iter()
+        .map(|b| Fe32::try_from(*b).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<Fe32>, String>>()?;
+    RawBolt11Invoice::from_raw(hrp, &data).map_err(|e| e.to_string())
+}
+
+fn fmt_amount(a: Option<u64>) -> String {
+    a.map(|a| format!("{}msat", a))
+        .unwrap_or_else(|| "any".to_string())
+}
+
+/// Compare the invoice we are asked to sign with what the context
+/// request asked for, returning the fields that differ.
+fn check_invoice(m: &SignInvoice, expected: &ExpectedInvoice) -> Vec<FieldMismatch> {
+    let raw = match decode_invoice(m) {
+        Ok(r) => r,
+        Err(e) => return vec![FieldMismatch::new("invoice", "a valid invoice", e)],
+    };
+    let mut diff = vec![];
+
+    if expected.description_hash_only {
+        let want = sha256::Hash::hash(expected.description.as_bytes());
+        match raw.description_hash() {
+            Some(h) if h.0 == want => {}
+            h => diff.push(FieldMismatch::new(
+                "description_hash",
+                want,
+                h.map(|h| h.0.to_string())
+                    .unwrap_or_else(|| "none".to_string()),
+            )),
+        }
+    } else {
+        match raw.description() {
+            Some(d) if d.as_inner().0 == expected.description => {}
+            d => diff.push(FieldMismatch::new(
+                "description",
+                format!("{:?}", expected.description),
+                d.map(|d| format!("{:?}", d.as_inner().0))
+                    .unwrap_or_else(|| "none".to_string()),
+            )),
+        }
+    }
+
+    // BOLT11 amounts are denominated in pico-BTC, i.e., 1/10 msat.
+    let amount_msat = raw.amount_pico_btc().map(|a| a / 10);
+    if amount_msat != expected.amount_msat {
+        diff.push(FieldMismatch::new(
+            "amount_msat",
+            fmt_amount(expected.amount_msat),
+            fmt_amount(amount_msat),
+        ));
+    }
+
+    if let Some(want) = expected.expiry {
+        let expiry = raw
+            .expiry_time()
+            .map(|e| e.as_seconds())
+            .unwrap_or(DEFAULT_INVOICE_EXPIRY);
+        if expiry != want {
+            diff.push(FieldMismatch::new("expiry", want, expiry));
+        }
+    }
+
+    if let Some(want) = expected.payment_hash {
+        match raw.payment_hash() {
+            Some(h) if h.0.to_byte_array() == want => {}
+            h => diff.push(FieldMismatch::new(
+                "payment_hash",
+                hex::encode(want),
+                h.map(|h| h.0.to_string())
+                    .unwrap_or_else(|| "none".to_string()),
+            )),
+        }
+    }
+    diff
+}
+
+/// The amount the user asked for in an on-chain request.
+enum ExpectedAmount {
+    Sat(u64),
+    /// All funds in the inputs, minus the fee.
+    All,
+    /// Any amount, e.g., our share of a channel being closed.
+    Any,
+}
+
+impl Display for ExpectedAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpectedAmount::Sat(a) => write!(f, "{}sat", a),
+            ExpectedAmount::All => write!(f, "all funds"),
+            ExpectedAmount::Any => write!(f, "any amount"),
+        }
+    }
+}
+
+/// An output the user asked for in an on-chain request.
+struct ExpectedOutput {
+    /// The address and its script, or `None` for a channel funding
+    /// output, whose script depends on keys we don't know yet. Those
+    /// must instead be the funding outpoint of a channel we set up.
+    destination: Option<(String, ScriptBuf)>,
+    amount: ExpectedAmount,
+}
+
+impl ExpectedOutput {
+    fn new(address: &str, amount: ExpectedAmount) -> Result<Self, FieldMismatch> {
+        let script = Address::from_str(address)
+            .map_err(|e| FieldMismatch::new("destination", "a valid address", e))?
+            .assume_checked()
+            .script_pubkey();
+        Ok(ExpectedOutput {
+            destination: Some((address.to_string(), script)),
+            amount,
+        })
+    }
+
+    fn matches(&self, outpoint: &OutPoint, txo: &TxOut, funding: &[OutPoint]) -> bool {
+        let destination = match &self.destination {
+            Some((_, script)) => *script == txo.script_pubkey,
+            None => funding.contains(outpoint),
+        };
+        destination && !matches!(self.amount, ExpectedAmount::Sat(a) if a != txo.value.to_sat())
+    }
+}
+
+impl Display for ExpectedOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.destination {
+            Some((address, _)) => write!(f, "{} to {}", self.amount, address),
+            None => write!(f, "{} to a channel we set up", self.amount),
+        }
+    }
+}
+
+fn amount_or_all(a: &Option<crate::signer::model::cln::AmountOrAll>) -> ExpectedAmount {
+    match a.as_ref().and_then(|a| a.value.as_ref()) {
+        Some(amount_or_all::Value::Amount(a)) => ExpectedAmount::Sat(a.msat / 1000),
+        _ => ExpectedAmount::All,
+    }
+}
+
+fn withdraw_outputs(r: &WithdrawRequest) -> Result<Vec<ExpectedOutput>, FieldMismatch> {
+    Ok(vec![ExpectedOutput::new(
+        &r.destination,
+        amount_or_all(&r.satoshi),
+    )?])
+}
+
+fn txprepare_outputs(r: &TxprepareRequest) -> Result<Vec<ExpectedOutput>, FieldMismatch> {
+    r.outputs
+        .iter()
+        .map(|o: &OutputDesc| {
+            let amount = match &o.amount {
+                Some(a) => ExpectedAmount::Sat(a.msat / 1000),
+                None => ExpectedAmount::All,
+            };
+            ExpectedOutput::new(&o.address, amount)
+        })
+        .collect()
+}
+
+fn fundchannel_outputs(r: &FundchannelRequest) -> Result<Vec<ExpectedOutput>, FieldMismatch> {
+    Ok(vec![ExpectedOutput {
+        destination: None,
+        amount: amount_or_all(&r.amount),
+    }])
+}
+
+fn close_outputs(r: &CloseRequest) -> Result<Vec<ExpectedOutput>, FieldMismatch> {
+    r.destination
+        .iter()
+        .map(|d| ExpectedOutput::new(d, ExpectedAmount::Any))
+        .collect()
+}
+
+fn describe_output(txo: &TxOut) -> String {
+    format!(
+        "{}sat to script {}",
+        txo.value.to_sat(),
+        hex::encode(txo.script_pubkey.as_bytes())
+    )
+}
+
+/// Match the outputs of `psbt` with the `expected` ones. Any output
+/// the user didn't ask for must go back to our wallet, i.e., carry
+/// its derivation path. Channel funding outputs must spend to one of
+/// the `funding` outpoints of the channels we set up.
+fn check_outputs(
+    psbt: &Psbt,
+    expected: Result<Vec<ExpectedOutput>, FieldMismatch>,
+    funding: &[OutPoint],
+) -> Vec<FieldMismatch> {
+    let expected = match expected {
+        Ok(e) => e,
+        Err(m) => return vec![m],
+    };
+    let tx = &psbt.unsigned_tx;
+    let txid = tx.compute_txid();
+    let mut unused: Vec<usize> = (0..tx.output.len()).collect();
+    let mut diff = vec![];
+    for e in expected.iter() {
+        match unused
+            .iter()
+            .position(|i| e.matches(&OutPoint::new(txid, *i as u32), &tx.output[*i], funding))
+        {
+            Some(i) => {
+                unused.remove(i);
+            }
+            None => diff.push(FieldMismatch::new("output", e, "none")),
+        }
+    }
+
+    for i in unused.iter() {
+        let ours = psbt
+            .outputs
+            .get(*i)
+            .map(|o| !o.bip32_derivation.is_empty() || !o.tap_key_origins.is_empty())
+            .unwrap_or(false);
+        if !ours {
+            diff.push(FieldMismatch::new(
+                "output",
+                "change to our wallet",
+                describe_output(&tx.output[*i]),
+            ));
+        }
+    }
+
+    // Sending all funds leaves the inputs minus the fee for the
+    // requested outputs, so there can't be any change. The fee
+    // itself is bounded by the VLS policy.
+    if expected
+        .iter()
+        .any(|e| matches!(e.amount, ExpectedAmount::All))
+        && !unused.is_empty()
+    {
+        diff.push(FieldMismatch::new(
+            "change",
+            "none when sending all funds",
+            unused
+                .iter()
+                .map(|i| describe_output(&tx.output[*i]))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+    }
+    diff
+}
+
+/// Check that a mutual close pays the destination the user asked
+/// for. The only other output is the peer's share, which VLS checks
+/// against the channel balance.
+fn check_close_outputs(
+    tx: &Transaction,
+    expected: Result<Vec<ExpectedOutput>, FieldMismatch>,
+) -> Vec<FieldMismatch> {
+    let expected = match expected {
+        Ok(e) => e,
+        Err(m) => return vec![m],
+    };
+    let mut diff = vec![];
+    for e in expected.iter() {
+        if !tx
+            .output
+            .iter()
+            .any(|o| e.matches(&OutPoint::null(), o, &[]))
+        {
+            diff.push(FieldMismatch::new("output", e, "none"));
+        }
+    }
+    if tx.output.len() > expected.len() + 1 {
+        diff.push(FieldMismatch::new(
+            "outputs",
+            format!("at most {}", expected.len() + 1),
+            tx.output.len(),
+        ));
+    }
+    diff
+}
+
+fn check_txid(tx: &Transaction, txid: &[u8]) -> Vec<FieldMismatch> {
+    // Txids in the grpc interface are in display order.
+    let actual = tx.compute_txid().to_string();
+    let expected = hex::encode(txid);
+    if actual == expected {
+        vec![]
+    } else {
+        vec![FieldMismatch::new("txid", expected, actual)]
+    }
+}
+
+fn check_txsend(tx: &Transaction, r: &TxsendRequest) -> Vec<FieldMismatch> {
+    check_txid(tx, &r.txid)
+}
+
+fn check_signpsbt(tx: &Transaction, r: &SignpsbtRequest) -> Vec<FieldMismatch> {
+    // We must be signing the very PSBT the user passed in.
+    let psbt = general_purpose::STANDARD
+        .decode(&r.psbt)
+        .map_err(|e| e.to_string())
+        .and_then(|b| Psbt::deserialize(&b).map_err(|e| e.to_string()));
+    match psbt {
+        Ok(p) if p.unsigned_tx.compute_txid() == tx.compute_txid() => vec![],
+        Ok(p) => vec![FieldMismatch::new(
+            "txid",
+            p.unsigned_tx.compute_txid(),
+            tx.compute_txid(),
+        )],
+        Err(e) => vec![FieldMismatch::new("psbt", "a valid psbt", e)],
+    }
+}
+
 pub struct Resolver {}
 
 impl Resolver {
@@ -11,8 +419,14 @@ impl Resolver {
     /// context requests and the signer request being resolved. Where
     /// possible we also verify the contents of the request against
     /// the contents of the context request. TODOs in here may
-    /// indicate ways to strengthen the verification.
-    pub fn try_resolve(req: &Message, reqctx: &Vec<Request>) -> Result<(), Error> {
+    /// indicate ways to strengthen the verification. `funding` are
+    /// the funding outpoints of the channels we know about.
+    #[allow(clippy::result_large_err)]
+    pub fn try_resolve(
+        req: &Message,
+        reqctx: &Vec<Request>,
+        funding: &[OutPoint],
+    ) -> Result<(), Error> {
         log::trace!("Resolving {:?}", req);
         // Some requests do not need a justification. For example we
         // reconnect automatically, so there may not even be a context
@@ -31,14 +445,25 @@ impl Resolver {
             // Duplicate verification with VLS, we defer to VLS
             Message::GetChannelBasepoints(_) => true,
             Message::ValidateCommitmentTx(_) => true,
-            Message::SignWithdrawal(_) => true,
             Message::SetupChannel(_) => true,
             Message::GetPerCommitmentPoint(_) => true,
             Message::ValidateRevocation(_) => true,
             Message::NewChannel(_) => true,
             Message::SignCommitmentTx(_) => true,
             Message::SignGossipMessage(_) => true,
-            Message::SignMutualCloseTx(_) => true,
+            // Peers may initiate a close at any time, VLS makes sure
+            // our output goes to the wallet or an allowlisted
+            // address. Only if the user asked for a specific
+            // destination do we need to check it.
+            Message::SignMutualCloseTx(_) => !reqctx.iter().any(|r| {
+                matches!(
+                    r,
+                    Request::Close(CloseRequest {
+                        destination: Some(_),
+                        ..
+                    })
+                )
+            }),
             Message::SignMutualCloseTx2(_) => true,
             Message::SignRemoteCommitmentTx(_) => true,
             Message::SignRemoteCommitmentTx2(_) => true,
@@ -70,6 +495,22 @@ impl Resolver {
             return Ok(());
         }
 
+        // The closest match for an invoice or transaction we were
+        // asked to sign, in case none of the context requests matches
+        // exactly.
+        let mut closest: Option<Vec<FieldMismatch>> = None;
+        let mut check = |diff: Vec<FieldMismatch>| {
+            let accept = diff.is_empty();
+            let closer = match &closest {
+                Some(m) => diff.len() < m.len(),
+                None => true,
+            };
+            if closer {
+                closest = Some(diff);
+            }
+            accept
+        };
+
         for cr in reqctx {
             let accept = match (req, cr) {
                 (Message::SignMessage(m1), Request::SignMessage(m2)) => {
@@ -78,20 +519,40 @@ impl Resolver {
                 (Message::NewChannel(m1), Request::FundChannel(m2)) => {
                     // Different node_id? Reject!
                     m1.peer_id.0 == m2.id.as_slice()
-                    // TODO: Add `close_to` to allowlist for the close
-                    // later on
                 }
-                (Message::SignInvoice(_l), Request::LspInvoice(_r)) => {
-		    // TODO: This could also need some
-		    // strengthening. See below.
-		    true
-		}
-                (Message::SignInvoice(_l), Request::Invoice(_r)) => {
-                    // TODO: This could be strengthened by parsing the
-                    // invoice from `l.u5bytes` and verify the
-                    // description, amount and (maybe) payment_hash
-                    true
+                (Message::SignInvoice(l), Request::LspInvoice(r)) => {
+                    check(check_invoice(l, &r.into()))
+                }
+                (Message::SignInvoice(l), Request::Invoice(r)) => {
+                    check(check_invoice(l, &r.into()))
                 }
+                (Message::SignWithdrawal(l), Request::Withdraw(r)) => check(check_outputs(
+                    &l.psbt.0.psbt.inner,
+                    withdraw_outputs(r),
+                    funding,
+                )),
+                (Message::SignWithdrawal(l), Request::TxPrepare(r)) => check(check_outputs(
+                    &l.psbt.0.psbt.inner,
+                    txprepare_outputs(r),
+                    funding,
+                )),
+                (Message::SignWithdrawal(l), Request::TxSend(r)) => {
+                    check(check_txsend(&l.psbt.0.psbt.inner.unsigned_tx, r))
+                }
+                (Message::SignWithdrawal(l), Request::SignPsbt(r)) => {
+                    // `fundpsbt` only selects inputs, the signature
+                    // is requested by the following `signpsbt`.
+                    check(check_signpsbt(&l.psbt.0.psbt.inner.unsigned_tx, r))
+                }
+                (Message::SignWithdrawal(l), Request::FundChannel(r)) => check(check_outputs(
+                    &l.psbt.0.psbt.inner,
+                    fundchannel_outputs(r),
+                    funding,
+                )),
+                (Message::SignMutualCloseTx(l), Request::Close(r)) => match r.destination {
+                    Some(_) => check(check_close_outputs(&l.tx.0, close_outputs(r))),
+                    None => true,
+                },
                 (Message::PreapproveInvoice(l), Request::Pay(r)) => {
                     l.invstring.0 == r.bolt11.as_bytes()
                 }
@@ -114,7 +575,249 @@ impl Resolver {
             }
         }
 
+        match (req, closest) {
+            (Message::SignInvoice(_), Some(diff)) => return Err(Error::InvoiceMismatch(diff)),
+            (_, Some(diff)) => return Err(Error::TransactionMismatch(diff)),
+            (_, None) => {}
+        }
+
         let ser = req.inner().as_vec();
         Err(Error::Resolver(ser, reqctx.to_vec()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::model::cln::{amount_or_any, Amount, AmountOrAny};
+    use lightning_signer::bitcoin::bip32::{DerivationPath, Fingerprint};
+    use lightning_signer::bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning_signer::bitcoin::{
+        absolute::LockTime, transaction::Version, Amount as BtcAmount,
+    };
+    use lightning_signer::lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+    use std::time::Duration;
+    use vls_protocol::msgs::SignWithdrawal;
+    use vls_protocol::psbt::{PsbtWrapper, StreamedPSBT};
+    use vls_protocol::serde_bolt::{Array, Octets, WithSize};
+
+    fn sign_invoice(description: &str, amount_msat: Option<u64>, preimage: &[u8]) -> Message {
+        let mut b = InvoiceBuilder::new(Currency::Bitcoin)
+            .description(description.to_string())
+            .payment_hash(sha256::Hash::hash(preimage))
+            .payment_secret(PaymentSecret([42; 32]))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .expiry_time(Duration::from_secs(600));
+        if let Some(a) = amount_msat {
+            b = b.amount_milli_satoshis(a);
+        }
+        let (hrp, data) = b.build_raw().unwrap().to_raw();
+        Message::SignInvoice(SignInvoice {
+            u5bytes: Octets(data.iter().map(|f| f.to_u8()).collect()),
+            hrp: Octets(hrp.into_bytes()),
+        })
+    }
+
+    fn invoice_request(description: &str, amount_msat: Option<u64>) -> Request {
+        Request::Invoice(InvoiceRequest {
+            description: description.to_string(),
+            amount_msat: Some(AmountOrAny {
+                value: Some(match amount_msat {
+                    Some(msat) => amount_or_any::Value::Amount(Amount { msat }),
+                    None => amount_or_any::Value::Any(true),
+                }),
+            }),
+            expiry: Some(600),
+            preimage: Some(vec![1; 32]),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_invoice_match() {
+        let msg = sign_invoice("coffee", Some(5000), &[1; 32]);
+        assert!(
+            Resolver::try_resolve(&msg, &vec![invoice_request("coffee", Some(5000))], &[]).is_ok()
+        );
+
+        let msg = sign_invoice("coffee", None, &[1; 32]);
+        assert!(Resolver::try_resolve(&msg, &vec![invoice_request("coffee", None)], &[]).is_ok());
+    }
+
+    #[test]
+    fn test_invoice_mismatch() {
+        // The node swapped out amount and payment_hash.
+        let msg = sign_invoice("coffee", Some(9000), &[2; 32]);
+        let reqs = vec![
+            invoice_request("tea", None),
+            invoice_request("coffee", Some(5000)),
+        ];
+        match Resolver::try_resolve(&msg, &reqs, &[]) {
+            Err(Error::InvoiceMismatch(diff)) => {
+                let fields: Vec<&str> = diff.iter().map(|m| m.field.as_str()).collect();
+                assert_eq!(fields, vec!["amount_msat", "payment_hash"]);
+                assert_eq!(diff[0].expected, "5000msat");
+                assert_eq!(diff[0].actual, "9000msat");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    const DEST: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const CHANGE: &str = "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3";
+
+    fn sign_withdrawal(outputs: &[(&str, u64)]) -> Message {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: outputs
+                .iter()
+                .map(|(a, v)| TxOut {
+                    value: BtcAmount::from_sat(*v),
+                    script_pubkey: Address::from_str(a)
+                        .unwrap()
+                        .assume_checked()
+                        .script_pubkey(),
+                })
+                .collect(),
+        };
+        // Outputs to `CHANGE` go back to our wallet.
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let key = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        for (o, (a, _)) in psbt.outputs.iter_mut().zip(outputs.iter()) {
+            if *a == CHANGE {
+                o.bip32_derivation
+                    .insert(key, (Fingerprint::from([0; 4]), DerivationPath::master()));
+            }
+        }
+        Message::SignWithdrawal(SignWithdrawal {
+            utxos: Array(vec![]),
+            psbt: WithSize(StreamedPSBT {
+                psbt: PsbtWrapper { inner: psbt },
+                segwit_flags: vec![],
+            }),
+        })
+    }
+
+    fn withdraw_request(destination: &str, sat: u64) -> Request {
+        Request::Withdraw(WithdrawRequest {
+            destination: destination.to_string(),
+            satoshi: Some(crate::signer::model::cln::AmountOrAll {
+                value: Some(amount_or_all::Value::Amount(Amount { msat: sat * 1000 })),
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let msg = sign_withdrawal(&[(DEST, 10_000), (CHANGE, 5_000)]);
+        assert!(Resolver::try_resolve(&msg, &vec![withdraw_request(DEST, 10_000)], &[]).is_ok());
+
+        // Without a pending withdraw the node cannot move funds.
+        assert!(matches!(
+            Resolver::try_resolve(&msg, &vec![], &[]),
+            Err(Error::Resolver(_, _))
+        ));
+
+        // The node redirected the funds to another address.
+        let msg = sign_withdrawal(&[(CHANGE, 10_000)]);
+        match Resolver::try_resolve(&msg, &vec![withdraw_request(DEST, 10_000)], &[]) {
+            Err(Error::TransactionMismatch(diff)) => {
+                assert_eq!(diff.len(), 1);
+                assert_eq!(diff[0].field, "output");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // Sneaking in an additional output besides the change.
+        let msg = sign_withdrawal(&[(DEST, 10_000), (CHANGE, 5_000), (DEST, 1_000)]);
+        match Resolver::try_resolve(&msg, &vec![withdraw_request(DEST, 10_000)], &[]) {
+            Err(Error::TransactionMismatch(diff)) => {
+                assert_eq!(diff.len(), 1);
+                assert_eq!(diff[0].expected, "change to our wallet");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_withdraw_all() {
+        let all = Request::Withdraw(WithdrawRequest {
+            destination: DEST.to_string(),
+            satoshi: Some(crate::signer::model::cln::AmountOrAll {
+                value: Some(amount_or_all::Value::All(true)),
+            }),
+            ..Default::default()
+        });
+        let msg = sign_withdrawal(&[(DEST, 15_000)]);
+        assert!(Resolver::try_resolve(&msg, &vec![all.clone()], &[]).is_ok());
+
+        // Holding back part of the funds, even in our own wallet.
+        let msg = sign_withdrawal(&[(DEST, 10_000), (CHANGE, 5_000)]);
+        match Resolver::try_resolve(&msg, &vec![all], &[]) {
+            Err(Error::TransactionMismatch(diff)) => {
+                assert_eq!(diff.len(), 1);
+                assert_eq!(diff[0].field, "change");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fundchannel() {
+        let msg = sign_withdrawal(&[(DEST, 10_000), (CHANGE, 5_000)]);
+        let txid = match &msg {
+            Message::SignWithdrawal(m) => m.psbt.0.psbt.inner.unsigned_tx.compute_txid(),
+            _ => unreachable!(),
+        };
+        let fund = Request::FundChannel(FundchannelRequest {
+            amount: Some(crate::signer::model::cln::AmountOrAll {
+                value: Some(amount_or_all::Value::Amount(Amount { msat: 10_000_000 })),
+            }),
+            ..Default::default()
+        });
+        let channel = OutPoint::new(txid, 0);
+        assert!(Resolver::try_resolve(&msg, &vec![fund.clone()], &[channel]).is_ok());
+
+        // An output of the right amount is not enough, it has to
+        // fund a channel we set up.
+        assert!(matches!(
+            Resolver::try_resolve(&msg, &vec![fund.clone()], &[]),
+            Err(Error::TransactionMismatch(_))
+        ));
+        assert!(matches!(
+            Resolver::try_resolve(&msg, &vec![fund], &[OutPoint::new(txid, 1)]),
+            Err(Error::TransactionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_signpsbt() {
+        let msg = sign_withdrawal(&[(DEST, 10_000)]);
+        let psbt = match &msg {
+            Message::SignWithdrawal(m) => m.psbt.0.psbt.inner.serialize(),
+            _ => unreachable!(),
+        };
+        let req = |psbt: &[u8]| {
+            Request::SignPsbt(SignpsbtRequest {
+                psbt: general_purpose::STANDARD.encode(psbt),
+                ..Default::default()
+            })
+        };
+        assert!(Resolver::try_resolve(&msg, &vec![req(&psbt)], &[]).is_ok());
+
+        let other = match sign_withdrawal(&[(DEST, 20_000)]) {
+            Message::SignWithdrawal(m) => m.psbt.0.psbt.inner.serialize(),
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            Resolver::try_resolve(&msg, &vec![req(&other)], &[]),
+            Err(Error::TransactionMismatch(_))
+        ));
+    }
+}
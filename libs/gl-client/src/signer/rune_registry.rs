@@ -0,0 +1,198 @@
+//! Book-keeping of the runes the signer issued.
+//!
+//! Every rune created by `Signer::create_rune` gets a random 64-bit
+//! id, so runes handed to different devices can be told apart and
+//! revoked individually. The ids are random rather than counted, so
+//! a signer without local state, e.g., one recovered on a new device,
+//! does not hand out the id of an existing or revoked rune before it
+//! learned the registry from the node. Runes carved from an existing
+//! rune share its id, and are revoked along with it.
+//!
+//! The registry is synced to the node with the rest of the state, but
+//! like the spending ledger it is authenticated by the signer, so the
+//! node can't un-revoke a rune. Runes issued before the signer learned
+//! the registry from the node are added back by `absorb`.
+
+use crate::runes::MAX_RATE_PERIOD;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// Key under which the registry is stored in the signer state.
+pub(crate) const RUNE_REGISTRY_KEY: &str = "runes/registry";
+
+/// How often we update the last use of a rune, in seconds. Every
+/// update is a state change that is synced to the node, so we don't
+/// record every single call.
+const LAST_USE_RESOLUTION: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuneInfo {
+    /// The unique id of the rune.
+    pub id: u64,
+    /// The restrictions the rune was created with, e.g.,
+    /// `pubkey=02ab...&method^list|method^get`.
+    pub restrictions: String,
+    /// Seconds since the UNIX epoch.
+    pub created_at: u64,
+    /// When the rune last authorized a request, in seconds since the
+    /// UNIX epoch, with a resolution of a minute.
+    pub last_used: Option<u64>,
+    pub revoked: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RuneRegistry {
+    pub runes: Vec<RuneInfo>,
+    /// Ids of the revoked runes. This may include ids that are not
+    /// in `runes`, e.g., runes issued before ids were tracked.
+    pub revoked: BTreeSet<u64>,
+}
+
+impl RuneRegistry {
+    /// Allocate an id for a new rune with `restrictions`. Runes
+    /// issued before ids were allocated all have the id 0, so it is
+    /// never handed out. Revoking 0 revokes all of those runes.
+    pub fn issue(&mut self, restrictions: String, now: u64) -> u64 {
+        let id = loop {
+            let id: u64 = rand::random();
+            if id != 0 && !self.is_revoked(id) && !self.runes.iter().any(|r| r.id == id) {
+                break id;
+            }
+        };
+        self.runes.push(RuneInfo {
+            id,
+            restrictions,
+            created_at: now,
+            last_used: None,
+            revoked: false,
+        });
+        id
+    }
+
+    pub fn revoke(&mut self, id: u64) {
+        self.revoked.insert(id);
+        if let Some(r) = self.runes.iter_mut().find(|r| r.id == id) {
+            r.revoked = true;
+        }
+    }
+
+    pub fn is_revoked(&self, id: u64) -> bool {
+        self.revoked.contains(&id)
+    }
+
+    /// Add the runes and revocations of `other` that we don't know
+    /// about, e.g., those issued locally before the registry of the
+    /// node replaced ours. Returns whether the registry changed.
+    pub fn absorb(&mut self, other: &RuneRegistry) -> bool {
+        let mut changed = false;
+        for id in other.revoked.difference(&self.revoked.clone()) {
+            self.revoke(*id);
+            changed = true;
+        }
+        for rune in &other.runes {
+            if !self.runes.iter().any(|r| r.id == rune.id) {
+                let mut rune = rune.clone();
+                rune.revoked = self.is_revoked(rune.id);
+                self.runes.push(rune);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Note that the rune with `id` was used. Returns whether the
+    /// registry changed.
+    pub fn touch(&mut self, id: u64, now: u64) -> bool {
+        match self.runes.iter_mut().find(|r| r.id == id) {
+            Some(r) if !matches!(r.last_used, Some(t) if t + LAST_USE_RESOLUTION > now) => {
+                r.last_used = Some(now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The time and signature of a request authorized by a rune.
+type RuneUse = (u64, Vec<u8>);
+
+/// Recent requests authorized by each rune, to check rate limits
+/// against. Like the rate limits of CLN's runes these are only kept
+/// in memory, so they reset when the signer restarts.
+#[derive(Default)]
+pub(crate) struct RuneUses {
+    /// Keyed by the authcode of the rune.
+    uses: Mutex<HashMap<[u8; 32], Vec<RuneUse>>>,
+}
+
+impl RuneUses {
+    /// Times of the requests authorized by the rune, other than the
+    /// request with `signature`.
+    pub fn get(&self, rune: &[u8; 32], signature: &[u8], now: u64) -> Vec<u64> {
+        let mut uses = self.uses.lock().unwrap();
+        match uses.get_mut(rune) {
+            Some(u) => {
+                u.retain(|(t, _)| t + MAX_RATE_PERIOD.as_secs() > now);
+                u.iter()
+                    .filter(|(_, s)| s != signature)
+                    .map(|(t, _)| *t)
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    pub fn record(&self, rune: [u8; 32], signature: Vec<u8>, now: u64) {
+        let mut uses = self.uses.lock().unwrap();
+        let u = uses.entry(rune).or_default();
+        if !u.iter().any(|(_, s)| *s == signature) {
+            u.push((now, signature));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let mut reg = RuneRegistry::default();
+        let first = reg.issue("pubkey=02".to_string(), 0);
+        let second = reg.issue("pubkey=03".to_string(), 0);
+        assert_ne!(first, second);
+        assert!(first != 0 && second != 0);
+
+        assert!(reg.touch(second, 10));
+        assert!(!reg.touch(second, 20));
+        assert!(reg.touch(second, 10 + LAST_USE_RESOLUTION));
+        assert!(!reg.touch(5, 10));
+
+        reg.revoke(first);
+        reg.revoke(0);
+        assert!(reg.is_revoked(0) && reg.is_revoked(first));
+        assert!(!reg.is_revoked(second));
+        assert!(reg.runes[0].revoked);
+    }
+
+    #[test]
+    fn test_absorb() {
+        let mut node = RuneRegistry::default();
+        let old = node.issue("pubkey=02".to_string(), 0);
+        node.revoke(old);
+
+        let mut local = RuneRegistry::default();
+        let new = local.issue("pubkey=02".to_string(), 1);
+        local.revoke(7);
+
+        assert!(node.absorb(&local));
+        assert!(!node.absorb(&local));
+        assert_eq!(
+            node.runes.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![old, new]
+        );
+        assert!(node.is_revoked(old) && node.is_revoked(7));
+        assert!(!node.is_revoked(new));
+    }
+}
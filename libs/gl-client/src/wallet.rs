@@ -0,0 +1,873 @@
+//! A typed wallet API on top of the node's gRPC interface.
+//!
+//! The generated `cln` messages mirror the JSON-RPC of the node, with
+//! most fields optional and amounts wrapped in `Amount`. `Wallet`
+//! wraps a `ClnClient` and offers the handful of operations a wallet
+//! needs, with plain structs and enums as results: the balance,
+//! payments, invoices, fee estimates, channels and on-chain funds.
+//! Use `Wallet::client` for anything not covered here.
+
+use crate::bitcoin::secp256k1::PublicKey;
+use crate::node::{reconnect::RetryPolicy, ClnClient, Node};
+use crate::payment_input::PaymentInput;
+use crate::pb::cln;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use cln::listfunds_outputs::ListfundsOutputsStatus as OutputStatus;
+use cln::ChannelState as PeerChannelState;
+
+#[derive(Clone)]
+pub struct Wallet {
+    client: ClnClient,
+}
+
+/// The funds of the node, by where they are and whether they can be
+/// spent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub onchain_confirmed_msat: u64,
+    pub onchain_unconfirmed_msat: u64,
+    /// Coinbase outputs that are not mature yet.
+    pub onchain_immature_msat: u64,
+    /// Outputs reserved for a transaction that was not broadcast yet.
+    pub onchain_reserved_msat: u64,
+    /// What we can send over channels that are open right now.
+    pub channel_spendable_msat: u64,
+    /// What we can receive over channels that are open right now.
+    pub channel_receivable_msat: u64,
+    /// Our side of channels that are being opened or closed.
+    pub channel_pending_msat: u64,
+}
+
+impl Balance {
+    fn new(funds: &cln::ListfundsResponse, channels: &cln::ListpeerchannelsResponse) -> Self {
+        let mut balance = Balance::default();
+        for o in &funds.outputs {
+            let amount = msat(&o.amount_msat);
+            match o.status() {
+                OutputStatus::Spent => {}
+                _ if o.reserved => balance.onchain_reserved_msat += amount,
+                OutputStatus::Confirmed => balance.onchain_confirmed_msat += amount,
+                OutputStatus::Unconfirmed => balance.onchain_unconfirmed_msat += amount,
+                OutputStatus::Immature => balance.onchain_immature_msat += amount,
+            }
+        }
+        for c in &channels.channels {
+            match ChannelState::from(c.state()) {
+                ChannelState::Open => {
+                    balance.channel_spendable_msat += msat(&c.spendable_msat);
+                    balance.channel_receivable_msat += msat(&c.receivable_msat);
+                }
+                ChannelState::Opening | ChannelState::Closing => {
+                    balance.channel_pending_msat += msat(&c.to_us_msat);
+                }
+                // Funds of closed channels show up as outputs.
+                ChannelState::Closed => {}
+            }
+        }
+        balance
+    }
+
+    /// Everything we own, including funds that cannot be spent yet.
+    pub fn total_msat(&self) -> u64 {
+        self.onchain_confirmed_msat
+            + self.onchain_unconfirmed_msat
+            + self.onchain_immature_msat
+            + self.onchain_reserved_msat
+            + self.channel_spendable_msat
+            + self.channel_pending_msat
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentStatus {
+    Pending,
+    Complete,
+    Failed,
+}
+
+/// A lightning payment we sent or received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Payment {
+    pub direction: Direction,
+    pub status: PaymentStatus,
+    pub payment_hash: Vec<u8>,
+    /// The amount the recipient got.
+    pub amount_msat: Option<u64>,
+    /// Routing fees, for outgoing payments.
+    pub fee_msat: Option<u64>,
+    pub preimage: Option<Vec<u8>>,
+    pub description: Option<String>,
+    /// The BOLT11 or BOLT12 invoice, if there was one.
+    pub invoice: Option<String>,
+    pub destination: Option<Vec<u8>>,
+    /// When the payment was sent, or when it was received.
+    pub timestamp: u64,
+}
+
+impl From<cln::ListpaysPays> for Payment {
+    fn from(p: cln::ListpaysPays) -> Self {
+        use cln::listpays_pays::ListpaysPaysStatus;
+        let status = match p.status() {
+            ListpaysPaysStatus::Pending => PaymentStatus::Pending,
+            ListpaysPaysStatus::Complete => PaymentStatus::Complete,
+            ListpaysPaysStatus::Failed => PaymentStatus::Failed,
+        };
+        let amount_msat = p.amount_msat.as_ref().map(|a| a.msat);
+        let fee_msat = p
+            .amount_sent_msat
+            .as_ref()
+            .zip(amount_msat)
+            .map(|(sent, amount)| sent.msat.saturating_sub(amount));
+        Payment {
+            direction: Direction::Outgoing,
+            status,
+            payment_hash: p.payment_hash,
+            amount_msat,
+            fee_msat,
+            preimage: p.preimage,
+            description: p.description,
+            invoice: p.bolt11.or(p.bolt12),
+            destination: p.destination,
+            timestamp: p.created_at,
+        }
+    }
+}
+
+impl From<cln::PayResponse> for Payment {
+    fn from(p: cln::PayResponse) -> Self {
+        use cln::pay_response::PayStatus;
+        let status = match p.status() {
+            PayStatus::Pending => PaymentStatus::Pending,
+            PayStatus::Complete => PaymentStatus::Complete,
+            PayStatus::Failed => PaymentStatus::Failed,
+        };
+        let amount_msat = msat(&p.amount_msat);
+        Payment {
+            direction: Direction::Outgoing,
+            status,
+            payment_hash: p.payment_hash,
+            amount_msat: Some(amount_msat),
+            fee_msat: Some(msat(&p.amount_sent_msat).saturating_sub(amount_msat)),
+            preimage: Some(p.payment_preimage),
+            description: None,
+            invoice: None,
+            destination: p.destination,
+            timestamp: p.created_at as u64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvoiceStatus {
+    Unpaid,
+    Paid,
+    Expired,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Invoice {
+    pub label: String,
+    pub status: InvoiceStatus,
+    /// The BOLT11 or BOLT12 invoice.
+    pub invoice: Option<String>,
+    pub payment_hash: Vec<u8>,
+    pub description: Option<String>,
+    /// `None` for invoices that accept any amount.
+    pub amount_msat: Option<u64>,
+    pub amount_received_msat: Option<u64>,
+    pub expires_at: u64,
+    pub paid_at: Option<u64>,
+    pub preimage: Option<Vec<u8>>,
+}
+
+impl From<cln::ListinvoicesInvoices> for Invoice {
+    fn from(i: cln::ListinvoicesInvoices) -> Self {
+        use cln::listinvoices_invoices::ListinvoicesInvoicesStatus;
+        let status = match i.status() {
+            ListinvoicesInvoicesStatus::Unpaid => InvoiceStatus::Unpaid,
+            ListinvoicesInvoicesStatus::Paid => InvoiceStatus::Paid,
+            ListinvoicesInvoicesStatus::Expired => InvoiceStatus::Expired,
+        };
+        Invoice {
+            label: i.label,
+            status,
+            invoice: i.bolt11.or(i.bolt12),
+            payment_hash: i.payment_hash,
+            description: i.description,
+            amount_msat: i.amount_msat.map(|a| a.msat),
+            amount_received_msat: i.amount_received_msat.map(|a| a.msat),
+            expires_at: i.expires_at,
+            paid_at: i.paid_at,
+            preimage: i.payment_preimage,
+        }
+    }
+}
+
+impl From<cln::WaitinvoiceResponse> for Invoice {
+    fn from(i: cln::WaitinvoiceResponse) -> Self {
+        use cln::waitinvoice_response::WaitinvoiceStatus;
+        let status = match i.status() {
+            WaitinvoiceStatus::Paid => InvoiceStatus::Paid,
+            WaitinvoiceStatus::Expired => InvoiceStatus::Expired,
+        };
+        Invoice {
+            label: i.label,
+            status,
+            invoice: i.bolt11.or(i.bolt12),
+            payment_hash: i.payment_hash,
+            description: i.description,
+            amount_msat: i.amount_msat.map(|a| a.msat),
+            amount_received_msat: i.amount_received_msat.map(|a| a.msat),
+            expires_at: i.expires_at,
+            paid_at: i.paid_at,
+            preimage: i.payment_preimage,
+        }
+    }
+}
+
+impl Invoice {
+    /// Incoming payments are the invoices that were paid.
+    fn payment(&self) -> Option<Payment> {
+        if self.status != InvoiceStatus::Paid {
+            return None;
+        }
+        Some(Payment {
+            direction: Direction::Incoming,
+            status: PaymentStatus::Complete,
+            payment_hash: self.payment_hash.clone(),
+            amount_msat: self.amount_received_msat.or(self.amount_msat),
+            fee_msat: None,
+            preimage: self.preimage.clone(),
+            description: self.description.clone(),
+            invoice: self.invoice.clone(),
+            destination: None,
+            timestamp: self.paid_at.unwrap_or_default(),
+        })
+    }
+}
+
+/// The parameters of a new invoice, see `Wallet::create_invoice`.
+#[derive(Clone, Debug, Default)]
+pub struct NewInvoice {
+    /// Unique among the invoices of the node.
+    pub label: String,
+    pub description: String,
+    /// `None` to let the payer choose the amount.
+    pub amount_msat: Option<u64>,
+    /// Seconds until the invoice expires, one week if not set.
+    pub expiry: Option<u64>,
+}
+
+/// How much to pay for an on-chain transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeeRate {
+    Slow,
+    Normal,
+    Urgent,
+    /// Satoshis per 1000 virtual bytes.
+    PerKb(u32),
+}
+
+impl From<FeeRate> for cln::Feerate {
+    fn from(f: FeeRate) -> Self {
+        use cln::feerate::Style;
+        let style = match f {
+            FeeRate::Slow => Style::Slow(true),
+            FeeRate::Normal => Style::Normal(true),
+            FeeRate::Urgent => Style::Urgent(true),
+            FeeRate::PerKb(perkb) => Style::Perkb(perkb),
+        };
+        cln::Feerate { style: Some(style) }
+    }
+}
+
+/// The feerate to confirm a transaction within `blocks`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeEstimate {
+    pub blocks: u32,
+    /// Satoshis per 1000 virtual bytes.
+    pub perkb: u32,
+}
+
+/// The node's view of on-chain fees, in satoshis per 1000 virtual
+/// bytes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeeEstimates {
+    /// The lowest feerate the node uses or accepts from peers.
+    pub min_acceptable: u32,
+    pub opening: Option<u32>,
+    pub mutual_close: Option<u32>,
+    /// Ordered by `blocks`.
+    pub estimates: Vec<FeeEstimate>,
+}
+
+impl From<cln::FeeratesPerkb> for FeeEstimates {
+    fn from(f: cln::FeeratesPerkb) -> Self {
+        let mut estimates: Vec<FeeEstimate> = f
+            .estimates
+            .iter()
+            .map(|e| FeeEstimate {
+                blocks: e.blockcount,
+                perkb: e.smoothed_feerate,
+            })
+            .collect();
+        estimates.sort_by_key(|e| e.blocks);
+        FeeEstimates {
+            min_acceptable: f.min_acceptable,
+            opening: f.opening,
+            mutual_close: f.mutual_close,
+            estimates,
+        }
+    }
+}
+
+impl FeeEstimates {
+    /// The feerate to confirm within `blocks`: the estimate for the
+    /// longest target that is not longer than `blocks`, or the
+    /// fastest estimate if `blocks` is shorter than all of them.
+    pub fn for_target(&self, blocks: u32) -> Option<u32> {
+        self.estimates
+            .iter()
+            .rev()
+            .find(|e| e.blocks <= blocks)
+            .or_else(|| self.estimates.first())
+            .map(|e| e.perkb.max(self.min_acceptable))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelState {
+    /// Waiting for the funding transaction to confirm.
+    Opening,
+    Open,
+    /// Being closed, the funds are not on-chain yet.
+    Closing,
+    Closed,
+}
+
+impl From<PeerChannelState> for ChannelState {
+    fn from(s: PeerChannelState) -> Self {
+        match s {
+            PeerChannelState::ChanneldNormal | PeerChannelState::ChanneldAwaitingSplice => {
+                ChannelState::Open
+            }
+            PeerChannelState::ChanneldShuttingDown
+            | PeerChannelState::ClosingdSigexchange
+            | PeerChannelState::ClosingdComplete
+            | PeerChannelState::AwaitingUnilateral
+            | PeerChannelState::FundingSpendSeen => ChannelState::Closing,
+            PeerChannelState::Onchain => ChannelState::Closed,
+            _ => ChannelState::Opening,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Channel {
+    pub peer_id: Vec<u8>,
+    pub peer_connected: bool,
+    pub state: ChannelState,
+    pub channel_id: Option<Vec<u8>>,
+    pub short_channel_id: Option<String>,
+    pub funding_txid: Option<Vec<u8>>,
+    pub total_msat: u64,
+    pub to_us_msat: u64,
+    pub spendable_msat: u64,
+    pub receivable_msat: u64,
+}
+
+impl From<cln::ListpeerchannelsChannels> for Channel {
+    fn from(c: cln::ListpeerchannelsChannels) -> Self {
+        Channel {
+            state: c.state().into(),
+            peer_id: c.peer_id,
+            peer_connected: c.peer_connected,
+            channel_id: c.channel_id,
+            short_channel_id: c.short_channel_id,
+            funding_txid: c.funding_txid,
+            total_msat: msat(&c.total_msat),
+            to_us_msat: msat(&c.to_us_msat),
+            spendable_msat: msat(&c.spendable_msat),
+            receivable_msat: msat(&c.receivable_msat),
+        }
+    }
+}
+
+/// An on-chain amount, or all available funds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnchainAmount {
+    Sat(u64),
+    All,
+}
+
+impl From<OnchainAmount> for cln::AmountOrAll {
+    fn from(a: OnchainAmount) -> Self {
+        use cln::amount_or_all::Value;
+        let value = match a {
+            OnchainAmount::Sat(sat) => Value::Amount(cln::Amount { msat: sat * 1000 }),
+            OnchainAmount::All => Value::All(true),
+        };
+        cln::AmountOrAll { value: Some(value) }
+    }
+}
+
+/// The parameters of a new channel, see `Wallet::open_channel`.
+#[derive(Clone, Debug)]
+pub struct OpenChannel {
+    pub peer_id: PublicKey,
+    /// `host:port` of the peer, if the node is not connected to it
+    /// yet.
+    pub address: Option<String>,
+    pub amount: OnchainAmount,
+    /// Give the peer part of the channel.
+    pub push_msat: Option<u64>,
+    pub feerate: Option<FeeRate>,
+    /// Announce the channel to the network for routing.
+    pub announce: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenedChannel {
+    pub channel_id: Vec<u8>,
+    pub txid: Vec<u8>,
+    pub outnum: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseType {
+    Mutual,
+    Unilateral,
+    /// The channel was never funded, nothing to do on-chain.
+    Unopened,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosedChannel {
+    pub close_type: CloseType,
+    pub txid: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub txid: Vec<u8>,
+    pub tx: Vec<u8>,
+}
+
+impl Wallet {
+    pub fn new(client: ClnClient) -> Self {
+        Wallet { client }
+    }
+
+    /// Schedule `node` and connect to it, rescheduling it whenever it
+    /// goes offline.
+    pub async fn schedule(node: Node) -> Result<Self> {
+        let client = node.schedule_reconnecting(RetryPolicy::default()).await?;
+        Ok(Wallet::new(client))
+    }
+
+    /// The raw client, for calls not covered by the wallet.
+    pub fn client(&mut self) -> &mut ClnClient {
+        &mut self.client
+    }
+
+    pub async fn balance(&mut self) -> Result<Balance> {
+        let funds = self
+            .client
+            .list_funds(cln::ListfundsRequest::default())
+            .await?
+            .into_inner();
+        let channels = self
+            .client
+            .list_peer_channels(cln::ListpeerchannelsRequest::default())
+            .await?
+            .into_inner();
+        Ok(Balance::new(&funds, &channels))
+    }
+
+    /// Sent payments and paid invoices, newest first.
+    pub async fn payments(&mut self) -> Result<Vec<Payment>> {
+        let pays = self
+            .client
+            .list_pays(cln::ListpaysRequest::default())
+            .await?
+            .into_inner()
+            .pays;
+        let mut payments: Vec<Payment> = pays.into_iter().map(Payment::from).collect();
+        payments.extend(self.invoices().await?.iter().filter_map(Invoice::payment));
+        payments.sort_by_key(|p| std::cmp::Reverse(p.timestamp));
+        Ok(payments)
+    }
+
+    /// Pay anything `PaymentInput::parse` accepts. Human-readable
+    /// names are only paid through their BIP-353 record, without
+    /// falling back to LNURL.
+    pub async fn pay(&mut self, input: &str, amount_msat: Option<u64>) -> Result<Payment> {
+        let input = PaymentInput::parse_for_node(input, &mut self.client).await?;
+        Ok(input
+            .pay(&mut self.client, amount_msat, false)
+            .await?
+            .into())
+    }
+
+    pub async fn create_invoice(&mut self, invoice: NewInvoice) -> Result<Invoice> {
+        use cln::amount_or_any::Value;
+        let amount = match invoice.amount_msat {
+            Some(msat) => Value::Amount(cln::Amount { msat }),
+            None => Value::Any(true),
+        };
+        let res = self
+            .client
+            .invoice(cln::InvoiceRequest {
+                amount_msat: Some(cln::AmountOrAny {
+                    value: Some(amount),
+                }),
+                description: invoice.description.clone(),
+                label: invoice.label.clone(),
+                expiry: invoice.expiry,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        Ok(Invoice {
+            label: invoice.label,
+            status: InvoiceStatus::Unpaid,
+            invoice: Some(res.bolt11),
+            payment_hash: res.payment_hash,
+            description: Some(invoice.description),
+            amount_msat: invoice.amount_msat,
+            amount_received_msat: None,
+            expires_at: res.expires_at,
+            paid_at: None,
+            preimage: None,
+        })
+    }
+
+    pub async fn invoice(&mut self, label: &str) -> Result<Option<Invoice>> {
+        let invoices = self
+            .client
+            .list_invoices(cln::ListinvoicesRequest {
+                label: Some(label.to_owned()),
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .invoices;
+        Ok(invoices.into_iter().next().map(Invoice::from))
+    }
+
+    pub async fn invoices(&mut self) -> Result<Vec<Invoice>> {
+        let invoices = self
+            .client
+            .list_invoices(cln::ListinvoicesRequest::default())
+            .await?
+            .into_inner()
+            .invoices;
+        Ok(invoices.into_iter().map(Invoice::from).collect())
+    }
+
+    /// Wait until the invoice is paid or expires.
+    pub async fn wait_invoice(&mut self, label: &str) -> Result<Invoice> {
+        let res = self
+            .client
+            .wait_invoice(cln::WaitinvoiceRequest {
+                label: label.to_owned(),
+            })
+            .await?
+            .into_inner();
+        Ok(res.into())
+    }
+
+    /// Delete an unpaid or expired invoice, so it can no longer be
+    /// paid. Paid invoices are kept.
+    pub async fn cancel_invoice(&mut self, label: &str) -> Result<()> {
+        use cln::delinvoice_request::DelinvoiceStatus;
+        let invoice = self
+            .invoice(label)
+            .await?
+            .ok_or_else(|| anyhow!("No invoice with label {}", label))?;
+        let status = match invoice.status {
+            InvoiceStatus::Unpaid => DelinvoiceStatus::Unpaid,
+            InvoiceStatus::Expired => DelinvoiceStatus::Expired,
+            InvoiceStatus::Paid => bail!("Invoice {} is already paid", label),
+        };
+        self.client
+            .del_invoice(cln::DelinvoiceRequest {
+                label: label.to_owned(),
+                status: status.into(),
+                desconly: None,
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn fee_estimates(&mut self) -> Result<FeeEstimates> {
+        use cln::feerates_request::FeeratesStyle;
+        let res = self
+            .client
+            .feerates(cln::FeeratesRequest {
+                style: FeeratesStyle::Perkb.into(),
+            })
+            .await?
+            .into_inner();
+        res.perkb
+            .map(FeeEstimates::from)
+            .ok_or_else(|| anyhow!("Node did not return feerates"))
+    }
+
+    pub async fn channels(&mut self) -> Result<Vec<Channel>> {
+        let channels = self
+            .client
+            .list_peer_channels(cln::ListpeerchannelsRequest::default())
+            .await?
+            .into_inner()
+            .channels;
+        Ok(channels.into_iter().map(Channel::from).collect())
+    }
+
+    /// Connect to the peer if an address is given, and fund a
+    /// channel with it.
+    pub async fn open_channel(&mut self, open: OpenChannel) -> Result<OpenedChannel> {
+        let peer_id = open.peer_id.to_string();
+        if let Some(address) = &open.address {
+            self.client
+                .connect_peer(cln::ConnectRequest {
+                    id: format!("{}@{}", peer_id, address),
+                    host: None,
+                    port: None,
+                })
+                .await?;
+        }
+        let res = self
+            .client
+            .fund_channel(cln::FundchannelRequest {
+                id: open.peer_id.serialize().to_vec(),
+                amount: Some(open.amount.into()),
+                feerate: open.feerate.map(Into::into),
+                announce: Some(open.announce),
+                push_msat: open.push_msat.map(|msat| cln::Amount { msat }),
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        Ok(OpenedChannel {
+            channel_id: res.channel_id,
+            txid: res.txid,
+            outnum: res.outnum,
+        })
+    }
+
+    /// Close the channel identified by `id`, a channel id, short
+    /// channel id or peer id. `destination` is the address to close
+    /// to, the node's wallet if not set.
+    pub async fn close_channel(
+        &mut self,
+        id: &str,
+        destination: Option<String>,
+    ) -> Result<ClosedChannel> {
+        use cln::close_response::CloseType as ClnCloseType;
+        let res = self
+            .client
+            .close(cln::CloseRequest {
+                id: id.to_owned(),
+                destination,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        let close_type = match res.item_type() {
+            ClnCloseType::Mutual => CloseType::Mutual,
+            ClnCloseType::Unilateral => CloseType::Unilateral,
+            ClnCloseType::Unopened => CloseType::Unopened,
+        };
+        Ok(ClosedChannel {
+            close_type,
+            txid: res.txid,
+        })
+    }
+
+    /// Set the routing fees of the channel identified by `id`, or of
+    /// all channels if `id` is `all`.
+    pub async fn set_channel_fees(&mut self, id: &str, base_msat: u64, ppm: u32) -> Result<()> {
+        self.client
+            .set_channel(cln::SetchannelRequest {
+                id: id.to_owned(),
+                feebase: Some(cln::Amount { msat: base_msat }),
+                feeppm: Some(ppm),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// A new bech32 address to receive on-chain funds.
+    pub async fn new_address(&mut self) -> Result<String> {
+        use cln::newaddr_request::NewaddrAddresstype;
+        let res = self
+            .client
+            .new_addr(cln::NewaddrRequest {
+                addresstype: Some(NewaddrAddresstype::Bech32.into()),
+            })
+            .await?
+            .into_inner();
+        res.bech32
+            .ok_or_else(|| anyhow!("Node did not return an address"))
+    }
+
+    pub async fn withdraw(
+        &mut self,
+        destination: &str,
+        amount: OnchainAmount,
+        feerate: Option<FeeRate>,
+    ) -> Result<Withdrawal> {
+        let res = self
+            .client
+            .withdraw(cln::WithdrawRequest {
+                destination: destination.to_owned(),
+                satoshi: Some(amount.into()),
+                feerate: feerate.map(Into::into),
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        Ok(Withdrawal {
+            txid: res.txid,
+            tx: res.tx,
+        })
+    }
+}
+
+fn msat(amount: &Option<cln::Amount>) -> u64 {
+    amount.as_ref().map(|a| a.msat).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(msat: u64) -> Option<cln::Amount> {
+        Some(cln::Amount { msat })
+    }
+
+    fn output(msat: u64, status: OutputStatus, reserved: bool) -> cln::ListfundsOutputs {
+        cln::ListfundsOutputs {
+            amount_msat: amount(msat),
+            status: status.into(),
+            reserved,
+            ..Default::default()
+        }
+    }
+
+    fn channel(
+        state: PeerChannelState,
+        to_us: u64,
+        spendable: u64,
+    ) -> cln::ListpeerchannelsChannels {
+        cln::ListpeerchannelsChannels {
+            state: state.into(),
+            to_us_msat: amount(to_us),
+            spendable_msat: amount(spendable),
+            receivable_msat: amount(1000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        let funds = cln::ListfundsResponse {
+            outputs: vec![
+                output(10_000, OutputStatus::Confirmed, false),
+                output(20_000, OutputStatus::Confirmed, true),
+                output(30_000, OutputStatus::Unconfirmed, false),
+                output(40_000, OutputStatus::Spent, false),
+                output(50_000, OutputStatus::Immature, false),
+            ],
+            channels: vec![],
+        };
+        let channels = cln::ListpeerchannelsResponse {
+            channels: vec![
+                channel(PeerChannelState::ChanneldNormal, 5_000, 4_000),
+                channel(PeerChannelState::ChanneldAwaitingLockin, 7_000, 0),
+                channel(PeerChannelState::ClosingdComplete, 9_000, 0),
+                channel(PeerChannelState::Onchain, 11_000, 0),
+            ],
+        };
+
+        let balance = Balance::new(&funds, &channels);
+        assert_eq!(
+            balance,
+            Balance {
+                onchain_confirmed_msat: 10_000,
+                onchain_unconfirmed_msat: 30_000,
+                onchain_immature_msat: 50_000,
+                onchain_reserved_msat: 20_000,
+                channel_spendable_msat: 4_000,
+                channel_receivable_msat: 1_000,
+                channel_pending_msat: 16_000,
+            }
+        );
+        assert_eq!(balance.total_msat(), 130_000);
+    }
+
+    #[test]
+    fn test_fee_target() {
+        let estimates = FeeEstimates::from(cln::FeeratesPerkb {
+            min_acceptable: 1_000,
+            estimates: vec![
+                cln::FeeratesPerkbEstimates {
+                    blockcount: 6,
+                    feerate: 5_000,
+                    smoothed_feerate: 4_000,
+                },
+                cln::FeeratesPerkbEstimates {
+                    blockcount: 2,
+                    feerate: 12_000,
+                    smoothed_feerate: 10_000,
+                },
+                cln::FeeratesPerkbEstimates {
+                    blockcount: 100,
+                    feerate: 400,
+                    smoothed_feerate: 500,
+                },
+            ],
+            ..Default::default()
+        });
+        assert_eq!(estimates.estimates[0].blocks, 2);
+        assert_eq!(estimates.for_target(1), Some(10_000));
+        assert_eq!(estimates.for_target(2), Some(10_000));
+        assert_eq!(estimates.for_target(12), Some(4_000));
+        // Estimates below the minimum are raised to it.
+        assert_eq!(estimates.for_target(144), Some(1_000));
+        assert_eq!(FeeEstimates::default().for_target(6), None);
+    }
+
+    #[test]
+    fn test_incoming_payments() {
+        use cln::listinvoices_invoices::ListinvoicesInvoicesStatus;
+        let invoice = |status: ListinvoicesInvoicesStatus| cln::ListinvoicesInvoices {
+            label: "label".to_owned(),
+            status: status.into(),
+            amount_msat: amount(1_000),
+            amount_received_msat: amount(1_100),
+            paid_at: Some(42),
+            ..Default::default()
+        };
+
+        let paid = Invoice::from(invoice(ListinvoicesInvoicesStatus::Paid));
+        let payment = paid.payment().unwrap();
+        assert_eq!(payment.direction, Direction::Incoming);
+        assert_eq!(payment.amount_msat, Some(1_100));
+        assert_eq!(payment.timestamp, 42);
+
+        let unpaid = Invoice::from(invoice(ListinvoicesInvoicesStatus::Unpaid));
+        assert_eq!(unpaid.status, InvoiceStatus::Unpaid);
+        assert!(unpaid.payment().is_none());
+    }
+}
@@ -6,6 +6,7 @@ fn main() {
             "TrampolinePayRequest",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
+        .protoc_arg("--experimental_allow_proto3_optional")
         .compile(
             &[".resources/proto/glclient/greenlight.proto"],
             &[".resources/proto/glclient"],
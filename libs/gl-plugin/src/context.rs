@@ -35,6 +35,9 @@ pub struct Request {
 
     // Timestamp in millis
     timestamp: Option<u64>,
+
+    // The position of the message in a streamed request.
+    stream_seq: Option<u64>,
 }
 
 impl Request {
@@ -53,8 +56,16 @@ impl Request {
             timestamp,
             pubkey,
             rune,
+            stream_seq: None,
         }
     }
+
+    /// Mark this as the `seq`th message of a streamed request, see
+    /// `gl_client::node::stream`.
+    pub fn with_stream_seq(mut self, seq: u64) -> Self {
+        self.stream_seq = Some(seq);
+        self
+    }
 }
 
 impl From<Request> for crate::pb::PendingRequest {
@@ -66,6 +77,7 @@ impl From<Request> for crate::pb::PendingRequest {
             uri: r.uri,
             timestamp: r.timestamp.unwrap_or_default(),
             rune: r.rune,
+            stream_seq: r.stream_seq,
         }
     }
 }
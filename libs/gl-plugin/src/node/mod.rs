@@ -920,6 +920,7 @@ impl PluginNodeServer {
     }
 }
 
+use gl_client::node::stream::{EnvelopeDecoder, STREAM_HEADER};
 use tower::{Layer, Service};
 
 #[derive(Debug, Clone)]
@@ -1012,6 +1013,37 @@ where
                 .get("glrune")
                 .and_then(|k| general_purpose::URL_SAFE.decode(k).ok());
 
+            if parts.headers.contains_key(STREAM_HEADER) {
+                let (pk, rune, timestamp) = match (pubkey, rune, timestamp) {
+                    (Some(pk), Some(rune), Some(ts)) => (pk, rune, ts),
+                    _ => {
+                        return Ok(tonic::Status::new(
+                            tonic::Code::Unauthenticated,
+                            "streamed request is missing authentication headers",
+                        )
+                        .to_http())
+                    }
+                };
+                let pending = Arc::new(PendingRequests {
+                    ctx: reqctx,
+                    requests: std::sync::Mutex::new(vec![]),
+                });
+                let body = hyper::Body::wrap_stream(verified_messages(
+                    body,
+                    uri.to_string(),
+                    pk,
+                    timestamp,
+                    rune,
+                    pending.clone(),
+                ));
+                let request = hyper::Request::from_parts(parts, body);
+                let res = inner.call(request).await;
+                // The messages stay in the context until both the
+                // request stream and this call are done.
+                drop(pending);
+                return res.map_err(Into::into);
+            }
+
             if let (Some(pk), Some(sig), Some(rune)) = (pubkey, sig, rune) {
                 // Now that we know we'll be adding this to the
                 // context we can start buffering the request.
@@ -1072,5 +1104,182 @@ where
     }
 }
 
+/// Requests added to the signature context by a streamed request,
+/// removed once the last reference goes away.
+struct PendingRequests {
+    ctx: crate::context::Context,
+    requests: std::sync::Mutex<Vec<crate::context::Request>>,
+}
+
+impl Drop for PendingRequests {
+    fn drop(&mut self) {
+        let ctx = self.ctx.clone();
+        let requests = std::mem::take(self.requests.get_mut().unwrap());
+        tokio::spawn(async move {
+            for r in requests {
+                ctx.remove_request(r).await;
+            }
+        });
+    }
+}
+
+/// Verifies and strips the signatures of the messages of a streamed
+/// request, and adds each message to the signature context, see
+/// `gl_client::node::stream`.
+fn verified_messages(
+    mut body: hyper::Body,
+    uri: String,
+    pubkey: Vec<u8>,
+    timestamp: u64,
+    rune: Vec<u8>,
+    pending: Arc<PendingRequests>,
+) -> impl futures::Stream<Item = Result<bytes::Bytes, tonic::Status>> + Send {
+    use tonic::codegen::Body;
+
+    async_stream::try_stream! {
+        let mut decoder = EnvelopeDecoder::new(pubkey.clone(), timestamp, MAX_MESSAGE_SIZE);
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|e| tonic::Status::internal(e.to_string()))?;
+            let messages = decoder.push(&chunk).map_err(|e| {
+                debug!("Rejecting streamed request to {}: {}", uri, e);
+                tonic::Status::unauthenticated(e.to_string())
+            })?;
+            for m in messages {
+                let req = crate::context::Request::new(
+                    uri.clone(),
+                    m.message.clone(),
+                    pubkey.clone(),
+                    m.signature,
+                    Some(m.timestamp),
+                    rune.clone(),
+                )
+                .with_stream_seq(m.seq);
+                pending.ctx.add_request(req.clone()).await;
+                pending.requests.lock().unwrap().push(req);
+                yield m.message;
+            }
+        }
+        decoder
+            .finish()
+            .map_err(|e| tonic::Status::unauthenticated(e.to_string()))?;
+    }
+}
+
 mod rpcwait;
 pub use rpcwait::RpcWaitService;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl_client::node::service::AuthLayer;
+    use hyper::body::HttpBody;
+    use prost::Message;
+
+    const METHOD: &str = "/test.Test/Upload";
+
+    fn grpc_message(payload: &[u8]) -> Vec<u8> {
+        let mut m = vec![0];
+        m.put_u32(payload.len() as u32);
+        m.put_slice(payload);
+        m
+    }
+
+    /// A streamed request goes through the client's `AuthService`
+    /// and the node's `SignatureContextService`, and the signer can
+    /// verify each message in the context.
+    #[tokio::test]
+    async fn test_streamed_request_signatures() {
+        let ctx = crate::context::Context::new();
+        let seen = Arc::new(std::sync::Mutex::new((vec![], vec![])));
+        let inner = {
+            let (ctx, seen) = (ctx.clone(), seen.clone());
+            tower::service_fn(move |req: hyper::Request<hyper::Body>| {
+                let (ctx, seen) = (ctx.clone(), seen.clone());
+                async move {
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+                    // The messages are in the context while the call
+                    // is in flight.
+                    let pending = ctx.snapshot().await;
+                    *seen.lock().unwrap() = (body.to_vec(), pending);
+                    let res = hyper::Response::builder()
+                        .header("content-type", "application/grpc")
+                        .header("grpc-status", "0")
+                        .body(tonic::body::empty_body())
+                        .unwrap();
+                    Ok::<_, hyper::Error>(res)
+                }
+            })
+        };
+        let svc = SignatureContextLayer::new(ctx).layer(inner);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let make = hyper::service::make_service_fn(move |_| {
+            let svc = svc.clone();
+            async move { Ok::<_, std::convert::Infallible>(svc) }
+        });
+        tokio::spawn(
+            hyper::Server::from_tcp(listener)
+                .unwrap()
+                .http2_only(true)
+                .serve(make),
+        );
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let creds = gl_client::credentials::Nobody::default();
+        let mut client = AuthLayer::new(creds.key, "cnVuZQ==".to_string())
+            .unwrap()
+            .with_streaming_method(METHOD)
+            .layer(channel);
+
+        let messages = [grpc_message(b"first"), grpc_message(b"second")];
+        let body = hyper::Body::from(messages.concat())
+            .map_err(|e| tonic::Status::from_error(Box::new(e)))
+            .boxed_unsync();
+        let request = hyper::Request::builder()
+            .uri(format!("http://{}{}", addr, METHOD))
+            .header("content-type", "application/grpc")
+            .body(body)
+            .unwrap();
+        futures::future::poll_fn(|cx| client.poll_ready(cx))
+            .await
+            .unwrap();
+        let res = client.call(request).await.unwrap();
+        assert!(res.status().is_success());
+
+        let (body, pending) = seen.lock().unwrap().clone();
+        assert_eq!(body, messages.concat());
+        assert_eq!(pending.len(), 2);
+
+        // The signer gets the requests as protobuf messages.
+        let pending: Vec<gl_client::pb::PendingRequest> = pending
+            .into_iter()
+            .map(|r| {
+                let r: pb::PendingRequest = r.into();
+                gl_client::pb::PendingRequest::decode(&r.encode_to_vec()[..]).unwrap()
+            })
+            .collect();
+        for (seq, r) in pending.iter().enumerate() {
+            assert_eq!(r.stream_seq, Some(seq as u64));
+            assert_eq!(r.request, messages[seq]);
+            gl_client::signer::verify_request_signature(r).unwrap();
+        }
+
+        // The signature is neither valid for a unary request, nor for
+        // another position in the stream.
+        let unary = gl_client::pb::PendingRequest {
+            stream_seq: None,
+            ..pending[0].clone()
+        };
+        assert!(gl_client::signer::verify_request_signature(&unary).is_err());
+        let moved = gl_client::pb::PendingRequest {
+            stream_seq: Some(1),
+            ..pending[0].clone()
+        };
+        assert!(gl_client::signer::verify_request_signature(&moved).is_err());
+    }
+}
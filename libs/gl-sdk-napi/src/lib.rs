@@ -1,16 +1,21 @@
 #![deny(clippy::all)]
 
 use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction};
 use napi_derive::napi;
+use std::sync::Arc;
 
 // Import from glsdk crate (gl-sdk library)
 use glsdk::{
-    Credentials as GlCredentials,
-    Node as GlNode,
-    Scheduler as GlScheduler,
-    Signer as GlSigner,
-    Handle as GlHandle,
-    Network as GlNetwork,
+    Activity as GlActivity, ActivityChange as GlActivityChange, ActivityKind as GlActivityKind,
+    ActivityStatus as GlActivityStatus, ApprovalHandler as GlApprovalHandler,
+    ApprovalRequest as GlApprovalRequest, Balance as GlBalance, Channel as GlChannel,
+    ChannelState as GlChannelState, CloseType as GlCloseType, Credentials as GlCredentials,
+    FeeEstimates as GlFeeEstimates, Handle as GlHandle, Invoice as GlInvoice,
+    InvoiceStatus as GlInvoiceStatus, LnUrlKind as GlLnUrlKind, Network as GlNetwork,
+    Node as GlNode, PairingRequest as GlPairingRequest, Payment as GlPayment,
+    PaymentDirection as GlPaymentDirection, PaymentInput as GlPaymentInput,
+    PaymentStatus as GlPaymentStatus, Scheduler as GlScheduler, Signer as GlSigner,
 };
 
 // ============================================================================
@@ -46,6 +51,546 @@ pub struct OnchainReceiveResponse {
     pub p2tr: String,
 }
 
+#[napi(object)]
+pub struct OnchainOutput {
+    pub script_pubkey: Buffer,
+    pub address: Option<String>,
+    /// Amount in satoshis (as i64 for JS compatibility)
+    pub amount_sat: i64,
+}
+
+/// A new device asking to be paired with the node.
+#[napi(object)]
+pub struct PairingRequest {
+    pub device_id: String,
+    pub device_name: String,
+    /// The restrictions the device asked for
+    pub restrictions: String,
+    pub pubkey: Buffer,
+    /// Seconds since the UNIX epoch (as i64 for JS compatibility)
+    pub expires_at: i64,
+}
+
+impl From<GlPairingRequest> for PairingRequest {
+    fn from(r: GlPairingRequest) -> Self {
+        PairingRequest {
+            device_id: r.device_id,
+            device_name: r.device_name,
+            restrictions: r.restrictions,
+            pubkey: Buffer::from(r.pubkey),
+            expires_at: r.expires_at as i64,
+        }
+    }
+}
+
+/// A request the signer needs the app to approve. Which fields are
+/// set depends on `kind`.
+#[napi(object)]
+pub struct ApprovalRequest {
+    /// One of "invoice", "keysend", "onchain", "over_limit" or
+    /// "unresolved"
+    pub kind: String,
+    pub payee: Option<Buffer>,
+    pub destination: Option<Buffer>,
+    pub payment_hash: Option<Buffer>,
+    /// Amount in millisatoshis (as i64 for JS compatibility)
+    pub amount_msat: Option<i64>,
+    pub description: Option<String>,
+    pub txid: Option<Buffer>,
+    pub outputs: Option<Vec<OnchainOutput>>,
+    /// Fee in satoshis (as i64 for JS compatibility)
+    pub fee_sat: Option<i64>,
+    /// The spending limit that would be exceeded, in millisatoshis
+    pub limit_msat: Option<i64>,
+    pub window_secs: Option<i64>,
+    /// Amount already spent within the window, in millisatoshis
+    pub spent_msat: Option<i64>,
+    /// Destination of a per-destination spending limit
+    pub address: Option<String>,
+    pub message: Option<String>,
+}
+
+impl From<GlApprovalRequest> for ApprovalRequest {
+    fn from(r: GlApprovalRequest) -> Self {
+        let empty = ApprovalRequest {
+            kind: String::new(),
+            payee: None,
+            destination: None,
+            payment_hash: None,
+            amount_msat: None,
+            description: None,
+            txid: None,
+            outputs: None,
+            fee_sat: None,
+            limit_msat: None,
+            window_secs: None,
+            spent_msat: None,
+            address: None,
+            message: None,
+        };
+        match r {
+            GlApprovalRequest::Invoice {
+                payee,
+                payment_hash,
+                amount_msat,
+                description,
+            } => ApprovalRequest {
+                kind: "invoice".to_string(),
+                payee: Some(Buffer::from(payee)),
+                payment_hash: Some(Buffer::from(payment_hash)),
+                amount_msat: Some(amount_msat as i64),
+                description,
+                ..empty
+            },
+            GlApprovalRequest::Keysend {
+                destination,
+                payment_hash,
+                amount_msat,
+            } => ApprovalRequest {
+                kind: "keysend".to_string(),
+                destination: destination.map(Buffer::from),
+                payment_hash: Some(Buffer::from(payment_hash)),
+                amount_msat: Some(amount_msat as i64),
+                ..empty
+            },
+            GlApprovalRequest::Onchain {
+                txid,
+                outputs,
+                fee_sat,
+            } => ApprovalRequest {
+                kind: "onchain".to_string(),
+                txid: Some(Buffer::from(txid)),
+                outputs: Some(
+                    outputs
+                        .into_iter()
+                        .map(|o| OnchainOutput {
+                            script_pubkey: Buffer::from(o.script_pubkey),
+                            address: o.address,
+                            amount_sat: o.amount_sat as i64,
+                        })
+                        .collect(),
+                ),
+                fee_sat: fee_sat.map(|f| f as i64),
+                ..empty
+            },
+            GlApprovalRequest::OverLimit {
+                limit_msat,
+                window_secs,
+                destination,
+                spent_msat,
+                amount_msat,
+            } => ApprovalRequest {
+                kind: "over_limit".to_string(),
+                limit_msat: Some(limit_msat as i64),
+                window_secs: Some(window_secs as i64),
+                address: destination,
+                spent_msat: Some(spent_msat as i64),
+                amount_msat: Some(amount_msat as i64),
+                ..empty
+            },
+            GlApprovalRequest::Unresolved { message } => ApprovalRequest {
+                kind: "unresolved".to_string(),
+                message: Some(message),
+                ..empty
+            },
+        }
+    }
+}
+
+/// A decoded payment string. Which fields are set depends on `kind`.
+#[napi(object)]
+pub struct PaymentInput {
+    /// One of "bolt11", "bolt12_offer", "bolt12_invoice", "lnurl",
+    /// "lightning_address", "node_id" or "onchain"
+    pub kind: String,
+    pub invoice: Option<String>,
+    pub offer: Option<String>,
+    pub lnurl: Option<String>,
+    pub url: Option<String>,
+    /// What the LNURL is for, if known: "pay", "withdraw", "auth" or
+    /// "channel"
+    pub lnurl_kind: Option<String>,
+    pub address: Option<String>,
+    pub node_id: Option<Buffer>,
+    /// Amount in millisatoshis (as i64 for JS compatibility)
+    pub amount_msat: Option<i64>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+}
+
+impl From<GlPaymentInput> for PaymentInput {
+    fn from(i: GlPaymentInput) -> Self {
+        let empty = PaymentInput {
+            kind: String::new(),
+            invoice: None,
+            offer: None,
+            lnurl: None,
+            url: None,
+            lnurl_kind: None,
+            address: None,
+            node_id: None,
+            amount_msat: None,
+            label: None,
+            message: None,
+        };
+        match i {
+            GlPaymentInput::Bolt11 {
+                invoice,
+                amount_msat,
+            } => PaymentInput {
+                kind: "bolt11".to_string(),
+                invoice: Some(invoice),
+                amount_msat: amount_msat.map(|a| a as i64),
+                ..empty
+            },
+            GlPaymentInput::Bolt12Offer { offer, amount_msat } => PaymentInput {
+                kind: "bolt12_offer".to_string(),
+                offer: Some(offer),
+                amount_msat: amount_msat.map(|a| a as i64),
+                ..empty
+            },
+            GlPaymentInput::Bolt12Invoice { invoice } => PaymentInput {
+                kind: "bolt12_invoice".to_string(),
+                invoice: Some(invoice),
+                ..empty
+            },
+            GlPaymentInput::LnUrl { lnurl, url, kind } => PaymentInput {
+                kind: "lnurl".to_string(),
+                lnurl: Some(lnurl),
+                url: Some(url),
+                lnurl_kind: kind.map(|k| {
+                    match k {
+                        GlLnUrlKind::PAY => "pay",
+                        GlLnUrlKind::WITHDRAW => "withdraw",
+                        GlLnUrlKind::AUTH => "auth",
+                        GlLnUrlKind::CHANNEL => "channel",
+                    }
+                    .to_string()
+                }),
+                ..empty
+            },
+            GlPaymentInput::LightningAddress { address } => PaymentInput {
+                kind: "lightning_address".to_string(),
+                address: Some(address),
+                ..empty
+            },
+            GlPaymentInput::NodeId { node_id } => PaymentInput {
+                kind: "node_id".to_string(),
+                node_id: Some(Buffer::from(node_id)),
+                ..empty
+            },
+            GlPaymentInput::OnChain {
+                address,
+                amount_msat,
+                label,
+                message,
+            } => PaymentInput {
+                kind: "onchain".to_string(),
+                address: Some(address),
+                amount_msat: amount_msat.map(|a| a as i64),
+                label,
+                message,
+                ..empty
+            },
+        }
+    }
+}
+
+/// The funds of the node. Amounts in millisatoshis (as i64 for JS
+/// compatibility)
+#[napi(object)]
+pub struct Balance {
+    pub onchain_confirmed_msat: i64,
+    pub onchain_unconfirmed_msat: i64,
+    pub onchain_immature_msat: i64,
+    pub onchain_reserved_msat: i64,
+    pub channel_spendable_msat: i64,
+    pub channel_receivable_msat: i64,
+    pub channel_pending_msat: i64,
+    pub total_msat: i64,
+}
+
+impl From<GlBalance> for Balance {
+    fn from(b: GlBalance) -> Self {
+        Balance {
+            onchain_confirmed_msat: b.onchain_confirmed_msat as i64,
+            onchain_unconfirmed_msat: b.onchain_unconfirmed_msat as i64,
+            onchain_immature_msat: b.onchain_immature_msat as i64,
+            onchain_reserved_msat: b.onchain_reserved_msat as i64,
+            channel_spendable_msat: b.channel_spendable_msat as i64,
+            channel_receivable_msat: b.channel_receivable_msat as i64,
+            channel_pending_msat: b.channel_pending_msat as i64,
+            total_msat: b.total_msat as i64,
+        }
+    }
+}
+
+#[napi(object)]
+pub struct Payment {
+    /// "incoming" or "outgoing"
+    pub direction: String,
+    /// "pending", "complete" or "failed"
+    pub status: String,
+    pub payment_hash: Buffer,
+    pub amount_msat: Option<i64>,
+    pub fee_msat: Option<i64>,
+    pub preimage: Option<Buffer>,
+    pub description: Option<String>,
+    pub invoice: Option<String>,
+    pub destination: Option<Buffer>,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+}
+
+impl From<GlPayment> for Payment {
+    fn from(p: GlPayment) -> Self {
+        Payment {
+            direction: match p.direction {
+                GlPaymentDirection::INCOMING => "incoming",
+                GlPaymentDirection::OUTGOING => "outgoing",
+            }
+            .to_string(),
+            status: match p.status {
+                GlPaymentStatus::PENDING => "pending",
+                GlPaymentStatus::COMPLETE => "complete",
+                GlPaymentStatus::FAILED => "failed",
+            }
+            .to_string(),
+            payment_hash: Buffer::from(p.payment_hash),
+            amount_msat: p.amount_msat.map(|a| a as i64),
+            fee_msat: p.fee_msat.map(|a| a as i64),
+            preimage: p.preimage.map(Buffer::from),
+            description: p.description,
+            invoice: p.invoice,
+            destination: p.destination.map(Buffer::from),
+            timestamp: p.timestamp as i64,
+        }
+    }
+}
+
+#[napi(object)]
+pub struct Invoice {
+    pub label: String,
+    /// "unpaid", "paid" or "expired"
+    pub status: String,
+    pub invoice: Option<String>,
+    pub payment_hash: Buffer,
+    pub description: Option<String>,
+    pub amount_msat: Option<i64>,
+    pub amount_received_msat: Option<i64>,
+    pub expires_at: i64,
+    pub paid_at: Option<i64>,
+    pub preimage: Option<Buffer>,
+}
+
+impl From<GlInvoice> for Invoice {
+    fn from(i: GlInvoice) -> Self {
+        Invoice {
+            label: i.label,
+            status: match i.status {
+                GlInvoiceStatus::UNPAID => "unpaid",
+                GlInvoiceStatus::PAID => "paid",
+                GlInvoiceStatus::EXPIRED => "expired",
+            }
+            .to_string(),
+            invoice: i.invoice,
+            payment_hash: Buffer::from(i.payment_hash),
+            description: i.description,
+            amount_msat: i.amount_msat.map(|a| a as i64),
+            amount_received_msat: i.amount_received_msat.map(|a| a as i64),
+            expires_at: i.expires_at as i64,
+            paid_at: i.paid_at.map(|t| t as i64),
+            preimage: i.preimage.map(Buffer::from),
+        }
+    }
+}
+
+#[napi(object)]
+pub struct FeeEstimate {
+    pub blocks: u32,
+    pub perkb: u32,
+}
+
+/// On-chain feerates in satoshis per 1000 virtual bytes
+#[napi(object)]
+pub struct FeeEstimates {
+    pub min_acceptable: u32,
+    pub opening: Option<u32>,
+    pub mutual_close: Option<u32>,
+    pub estimates: Vec<FeeEstimate>,
+}
+
+impl From<GlFeeEstimates> for FeeEstimates {
+    fn from(f: GlFeeEstimates) -> Self {
+        FeeEstimates {
+            min_acceptable: f.min_acceptable,
+            opening: f.opening,
+            mutual_close: f.mutual_close,
+            estimates: f
+                .estimates
+                .into_iter()
+                .map(|e| FeeEstimate {
+                    blocks: e.blocks,
+                    perkb: e.perkb,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[napi(object)]
+pub struct Channel {
+    pub peer_id: Buffer,
+    pub peer_connected: bool,
+    /// "opening", "open", "closing" or "closed"
+    pub state: String,
+    pub channel_id: Option<Buffer>,
+    pub short_channel_id: Option<String>,
+    pub funding_txid: Option<Buffer>,
+    pub total_msat: i64,
+    pub to_us_msat: i64,
+    pub spendable_msat: i64,
+    pub receivable_msat: i64,
+}
+
+impl From<GlChannel> for Channel {
+    fn from(c: GlChannel) -> Self {
+        Channel {
+            peer_id: Buffer::from(c.peer_id),
+            peer_connected: c.peer_connected,
+            state: match c.state {
+                GlChannelState::OPENING => "opening",
+                GlChannelState::OPEN => "open",
+                GlChannelState::CLOSING => "closing",
+                GlChannelState::CLOSED => "closed",
+            }
+            .to_string(),
+            channel_id: c.channel_id.map(Buffer::from),
+            short_channel_id: c.short_channel_id,
+            funding_txid: c.funding_txid.map(Buffer::from),
+            total_msat: c.total_msat as i64,
+            to_us_msat: c.to_us_msat as i64,
+            spendable_msat: c.spendable_msat as i64,
+            receivable_msat: c.receivable_msat as i64,
+        }
+    }
+}
+
+#[napi(object)]
+pub struct OpenChannelResponse {
+    pub channel_id: Buffer,
+    pub txid: Buffer,
+    pub outnum: u32,
+}
+
+#[napi(object)]
+pub struct CloseChannelResponse {
+    /// "mutual", "unilateral" or "unopened"
+    pub close_type: String,
+    pub txid: Option<Buffer>,
+}
+
+fn activity_status(s: GlActivityStatus) -> String {
+    match s {
+        GlActivityStatus::PENDING => "pending",
+        GlActivityStatus::COMPLETE => "complete",
+        GlActivityStatus::FAILED => "failed",
+        GlActivityStatus::EXPIRED => "expired",
+    }
+    .to_string()
+}
+
+/// An entry of the payment history
+#[napi(object)]
+pub struct Activity {
+    /// Stable across syncs
+    pub id: String,
+    /// One of "invoice", "payment", "keysend", "onchain" or "forward"
+    pub kind: String,
+    /// "incoming" or "outgoing"
+    pub direction: String,
+    /// One of "pending", "complete", "failed" or "expired"
+    pub status: String,
+    /// Amount in millisatoshis (as i64 for JS compatibility)
+    pub amount_msat: i64,
+    pub fee_msat: Option<i64>,
+    pub payment_hash: Option<Buffer>,
+    pub preimage: Option<Buffer>,
+    pub txid: Option<Buffer>,
+    pub description: Option<String>,
+    pub invoice: Option<String>,
+    pub destination: Option<Buffer>,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+    pub completed_at: Option<i64>,
+}
+
+impl From<GlActivity> for Activity {
+    fn from(a: GlActivity) -> Self {
+        Activity {
+            id: a.id,
+            kind: match a.kind {
+                GlActivityKind::INVOICE => "invoice",
+                GlActivityKind::PAYMENT => "payment",
+                GlActivityKind::KEYSEND => "keysend",
+                GlActivityKind::ONCHAIN => "onchain",
+                GlActivityKind::FORWARD => "forward",
+            }
+            .to_string(),
+            direction: match a.direction {
+                GlPaymentDirection::INCOMING => "incoming",
+                GlPaymentDirection::OUTGOING => "outgoing",
+            }
+            .to_string(),
+            status: activity_status(a.status),
+            amount_msat: a.amount_msat as i64,
+            fee_msat: a.fee_msat.map(|f| f as i64),
+            payment_hash: a.payment_hash.map(Buffer::from),
+            preimage: a.preimage.map(Buffer::from),
+            txid: a.txid.map(Buffer::from),
+            description: a.description,
+            invoice: a.invoice,
+            destination: a.destination.map(Buffer::from),
+            timestamp: a.timestamp as i64,
+            completed_at: a.completed_at.map(|t| t as i64),
+        }
+    }
+}
+
+#[napi(object)]
+pub struct ActivityChange {
+    pub activity: Activity,
+    /// Not set if the activity is new
+    pub previous_status: Option<String>,
+}
+
+impl From<GlActivityChange> for ActivityChange {
+    fn from(c: GlActivityChange) -> Self {
+        ActivityChange {
+            activity: c.activity.into(),
+            previous_status: c.previous_status.map(activity_status),
+        }
+    }
+}
+
+/// Forwards approval requests to a JS callback returning a promise.
+struct JsApprovalHandler {
+    callback: ThreadsafeFunction<ApprovalRequest, ErrorStrategy::Fatal>,
+}
+
+impl GlApprovalHandler for JsApprovalHandler {
+    fn approve(&self, request: GlApprovalRequest) -> bool {
+        // Called from a blocking thread in the signer, so we can
+        // wait for the JS side to settle the promise here.
+        let callback = self.callback.clone();
+        futures::executor::block_on(async move {
+            let approved: Promise<bool> = callback.call_async(request.into()).await?;
+            approved.await
+        })
+        .unwrap_or(false)
+    }
+}
+
 // ============================================================================
 // Struct Definitions (all structs must be defined before impl blocks)
 // ============================================================================
@@ -79,6 +624,31 @@ pub struct Node {
 // NAPI Implementations
 // ============================================================================
 
+fn parse_network(network: &str) -> Result<GlNetwork> {
+    match network.to_lowercase().as_str() {
+        "bitcoin" => Ok(GlNetwork::BITCOIN),
+        "regtest" => Ok(GlNetwork::REGTEST),
+        _ => Err(Error::from_reason(format!(
+            "Invalid network: {}. Must be 'bitcoin' or 'regtest'",
+            network
+        ))),
+    }
+}
+
+/// Decode a payment string: a BIP-21 URI, BOLT11 invoice, BOLT12
+/// offer or invoice, LNURL, lightning address, node id or on-chain
+/// address
+///
+/// # Arguments
+/// * `input` - The string the user pasted or scanned
+/// * `network` - Network name ("bitcoin" or "regtest") the input must be for
+#[napi]
+pub fn parse_payment_input(input: String, network: String) -> Result<PaymentInput> {
+    glsdk::parse_payment_input(input, parse_network(&network)?)
+        .map(PaymentInput::from)
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
 #[napi]
 impl Credentials {
     /// Load credentials from raw bytes
@@ -108,6 +678,36 @@ impl Credentials {
 
         Ok(Buffer::from(bytes))
     }
+
+    /// Load credentials saved with `saveWithPassphrase`, or
+    /// unencrypted ones to migrate them
+    #[napi(factory)]
+    pub async fn load_with_passphrase(raw: Buffer, passphrase: String) -> Result<Credentials> {
+        let bytes = raw.to_vec();
+        let inner = tokio::task::spawn_blocking(move || {
+            GlCredentials::load_with_passphrase(bytes, passphrase)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(Self { inner })
+    }
+
+    /// Save credentials to raw bytes, encrypted with `passphrase`
+    #[napi]
+    pub async fn save_with_passphrase(&self, passphrase: String) -> Result<Buffer> {
+        let inner = self.inner.clone();
+        let bytes = tokio::task::spawn_blocking(move || {
+            inner
+                .save_with_passphrase(passphrase)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(Buffer::from(bytes))
+    }
 }
 
 #[napi]
@@ -119,16 +719,7 @@ impl Scheduler {
     #[napi(constructor)]
     pub fn new(network: String) -> Result<Self> {
         // Constructor stays sync — it's just parsing a string and initialising a struct
-        let gl_network = match network.to_lowercase().as_str() {
-            "bitcoin" => GlNetwork::BITCOIN,
-            "regtest" => GlNetwork::REGTEST,
-            _ => return Err(Error::from_reason(format!(
-                "Invalid network: {}. Must be 'bitcoin' or 'regtest'",
-                network
-            ))),
-        };
-
-        let inner = GlScheduler::new(gl_network)
+        let inner = GlScheduler::new(parse_network(&network)?)
             .map_err(|e| Error::from_reason(e.to_string()))?;
 
         Ok(Self { inner })
@@ -208,6 +799,78 @@ impl Signer {
         Ok(Signer { inner })
     }
 
+    /// Ask `handler` to approve requests the signer cannot match up
+    /// with a request from this app. Requests that are not approved
+    /// within `timeout_secs` are denied.
+    ///
+    /// # Arguments
+    /// * `handler` - Async callback resolving to `true` to approve
+    /// * `timeout_secs` - Seconds to wait for the handler
+    #[napi(
+        ts_args_type = "handler: (request: ApprovalRequest) => Promise<boolean>, timeoutSecs: number"
+    )]
+    pub fn with_approval_handler(
+        &self,
+        handler: ThreadsafeFunction<ApprovalRequest, ErrorStrategy::Fatal>,
+        timeout_secs: u32,
+    ) -> Result<Signer> {
+        let handler = Arc::new(JsApprovalHandler { callback: handler });
+        let inner = self
+            .inner
+            .with_approval_handler(handler, timeout_secs as u64)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        Ok(Signer { inner })
+    }
+
+    /// Hold pairing requests from new devices until they are
+    /// answered with `approvePairing` or `denyPairing`. Requests that
+    /// are not answered within `timeout_secs` are denied.
+    ///
+    /// # Arguments
+    /// * `timeout_secs` - Seconds a request waits for an answer
+    #[napi]
+    pub fn with_pairing_queue(&self, timeout_secs: u32) -> Result<Signer> {
+        let inner = self
+            .inner
+            .with_pairing_queue(timeout_secs as u64)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+
+        Ok(Signer { inner })
+    }
+
+    /// List the pairing requests waiting for an answer
+    /// (stays sync — pure in-memory computation, no I/O)
+    #[napi]
+    pub fn pending_pairings(&self) -> Vec<PairingRequest> {
+        self.inner
+            .pending_pairings()
+            .into_iter()
+            .map(|r| r.into())
+            .collect()
+    }
+
+    /// Approve the pairing request from a device
+    ///
+    /// # Arguments
+    /// * `device_id` - The device that asked to be paired
+    /// * `restrictions` - Optional restrictions added to the requested ones,
+    ///   e.g., "readonly,expires=30d"
+    #[napi]
+    pub fn approve_pairing(&self, device_id: String, restrictions: Option<String>) -> Result<()> {
+        self.inner
+            .approve_pairing(device_id, restrictions)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Deny the pairing request from a device
+    #[napi]
+    pub fn deny_pairing(&self, device_id: String) -> Result<()> {
+        self.inner
+            .deny_pairing(device_id)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
     /// Start the signer's background task
     /// Returns a handle to control the signer
     #[napi]
@@ -224,6 +887,22 @@ impl Signer {
         Ok(Handle { inner })
     }
 
+    /// Log into the service behind an LNURL-auth link
+    ///
+    /// # Arguments
+    /// * `lnurl` - The bech32 encoded LNURL with the `login` tag
+    #[napi]
+    pub async fn lnurl_auth(&self, lnurl: String) -> Result<()> {
+        let inner_signer = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            inner_signer
+                .lnurl_auth(lnurl)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))?
+    }
+
     /// Get the node ID for this signer
     /// (stays sync — pure in-memory computation, no I/O)
     #[napi]
@@ -300,15 +979,23 @@ impl Node {
     /// Send a payment
     ///
     /// # Arguments
-    /// * `invoice` - BOLT11 invoice string
-    /// * `amount_msat` - Optional amount for zero-amount invoices
+    /// * `invoice` - BOLT11 invoice, BOLT12 offer, LNURL, lightning
+    ///   address, node id or BIP-21 URI, see `parsePaymentInput`
+    /// * `amount_msat` - Optional amount if the destination has none
+    /// * `lnurl_fallback` - Pay a name without a BIP-353 record as a
+    ///   lightning address, trusting the DNS resolver. Off by default
     #[napi]
-    pub async fn send(&self, invoice: String, amount_msat: Option<i64>) -> Result<SendResponse> {
+    pub async fn send(
+        &self,
+        invoice: String,
+        amount_msat: Option<i64>,
+        lnurl_fallback: Option<bool>,
+    ) -> Result<SendResponse> {
         let inner = self.inner.clone();
         let amount = amount_msat.map(|a| a as u64);
         let response = tokio::task::spawn_blocking(move || {
             inner
-                .send(invoice, amount)
+                .send(invoice, amount, lnurl_fallback)
                 .map_err(|e| Error::from_reason(e.to_string()))
         })
         .await
@@ -323,6 +1010,256 @@ impl Node {
         })
     }
 
+    /// Decode a payment string, checking that it is meant for the
+    /// network of this node
+    #[napi]
+    pub async fn parse_input(&self, input: String) -> Result<PaymentInput> {
+        let inner = self.inner.clone();
+        let parsed = tokio::task::spawn_blocking(move || {
+            inner
+                .parse_input(input)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(parsed.into())
+    }
+
+    /// The funds of the node, on-chain and in channels
+    #[napi]
+    pub async fn balance(&self) -> Result<Balance> {
+        let inner = self.inner.clone();
+        let balance = tokio::task::spawn_blocking(move || {
+            inner
+                .balance()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(balance.into())
+    }
+
+    /// Sent payments and paid invoices, newest first
+    #[napi]
+    pub async fn list_payments(&self) -> Result<Vec<Payment>> {
+        let inner = self.inner.clone();
+        let payments = tokio::task::spawn_blocking(move || {
+            inner
+                .list_payments()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(payments.into_iter().map(Payment::from).collect())
+    }
+
+    /// Create a plain invoice, without JIT channel support
+    ///
+    /// # Arguments
+    /// * `label` - Unique label for this invoice
+    /// * `description` - Invoice description
+    /// * `amount_msat` - Optional amount in millisatoshis
+    /// * `expiry` - Optional seconds until the invoice expires
+    #[napi]
+    pub async fn create_invoice(
+        &self,
+        label: String,
+        description: String,
+        amount_msat: Option<i64>,
+        expiry: Option<i64>,
+    ) -> Result<Invoice> {
+        let inner = self.inner.clone();
+        let amount = amount_msat.map(|a| a as u64);
+        let expiry = expiry.map(|e| e as u64);
+        let invoice = tokio::task::spawn_blocking(move || {
+            inner
+                .create_invoice(label, description, amount, expiry)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(invoice.into())
+    }
+
+    #[napi]
+    pub async fn list_invoices(&self) -> Result<Vec<Invoice>> {
+        let inner = self.inner.clone();
+        let invoices = tokio::task::spawn_blocking(move || {
+            inner
+                .list_invoices()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(invoices.into_iter().map(Invoice::from).collect())
+    }
+
+    /// Delete an unpaid or expired invoice, so it can no longer be paid
+    #[napi]
+    pub async fn cancel_invoice(&self, label: String) -> Result<()> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            inner
+                .cancel_invoice(label)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))?
+    }
+
+    #[napi]
+    pub async fn fee_estimates(&self) -> Result<FeeEstimates> {
+        let inner = self.inner.clone();
+        let estimates = tokio::task::spawn_blocking(move || {
+            inner
+                .fee_estimates()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(estimates.into())
+    }
+
+    #[napi]
+    pub async fn list_channels(&self) -> Result<Vec<Channel>> {
+        let inner = self.inner.clone();
+        let channels = tokio::task::spawn_blocking(move || {
+            inner
+                .list_channels()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(channels.into_iter().map(Channel::from).collect())
+    }
+
+    /// Open a channel
+    ///
+    /// # Arguments
+    /// * `peer_id` - Node id of the peer
+    /// * `address` - Optional `host:port` to connect to the peer first
+    /// * `amount_sat` - Channel size in satoshis, all on-chain funds if not set
+    /// * `announce` - Whether to announce the channel for routing
+    #[napi]
+    pub async fn open_channel(
+        &self,
+        peer_id: Buffer,
+        address: Option<String>,
+        amount_sat: Option<i64>,
+        announce: bool,
+    ) -> Result<OpenChannelResponse> {
+        let inner = self.inner.clone();
+        let peer_id = peer_id.to_vec();
+        let amount = amount_sat.map(|a| a as u64);
+        let response = tokio::task::spawn_blocking(move || {
+            inner
+                .open_channel(peer_id, address, amount, announce)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(OpenChannelResponse {
+            channel_id: Buffer::from(response.channel_id),
+            txid: Buffer::from(response.txid),
+            outnum: response.outnum,
+        })
+    }
+
+    /// Close a channel
+    ///
+    /// # Arguments
+    /// * `id` - Channel id, short channel id or peer id
+    /// * `destination` - Optional address for the funds, the node's wallet if not set
+    #[napi]
+    pub async fn close_channel(
+        &self,
+        id: String,
+        destination: Option<String>,
+    ) -> Result<CloseChannelResponse> {
+        let inner = self.inner.clone();
+        let response = tokio::task::spawn_blocking(move || {
+            inner
+                .close_channel(id, destination)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(CloseChannelResponse {
+            close_type: match response.close_type {
+                GlCloseType::MUTUAL => "mutual",
+                GlCloseType::UNILATERAL => "unilateral",
+                GlCloseType::UNOPENED => "unopened",
+            }
+            .to_string(),
+            txid: response.txid.map(Buffer::from),
+        })
+    }
+
+    /// Fetch what changed since the last call, and return the new
+    /// history entries and those whose status changed
+    #[napi]
+    pub async fn sync_history(&self) -> Result<Vec<ActivityChange>> {
+        let inner = self.inner.clone();
+        let changes = tokio::task::spawn_blocking(move || {
+            inner
+                .sync_history()
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(changes.into_iter().map(ActivityChange::from).collect())
+    }
+
+    /// Serialize the payment history, to restore it with
+    /// `importHistory` when the app starts again
+    #[napi]
+    pub fn export_history(&self) -> Result<Buffer> {
+        self.inner
+            .export_history()
+            .map(Buffer::from)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// Restore a payment history saved with `exportHistory`
+    ///
+    /// # Arguments
+    /// * `data` - The output of `exportHistory`
+    #[napi]
+    pub fn import_history(&self, data: Buffer) -> Result<()> {
+        self.inner
+            .import_history(data.to_vec())
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    /// A page of the payment history, newest first
+    ///
+    /// # Arguments
+    /// * `before` - Optional id of the last entry of the previous page
+    /// * `limit` - Maximum number of entries
+    #[napi]
+    pub async fn list_history(&self, before: Option<String>, limit: u32) -> Result<Vec<Activity>> {
+        let inner = self.inner.clone();
+        let page = tokio::task::spawn_blocking(move || {
+            inner
+                .list_history(before, limit)
+                .map_err(|e| Error::from_reason(e.to_string()))
+        })
+        .await
+        .map_err(|e| Error::from_reason(e.to_string()))??;
+
+        Ok(page.into_iter().map(Activity::from).collect())
+    }
+
     /// Send an on-chain transaction
     ///
     /// # Arguments
@@ -1,5 +1,5 @@
 use crate::Error;
-use gl_client::credentials::Device as DeviceCredentials;
+use gl_client::credentials::{CredentialsKey, Device as DeviceCredentials};
 
 /// `Credentials` is a container for `node_id`, the mTLS client
 /// certificate used to authenticate a client against a node, as well
@@ -13,14 +13,37 @@ pub struct Credentials {
 
 #[uniffi::export]
 impl Credentials {
+    /// Load credentials saved with `save`. Encrypted credentials are
+    /// decrypted with the `GL_CREDENTIALS_PASSPHRASE` environment
+    /// variable, and fail to load if that doesn't work.
     #[uniffi::constructor()]
     pub fn load(raw: Vec<u8>) -> Result<Credentials, Error> {
         Ok(Self {
-            inner: DeviceCredentials::from_bytes(raw),
+            inner: DeviceCredentials::try_from_bytes(raw)
+                .map_err(|e| Error::Other(e.to_string()))?,
         })
     }
 
     pub fn save(&self) -> Result<Vec<u8>, Error> {
         Ok(self.inner.to_bytes())
     }
+
+    /// Load credentials saved with `save_with_passphrase`. Credentials
+    /// saved with `save` are loaded as well, so they can be migrated
+    /// by saving them again with a passphrase.
+    #[uniffi::constructor()]
+    pub fn load_with_passphrase(raw: Vec<u8>, passphrase: String) -> Result<Credentials, Error> {
+        let key = CredentialsKey::Passphrase(passphrase);
+        Ok(Self {
+            inner: DeviceCredentials::from_bytes_with_key(raw, &key)
+                .map_err(|e| Error::Other(e.to_string()))?,
+        })
+    }
+
+    /// Save the credentials encrypted with `passphrase`.
+    pub fn save_with_passphrase(&self, passphrase: String) -> Result<Vec<u8>, Error> {
+        self.inner
+            .to_encrypted_bytes(&CredentialsKey::Passphrase(passphrase))
+            .map_err(|e| Error::Other(e.to_string()))
+    }
 }
@@ -0,0 +1,98 @@
+use crate::wallet::PaymentDirection;
+use gl_client::history;
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum ActivityKind {
+    INVOICE,
+    PAYMENT,
+    KEYSEND,
+    ONCHAIN,
+    FORWARD,
+}
+
+impl From<history::ActivityKind> for ActivityKind {
+    fn from(other: history::ActivityKind) -> Self {
+        match other {
+            history::ActivityKind::Invoice => ActivityKind::INVOICE,
+            history::ActivityKind::Payment => ActivityKind::PAYMENT,
+            history::ActivityKind::Keysend => ActivityKind::KEYSEND,
+            history::ActivityKind::OnChain => ActivityKind::ONCHAIN,
+            history::ActivityKind::Forward => ActivityKind::FORWARD,
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum ActivityStatus {
+    PENDING,
+    COMPLETE,
+    FAILED,
+    EXPIRED,
+}
+
+impl From<history::ActivityStatus> for ActivityStatus {
+    fn from(other: history::ActivityStatus) -> Self {
+        match other {
+            history::ActivityStatus::Pending => ActivityStatus::PENDING,
+            history::ActivityStatus::Complete => ActivityStatus::COMPLETE,
+            history::ActivityStatus::Failed => ActivityStatus::FAILED,
+            history::ActivityStatus::Expired => ActivityStatus::EXPIRED,
+        }
+    }
+}
+
+/// An entry of the payment history, see `Node::list_history`.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct Activity {
+    pub id: String,
+    pub kind: ActivityKind,
+    pub direction: PaymentDirection,
+    pub status: ActivityStatus,
+    pub amount_msat: u64,
+    pub fee_msat: Option<u64>,
+    pub payment_hash: Option<Vec<u8>>,
+    pub preimage: Option<Vec<u8>>,
+    pub txid: Option<Vec<u8>>,
+    pub description: Option<String>,
+    pub invoice: Option<String>,
+    pub destination: Option<Vec<u8>>,
+    pub timestamp: u64,
+    pub completed_at: Option<u64>,
+}
+
+impl From<history::Activity> for Activity {
+    fn from(other: history::Activity) -> Self {
+        Self {
+            id: other.id,
+            kind: other.kind.into(),
+            direction: other.direction.into(),
+            status: other.status.into(),
+            amount_msat: other.amount_msat,
+            fee_msat: other.fee_msat,
+            payment_hash: other.payment_hash,
+            preimage: other.preimage,
+            txid: other.txid,
+            description: other.description,
+            invoice: other.invoice,
+            destination: other.destination,
+            timestamp: other.timestamp,
+            completed_at: other.completed_at,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct ActivityChange {
+    pub activity: Activity,
+    /// Not set if the activity is new.
+    pub previous_status: Option<ActivityStatus>,
+}
+
+impl From<history::ActivityChange> for ActivityChange {
+    fn from(other: history::ActivityChange) -> Self {
+        Self {
+            activity: other.activity.into(),
+            previous_status: other.previous_status.map(|s| s.into()),
+        }
+    }
+}
@@ -24,16 +24,25 @@ pub enum Error {
     Other(String),
 }
 mod credentials;
+mod history;
 mod node;
+mod payment_input;
 mod scheduler;
 mod signer;
 mod util;
+mod wallet;
 
 pub use crate::{
     credentials::Credentials,
+    history::{Activity, ActivityChange, ActivityKind, ActivityStatus},
     node::{Node, OnchainReceiveResponse, OnchainSendResponse, PayStatus, ReceiveResponse, SendResponse},
+    payment_input::{parse_payment_input, LnUrlKind, PaymentInput},
     scheduler::Scheduler,
-    signer::{Handle, Signer},
+    signer::{ApprovalHandler, ApprovalRequest, Handle, OnchainOutput, PairingRequest, Signer},
+    wallet::{
+        Balance, Channel, ChannelState, CloseChannelResponse, CloseType, FeeEstimate, FeeEstimates,
+        Invoice, InvoiceStatus, OpenChannelResponse, Payment, PaymentDirection, PaymentStatus,
+    },
 };
 
 #[derive(uniffi::Enum, Debug)]
@@ -1,9 +1,20 @@
+use crate::history::{Activity, ActivityChange};
+use crate::payment_input::PaymentInput;
+use crate::wallet::{
+    Balance, Channel, CloseChannelResponse, FeeEstimates, Invoice, OpenChannelResponse, Payment,
+};
 use crate::{credentials::Credentials, util::exec, Error};
+use gl_client::bitcoin::secp256k1::PublicKey;
 use gl_client::credentials::NodeIdProvider;
+use gl_client::history::History;
+use gl_client::node::reconnect::RetryPolicy;
 use gl_client::node::{Client as GlClient, ClnClient, Node as ClientNode};
+use gl_client::payment_input::PaymentInput as ClientPaymentInput;
+use gl_client::wallet::{NewInvoice, OnchainAmount, OpenChannel, Wallet};
 
 use gl_client::pb::cln as clnpb;
-use tokio::sync::OnceCell;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
 
 /// The `Node` is an RPC stub representing the node running in the
 /// cloud. It is the main entrypoint to interact with the node.
@@ -13,6 +24,7 @@ pub struct Node {
     inner: ClientNode,
     cln_client: OnceCell<ClnClient>,
     gl_client: OnceCell<GlClient>,
+    history: Arc<Mutex<History>>,
 }
 
 #[uniffi::export]
@@ -32,6 +44,7 @@ impl Node {
             inner,
             cln_client,
             gl_client,
+            history: Arc::new(Mutex::new(History::new())),
         })
     }
 
@@ -79,30 +92,47 @@ impl Node {
         Ok(ReceiveResponse { bolt11: res.bolt11 })
     }
 
-    pub fn send(&self, invoice: String, amount_msat: Option<u64>) -> Result<SendResponse, Error> {
+    /// Send an off-chain payment.
+    ///
+    /// `invoice` is anything `parse_payment_input` accepts, except
+    /// on-chain addresses: a BOLT11 invoice, a BOLT12 offer or
+    /// invoice, an LNURL-pay, a lightning address or human-readable
+    /// name like `₿alice@example.com`, a node id to keysend to, or a
+    /// BIP-21 URI carrying any of those. `amount_msat` is required
+    /// if the destination does not specify the amount.
+    ///
+    /// If `lnurl_fallback` is set, a human-readable name is paid as
+    /// a lightning address if the DNS resolver says it has no BIP-353
+    /// record. That answer is not DNSSEC-proven, so this trusts the
+    /// resolver. Defaults to off.
+    pub fn send(
+        &self,
+        invoice: String,
+        amount_msat: Option<u64>,
+        lnurl_fallback: Option<bool>,
+    ) -> Result<SendResponse, Error> {
         let mut cln_client = exec(self.get_cln_client())?.clone();
-        let req = clnpb::PayRequest {
-            amount_msat: match amount_msat {
-                Some(a) => Some(clnpb::Amount { msat: a }),
-                None => None,
-            },
+        let input = exec(ClientPaymentInput::parse_for_node(
+            &invoice,
+            &mut cln_client,
+        ))
+        .map_err(|e| Error::Argument("invoice".to_owned(), e.to_string()))?;
+        exec(input.pay(
+            &mut cln_client,
+            amount_msat,
+            lnurl_fallback.unwrap_or(false),
+        ))
+        .map_err(|e| Error::Rpc(e.to_string()))
+        .map(|r| r.into())
+    }
 
-            bolt11: invoice,
-            description: None,
-            exclude: vec![],
-            exemptfee: None,
-            label: None,
-            localinvreqid: None,
-            maxdelay: None,
-            maxfee: None,
-            maxfeepercent: None,
-            partial_msat: None,
-            retry_for: None,
-            riskfactor: None,
-        };
-        exec(cln_client.pay(req))
-            .map_err(|e| Error::Rpc(e.to_string()))
-            .map(|r| r.into_inner().into())
+    /// Decode a payment string, checking that it is meant for the
+    /// network this node runs on. See `parse_payment_input`.
+    pub fn parse_input(&self, input: String) -> Result<PaymentInput, Error> {
+        let mut cln_client = exec(self.get_cln_client())?.clone();
+        exec(ClientPaymentInput::parse_for_node(&input, &mut cln_client))
+            .map(|i| i.into())
+            .map_err(|e| Error::Argument("input".to_owned(), e.to_string()))
     }
 
     pub fn onchain_send(
@@ -152,9 +182,162 @@ impl Node {
             utxos: vec![],
         };
 
-        exec(cln_client.withdraw(req))
+        let res = exec(cln_client.withdraw(req)).map_err(|e| Error::Rpc(e.to_string()))?;
+        // Show the transaction in the history before it confirms.
+        exec(self.history.lock()).rescan_transactions();
+        Ok(res.into_inner().into())
+    }
+
+    /// The funds of the node, on-chain and in channels.
+    pub fn balance(&self) -> Result<Balance, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.balance())
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|b| b.into())
+    }
+
+    /// Sent payments and paid invoices, newest first.
+    pub fn list_payments(&self) -> Result<Vec<Payment>, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.payments())
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|p| p.into_iter().map(|p| p.into()).collect())
+    }
+
+    /// Create a plain invoice. Unlike `receive` this does not
+    /// negotiate a JIT channel, so the node needs enough inbound
+    /// capacity to receive `amount_msat`.
+    pub fn create_invoice(
+        &self,
+        label: String,
+        description: String,
+        amount_msat: Option<u64>,
+        expiry: Option<u64>,
+    ) -> Result<Invoice, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        let invoice = NewInvoice {
+            label,
+            description,
+            amount_msat,
+            expiry,
+        };
+        exec(wallet.create_invoice(invoice))
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|i| i.into())
+    }
+
+    pub fn list_invoices(&self) -> Result<Vec<Invoice>, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.invoices())
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|i| i.into_iter().map(|i| i.into()).collect())
+    }
+
+    /// Delete an unpaid or expired invoice, so it can no longer be
+    /// paid.
+    pub fn cancel_invoice(&self, label: String) -> Result<(), Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.cancel_invoice(&label)).map_err(|e| Error::Rpc(e.to_string()))
+    }
+
+    pub fn fee_estimates(&self) -> Result<FeeEstimates, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.fee_estimates())
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|f| f.into())
+    }
+
+    pub fn list_channels(&self) -> Result<Vec<Channel>, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.channels())
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|c| c.into_iter().map(|c| c.into()).collect())
+    }
+
+    /// Open a channel with `peer_id`, connecting to it at `address`
+    /// (`host:port`) first if given. Without `amount_sat` all
+    /// on-chain funds go into the channel.
+    pub fn open_channel(
+        &self,
+        peer_id: Vec<u8>,
+        address: Option<String>,
+        amount_sat: Option<u64>,
+        announce: bool,
+    ) -> Result<OpenChannelResponse, Error> {
+        let peer_id = PublicKey::from_slice(&peer_id)
+            .map_err(|e| Error::Argument("peer_id".to_owned(), e.to_string()))?;
+        let open = OpenChannel {
+            peer_id,
+            address,
+            amount: amount_sat.map_or(OnchainAmount::All, OnchainAmount::Sat),
+            push_msat: None,
+            feerate: None,
+            announce,
+        };
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.open_channel(open))
+            .map_err(|e| Error::Rpc(e.to_string()))
+            .map(|r| r.into())
+    }
+
+    /// Close a channel, identified by channel id, short channel id
+    /// or peer id, sending the funds to `destination` or the node's
+    /// wallet.
+    pub fn close_channel(
+        &self,
+        id: String,
+        destination: Option<String>,
+    ) -> Result<CloseChannelResponse, Error> {
+        let mut wallet = exec(self.get_wallet())?;
+        exec(wallet.close_channel(&id, destination))
             .map_err(|e| Error::Rpc(e.to_string()))
-            .map(|r| r.into_inner().into())
+            .map(|r| r.into())
+    }
+
+    /// Fetch what changed since the last call, and return the new
+    /// entries of the payment history and those whose status
+    /// changed. The first call fetches the whole history.
+    pub fn sync_history(&self) -> Result<Vec<ActivityChange>, Error> {
+        let mut cln_client = exec(self.get_cln_client())?.clone();
+        exec(async {
+            let mut history = self.history.lock().await;
+            history.sync(&mut cln_client).await
+        })
+        .map_err(|e| Error::Rpc(e.to_string()))
+        .map(|c| c.into_iter().map(|c| c.into()).collect())
+    }
+
+    /// Serialize the payment history, to restore it with
+    /// `import_history` when the app starts again. On-chain
+    /// transactions are stamped with the time the history first saw
+    /// them, so without it they all get the time of the next sync.
+    pub fn export_history(&self) -> Result<Vec<u8>, Error> {
+        exec(self.history.lock())
+            .to_bytes()
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    /// Restore a payment history saved with `export_history`, and
+    /// resume syncing from where it left off.
+    pub fn import_history(&self, data: Vec<u8>) -> Result<(), Error> {
+        let history = History::from_bytes(&data)
+            .map_err(|e| Error::Argument("data".to_owned(), e.to_string()))?;
+        *exec(self.history.lock()) = history;
+        Ok(())
+    }
+
+    /// A page of the payment history: invoices, payments, keysends,
+    /// on-chain transactions and forwards, newest first. Pass the id
+    /// of the last entry of the previous page as `before` to get the
+    /// next page. Syncs the history first.
+    pub fn list_history(&self, before: Option<String>, limit: u32) -> Result<Vec<Activity>, Error> {
+        self.sync_history()?;
+        let history = exec(self.history.lock());
+        Ok(history
+            .page(before.as_deref(), limit as usize)
+            .into_iter()
+            .map(|a| a.into())
+            .collect())
     }
 
     pub fn onchain_receive(&self) -> Result<OnchainReceiveResponse, Error> {
@@ -176,16 +359,28 @@ impl Node {
     async fn get_gl_client<'a>(&'a self) -> Result<&'a GlClient, Error> {
         let inner = self.inner.clone();
         self.gl_client
-            .get_or_try_init(|| async { inner.schedule::<GlClient>().await })
+            .get_or_try_init(|| async {
+                inner
+                    .schedule_reconnecting::<GlClient>(RetryPolicy::default())
+                    .await
+            })
             .await
             .map_err(|e| Error::Rpc(e.to_string()))
     }
 
+    async fn get_wallet(&self) -> Result<Wallet, Error> {
+        Ok(Wallet::new(self.get_cln_client().await?.clone()))
+    }
+
     async fn get_cln_client<'a>(&'a self) -> Result<&'a ClnClient, Error> {
         let inner = self.inner.clone();
 
         self.cln_client
-            .get_or_try_init(|| async { inner.schedule::<ClnClient>().await })
+            .get_or_try_init(|| async {
+                inner
+                    .schedule_reconnecting::<ClnClient>(RetryPolicy::default())
+                    .await
+            })
             .await
             .map_err(|e| Error::Rpc(e.to_string()))
     }
@@ -0,0 +1,107 @@
+use crate::{Error, Network};
+use gl_client::payment_input::{LnUrlKind as ClientLnUrlKind, PaymentInput as ClientPaymentInput};
+
+/// What an LNURL is for, if it can be told without fetching it.
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum LnUrlKind {
+    PAY,
+    WITHDRAW,
+    AUTH,
+    CHANNEL,
+}
+
+impl From<ClientLnUrlKind> for LnUrlKind {
+    fn from(other: ClientLnUrlKind) -> Self {
+        match other {
+            ClientLnUrlKind::Pay => LnUrlKind::PAY,
+            ClientLnUrlKind::Withdraw => LnUrlKind::WITHDRAW,
+            ClientLnUrlKind::Auth => LnUrlKind::AUTH,
+            ClientLnUrlKind::Channel => LnUrlKind::CHANNEL,
+        }
+    }
+}
+
+/// A decoded payment string, as pasted or scanned by the user.
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum PaymentInput {
+    Bolt11 {
+        invoice: String,
+        amount_msat: Option<u64>,
+    },
+    Bolt12Offer {
+        offer: String,
+        amount_msat: Option<u64>,
+    },
+    Bolt12Invoice {
+        invoice: String,
+    },
+    LnUrl {
+        lnurl: String,
+        url: String,
+        kind: Option<LnUrlKind>,
+    },
+    LightningAddress {
+        address: String,
+    },
+    NodeId {
+        node_id: Vec<u8>,
+    },
+    OnChain {
+        address: String,
+        amount_msat: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    },
+}
+
+impl From<ClientPaymentInput> for PaymentInput {
+    fn from(other: ClientPaymentInput) -> Self {
+        match other {
+            ClientPaymentInput::Bolt11 {
+                invoice,
+                amount_msat,
+            } => PaymentInput::Bolt11 {
+                invoice,
+                amount_msat,
+            },
+            ClientPaymentInput::Bolt12Offer { offer, amount_msat } => {
+                PaymentInput::Bolt12Offer { offer, amount_msat }
+            }
+            ClientPaymentInput::Bolt12Invoice { invoice } => {
+                PaymentInput::Bolt12Invoice { invoice }
+            }
+            ClientPaymentInput::LnUrl { lnurl, url, kind } => PaymentInput::LnUrl {
+                lnurl,
+                url,
+                kind: kind.map(|k| k.into()),
+            },
+            ClientPaymentInput::LightningAddress(name) => PaymentInput::LightningAddress {
+                address: name.to_string(),
+            },
+            ClientPaymentInput::NodeId { node_id } => PaymentInput::NodeId {
+                node_id: node_id.serialize().to_vec(),
+            },
+            ClientPaymentInput::OnChain {
+                address,
+                amount_msat,
+                label,
+                message,
+            } => PaymentInput::OnChain {
+                address,
+                amount_msat,
+                label,
+                message,
+            },
+        }
+    }
+}
+
+/// Classify and decode a BIP-21 URI, BOLT11 invoice, BOLT12 offer or
+/// invoice, LNURL, lightning address, node id or on-chain address,
+/// and check that it can be paid on `network`.
+#[uniffi::export]
+pub fn parse_payment_input(input: String, network: Network) -> Result<PaymentInput, Error> {
+    ClientPaymentInput::parse(&input, network.into())
+        .map(|i| i.into())
+        .map_err(|e| Error::Argument("input".to_owned(), e.to_string()))
+}
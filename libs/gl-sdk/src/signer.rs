@@ -1,6 +1,8 @@
-use crate::{Credentials, Error};
+use crate::{util::exec, Credentials, Error};
 use bip39::Mnemonic;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing;
 
 #[derive(uniffi::Object, Clone)]
@@ -8,6 +10,9 @@ pub struct Signer {
     seed: Vec<u8>,
     pub(crate) inner: gl_client::signer::Signer,
     credentials: Option<Credentials>,
+    policy: gl_client::signer::SignerPolicy,
+    approval: Option<(Arc<dyn gl_client::signer::ApprovalHandler>, Duration)>,
+    pairing: Option<Duration>,
 }
 
 #[uniffi::export]
@@ -32,24 +37,93 @@ impl Signer {
             seed,
             inner,
             credentials,
+            policy: gl_client::signer::SignerPolicy::default(),
+            approval: None,
+            pairing: None,
         })
     }
 
-    pub fn authenticate(&self, creds: &Credentials) -> Result<Signer, Error> {
-        let credentials = Some(creds.clone());
+    /// Return a copy of this signer that enforces the given policy
+    /// profile, serialized as JSON. Fields that are omitted keep
+    /// their default values.
+    pub fn with_policy(&self, policy: String) -> Result<Signer, Error> {
+        let policy = gl_client::signer::SignerPolicy::from_json(&policy)
+            .map_err(|e| Error::Argument("policy".to_string(), e.to_string()))?;
+        Signer {
+            policy,
+            ..self.clone()
+        }
+        .rebuild()
+    }
 
-        let inner = gl_client::signer::Signer::new(
-            self.seed.clone(),
-            gl_client::bitcoin::Network::Bitcoin,
-            creds.inner.clone(),
-        )
-        .map_err(|e| Error::Other(e.to_string()))?;
+    /// Return a copy of this signer that asks `handler` to approve
+    /// requests it cannot match up with a request from this app,
+    /// such as a payment initiated elsewhere. Requests that are not
+    /// approved within `timeout_secs` are denied.
+    pub fn with_approval_handler(
+        &self,
+        handler: Arc<dyn ApprovalHandler>,
+        timeout_secs: u64,
+    ) -> Result<Signer, Error> {
+        let handler: Arc<dyn gl_client::signer::ApprovalHandler> =
+            Arc::new(ApprovalHandlerAdapter(handler));
+        Signer {
+            approval: Some((handler, Duration::from_secs(timeout_secs))),
+            ..self.clone()
+        }
+        .rebuild()
+    }
 
-        Ok(Signer {
-            inner,
-            credentials,
+    /// Return a copy of this signer that holds pairing requests from
+    /// new devices until they are answered with `approve_pairing` or
+    /// `deny_pairing`. Requests that are not answered within
+    /// `timeout_secs` are denied.
+    pub fn with_pairing_queue(&self, timeout_secs: u64) -> Result<Signer, Error> {
+        Signer {
+            pairing: Some(Duration::from_secs(timeout_secs)),
             ..self.clone()
-        })
+        }
+        .rebuild()
+    }
+
+    /// The pairing requests waiting for an answer.
+    pub fn pending_pairings(&self) -> Vec<PairingRequest> {
+        self.inner
+            .pending_pairings()
+            .into_iter()
+            .map(|r| r.into())
+            .collect()
+    }
+
+    /// Approve the pairing request from `device_id`. Any
+    /// `restrictions`, e.g., `readonly,expires=30d`, are added to
+    /// the ones the device asked for.
+    pub fn approve_pairing(
+        &self,
+        device_id: String,
+        restrictions: Option<String>,
+    ) -> Result<(), Error> {
+        let decision = match restrictions {
+            Some(r) => gl_client::signer::PairingDecision::Narrow(r),
+            None => gl_client::signer::PairingDecision::Approve,
+        };
+        self.inner
+            .decide_pairing(&device_id, decision)
+            .map_err(|e| Error::Argument("device_id".to_string(), e.to_string()))
+    }
+
+    pub fn deny_pairing(&self, device_id: String) -> Result<(), Error> {
+        self.inner
+            .decide_pairing(&device_id, gl_client::signer::PairingDecision::Deny)
+            .map_err(|e| Error::Argument("device_id".to_string(), e.to_string()))
+    }
+
+    pub fn authenticate(&self, creds: &Credentials) -> Result<Signer, Error> {
+        Signer {
+            credentials: Some(creds.clone()),
+            ..self.clone()
+        }
+        .rebuild()
     }
 
     pub fn start(&self) -> Result<Handle, Error> {
@@ -73,10 +147,43 @@ impl Signer {
     pub fn node_id(&self) -> Vec<u8> {
         self.inner.node_id()
     }
+
+    /// Log into the service behind an LNURL-auth `lnurl` (LUD-04),
+    /// with a key derived from the node's keys for the service's
+    /// domain.
+    pub fn lnurl_auth(&self, lnurl: String) -> Result<(), Error> {
+        exec(self.inner.lnurl_auth(&lnurl)).map_err(|e| Error::Other(e.to_string()))
+    }
 }
 
 // Not exported through uniffi, internal logic only.
 impl Signer {
+    /// Create a new inner signer matching our configuration.
+    fn rebuild(self) -> Result<Signer, Error> {
+        let network = gl_client::bitcoin::Network::Bitcoin;
+        let builder = match &self.credentials {
+            Some(c) => {
+                gl_client::signer::Signer::builder(self.seed.clone(), network, c.inner.clone())
+            }
+            None => gl_client::signer::Signer::builder(
+                self.seed.clone(),
+                network,
+                gl_client::credentials::Nobody::new(),
+            ),
+        };
+        let builder = builder.with_policy(self.policy.clone());
+        let builder = match &self.approval {
+            Some((handler, timeout)) => builder.with_approval_handler(handler.clone(), *timeout),
+            None => builder,
+        };
+        let builder = match self.pairing {
+            Some(timeout) => builder.with_pairing_queue(timeout),
+            None => builder,
+        };
+        let inner = builder.build().map_err(|e| Error::Other(e.to_string()))?;
+        Ok(Signer { inner, ..self })
+    }
+
     async fn run(&self, signal: tokio::sync::mpsc::Receiver<()>) {
         self.inner
             .run_forever(signal)
@@ -99,3 +206,143 @@ impl Handle {
         self.chan.try_send(()).expect("sending shutdown signal");
     }
 }
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct OnchainOutput {
+    pub script_pubkey: Vec<u8>,
+    pub address: Option<String>,
+    pub amount_sat: u64,
+}
+
+/// A new device asking to be paired with the node.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct PairingRequest {
+    pub device_id: String,
+    pub device_name: String,
+    /// The restrictions the device asked for.
+    pub restrictions: String,
+    pub pubkey: Vec<u8>,
+    /// Seconds since the UNIX epoch.
+    pub expires_at: u64,
+}
+
+impl From<gl_client::signer::PairingRequest> for PairingRequest {
+    fn from(r: gl_client::signer::PairingRequest) -> Self {
+        PairingRequest {
+            device_id: r.device_id,
+            device_name: r.device_name,
+            restrictions: r.restrictions,
+            pubkey: r.pubkey,
+            expires_at: r.expires_at,
+        }
+    }
+}
+
+/// A request the signer needs the app, and likely the user, to
+/// approve before it signs.
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum ApprovalRequest {
+    Invoice {
+        payee: Vec<u8>,
+        payment_hash: Vec<u8>,
+        amount_msat: u64,
+        description: Option<String>,
+    },
+    Keysend {
+        destination: Option<Vec<u8>>,
+        payment_hash: Vec<u8>,
+        amount_msat: u64,
+    },
+    Onchain {
+        txid: Vec<u8>,
+        outputs: Vec<OnchainOutput>,
+        fee_sat: Option<u64>,
+    },
+    OverLimit {
+        limit_msat: u64,
+        window_secs: u64,
+        destination: Option<String>,
+        spent_msat: u64,
+        amount_msat: u64,
+    },
+    Unresolved {
+        message: String,
+    },
+}
+
+impl From<gl_client::signer::ApprovalRequest> for ApprovalRequest {
+    fn from(r: gl_client::signer::ApprovalRequest) -> Self {
+        use gl_client::signer::ApprovalRequest as R;
+        match r {
+            R::Invoice {
+                payee,
+                payment_hash,
+                amount_msat,
+                description,
+            } => ApprovalRequest::Invoice {
+                payee,
+                payment_hash,
+                amount_msat,
+                description,
+            },
+            R::Keysend {
+                destination,
+                payment_hash,
+                amount_msat,
+            } => ApprovalRequest::Keysend {
+                destination,
+                payment_hash,
+                amount_msat,
+            },
+            R::Onchain {
+                txid,
+                outputs,
+                fee_sat,
+            } => ApprovalRequest::Onchain {
+                txid,
+                outputs: outputs
+                    .into_iter()
+                    .map(|o| OnchainOutput {
+                        script_pubkey: o.script_pubkey,
+                        address: o.address,
+                        amount_sat: o.amount_sat,
+                    })
+                    .collect(),
+                fee_sat,
+            },
+            R::OverLimit {
+                limit_msat,
+                window_secs,
+                destination,
+                spent_msat,
+                amount_msat,
+            } => ApprovalRequest::OverLimit {
+                limit_msat,
+                window_secs,
+                destination,
+                spent_msat,
+                amount_msat,
+            },
+            R::Unresolved { message } => ApprovalRequest::Unresolved { message },
+        }
+    }
+}
+
+/// Implemented by the app to approve or deny signer requests. This
+/// may block, e.g., while waiting for the user to confirm.
+#[uniffi::export(with_foreign)]
+pub trait ApprovalHandler: Send + Sync {
+    fn approve(&self, request: ApprovalRequest) -> bool;
+}
+
+struct ApprovalHandlerAdapter(Arc<dyn ApprovalHandler>);
+
+#[async_trait::async_trait]
+impl gl_client::signer::ApprovalHandler for ApprovalHandlerAdapter {
+    async fn approve(&self, request: gl_client::signer::ApprovalRequest) -> bool {
+        let handler = self.0.clone();
+        tokio::task::spawn_blocking(move || handler.approve(request.into()))
+            .await
+            .unwrap_or(false)
+    }
+}
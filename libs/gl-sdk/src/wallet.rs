@@ -0,0 +1,266 @@
+use gl_client::wallet;
+
+/// The funds of the node, see `Node::balance`.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct Balance {
+    pub onchain_confirmed_msat: u64,
+    pub onchain_unconfirmed_msat: u64,
+    pub onchain_immature_msat: u64,
+    pub onchain_reserved_msat: u64,
+    pub channel_spendable_msat: u64,
+    pub channel_receivable_msat: u64,
+    pub channel_pending_msat: u64,
+    pub total_msat: u64,
+}
+
+impl From<wallet::Balance> for Balance {
+    fn from(other: wallet::Balance) -> Self {
+        Self {
+            total_msat: other.total_msat(),
+            onchain_confirmed_msat: other.onchain_confirmed_msat,
+            onchain_unconfirmed_msat: other.onchain_unconfirmed_msat,
+            onchain_immature_msat: other.onchain_immature_msat,
+            onchain_reserved_msat: other.onchain_reserved_msat,
+            channel_spendable_msat: other.channel_spendable_msat,
+            channel_receivable_msat: other.channel_receivable_msat,
+            channel_pending_msat: other.channel_pending_msat,
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum PaymentDirection {
+    INCOMING,
+    OUTGOING,
+}
+
+impl From<wallet::Direction> for PaymentDirection {
+    fn from(other: wallet::Direction) -> Self {
+        match other {
+            wallet::Direction::Incoming => PaymentDirection::INCOMING,
+            wallet::Direction::Outgoing => PaymentDirection::OUTGOING,
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum PaymentStatus {
+    PENDING,
+    COMPLETE,
+    FAILED,
+}
+
+impl From<wallet::PaymentStatus> for PaymentStatus {
+    fn from(other: wallet::PaymentStatus) -> Self {
+        match other {
+            wallet::PaymentStatus::Pending => PaymentStatus::PENDING,
+            wallet::PaymentStatus::Complete => PaymentStatus::COMPLETE,
+            wallet::PaymentStatus::Failed => PaymentStatus::FAILED,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct Payment {
+    pub direction: PaymentDirection,
+    pub status: PaymentStatus,
+    pub payment_hash: Vec<u8>,
+    pub amount_msat: Option<u64>,
+    pub fee_msat: Option<u64>,
+    pub preimage: Option<Vec<u8>>,
+    pub description: Option<String>,
+    pub invoice: Option<String>,
+    pub destination: Option<Vec<u8>>,
+    pub timestamp: u64,
+}
+
+impl From<wallet::Payment> for Payment {
+    fn from(other: wallet::Payment) -> Self {
+        Self {
+            direction: other.direction.into(),
+            status: other.status.into(),
+            payment_hash: other.payment_hash,
+            amount_msat: other.amount_msat,
+            fee_msat: other.fee_msat,
+            preimage: other.preimage,
+            description: other.description,
+            invoice: other.invoice,
+            destination: other.destination,
+            timestamp: other.timestamp,
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum InvoiceStatus {
+    UNPAID,
+    PAID,
+    EXPIRED,
+}
+
+impl From<wallet::InvoiceStatus> for InvoiceStatus {
+    fn from(other: wallet::InvoiceStatus) -> Self {
+        match other {
+            wallet::InvoiceStatus::Unpaid => InvoiceStatus::UNPAID,
+            wallet::InvoiceStatus::Paid => InvoiceStatus::PAID,
+            wallet::InvoiceStatus::Expired => InvoiceStatus::EXPIRED,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct Invoice {
+    pub label: String,
+    pub status: InvoiceStatus,
+    pub invoice: Option<String>,
+    pub payment_hash: Vec<u8>,
+    pub description: Option<String>,
+    pub amount_msat: Option<u64>,
+    pub amount_received_msat: Option<u64>,
+    pub expires_at: u64,
+    pub paid_at: Option<u64>,
+    pub preimage: Option<Vec<u8>>,
+}
+
+impl From<wallet::Invoice> for Invoice {
+    fn from(other: wallet::Invoice) -> Self {
+        Self {
+            label: other.label,
+            status: other.status.into(),
+            invoice: other.invoice,
+            payment_hash: other.payment_hash,
+            description: other.description,
+            amount_msat: other.amount_msat,
+            amount_received_msat: other.amount_received_msat,
+            expires_at: other.expires_at,
+            paid_at: other.paid_at,
+            preimage: other.preimage,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct FeeEstimate {
+    pub blocks: u32,
+    pub perkb: u32,
+}
+
+/// On-chain feerates in satoshis per 1000 virtual bytes.
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct FeeEstimates {
+    pub min_acceptable: u32,
+    pub opening: Option<u32>,
+    pub mutual_close: Option<u32>,
+    pub estimates: Vec<FeeEstimate>,
+}
+
+impl From<wallet::FeeEstimates> for FeeEstimates {
+    fn from(other: wallet::FeeEstimates) -> Self {
+        Self {
+            min_acceptable: other.min_acceptable,
+            opening: other.opening,
+            mutual_close: other.mutual_close,
+            estimates: other
+                .estimates
+                .into_iter()
+                .map(|e| FeeEstimate {
+                    blocks: e.blocks,
+                    perkb: e.perkb,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum ChannelState {
+    OPENING,
+    OPEN,
+    CLOSING,
+    CLOSED,
+}
+
+impl From<wallet::ChannelState> for ChannelState {
+    fn from(other: wallet::ChannelState) -> Self {
+        match other {
+            wallet::ChannelState::Opening => ChannelState::OPENING,
+            wallet::ChannelState::Open => ChannelState::OPEN,
+            wallet::ChannelState::Closing => ChannelState::CLOSING,
+            wallet::ChannelState::Closed => ChannelState::CLOSED,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct Channel {
+    pub peer_id: Vec<u8>,
+    pub peer_connected: bool,
+    pub state: ChannelState,
+    pub channel_id: Option<Vec<u8>>,
+    pub short_channel_id: Option<String>,
+    pub funding_txid: Option<Vec<u8>>,
+    pub total_msat: u64,
+    pub to_us_msat: u64,
+    pub spendable_msat: u64,
+    pub receivable_msat: u64,
+}
+
+impl From<wallet::Channel> for Channel {
+    fn from(other: wallet::Channel) -> Self {
+        Self {
+            peer_id: other.peer_id,
+            peer_connected: other.peer_connected,
+            state: other.state.into(),
+            channel_id: other.channel_id,
+            short_channel_id: other.short_channel_id,
+            funding_txid: other.funding_txid,
+            total_msat: other.total_msat,
+            to_us_msat: other.to_us_msat,
+            spendable_msat: other.spendable_msat,
+            receivable_msat: other.receivable_msat,
+        }
+    }
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct OpenChannelResponse {
+    pub channel_id: Vec<u8>,
+    pub txid: Vec<u8>,
+    pub outnum: u32,
+}
+
+impl From<wallet::OpenedChannel> for OpenChannelResponse {
+    fn from(other: wallet::OpenedChannel) -> Self {
+        Self {
+            channel_id: other.channel_id,
+            txid: other.txid,
+            outnum: other.outnum,
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Clone, Debug)]
+pub enum CloseType {
+    MUTUAL,
+    UNILATERAL,
+    UNOPENED,
+}
+
+#[derive(uniffi::Record, Clone, Debug)]
+pub struct CloseChannelResponse {
+    pub close_type: CloseType,
+    pub txid: Option<Vec<u8>>,
+}
+
+impl From<wallet::ClosedChannel> for CloseChannelResponse {
+    fn from(other: wallet::ClosedChannel) -> Self {
+        Self {
+            close_type: match other.close_type {
+                wallet::CloseType::Mutual => CloseType::MUTUAL,
+                wallet::CloseType::Unilateral => CloseType::UNILATERAL,
+                wallet::CloseType::Unopened => CloseType::UNOPENED,
+            },
+            txid: other.txid,
+        }
+    }
+}